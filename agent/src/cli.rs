@@ -0,0 +1,127 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// CSF agent: collects system metrics and connects agents over mTLS
+#[derive(Debug, Parser)]
+#[command(name = "csf-agent", version, about)]
+pub struct Cli {
+    /// Main config file (default: /etc/csf-agent/config.toml)
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "PATH",
+        env = "CSF_AGENT_CONFIG"
+    )]
+    pub config: Option<PathBuf>,
+
+    /// Override a config key, e.g. `--set p2p.listen_port=9443` (highest precedence)
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_override)]
+    pub overrides: Vec<(String, String)>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the agent (default)
+    Run,
+    /// Create a configuration file
+    Init(InitArgs),
+    /// Validate the configuration, backend reachability and certificates
+    Check,
+    /// Show the effective configuration and where it was loaded from
+    Config,
+    /// Show peers and the last results of the running agent
+    Status {
+        /// Print the raw status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the agent's P2P certificates
+    #[command(subcommand)]
+    Certs(CertsCommand),
+    /// Collect metrics and print them instead of sending them
+    Collect {
+        /// Collect a single sample and exit
+        #[arg(long)]
+        once: bool,
+        /// Print metrics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the hardware and software inventory sent to the backend
+    Inventory,
+    /// Remove this agent from the backend (run when uninstalling the agent)
+    Deregister,
+}
+
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    /// Don't prompt, use flags and defaults only
+    #[arg(long)]
+    pub non_interactive: bool,
+    /// Overwrite an existing config file (the agent ID is kept)
+    #[arg(long)]
+    pub force: bool,
+    /// Name of this agent
+    #[arg(long)]
+    pub name: Option<String>,
+    /// URL of the central server
+    #[arg(long)]
+    pub server_url: Option<String>,
+    /// API key for authentication, stored in a separate file readable only by its owner
+    #[arg(long, env = "CSF_AGENT_API_KEY", hide_env_values = true)]
+    pub api_key: Option<String>,
+    /// File to store the API key in (default: api_key next to the config file)
+    #[arg(long, value_name = "PATH")]
+    pub api_key_file: Option<PathBuf>,
+    /// Tags for this agent (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub tags: Option<Vec<String>>,
+    /// Skip the backend connection and only use P2P
+    #[arg(long)]
+    pub p2p_only: bool,
+    /// Enable P2P connections
+    #[arg(long)]
+    pub p2p: bool,
+    /// Port to listen for P2P connections
+    #[arg(long)]
+    pub listen_port: Option<u16>,
+    /// Peer to connect to (agent_id@host:port), may be repeated
+    #[arg(long = "peer", value_name = "ADDRESS")]
+    pub peers: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CertsCommand {
+    /// Generate the agent certificate (from the fleet CA or the local CA)
+    Generate {
+        /// Replace an existing certificate
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the certificates in a PEM file (default: the agent certificate)
+    Inspect { path: Option<PathBuf> },
+    /// Renew the agent certificate now
+    Renew,
+    /// Write a registration with a CSR, for agents that can't reach the backend
+    Request {
+        /// File to write to (default: stdout)
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Install the certificate from the backend's response to a registration
+    Install { response: PathBuf },
+}
+
+fn parse_override(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", s))?;
+    if key.is_empty() {
+        return Err("missing key".to_string());
+    }
+    Ok((key.trim().to_lowercase(), value.to_string()))
+}
@@ -1,5 +1,7 @@
 use crate::collector::SystemMetrics;
 use crate::config::AgentConfig;
+use crate::inventory::Inventory;
+use crate::services::ServiceState;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use reqwest::Client;
@@ -16,12 +18,70 @@ pub struct AgentRegistration {
     pub architecture: String,
     pub agent_version: String,
     pub tags: Vec<String>,
+    /// CSR to be signed by the backend's fleet CA
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csr: Option<String>,
+    /// Agent that forwarded the registration on behalf of an isolated agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayed_by: Option<Uuid>,
+    /// Hardware and software inventory of the host
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inventory: Option<Inventory>,
+}
+
+/// Inventory sent when it changed since the registration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryReport {
+    pub agent_id: Uuid,
+    pub collected_at: DateTime<Utc>,
+    pub inventory: Inventory,
+    /// Agent that forwarded the inventory on behalf of an isolated agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayed_by: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegistrationResponse {
     pub success: bool,
     pub message: String,
+    /// Certificate issued by the fleet CA
+    #[serde(default)]
+    pub certificate: Option<String>,
+    /// Fleet CA certificates to trust
+    #[serde(default)]
+    pub ca_bundle: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    /// Collect metrics in burst mode until then, as asked for by an operator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaBundleResponse {
+    pub ca_bundle: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateRenewal {
+    pub agent_id: Uuid,
+    pub csr: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateResponse {
+    pub certificate: String,
+    pub ca_bundle: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationResponse {
+    /// PEM CRLs, one per trusted fleet CA
+    pub crls: Vec<String>,
+    /// Agents that must be refused regardless of their certificate
+    pub denied_agents: Vec<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +89,20 @@ pub struct Heartbeat {
     pub agent_id: Uuid,
     pub timestamp: DateTime<Utc>,
     pub status: String,
+    /// Expiry of the agent's P2P certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_not_after: Option<DateTime<Utc>>,
+    /// Agent that forwarded the heartbeat on behalf of an isolated agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayed_by: Option<Uuid>,
+    /// States of the watched services
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceState>,
+}
+
+#[derive(Debug, Serialize)]
+struct Deregistration {
+    agent_id: Uuid,
 }
 
 #[derive(Clone)]
@@ -47,6 +121,24 @@ impl ServerClient {
         }
     }
 
+    /// Checks that the backend is reachable and healthy
+    pub async fn health_check(&self) -> Result<()> {
+        let url = format!("{}/api/system/health", self.server_url);
+
+        let response = self
+            .client
+            .get(&url)
+            .timeout(std::time::Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Health check failed: {}", response.status());
+        }
+
+        Ok(())
+    }
+
     pub async fn register(&self, registration: &AgentRegistration) -> Result<RegistrationResponse> {
         let url = format!("{}/api/agents/register", self.server_url);
 
@@ -65,7 +157,67 @@ impl ServerClient {
         }
     }
 
-    pub async fn send_heartbeat(&self, heartbeat: &Heartbeat) -> Result<()> {
+    pub async fn fetch_ca_bundle(&self) -> Result<Vec<String>> {
+        let url = format!("{}/api/agents/ca", self.server_url);
+
+        let response = self
+            .client
+            .get(&url)
+            .header("X-API-Key", &self.api_key)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let bundle: CaBundleResponse = response.json().await?;
+            Ok(bundle.ca_bundle)
+        } else {
+            anyhow::bail!("CA bundle request failed: {}", response.status())
+        }
+    }
+
+    pub async fn renew_certificate(
+        &self,
+        agent_id: Uuid,
+        csr: &str,
+    ) -> Result<CertificateResponse> {
+        let url = format!("{}/api/agents/certificate", self.server_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("X-API-Key", &self.api_key)
+            .json(&CertificateRenewal {
+                agent_id,
+                csr: csr.to_string(),
+            })
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            anyhow::bail!("Certificate renewal failed: {}", response.status())
+        }
+    }
+
+    pub async fn fetch_revocations(&self) -> Result<RevocationResponse> {
+        let url = format!("{}/api/agents/revocations", self.server_url);
+
+        let response = self
+            .client
+            .get(&url)
+            .header("X-API-Key", &self.api_key)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            Ok(response.json().await?)
+        } else {
+            anyhow::bail!("Revocation list request failed: {}", response.status())
+        }
+    }
+
+    pub async fn send_heartbeat(&self, heartbeat: &Heartbeat) -> Result<HeartbeatResponse> {
         let url = format!("{}/api/agents/heartbeat", self.server_url);
 
         let response = self
@@ -80,6 +232,29 @@ impl ServerClient {
             anyhow::bail!("Heartbeat failed: {}", response.status());
         }
 
+        // Older backends answer without a body
+        let body = response.text().await?;
+        if body.trim().is_empty() {
+            return Ok(HeartbeatResponse::default());
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    pub async fn send_inventory(&self, report: &InventoryReport) -> Result<()> {
+        let url = format!("{}/api/agents/inventory", self.server_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("X-API-Key", &self.api_key)
+            .json(report)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Inventory upload failed: {}", response.status());
+        }
+
         Ok(())
     }
 
@@ -100,4 +275,23 @@ impl ServerClient {
 
         Ok(())
     }
+
+    /// Removes this agent from the backend, e.g. when it is uninstalled
+    pub async fn deregister(&self, agent_id: Uuid) -> Result<()> {
+        let url = format!("{}/api/agents/deregister", self.server_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("X-API-Key", &self.api_key)
+            .json(&Deregistration { agent_id })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("Deregistration failed: {}", response.status());
+        }
+
+        Ok(())
+    }
 }
@@ -1,6 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sysinfo::{Disks, Networks, System};
+use sysinfo::{CpuRefreshKind, Disks, MemoryRefreshKind, Networks, RefreshKind, System};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,21 +12,30 @@ pub struct SystemMetrics {
     pub cpu_model: String,
     pub cpu_cores: u32,
     pub cpu_threads: u32,
-    pub cpu_usage_percent: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_usage_percent: Option<f32>,
 
     // Memory
-    pub memory_total_bytes: u64,
-    pub memory_used_bytes: u64,
-    pub memory_usage_percent: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_total_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_used_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_usage_percent: Option<f32>,
 
     // Disk
-    pub disk_total_bytes: u64,
-    pub disk_used_bytes: u64,
-    pub disk_usage_percent: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_total_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_used_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_usage_percent: Option<f32>,
 
     // Network
-    pub network_rx_bytes: u64,
-    pub network_tx_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_rx_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_tx_bytes: Option<u64>,
 
     // System
     pub os_name: String,
@@ -34,6 +43,52 @@ pub struct SystemMetrics {
     pub kernel_version: String,
     pub hostname: String,
     pub uptime_seconds: u64,
+
+    /// Agent that forwarded these metrics on behalf of an isolated agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relayed_by: Option<Uuid>,
+}
+
+impl SystemMetrics {
+    /// One line overview of the usage values in this sample
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+        if let Some(cpu) = self.cpu_usage_percent {
+            parts.push(format!("CPU: {:.1}%", cpu));
+        }
+        if let Some(memory) = self.memory_usage_percent {
+            parts.push(format!("RAM: {:.1}%", memory));
+        }
+        if let Some(disk) = self.disk_usage_percent {
+            parts.push(format!("Disk: {:.1}%", disk));
+        }
+        if let (Some(rx), Some(tx)) = (self.network_rx_bytes, self.network_tx_bytes) {
+            parts.push(format!("Net: {} rx / {} tx bytes", rx, tx));
+        }
+        parts.join(" | ")
+    }
+}
+
+/// Metric groups refreshed and included in a sample
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricGroups {
+    pub cpu: bool,
+    pub memory: bool,
+    pub disk: bool,
+    pub network: bool,
+}
+
+impl MetricGroups {
+    pub const ALL: Self = Self {
+        cpu: true,
+        memory: true,
+        disk: true,
+        network: true,
+    };
+
+    pub fn is_empty(&self) -> bool {
+        !(self.cpu || self.memory || self.disk || self.network)
+    }
 }
 
 pub struct MetricsCollector {
@@ -45,18 +100,18 @@ pub struct MetricsCollector {
 impl MetricsCollector {
     pub fn new() -> Self {
         Self {
-            system: System::new_all(),
+            system: System::new_with_specifics(
+                RefreshKind::new()
+                    .with_cpu(CpuRefreshKind::new().with_cpu_usage())
+                    .with_memory(MemoryRefreshKind::everything()),
+            ),
             networks: Networks::new_with_refreshed_list(),
             disks: Disks::new_with_refreshed_list(),
         }
     }
 
-    pub fn collect(&mut self, agent_id: Uuid) -> SystemMetrics {
-        // Refresh all data
-        self.system.refresh_all();
-        self.networks.refresh();
-        self.disks.refresh();
-
+    /// Takes a sample of the given metric groups, refreshing only what they need
+    pub fn collect(&mut self, agent_id: Uuid, groups: MetricGroups) -> SystemMetrics {
         // CPU info
         let cpu_model = self
             .system
@@ -68,39 +123,49 @@ impl MetricsCollector {
         let cpu_cores = self.system.physical_core_count().unwrap_or(0) as u32;
         let cpu_threads = self.system.cpus().len() as u32;
 
-        let cpu_usage_percent = if !self.system.cpus().is_empty() {
-            let total: f32 = self.system.cpus().iter().map(|cpu| cpu.cpu_usage()).sum();
-            total / self.system.cpus().len() as f32
-        } else {
-            0.0
-        };
+        let cpu_usage_percent = groups.cpu.then(|| {
+            self.system.refresh_cpu_usage();
+            if !self.system.cpus().is_empty() {
+                let total: f32 = self.system.cpus().iter().map(|cpu| cpu.cpu_usage()).sum();
+                total / self.system.cpus().len() as f32
+            } else {
+                0.0
+            }
+        });
 
         // Memory
-        let memory_total_bytes = self.system.total_memory();
-        let memory_used_bytes = self.system.used_memory();
-        let memory_usage_percent = if memory_total_bytes > 0 {
-            (memory_used_bytes as f32 / memory_total_bytes as f32) * 100.0
-        } else {
-            0.0
-        };
+        let memory = groups.memory.then(|| {
+            self.system.refresh_memory();
+            let total = self.system.total_memory();
+            let used = self.system.used_memory();
+            let usage_percent = if total > 0 {
+                (used as f32 / total as f32) * 100.0
+            } else {
+                0.0
+            };
+            (total, used, usage_percent)
+        });
 
         // Disk
-        let (disk_total_bytes, disk_used_bytes) =
-            self.disks.iter().fold((0u64, 0u64), |(total, used), disk| {
+        let disk = groups.disk.then(|| {
+            self.disks.refresh();
+            let (total, used) = self.disks.iter().fold((0u64, 0u64), |(total, used), disk| {
                 (
                     total + disk.total_space(),
                     used + (disk.total_space() - disk.available_space()),
                 )
             });
-
-        let disk_usage_percent = if disk_total_bytes > 0 {
-            (disk_used_bytes as f32 / disk_total_bytes as f32) * 100.0
-        } else {
-            0.0
-        };
+            let usage_percent = if total > 0 {
+                (used as f32 / total as f32) * 100.0
+            } else {
+                0.0
+            };
+            (total, used, usage_percent)
+        });
 
         // Network
-        let (network_rx_bytes, network_tx_bytes) =
+        let network = groups.network.then(|| {
+            self.networks.refresh();
             self.networks
                 .iter()
                 .fold((0u64, 0u64), |(rx, tx), (_name, network)| {
@@ -108,7 +173,8 @@ impl MetricsCollector {
                         rx + network.total_received(),
                         tx + network.total_transmitted(),
                     )
-                });
+                })
+        });
 
         // System info
         let hostname = System::host_name().unwrap_or_else(|| "unknown".to_string());
@@ -124,19 +190,20 @@ impl MetricsCollector {
             cpu_cores,
             cpu_threads,
             cpu_usage_percent,
-            memory_total_bytes,
-            memory_used_bytes,
-            memory_usage_percent,
-            disk_total_bytes,
-            disk_used_bytes,
-            disk_usage_percent,
-            network_rx_bytes,
-            network_tx_bytes,
+            memory_total_bytes: memory.map(|(total, _, _)| total),
+            memory_used_bytes: memory.map(|(_, used, _)| used),
+            memory_usage_percent: memory.map(|(_, _, usage)| usage),
+            disk_total_bytes: disk.map(|(total, _, _)| total),
+            disk_used_bytes: disk.map(|(_, used, _)| used),
+            disk_usage_percent: disk.map(|(_, _, usage)| usage),
+            network_rx_bytes: network.map(|(rx, _)| rx),
+            network_tx_bytes: network.map(|(_, tx)| tx),
             os_name,
             os_version,
             kernel_version,
             hostname,
             uptime_seconds,
+            relayed_by: None,
         }
     }
 }
@@ -0,0 +1,159 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+
+use crate::cli::CertsCommand;
+use crate::client::{RegistrationResponse, ServerClient};
+use crate::config::AgentConfig;
+use crate::connect::{certs, ensure_certificates, renewal};
+
+pub async fn run(config: &AgentConfig, command: CertsCommand) -> Result<()> {
+    match command {
+        CertsCommand::Generate { force } => generate(config, force).await,
+        CertsCommand::Inspect { path } => {
+            inspect(&path.unwrap_or_else(|| PathBuf::from(&config.p2p.cert_path)))
+        }
+        CertsCommand::Renew => {
+            renewal::renew_certificate(config, &ServerClient::new(config)).await?;
+            print_expiry(config)?;
+            println!("   Restart a running agent to use the new certificate");
+            Ok(())
+        }
+        CertsCommand::Request { out } => request(config, out.as_deref()),
+        CertsCommand::Install { response } => install(config, &response),
+    }
+}
+
+/// Writes a registration with a CSR to be sent to the backend from another host
+///
+/// Agents behind a collector proxy need a certificate of the fleet CA before
+/// the collector accepts their connection.
+fn request(config: &AgentConfig, out: Option<&Path>) -> Result<()> {
+    let registration = crate::agent_registration(config, Some(renewal::agent_csr(config)?), None);
+    let json = serde_json::to_string_pretty(&registration)?;
+
+    match out {
+        Some(path) => {
+            std::fs::write(path, json).context(format!("Failed to write {:?}", path))?;
+            eprintln!("📝 Registration written to {:?}", path);
+        }
+        None => println!("{}", json),
+    }
+    eprintln!("   POST it to /api/agents/register with an agent API key and install");
+    eprintln!("   the response with `csf-agent certs install <response.json>`");
+    Ok(())
+}
+
+/// Installs the certificate and CA bundle from a registration response
+fn install(config: &AgentConfig, response: &Path) -> Result<()> {
+    let content =
+        std::fs::read_to_string(response).context(format!("Failed to read {:?}", response))?;
+    let response: RegistrationResponse =
+        serde_json::from_str(&content).context("Not a registration response")?;
+    let (Some(certificate), Some(ca_bundle)) = (response.certificate, response.ca_bundle) else {
+        anyhow::bail!("The response contains no certificate: {}", response.message);
+    };
+
+    certs::install_issued_certificate(&config.p2p.cert_dir(), &certificate, &ca_bundle)?;
+    if !renewal::has_fleet_certificate(config) {
+        anyhow::bail!("The installed certificate was not issued for this agent by the fleet CA");
+    }
+    println!("🔏 Certificate issued by the fleet CA installed");
+    print_expiry(config)
+}
+
+/// Obtains the agent certificate from the fleet CA, or generates it with the local CA
+async fn generate(config: &AgentConfig, force: bool) -> Result<()> {
+    let cert_path = Path::new(&config.p2p.cert_path);
+    if cert_path.exists() && !force {
+        anyhow::bail!(
+            "Certificate {:?} already exists, use --force to replace it",
+            cert_path
+        );
+    }
+
+    if renewal::renews_through_collector(config) {
+        anyhow::bail!(
+            "The fleet CA is only reachable through the collector proxy, use `certs request`"
+        );
+    }
+
+    let cert_dir = config.p2p.cert_dir();
+    if config.p2p.use_backend_ca && config.connects_to_backend() {
+        let csr = certs::generate_csr(
+            &config.name,
+            &config.agent_id,
+            &config.p2p.subject_alt_names(),
+            &cert_dir,
+            config.p2p.key_passphrase(),
+        )?;
+        let response = ServerClient::new(config)
+            .register(&crate::agent_registration(config, Some(csr), None))
+            .await
+            .context("Registration with the fleet CA failed")?;
+        let (Some(certificate), Some(ca_bundle)) = (response.certificate, response.ca_bundle)
+        else {
+            anyhow::bail!("The backend did not issue a certificate");
+        };
+        certs::install_issued_certificate(&cert_dir, &certificate, &ca_bundle)?;
+        println!("🔏 Certificate issued by the fleet CA");
+    } else {
+        if force {
+            // Keep the local CA, peers trust it
+            for name in ["agent.crt", "agent.key"] {
+                let path = cert_dir.join(name);
+                if path.exists() {
+                    std::fs::remove_file(&path).context(format!("Failed to remove {:?}", path))?;
+                }
+            }
+        }
+        ensure_certificates(
+            &config.name,
+            &config.agent_id,
+            &config.p2p.subject_alt_names(),
+            &cert_dir,
+            true,
+            config.p2p.key_passphrase(),
+        )?;
+        println!("🔏 Certificate issued by the local CA");
+    }
+
+    print_expiry(config)
+}
+
+fn inspect(path: &Path) -> Result<()> {
+    for (i, cert) in certs::inspect_certificates(path)?.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Subject:    {}", cert.subject);
+        println!("Issuer:     {}", cert.issuer);
+        println!("Serial:     {}", cert.serial);
+        println!("Not before: {}", cert.not_before);
+
+        let remaining = (cert.not_after - Utc::now()).num_days();
+        if remaining < 0 {
+            println!("Not after:  {} (expired)", cert.not_after);
+        } else {
+            println!("Not after:  {} ({} days left)", cert.not_after, remaining);
+        }
+
+        if cert.is_ca {
+            println!("CA:         yes");
+        }
+        if let Some(agent_id) = cert.agent_id {
+            println!("Agent ID:   {}", agent_id);
+        }
+        if !cert.subject_alt_names.is_empty() {
+            println!("SANs:       {}", cert.subject_alt_names.join(", "));
+        }
+    }
+
+    Ok(())
+}
+
+fn print_expiry(config: &AgentConfig) -> Result<()> {
+    let not_after = certs::certificate_not_after(Path::new(&config.p2p.cert_path))?;
+    println!("✅ Agent certificate valid until {}", not_after);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::path::Path;
+
+use crate::client::ServerClient;
+use crate::config::{AgentConfig, LoadedConfig};
+use crate::connect::connector::{PeerAddress, TlsFiles};
+use crate::connect::{certs, P2PConnector};
+use crate::secrets::{self, SecretSource};
+
+/// Validates the configuration, backend reachability and certificate chain
+///
+/// Prints one line per check and fails if any of them failed.
+pub async fn run(loaded: Result<LoadedConfig>) -> Result<()> {
+    let mut report = Report::default();
+
+    let config = match loaded {
+        Ok(loaded) => {
+            report.ok(&format!("Config loaded from {}", loaded.sources.join("; ")));
+            if loaded.generated_agent_id {
+                report.warn("No agent_id configured yet, one is generated on first start");
+            }
+            for (name, source) in &loaded.secrets {
+                if *source == SecretSource::Inline {
+                    report.warn(&format!(
+                        "{} is set in plain text, move it to api_key_file or {}",
+                        name,
+                        secrets::API_KEY_ENV
+                    ));
+                } else {
+                    report.ok(&format!("{} loaded from {}", name, source));
+                }
+            }
+            loaded.config
+        }
+        Err(e) => {
+            report.fail(&format!("Failed to load config: {:#}", e));
+            return report.finish();
+        }
+    };
+
+    check_config(&config, &mut report);
+
+    if config.connects_to_backend() {
+        let client = ServerClient::new(&config);
+        match client.health_check().await {
+            Ok(_) => report.ok(&format!("Backend reachable at {}", config.server_url)),
+            Err(e) => report.fail(&format!(
+                "Backend not reachable at {}: {}",
+                config.server_url, e
+            )),
+        }
+    }
+
+    if config.p2p.enabled {
+        check_certificates(&config, &mut report);
+    }
+
+    report.finish()
+}
+
+fn check_config(config: &AgentConfig, report: &mut Report) {
+    if config.connects_to_backend() {
+        match reqwest::Url::parse(&config.server_url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(url) => report.fail(&format!("Unsupported server URL scheme: {}", url.scheme())),
+            Err(e) => report.fail(&format!("Invalid server URL {}: {}", config.server_url, e)),
+        }
+        if config.api_key.is_empty() {
+            report.fail("No API key configured");
+        }
+    }
+
+    if config.collection_interval == 0 || config.heartbeat_interval == 0 {
+        report.fail("Collection and heartbeat intervals must be greater than zero");
+    }
+
+    let addresses = config
+        .p2p
+        .peers
+        .iter()
+        .chain(&config.p2p.relays)
+        .chain(&config.p2p.collector);
+    for address in addresses {
+        if let Err(e) = address.parse::<PeerAddress>() {
+            report.fail(&format!("Invalid peer address {}: {}", address, e));
+        }
+    }
+
+    if !config.p2p.enabled && (config.p2p_only_mode || config.p2p.collector.is_some()) {
+        report.fail("P2P is disabled but the agent has no backend connection");
+    }
+
+    if !report.failed {
+        report.ok("Configuration valid");
+    }
+}
+
+fn check_certificates(config: &AgentConfig, report: &mut Report) {
+    let files = [
+        ("Agent certificate", &config.p2p.cert_path),
+        ("Agent private key", &config.p2p.key_path),
+        ("CA certificate", &config.p2p.ca_cert_path),
+    ];
+    let mut missing = false;
+    for (label, path) in files {
+        if !Path::new(path).exists() {
+            report.fail(&format!("{} not found at {}", label, path));
+            missing = true;
+        }
+    }
+    if missing {
+        return;
+    }
+
+    let cert_path = Path::new(&config.p2p.cert_path);
+    match certs::verify_agent_certificate(
+        cert_path,
+        Path::new(&config.p2p.ca_cert_path),
+        Path::new(&config.p2p.crl_path),
+        &config.agent_id,
+    ) {
+        Ok(_) => report.ok("Certificate chain valid for this agent"),
+        Err(e) => report.fail(&format!("Certificate verification failed: {}", e)),
+    }
+
+    if let Ok(not_after) = certs::certificate_not_after(cert_path) {
+        let remaining = not_after - Utc::now();
+        if remaining < chrono::Duration::days(config.p2p.renew_before_days as i64) {
+            report.warn(&format!(
+                "Certificate expires at {} ({} days left)",
+                not_after,
+                remaining.num_days()
+            ));
+        }
+    }
+
+    // Building the TLS configuration also checks the key matches the certificate
+    match P2PConnector::new(
+        config.agent_id,
+        config.name.clone(),
+        config.p2p.listen_port,
+        TlsFiles::from_config(&config.p2p),
+    ) {
+        Ok(_) => report.ok("TLS configuration loads"),
+        Err(e) => report.fail(&format!("TLS configuration invalid: {:#}", e)),
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    fn ok(&mut self, message: &str) {
+        println!("✅ {}", message);
+    }
+
+    fn warn(&mut self, message: &str) {
+        println!("⚠️  {}", message);
+    }
+
+    fn fail(&mut self, message: &str) {
+        println!("❌ {}", message);
+        self.failed = true;
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.failed {
+            anyhow::bail!("Some checks failed");
+        }
+        Ok(())
+    }
+}
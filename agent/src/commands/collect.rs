@@ -0,0 +1,69 @@
+use crate::collector::{MetricGroups, MetricsCollector, SystemMetrics};
+use crate::config::AgentConfig;
+use crate::schedule::CollectionSchedule;
+use anyhow::Result;
+
+/// Collects metrics like the running agent and prints them instead of sending them
+pub async fn run(config: &AgentConfig, once: bool, json: bool) -> Result<()> {
+    let mut collector = MetricsCollector::new();
+
+    // CPU usage is measured between two refreshes
+    collector.collect(config.agent_id, MetricGroups::ALL);
+    tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+
+    if once {
+        let metrics = collector.collect(config.agent_id, MetricGroups::ALL);
+        return print_metrics(&metrics, json);
+    }
+
+    let mut schedule = CollectionSchedule::new(config);
+    loop {
+        tokio::time::sleep_until(schedule.next_due()).await;
+
+        let groups = schedule.due(tokio::time::Instant::now());
+        if groups.is_empty() {
+            continue;
+        }
+        let metrics = collector.collect(config.agent_id, groups);
+        schedule.check_thresholds(&metrics);
+        print_metrics(&metrics, json)?;
+    }
+}
+
+fn print_metrics(metrics: &SystemMetrics, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(metrics)?);
+        return Ok(());
+    }
+
+    println!("{} {}", metrics.timestamp, metrics.hostname);
+    if let Some(usage) = metrics.cpu_usage_percent {
+        println!(
+            "  CPU:     {:.1}% ({}, {} cores / {} threads)",
+            usage, metrics.cpu_model, metrics.cpu_cores, metrics.cpu_threads
+        );
+    }
+    if let (Some(usage), Some(used), Some(total)) = (
+        metrics.memory_usage_percent,
+        metrics.memory_used_bytes,
+        metrics.memory_total_bytes,
+    ) {
+        println!("  Memory:  {:.1}% ({} / {} bytes)", usage, used, total);
+    }
+    if let (Some(usage), Some(used), Some(total)) = (
+        metrics.disk_usage_percent,
+        metrics.disk_used_bytes,
+        metrics.disk_total_bytes,
+    ) {
+        println!("  Disk:    {:.1}% ({} / {} bytes)", usage, used, total);
+    }
+    if let (Some(rx), Some(tx)) = (metrics.network_rx_bytes, metrics.network_tx_bytes) {
+        println!("  Network: {} bytes received, {} bytes sent", rx, tx);
+    }
+    println!(
+        "  System:  {} {} (kernel {}), up {}s",
+        metrics.os_name, metrics.os_version, metrics.kernel_version, metrics.uptime_seconds
+    );
+
+    Ok(())
+}
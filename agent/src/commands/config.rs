@@ -0,0 +1,22 @@
+use anyhow::Result;
+
+use crate::config::LoadedConfig;
+
+/// Prints the effective configuration without secrets, preceded by the sources it
+/// was merged from
+pub fn run(loaded: &LoadedConfig) -> Result<()> {
+    println!("# Effective configuration, merged from (lowest precedence first):");
+    for source in &loaded.sources {
+        println!("#   {}", source);
+    }
+    if loaded.generated_agent_id {
+        println!("# No agent_id is configured yet, it is generated on first start");
+    }
+    for (name, source) in &loaded.secrets {
+        println!("# {} loaded from {}", name, source);
+    }
+    println!();
+    print!("{}", loaded.config.to_toml()?);
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use anyhow::{Context, Result};
+
+use crate::client::ServerClient;
+use crate::config::AgentConfig;
+
+/// Tells the backend this agent is gone for good, so it is not reported as crashed
+pub async fn run(config: &AgentConfig) -> Result<()> {
+    if !config.connects_to_backend() {
+        anyhow::bail!("The agent has no direct backend connection to deregister with");
+    }
+
+    ServerClient::new(config)
+        .deregister(config.agent_id)
+        .await
+        .context(format!("Failed to deregister from {}", config.server_url))?;
+
+    println!(
+        "✅ Agent {} ({}) deregistered from {}",
+        config.name, config.agent_id, config.server_url
+    );
+    Ok(())
+}
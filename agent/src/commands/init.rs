@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::InitArgs;
+use crate::config::AgentConfig;
+use crate::connect::connector::PeerAddress;
+use crate::secrets;
+
+/// Writes a new config file from flags, prompting for anything not given
+/// when running in a terminal
+pub fn run(path: &Path, args: InitArgs) -> Result<()> {
+    // Keep the agent ID when replacing a config, the backend knows it by that ID
+    let mut config = if path.exists() {
+        if !args.force {
+            anyhow::bail!(
+                "Config file {:?} already exists, use --force to overwrite it",
+                path
+            );
+        }
+        AgentConfig::load_from(path).unwrap_or_default()
+    } else {
+        AgentConfig::default()
+    };
+
+    let interactive = !args.non_interactive && std::io::stdin().is_terminal();
+    let prompt = Prompt { interactive };
+
+    config.name = prompt.value("Agent name", args.name, &config.name)?;
+    config.p2p_only_mode =
+        args.p2p_only || prompt.confirm("P2P only (no backend)", config.p2p_only_mode)?;
+
+    if !config.p2p_only_mode {
+        config.server_url = prompt.value("Server URL", args.server_url, &config.server_url)?;
+        reqwest::Url::parse(&config.server_url)
+            .context(format!("Invalid server URL: {}", config.server_url))?;
+
+        // The key goes into its own file, never into the config file
+        let key_file = args
+            .api_key_file
+            .or_else(|| config.api_key_file.as_ref().map(PathBuf::from))
+            .unwrap_or_else(|| path.with_file_name("api_key"));
+        let has_key = !config.api_key.is_empty() || key_file.exists();
+        let label = if has_key {
+            "API key (empty keeps the current one)"
+        } else {
+            "API key"
+        };
+        let api_key = match prompt.value(label, args.api_key, "")? {
+            key if key.is_empty() => std::mem::take(&mut config.api_key),
+            key => key,
+        };
+        if !api_key.is_empty() {
+            secrets::write_secret_file(&key_file, &api_key)?;
+            println!("🔑 API key written to {:?}", key_file);
+        }
+        if key_file.exists() {
+            config.api_key_file = Some(key_file.to_string_lossy().to_string());
+        }
+    }
+
+    let tags = match args.tags {
+        Some(tags) => tags,
+        None => prompt
+            .value("Tags (comma separated)", None, &config.tags.join(","))?
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect(),
+    };
+    config.tags = tags;
+
+    config.p2p.enabled = args.p2p
+        || config.p2p_only_mode
+        || prompt.confirm("Enable P2P connections", config.p2p.enabled)?;
+    if config.p2p.enabled {
+        config.p2p.listen_port = match args.listen_port {
+            Some(port) => port,
+            None => prompt
+                .value("P2P listen port", None, &config.p2p.listen_port.to_string())?
+                .parse()
+                .context("Invalid port")?,
+        };
+
+        let peers = if args.peers.is_empty() {
+            prompt
+                .value(
+                    "Peers (agent_id@host:port, comma separated)",
+                    None,
+                    &config.p2p.peers.join(","),
+                )?
+                .split(',')
+                .map(|peer| peer.trim().to_string())
+                .filter(|peer| !peer.is_empty())
+                .collect()
+        } else {
+            args.peers
+        };
+        for peer in &peers {
+            peer.parse::<PeerAddress>()?;
+        }
+        config.p2p.peers = peers;
+    }
+
+    config.save_to(path)?;
+
+    println!("✅ Configuration written to {:?}", path);
+    println!("   Agent ID: {}", config.agent_id);
+    if config.connects_to_backend() && config.api_key_file.is_none() {
+        println!(
+            "⚠️  No API key configured, pass --api-key or set {} before starting the agent",
+            secrets::API_KEY_ENV
+        );
+    }
+    println!("   Run `csf-agent check` to verify the setup");
+
+    Ok(())
+}
+
+/// Asks for values on stdin, or takes the defaults when not interactive
+struct Prompt {
+    interactive: bool,
+}
+
+impl Prompt {
+    /// Uses `given` if set, otherwise asks with `default` as the suggestion
+    fn value(&self, label: &str, given: Option<String>, default: &str) -> Result<String> {
+        if let Some(value) = given {
+            return Ok(value);
+        }
+        if !self.interactive {
+            return Ok(default.to_string());
+        }
+
+        let answer = self.ask(&if default.is_empty() {
+            format!("{}: ", label)
+        } else {
+            format!("{} [{}]: ", label, default)
+        })?;
+        Ok(if answer.is_empty() {
+            default.to_string()
+        } else {
+            answer
+        })
+    }
+
+    fn confirm(&self, label: &str, default: bool) -> Result<bool> {
+        if !self.interactive {
+            return Ok(default);
+        }
+
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            match self
+                .ask(&format!("{} [{}]: ", label, hint))?
+                .to_lowercase()
+                .as_str()
+            {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => println!("Please answer y or n"),
+            }
+        }
+    }
+
+    fn ask(&self, question: &str) -> Result<String> {
+        print!("{}", question);
+        std::io::stdout().flush()?;
+
+        let mut line = String::new();
+        if std::io::stdin().lock().read_line(&mut line)? == 0 {
+            anyhow::bail!("Input closed");
+        }
+        Ok(line.trim().to_string())
+    }
+}
@@ -0,0 +1,8 @@
+use anyhow::Result;
+
+/// Prints the inventory as JSON, as it is sent to the backend
+pub async fn run() -> Result<()> {
+    let inventory = tokio::task::spawn_blocking(crate::inventory::collect).await?;
+    println!("{}", serde_json::to_string_pretty(&inventory)?);
+    Ok(())
+}
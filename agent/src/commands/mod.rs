@@ -0,0 +1,8 @@
+pub mod certs;
+pub mod check;
+pub mod collect;
+pub mod config;
+pub mod deregister;
+pub mod init;
+pub mod inventory;
+pub mod status;
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::path::Path;
+
+use crate::config::AgentConfig;
+use crate::status::{self, SendResult};
+
+/// Shows the status last reported by the running agent
+pub fn run(config: &AgentConfig, json: bool) -> Result<()> {
+    let status = status::read_status(Path::new(&config.state_path))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    let age = (Utc::now() - status.updated_at).num_seconds();
+    // The agent updates the file at least once per collection interval
+    let stale_after = 3 * config.collection_interval.max(config.heartbeat_interval) as i64;
+
+    println!("Agent:     {} ({})", status.name, status.agent_id);
+    println!("Version:   {}", status.version);
+    println!("PID:       {}", status.pid);
+    println!("Started:   {}", status.started_at);
+    if age > stale_after {
+        println!(
+            "Updated:   {} ({}s ago, the agent may not be running)",
+            status.updated_at, age
+        );
+    } else {
+        println!("Updated:   {} ({}s ago)", status.updated_at, age);
+    }
+
+    println!();
+    println!("Backend:");
+    print_result("Registration", status.registration.as_ref());
+    print_result("Heartbeat", status.heartbeat.as_ref());
+    print_result("Metrics", status.metrics.as_ref());
+
+    println!();
+    if status.peers.is_empty() {
+        println!("Peers:     none");
+    } else {
+        println!("Peers:");
+        for (address, peer) in &status.peers {
+            let agent_id = peer
+                .agent_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            if peer.connected {
+                println!(
+                    "  🟢 {} ({}) connected since {}",
+                    address, agent_id, peer.since
+                );
+            } else {
+                println!(
+                    "  🔴 {} ({}) disconnected since {}",
+                    address, agent_id, peer.since
+                );
+            }
+            if let Some(error) = &peer.error {
+                println!("     Error: {}", error);
+            }
+            if let Some(heartbeat) = &peer.last_heartbeat {
+                print_result("   Heartbeat", Some(heartbeat));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_result(label: &str, result: Option<&SendResult>) {
+    match result {
+        None => println!("  {:<14} -", label),
+        Some(result) if result.success => println!("  {:<14} ✅ {}", label, result.at),
+        Some(result) => println!(
+            "  {:<14} ❌ {} ({})",
+            label,
+            result.at,
+            result.error.as_deref().unwrap_or("failed")
+        ),
+    }
+}
@@ -1,6 +1,40 @@
+use ::config::builder::{ConfigBuilder, DefaultState};
+use ::config::{Config, File, FileFormat};
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::secrets::{self, Secret, SecretSource};
+
+/// Prefix of environment variables overriding config keys
+pub const ENV_PREFIX: &str = "CSF_AGENT_";
+
+/// Environment variable naming the main config file
+pub const CONFIG_PATH_ENV: &str = "CSF_AGENT_CONFIG";
+
+/// Keys holding lists, given as comma separated values when overridden
+const LIST_KEYS: &[&str] = &[
+    "tags",
+    "p2p.peers",
+    "p2p.cert_sans",
+    "p2p.denied_agents",
+    "p2p.relays",
+    "p2p.relayed_peers",
+    "p2p.offered_artifacts",
+];
+
+/// Effective configuration together with where it was loaded from
+pub struct LoadedConfig {
+    pub config: AgentConfig,
+    /// Sources that contributed, lowest precedence first
+    pub sources: Vec<String>,
+    /// No source set an agent ID, so a new one was generated
+    pub generated_agent_id: bool,
+    /// Secrets that were found, with where they were loaded from
+    pub secrets: Vec<(&'static str, SecretSource)>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentConfig {
     /// Unique agent ID (generated on first run)
@@ -13,8 +47,17 @@ pub struct AgentConfig {
     pub server_url: String,
 
     /// API key for authentication (optional if only P2P is used)
+    ///
+    /// Resolved from `CSF_AGENT_API_KEY`, the systemd credential `api_key`,
+    /// `api_key_file` or an inline `api_key`, in that order. Never written to
+    /// a config file.
+    #[serde(default, skip_serializing)]
     pub api_key: String,
 
+    /// File holding the API key, readable only by its owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+
     /// Skip backend connection if only P2P mode is needed
     pub p2p_only_mode: bool,
 
@@ -25,13 +68,43 @@ pub struct AgentConfig {
     pub heartbeat_interval: u64,
 
     /// Tags for this agent
+    #[serde(default)]
     pub tags: Vec<String>,
 
+    /// systemd units whose state is reported with each heartbeat
+    #[serde(default)]
+    pub watched_services: Vec<String>,
+
     /// P2P connection settings
     pub p2p: P2PConfig,
+
+    /// Per metric group intervals and burst mode
+    #[serde(default)]
+    pub collection: CollectionConfig,
+
+    /// File the running agent reports its status to (read by `csf-agent status`)
+    #[serde(default = "AgentConfig::default_state_path")]
+    pub state_path: String,
+
+    /// File metrics that could not be sent yet are kept in
+    #[serde(default = "AgentConfig::default_metrics_spool_path")]
+    pub metrics_spool_path: String,
+
+    /// Most metrics samples kept while the backend is unreachable (0 disables the spool)
+    #[serde(default = "AgentConfig::default_metrics_spool_size")]
+    pub metrics_spool_size: usize,
+
+    /// How long to spend on flushing metrics and saying goodbye when stopping (seconds)
+    #[serde(default = "AgentConfig::default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+
+    /// How often to check the inventory for changes (seconds, 0 only sends it on registration)
+    #[serde(default = "AgentConfig::default_inventory_interval")]
+    pub inventory_interval: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct P2PConfig {
     /// Enable P2P connections between agents
     pub enabled: bool,
@@ -39,7 +112,7 @@ pub struct P2PConfig {
     /// Port to listen for P2P connections
     pub listen_port: u16,
 
-    /// List of peer agents to connect to (host:port)
+    /// List of peer agents to connect to (agent_id@host:port)
     pub peers: Vec<String>,
 
     /// mTLS certificate path
@@ -48,11 +121,77 @@ pub struct P2PConfig {
     /// mTLS private key path
     pub key_path: String,
 
+    /// File holding the passphrase of the agent and local CA private keys
+    ///
+    /// With a passphrase, generated keys are stored encrypted and encrypted
+    /// PKCS#8 keys can be loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_passphrase_file: Option<String>,
+
+    /// Passphrase resolved from `CSF_AGENT_KEY_PASSPHRASE`, the systemd
+    /// credential `key_passphrase` or `key_passphrase_file`
+    #[serde(skip)]
+    pub key_passphrase: Option<Secret>,
+
     /// CA certificate path for verifying peers
     pub ca_cert_path: String,
 
     /// Auto-generate self-signed certificates if not found
     pub auto_generate_certs: bool,
+
+    /// Additional hostnames and IP addresses to include as certificate SANs
+    pub cert_sans: Vec<String>,
+
+    /// Add this host's detected hostnames and IP addresses as certificate SANs
+    pub auto_detect_sans: bool,
+
+    /// Obtain the agent certificate from the backend's fleet CA instead of a local CA
+    pub use_backend_ca: bool,
+
+    /// Renew the agent certificate this many days before it expires
+    pub renew_before_days: u32,
+
+    /// Path of the CRLs used to reject revoked peer certificates
+    pub crl_path: String,
+
+    /// Agent IDs to refuse in addition to the backend's deny list
+    pub denied_agents: Vec<Uuid>,
+
+    /// How often to refresh CRLs and the deny list from the backend (seconds)
+    pub revocation_refresh_interval: u64,
+
+    /// Largest P2P message accepted from or sent to a peer (bytes)
+    pub max_frame_size: u32,
+
+    /// Offer the compact MessagePack encoding to peers
+    pub binary_encoding: bool,
+
+    /// Forward messages between peers that register with this agent
+    pub relay_enabled: bool,
+
+    /// Relays to keep an outbound session to (agent_id@host:port)
+    pub relays: Vec<String>,
+
+    /// Agents to reach through the relays instead of connecting directly
+    pub relayed_peers: Vec<Uuid>,
+
+    /// Forward registrations, heartbeats and metrics of isolated peers to the backend
+    pub collector_proxy: bool,
+
+    /// Collector proxy to report through when the backend is unreachable (agent_id@host:port)
+    pub collector: Option<String>,
+
+    /// Directory of artifacts served to and downloaded from peers
+    pub transfer_dir: String,
+
+    /// Let peers download artifacts from the transfer directory
+    pub serve_artifacts: bool,
+
+    /// Download artifacts offered by peers into the transfer directory
+    pub auto_fetch_artifacts: bool,
+
+    /// Artifacts in the transfer directory to offer to peers when they connect
+    pub offered_artifacts: Vec<String>,
 }
 
 impl Default for P2PConfig {
@@ -63,13 +202,74 @@ impl Default for P2PConfig {
             peers: vec![],
             cert_path: Self::default_cert_path().to_string_lossy().to_string(),
             key_path: Self::default_key_path().to_string_lossy().to_string(),
+            key_passphrase_file: None,
+            key_passphrase: None,
             ca_cert_path: Self::default_ca_cert_path().to_string_lossy().to_string(),
             auto_generate_certs: true,
+            cert_sans: vec![],
+            auto_detect_sans: true,
+            use_backend_ca: true,
+            renew_before_days: 30,
+            crl_path: Self::default_crl_path().to_string_lossy().to_string(),
+            denied_agents: vec![],
+            revocation_refresh_interval: 3600,
+            max_frame_size: crate::connect::protocol::DEFAULT_MAX_FRAME_SIZE,
+            binary_encoding: true,
+            relay_enabled: false,
+            relays: vec![],
+            relayed_peers: vec![],
+            collector_proxy: false,
+            collector: None,
+            transfer_dir: Self::default_transfer_dir().to_string_lossy().to_string(),
+            serve_artifacts: false,
+            auto_fetch_artifacts: false,
+            offered_artifacts: vec![],
         }
     }
 }
 
 impl P2PConfig {
+    /// Passphrase of the agent and local CA private keys, if one is configured
+    pub fn key_passphrase(&self) -> Option<&str> {
+        self.key_passphrase.as_ref().map(Secret::expose)
+    }
+
+    /// Directory holding the agent and CA certificates
+    pub fn cert_dir(&self) -> std::path::PathBuf {
+        if let Some(parent) = std::path::Path::new(&self.cert_path).parent() {
+            parent.to_path_buf()
+        } else {
+            std::path::PathBuf::from(if cfg!(target_os = "windows") {
+                "C:\\ProgramData\\csf-agent\\certs"
+            } else {
+                "/etc/csf-agent/certs"
+            })
+        }
+    }
+
+    /// SubjectAlternativeNames to issue the agent certificate for
+    pub fn subject_alt_names(&self) -> Vec<String> {
+        let mut names = self.cert_sans.clone();
+        if self.auto_detect_sans {
+            names.extend(crate::connect::certs::detect_subject_alt_names());
+        }
+        names
+    }
+
+    /// Wire protocol settings for P2P connections
+    pub fn protocol_options(&self) -> crate::connect::protocol::ProtocolOptions {
+        use crate::connect::protocol::{Encoding, ProtocolOptions};
+
+        ProtocolOptions {
+            max_frame_size: self.max_frame_size,
+            encodings: if self.binary_encoding {
+                vec![Encoding::Msgpack, Encoding::Json]
+            } else {
+                vec![Encoding::Json]
+            },
+        }
+    }
+
     fn default_cert_path() -> std::path::PathBuf {
         if cfg!(target_os = "windows") {
             std::path::PathBuf::from("C:\\ProgramData\\csf-agent\\certs\\agent.crt")
@@ -86,6 +286,22 @@ impl P2PConfig {
         }
     }
 
+    fn default_crl_path() -> std::path::PathBuf {
+        if cfg!(target_os = "windows") {
+            std::path::PathBuf::from("C:\\ProgramData\\csf-agent\\certs\\crl.pem")
+        } else {
+            std::path::PathBuf::from("/etc/csf-agent/certs/crl.pem")
+        }
+    }
+
+    fn default_transfer_dir() -> std::path::PathBuf {
+        if cfg!(target_os = "windows") {
+            std::path::PathBuf::from("C:\\ProgramData\\csf-agent\\artifacts")
+        } else {
+            std::path::PathBuf::from("/var/lib/csf-agent/artifacts")
+        }
+    }
+
     fn default_ca_cert_path() -> std::path::PathBuf {
         if cfg!(target_os = "windows") {
             std::path::PathBuf::from("C:\\ProgramData\\csf-agent\\certs\\ca.crt")
@@ -95,6 +311,57 @@ impl P2PConfig {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollectionConfig {
+    /// How often to collect CPU usage (seconds, defaults to `collection_interval`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_interval: Option<u64>,
+
+    /// How often to collect memory usage (seconds, defaults to `collection_interval`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_interval: Option<u64>,
+
+    /// How often to collect disk usage (seconds, defaults to `collection_interval`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_interval: Option<u64>,
+
+    /// How often to collect network counters (seconds, defaults to `collection_interval`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_interval: Option<u64>,
+
+    /// Longest interval of any metric group while in burst mode (seconds)
+    pub burst_interval: u64,
+
+    /// How long burst mode lasts once a threshold is crossed (seconds)
+    pub burst_duration: u64,
+
+    /// Enter burst mode when CPU usage reaches this percentage (0 disables)
+    pub burst_cpu_percent: f32,
+
+    /// Enter burst mode when memory usage reaches this percentage (0 disables)
+    pub burst_memory_percent: f32,
+
+    /// Enter burst mode when disk usage reaches this percentage (0 disables)
+    pub burst_disk_percent: f32,
+}
+
+impl Default for CollectionConfig {
+    fn default() -> Self {
+        Self {
+            cpu_interval: None,
+            memory_interval: None,
+            disk_interval: None,
+            network_interval: None,
+            burst_interval: 5,
+            burst_duration: 300,
+            burst_cpu_percent: 90.0,
+            burst_memory_percent: 90.0,
+            burst_disk_percent: 0.0,
+        }
+    }
+}
+
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
@@ -105,64 +372,242 @@ impl Default for AgentConfig {
                 .unwrap_or_else(|| "unknown".to_string()),
             server_url: "http://localhost:8000".to_string(),
             api_key: String::new(),
+            api_key_file: None,
             p2p_only_mode: false,
             collection_interval: 30,
             heartbeat_interval: 60,
             tags: vec![],
+            watched_services: vec![],
             p2p: P2PConfig::default(),
+            collection: CollectionConfig::default(),
+            state_path: Self::default_state_path(),
+            metrics_spool_path: Self::default_metrics_spool_path(),
+            metrics_spool_size: Self::default_metrics_spool_size(),
+            shutdown_timeout: Self::default_shutdown_timeout(),
+            inventory_interval: Self::default_inventory_interval(),
         }
     }
 }
 
 impl AgentConfig {
-    pub fn load() -> anyhow::Result<Self> {
-        // Try to load from config file, otherwise use defaults
-        // Check local directory first (for testing), then system path
-        let local_config = std::path::PathBuf::from("config.toml");
-        let system_config = Self::config_path();
-
-        let config_path = if local_config.exists() {
-            local_config
+    /// Whether the agent talks to the backend itself (not P2P only or through a collector proxy)
+    pub fn connects_to_backend(&self) -> bool {
+        !self.p2p_only_mode && self.p2p.collector.is_none()
+    }
+
+    /// Loads the configuration from all layers, later ones overriding earlier ones:
+    ///
+    /// 1. built-in defaults
+    /// 2. the main config file at `path`
+    /// 3. drop-in files `conf.d/*.toml` next to the main file, in lexical order
+    /// 4. environment variables `CSF_AGENT_<KEY>`, nested keys joined by `__`
+    ///    (e.g. `CSF_AGENT_P2P__LISTEN_PORT=9443`)
+    /// 5. `overrides` from the command line (`--set key=value`)
+    ///
+    /// Lists are given as comma separated values in the environment and on
+    /// the command line. Secrets are resolved afterwards, see [`secrets::resolve`].
+    pub fn load_layered(path: &Path, overrides: &[(String, String)]) -> Result<LoadedConfig> {
+        let defaults = Self::default();
+        let mut sources = vec!["defaults".to_string()];
+        let mut builder = Config::builder().add_source(Config::try_from(&defaults)?);
+
+        if path.exists() {
+            sources.push(format!("file {}", path.display()));
+        }
+        builder = builder.add_source(File::from(path).format(FileFormat::Toml).required(false));
+
+        for drop_in in Self::drop_in_files(path)? {
+            sources.push(format!("file {}", drop_in.display()));
+            builder = builder.add_source(File::from(drop_in).format(FileFormat::Toml));
+        }
+
+        let env: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| {
+                name.starts_with(ENV_PREFIX)
+                    && ![
+                        CONFIG_PATH_ENV,
+                        secrets::API_KEY_ENV,
+                        secrets::KEY_PASSPHRASE_ENV,
+                    ]
+                    .contains(&name.as_str())
+            })
+            .collect();
+        if !env.is_empty() {
+            let names: Vec<&str> = env.iter().map(|(name, _)| name.as_str()).collect();
+            sources.push(format!("environment {}", names.join(", ")));
+        }
+        for (name, value) in &env {
+            let key = name[ENV_PREFIX.len()..].to_lowercase().replace("__", ".");
+            builder = Self::set_override(builder, &key, value)?;
+        }
+
+        if !overrides.is_empty() {
+            let keys: Vec<&str> = overrides.iter().map(|(key, _)| key.as_str()).collect();
+            sources.push(format!("command line {}", keys.join(", ")));
+        }
+        for (key, value) in overrides {
+            builder = Self::set_override(builder, key, value)?;
+        }
+
+        let mut config: Self = builder
+            .build()
+            .and_then(|settings| settings.try_deserialize())
+            .context("Invalid configuration")?;
+        let secrets = config.resolve_secrets()?;
+
+        Ok(LoadedConfig {
+            generated_agent_id: config.agent_id == defaults.agent_id,
+            config,
+            sources,
+            secrets,
+        })
+    }
+
+    /// Loads the API key and key passphrase from their secret sources
+    fn resolve_secrets(&mut self) -> Result<Vec<(&'static str, SecretSource)>> {
+        let mut found = vec![];
+
+        let api_key = secrets::resolve(
+            secrets::API_KEY_ENV,
+            secrets::API_KEY_CREDENTIAL,
+            self.api_key_file.as_deref(),
+            Some(&self.api_key),
+        )
+        .context("Failed to load the API key")?;
+        if let Some((secret, source)) = api_key {
+            self.api_key = secret.expose().to_string();
+            found.push(("api_key", source));
+        }
+
+        let passphrase = secrets::resolve(
+            secrets::KEY_PASSPHRASE_ENV,
+            secrets::KEY_PASSPHRASE_CREDENTIAL,
+            self.p2p.key_passphrase_file.as_deref(),
+            None,
+        )
+        .context("Failed to load the private key passphrase")?;
+        if let Some((secret, source)) = passphrase {
+            self.p2p.key_passphrase = Some(secret);
+            found.push(("key_passphrase", source));
+        }
+
+        Ok(found)
+    }
+
+    /// Drop-in files of the `conf.d` directory next to the main config file
+    fn drop_in_files(path: &Path) -> Result<Vec<PathBuf>> {
+        let dir = path.parent().unwrap_or(Path::new("")).join("conf.d");
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut files = std::fs::read_dir(&dir)
+            .context(format!("Failed to read {:?}", dir))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.retain(|file| file.extension().is_some_and(|ext| ext == "toml") && file.is_file());
+        files.sort();
+        Ok(files)
+    }
+
+    fn set_override(
+        builder: ConfigBuilder<DefaultState>,
+        key: &str,
+        value: &str,
+    ) -> Result<ConfigBuilder<DefaultState>> {
+        let builder = if LIST_KEYS.contains(&key) {
+            let items: Vec<String> = value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect();
+            builder.set_override(key, items)?
         } else {
-            system_config
+            builder.set_override(key, value)?
         };
+        Ok(builder)
+    }
 
-        if config_path.exists() {
-            let content = std::fs::read_to_string(&config_path)?;
+    /// Loads only the config file at `path`, using defaults if it doesn't exist
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let content = std::fs::read_to_string(path)?;
             Ok(toml::from_str(&content)?)
         } else {
             Ok(Self::default())
         }
     }
 
-    pub fn save(&self) -> anyhow::Result<()> {
-        // Try local directory first (for testing), then system path
-        let local_config = std::path::PathBuf::from("config.toml");
-        let system_config = Self::config_path();
-
-        // If we can write to local directory, use that
-        let config_path = if local_config.exists() || std::env::current_dir().is_ok() {
-            local_config
-        } else {
-            system_config
-        };
-
+    pub fn save_to(&self, path: &Path) -> Result<()> {
         // Create parent directory if it doesn't exist
-        if let Some(parent) = config_path.parent() {
+        if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent).ok();
         }
 
         let content = toml::to_string_pretty(self)?;
-        std::fs::write(&config_path, content)?;
+        std::fs::write(path, content)?;
+
+        Ok(())
+    }
 
+    /// Records a generated agent ID in the main config file so it survives restarts
+    ///
+    /// The ID is added to the top of an existing file, leaving the rest of it untouched.
+    pub fn persist_agent_id(&self, path: &Path) -> Result<()> {
+        let line = format!("agent_id = \"{}\"\n", self.agent_id);
+        let content = match std::fs::read_to_string(path) {
+            Ok(existing) => format!("{}\n{}", line, existing),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                format!("# All options are listed in config.example.toml\n{}", line)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        std::fs::write(path, content).context(format!("Failed to write {:?}", path))?;
         Ok(())
     }
 
-    fn config_path() -> std::path::PathBuf {
+    /// Effective configuration as TOML, secrets are never serialized
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn config_path() -> std::path::PathBuf {
         if cfg!(target_os = "windows") {
             std::path::PathBuf::from("C:\\ProgramData\\csf-agent\\config.toml")
         } else {
             std::path::PathBuf::from("/etc/csf-agent/config.toml")
         }
     }
+
+    fn default_state_path() -> String {
+        if cfg!(target_os = "windows") {
+            "C:\\ProgramData\\csf-agent\\state.json".to_string()
+        } else {
+            "/var/lib/csf-agent/state.json".to_string()
+        }
+    }
+
+    fn default_metrics_spool_path() -> String {
+        if cfg!(target_os = "windows") {
+            "C:\\ProgramData\\csf-agent\\metrics-spool.json".to_string()
+        } else {
+            "/var/lib/csf-agent/metrics-spool.json".to_string()
+        }
+    }
+
+    fn default_metrics_spool_size() -> usize {
+        1000
+    }
+
+    fn default_shutdown_timeout() -> u64 {
+        10
+    }
+
+    fn default_inventory_interval() -> u64 {
+        3600
+    }
 }
@@ -1,17 +1,25 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
-use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer, ServerName};
 use rustls::RootCertStore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::net::SocketAddr;
-use std::path::Path;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_rustls::{TlsAcceptor, TlsConnector};
 use uuid::Uuid;
 
-use super::certs;
+use super::certs::{self, PeerIdentity};
+use super::protocol::{self, Encoding, Frame, FrameCodec, ProtocolOptions};
+use super::session::PeerSession;
+use super::transfer::{self, ArtifactStore, TransferManifest, TransferOptions};
+use crate::client::{AgentRegistration, Heartbeat, InventoryReport, ServerClient};
+use crate::collector::SystemMetrics;
+use crate::config::P2PConfig;
+use crate::secrets::Secret;
 
 /// Message types for P2P communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +30,18 @@ pub enum P2PMessage {
         agent_id: Uuid,
         agent_name: String,
         timestamp: DateTime<Utc>,
+        /// Highest protocol version the sender speaks
+        #[serde(default = "legacy_protocol_version")]
+        protocol_version: u16,
+        /// Features the sender supports
+        #[serde(default)]
+        capabilities: Vec<String>,
+        /// Encodings the sender supports, in order of preference
+        #[serde(default)]
+        encodings: Vec<Encoding>,
+        /// Largest frame the sender accepts (bytes)
+        #[serde(default)]
+        max_frame_size: Option<u32>,
     },
     /// Heartbeat to maintain connection
     Heartbeat {
@@ -39,6 +59,60 @@ pub enum P2PMessage {
         agent_id: Uuid,
         timestamp: DateTime<Utc>,
     },
+    /// Registration of an isolated agent for a collector proxy to forward
+    ProxyRegistration {
+        agent_id: Uuid,
+        registration: AgentRegistration,
+    },
+    /// Heartbeat of an isolated agent for a collector proxy to forward
+    ProxyHeartbeat {
+        agent_id: Uuid,
+        heartbeat: Heartbeat,
+    },
+    /// Changed inventory of an isolated agent for a collector proxy to forward
+    ProxyInventory {
+        agent_id: Uuid,
+        report: InventoryReport,
+    },
+    /// Announce an artifact peers may download from the sender
+    TransferOffer {
+        agent_id: Uuid,
+        manifest: TransferManifest,
+    },
+    /// Ask for the manifest of an artifact
+    TransferManifestRequest { agent_id: Uuid, name: String },
+    /// Manifest of a requested artifact
+    TransferManifest { manifest: TransferManifest },
+    /// Ask for a chunk of an artifact
+    TransferChunkRequest {
+        agent_id: Uuid,
+        name: String,
+        /// Checksum from the manifest, to detect a changed artifact
+        sha256: String,
+        offset: u64,
+        length: u32,
+    },
+    /// Chunk of a requested artifact
+    TransferChunk {
+        offset: u64,
+        #[serde(with = "protocol::binary")]
+        data: Vec<u8>,
+        /// Hex SHA-256 of `data`
+        sha256: String,
+    },
+    /// Ask a relay to forward messages addressed to the sender
+    RelayRegister {
+        agent_id: Uuid,
+        timestamp: DateTime<Utc>,
+    },
+    /// Sender is closing the session on purpose, e.g. because it shuts down
+    Goodbye { agent_id: Uuid, reason: String },
+    /// Message passed through a relay
+    RelayForward {
+        from: Uuid,
+        to: Uuid,
+        message: Box<P2PMessage>,
+    },
     /// Response to a request
     Response {
         success: bool,
@@ -47,14 +121,150 @@ pub enum P2PMessage {
     },
 }
 
+impl P2PMessage {
+    /// Agent the message claims to be sent by
+    pub fn sender(&self) -> Option<Uuid> {
+        match self {
+            P2PMessage::Handshake { agent_id, .. }
+            | P2PMessage::Heartbeat { agent_id, .. }
+            | P2PMessage::MetricsShare { agent_id, .. }
+            | P2PMessage::MetricsRequest { agent_id, .. }
+            | P2PMessage::ProxyRegistration { agent_id, .. }
+            | P2PMessage::ProxyHeartbeat { agent_id, .. }
+            | P2PMessage::ProxyInventory { agent_id, .. }
+            | P2PMessage::RelayRegister { agent_id, .. }
+            | P2PMessage::Goodbye { agent_id, .. }
+            | P2PMessage::TransferOffer { agent_id, .. }
+            | P2PMessage::TransferManifestRequest { agent_id, .. }
+            | P2PMessage::TransferChunkRequest { agent_id, .. } => Some(*agent_id),
+            P2PMessage::RelayForward { .. }
+            | P2PMessage::TransferManifest { .. }
+            | P2PMessage::TransferChunk { .. }
+            | P2PMessage::Response { .. } => None,
+        }
+    }
+}
+
+/// Attempts to download an offered artifact, each resuming the previous one
+const ARTIFACT_FETCH_ATTEMPTS: u32 = 3;
+
+fn legacy_protocol_version() -> u16 {
+    protocol::LEGACY_PROTOCOL_VERSION
+}
+
+/// Address of a peer agent in the form `agent_id@host:port`
+///
+/// `host` may be a DNS name or an IP address (IPv6 in brackets). The peer's
+/// certificate is verified against its agent identity, not the host it was
+/// reached under, since any agent can get a certificate naming some host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAddress {
+    pub agent_id: Uuid,
+    pub host: String,
+    pub port: u16,
+}
+
+impl PeerAddress {
+    /// Name the peer's certificate must be valid for
+    pub fn server_name(&self) -> String {
+        certs::agent_identity_name(&self.agent_id)
+    }
+}
+
+impl std::str::FromStr for PeerAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (agent_id, addr) = s
+            .split_once('@')
+            .context(format!("Peer address is missing the agent ID: {}", s))?;
+        let agent_id =
+            Uuid::parse_str(agent_id).context(format!("Invalid peer agent ID: {}", agent_id))?;
+
+        let (host, port) = addr
+            .rsplit_once(':')
+            .context(format!("Peer address is missing a port: {}", s))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            anyhow::bail!("Peer address is missing a host: {}", s);
+        }
+        let port = port
+            .parse()
+            .context(format!("Invalid peer port: {}", port))?;
+
+        Ok(Self {
+            agent_id,
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl std::fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@", self.agent_id)?;
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+/// Certificate files the TLS configuration is built from
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// Passphrase of an encrypted private key
+    pub key_passphrase: Option<Secret>,
+    pub ca_cert_path: PathBuf,
+    pub crl_path: PathBuf,
+}
+
+impl TlsFiles {
+    pub fn from_config(config: &P2PConfig) -> Self {
+        Self {
+            cert_path: PathBuf::from(&config.cert_path),
+            key_path: PathBuf::from(&config.key_path),
+            key_passphrase: config.key_passphrase.clone(),
+            ca_cert_path: PathBuf::from(&config.ca_cert_path),
+            crl_path: PathBuf::from(&config.crl_path),
+        }
+    }
+}
+
+/// Server and client side TLS state, swapped as a whole on reload
+struct TlsContext {
+    acceptor: TlsAcceptor,
+    connector: TlsConnector,
+}
+
 /// mTLS P2P Connector for agent-to-agent communication
 #[derive(Clone)]
 pub struct P2PConnector {
     agent_id: Uuid,
     agent_name: String,
     listen_addr: SocketAddr,
-    tls_acceptor: TlsAcceptor,
-    tls_connector: TlsConnector,
+    tls_files: TlsFiles,
+    tls: Arc<RwLock<TlsContext>>,
+    denied_agents: Arc<RwLock<HashSet<Uuid>>>,
+    protocol: ProtocolOptions,
+    /// Forward messages between peers registered with us
+    relay_enabled: bool,
+    /// Sessions of peers registered with this relay, by agent ID
+    relay_routes: Arc<RwLock<HashMap<Uuid, PeerSession>>>,
+    /// Sessions to relays we registered with, by relay agent ID
+    relay_links: Arc<RwLock<HashMap<Uuid, PeerSession>>>,
+    /// Backend client used to forward data of isolated peers
+    collector_proxy: Option<ServerClient>,
+    /// Artifacts served to and downloaded from peers
+    artifacts: Option<Arc<ArtifactStore>>,
+    transfer: TransferOptions,
+    /// Artifacts announced to peers when they connect
+    offered_artifacts: Arc<RwLock<BTreeSet<String>>>,
+    /// Open sessions, inbound and outbound, closed politely on shutdown
+    sessions: Arc<RwLock<Vec<PeerSession>>>,
 }
 
 impl P2PConnector {
@@ -63,22 +273,9 @@ impl P2PConnector {
         agent_id: Uuid,
         agent_name: String,
         listen_port: u16,
-        cert_path: &Path,
-        key_path: &Path,
-        ca_cert_path: &Path,
+        tls_files: TlsFiles,
     ) -> Result<Self> {
-        // Load certificates
-        let certs = certs::load_certs(cert_path).context("Failed to load agent certificate")?;
-        let key = certs::load_private_key(key_path).context("Failed to load agent private key")?;
-        let ca_certs = certs::load_certs(ca_cert_path).context("Failed to load CA certificate")?;
-
-        // Build server config (for accepting connections)
-        let server_config = Self::build_server_config(certs.clone(), key.clone_key(), &ca_certs)?;
-        let tls_acceptor = TlsAcceptor::from(Arc::new(server_config));
-
-        // Build client config (for initiating connections)
-        let client_config = Self::build_client_config(certs, key, &ca_certs)?;
-        let tls_connector = TlsConnector::from(Arc::new(client_config));
+        let tls = Self::build_tls_context(&tls_files)?;
 
         let listen_addr = SocketAddr::from(([0, 0, 0, 0], listen_port));
 
@@ -86,16 +283,143 @@ impl P2PConnector {
             agent_id,
             agent_name,
             listen_addr,
-            tls_acceptor,
-            tls_connector,
+            tls_files,
+            tls: Arc::new(RwLock::new(tls)),
+            denied_agents: Arc::new(RwLock::new(HashSet::new())),
+            protocol: ProtocolOptions::default(),
+            relay_enabled: false,
+            relay_routes: Arc::new(RwLock::new(HashMap::new())),
+            relay_links: Arc::new(RwLock::new(HashMap::new())),
+            collector_proxy: None,
+            artifacts: None,
+            transfer: TransferOptions::default(),
+            offered_artifacts: Arc::new(RwLock::new(BTreeSet::new())),
+            sessions: Arc::new(RwLock::new(Vec::new())),
         })
     }
 
+    /// Take part in artifact distribution using the given store
+    pub fn with_artifacts(
+        mut self,
+        store: ArtifactStore,
+        options: TransferOptions,
+        offered: Vec<String>,
+    ) -> Self {
+        self.artifacts = Some(Arc::new(store));
+        self.transfer = options;
+        self.offered_artifacts = Arc::new(RwLock::new(offered.into_iter().collect()));
+        self
+    }
+
+    /// Forward registrations, heartbeats and metrics of peers to the backend
+    pub fn with_collector_proxy(mut self, client: ServerClient) -> Self {
+        self.collector_proxy = Some(client);
+        self
+    }
+
+    /// ID of this agent
+    pub fn agent_id(&self) -> Uuid {
+        self.agent_id
+    }
+
+    /// Forward messages between peers that register with this agent
+    pub fn with_relay_enabled(mut self, enabled: bool) -> Self {
+        self.relay_enabled = enabled;
+        self
+    }
+
+    /// Use the given wire protocol settings instead of the defaults
+    pub fn with_protocol(mut self, protocol: ProtocolOptions) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Replace the set of agent IDs refused during the handshake
+    pub fn set_denied_agents(&self, agents: impl IntoIterator<Item = Uuid>) {
+        let agents: HashSet<Uuid> = agents.into_iter().collect();
+        tracing::debug!("Deny list updated ({} agents)", agents.len());
+        *self
+            .denied_agents
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = agents;
+    }
+
+    /// Fail if the peer is on the deny list
+    fn check_not_denied(denied_agents: &RwLock<HashSet<Uuid>>, peer_id: &Uuid) -> Result<()> {
+        if denied_agents
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(peer_id)
+        {
+            anyhow::bail!("Peer {} is on the deny list", peer_id);
+        }
+        Ok(())
+    }
+
+    /// Reload certificates and CRLs from disk, e.g. after renewal
+    ///
+    /// New connections use the reloaded certificates; established sessions
+    /// keep running on the old ones.
+    pub fn reload_tls(&self) -> Result<()> {
+        let tls = Self::build_tls_context(&self.tls_files)?;
+        *self.tls.write().unwrap_or_else(|e| e.into_inner()) = tls;
+
+        tracing::info!("Reloaded P2P TLS certificates");
+        Ok(())
+    }
+
+    /// Load certificates and build both TLS configs
+    fn build_tls_context(files: &TlsFiles) -> Result<TlsContext> {
+        // Load certificates
+        let certs =
+            certs::load_certs(&files.cert_path).context("Failed to load agent certificate")?;
+        let passphrase = files.key_passphrase.as_ref().map(Secret::expose);
+        let key = certs::load_private_key(&files.key_path, passphrase)
+            .context("Failed to load agent private key")?;
+        let ca_certs =
+            certs::load_certs(&files.ca_cert_path).context("Failed to load CA certificate")?;
+        let crls = certs::load_crls(&files.crl_path).context("Failed to load CRLs")?;
+
+        // Build server config (for accepting connections)
+        let server_config =
+            Self::build_server_config(certs.clone(), key.clone_key(), &ca_certs, crls.clone())?;
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+        // Build client config (for initiating connections)
+        let client_config = Self::build_client_config(certs, key, &ca_certs, crls)?;
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        Ok(TlsContext {
+            acceptor,
+            connector,
+        })
+    }
+
+    fn tls_acceptor(&self) -> TlsAcceptor {
+        self.tls
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .acceptor
+            .clone()
+    }
+
+    fn tls_connector(&self) -> TlsConnector {
+        self.tls
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .connector
+            .clone()
+    }
+
     /// Build server TLS config for accepting connections
+    ///
+    /// Client certificates listed in `crls` are rejected; certificates from a
+    /// CA without a CRL are accepted.
     fn build_server_config(
         certs: Vec<CertificateDer<'static>>,
         key: PrivateKeyDer<'static>,
         ca_certs: &[CertificateDer<'static>],
+        crls: Vec<CertificateRevocationListDer<'static>>,
     ) -> Result<rustls::ServerConfig> {
         // Create root cert store for client verification
         let mut root_store = RootCertStore::empty();
@@ -107,6 +431,9 @@ impl P2PConnector {
 
         // Create verifier that requires client certificates
         let client_verifier = rustls::server::WebPkiClientVerifier::builder(Arc::new(root_store))
+            .with_crls(crls)
+            .only_check_end_entity_revocation()
+            .allow_unknown_revocation_status()
             .build()
             .context("Failed to build client verifier")?;
 
@@ -123,6 +450,7 @@ impl P2PConnector {
         certs: Vec<CertificateDer<'static>>,
         key: PrivateKeyDer<'static>,
         ca_certs: &[CertificateDer<'static>],
+        crls: Vec<CertificateRevocationListDer<'static>>,
     ) -> Result<rustls::ClientConfig> {
         // Create root cert store for server verification
         let mut root_store = RootCertStore::empty();
@@ -132,8 +460,16 @@ impl P2PConnector {
                 .context("Failed to add CA certificate to root store")?;
         }
 
+        // Verify the server certificate, rejecting revoked ones
+        let server_verifier = rustls::client::WebPkiServerVerifier::builder(Arc::new(root_store))
+            .with_crls(crls)
+            .only_check_end_entity_revocation()
+            .allow_unknown_revocation_status()
+            .build()
+            .context("Failed to build server verifier")?;
+
         let config = rustls::ClientConfig::builder()
-            .with_root_certificates(root_store)
+            .with_webpki_verifier(server_verifier)
             .with_client_auth_cert(certs, key)
             .context("Failed to build client config")?;
 
@@ -152,16 +488,10 @@ impl P2PConnector {
             match listener.accept().await {
                 Ok((stream, peer_addr)) => {
                     tracing::info!("Accepted connection from {}", peer_addr);
-                    let acceptor = self.tls_acceptor.clone();
-                    let agent_id = self.agent_id;
-                    let agent_name = self.agent_name.clone();
+                    let connector = self.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(
-                            stream, acceptor, agent_id, agent_name, peer_addr,
-                        )
-                        .await
-                        {
+                        if let Err(e) = connector.handle_connection(stream, peer_addr).await {
                             tracing::error!("Error handling connection from {}: {}", peer_addr, e);
                         }
                     });
@@ -174,15 +504,9 @@ impl P2PConnector {
     }
 
     /// Handle an incoming connection
-    async fn handle_connection(
-        stream: TcpStream,
-        acceptor: TlsAcceptor,
-        agent_id: Uuid,
-        agent_name: String,
-        peer_addr: SocketAddr,
-    ) -> Result<()> {
+    async fn handle_connection(&self, stream: TcpStream, peer_addr: SocketAddr) -> Result<()> {
         // Perform TLS handshake
-        let mut tls_stream = match acceptor.accept(stream).await {
+        let mut tls_stream = match self.tls_acceptor().accept(stream).await {
             Ok(s) => s,
             Err(e) => {
                 anyhow::bail!("TLS handshake failed: {:?}", e);
@@ -190,111 +514,47 @@ impl P2PConnector {
         };
 
         tracing::info!("TLS handshake completed with {}", peer_addr);
+        let identity = Self::peer_identity(tls_stream.get_ref().1.peer_certificates())?;
 
-        // Send handshake message
-        let handshake = P2PMessage::Handshake {
-            agent_id,
-            agent_name: agent_name.clone(),
-            timestamp: Utc::now(),
-        };
-        Self::send_message(&mut tls_stream, &handshake).await?;
+        // Send handshake message, then receive the peer's
+        protocol::write_handshake(
+            &mut tls_stream,
+            &self.local_handshake(),
+            self.protocol.max_frame_size,
+        )
+        .await?;
+        let handshake =
+            protocol::read_handshake(&mut tls_stream, self.protocol.max_frame_size).await?;
 
-        // Receive handshake response
-        let response = Self::receive_message(&mut tls_stream).await?;
-        match response {
-            P2PMessage::Handshake {
-                agent_id: peer_id,
-                agent_name: peer_name,
-                ..
-            } => {
-                tracing::info!("Connected to peer: {} ({})", peer_name, peer_id);
-            }
-            _ => {
-                anyhow::bail!("Expected handshake message, got: {:?}", response);
-            }
-        }
+        let (reader, writer) = tokio::io::split(tls_stream);
+        let session = self.establish_session(handshake, identity, writer, true)?;
 
-        // Keep connection alive and handle messages
-        loop {
-            match Self::receive_message(&mut tls_stream).await {
-                Ok(message) => {
-                    tracing::debug!("Received message: {:?}", message);
-
-                    // Handle different message types
-                    match message {
-                        P2PMessage::Heartbeat {
-                            agent_id: peer_id, ..
-                        } => {
-                            tracing::debug!("Heartbeat from {}", peer_id);
-
-                            // Send heartbeat response
-                            let response = P2PMessage::Response {
-                                success: true,
-                                message: "Heartbeat received".to_string(),
-                                data: None,
-                            };
-                            Self::send_message(&mut tls_stream, &response).await?;
-                        }
-                        P2PMessage::MetricsRequest {
-                            agent_id: peer_id, ..
-                        } => {
-                            tracing::debug!("Metrics request from {}", peer_id);
-
-                            // TODO: Get current metrics and send them
-                            let response = P2PMessage::Response {
-                                success: true,
-                                message: "Metrics data".to_string(),
-                                data: Some(serde_json::json!({"status": "ok"})),
-                            };
-                            Self::send_message(&mut tls_stream, &response).await?;
-                        }
-                        P2PMessage::MetricsShare {
-                            agent_id: peer_id,
-                            metrics,
-                            ..
-                        } => {
-                            tracing::info!("Received metrics from {}: {:?}", peer_id, metrics);
-                        }
-                        _ => {
-                            tracing::warn!("Unhandled message type: {:?}", message);
-                        }
-                    }
-                }
-                Err(e) => {
-                    tracing::info!("Connection closed: {}", e);
-                    break;
-                }
-            }
-        }
-
-        Ok(())
+        self.serve_session(session, reader).await
     }
 
     /// Connect to a peer agent
-    pub async fn connect_to_peer(
-        &self,
-        peer_addr: &str,
-    ) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+    pub async fn connect_to_peer(&self, peer_addr: &str) -> Result<PeerSession> {
         // Parse address
-        let addr: SocketAddr = peer_addr
+        let addr: PeerAddress = peer_addr
             .parse()
             .context(format!("Invalid peer address: {}", peer_addr))?;
 
+        Self::check_not_denied(&self.denied_agents, &addr.agent_id)?;
+
         tracing::info!("Connecting to peer at {}", addr);
 
-        // Connect to peer
-        let stream = TcpStream::connect(addr)
+        // Connect to peer (resolves DNS names)
+        let stream = TcpStream::connect((addr.host.as_str(), addr.port))
             .await
             .context(format!("Failed to connect to {}", addr))?;
 
-        // Extract hostname for SNI
-        let hostname = addr.ip().to_string();
-        let server_name = ServerName::try_from(hostname)
-            .context("Invalid hostname")?
+        // Verify the peer against its agent identity
+        let server_name = ServerName::try_from(addr.server_name())
+            .context("Invalid server name")?
             .to_owned();
 
         // Perform TLS handshake
-        let mut tls_stream = match self.tls_connector.connect(server_name, stream).await {
+        let mut tls_stream = match self.tls_connector().connect(server_name, stream).await {
             Ok(s) => s,
             Err(e) => {
                 anyhow::bail!("TLS handshake failed: {:?}", e);
@@ -302,82 +562,778 @@ impl P2PConnector {
         };
 
         tracing::info!("TLS handshake completed with {}", addr);
+        let identity = Self::peer_identity(tls_stream.get_ref().1.peer_certificates())?;
+        if identity.agent_id != addr.agent_id {
+            anyhow::bail!(
+                "Peer at {} is agent {}, expected {}",
+                addr,
+                identity.agent_id,
+                addr.agent_id
+            );
+        }
 
-        // Receive handshake from server
-        let handshake = Self::receive_message(&mut tls_stream).await?;
-        match handshake {
-            P2PMessage::Handshake {
-                agent_id: peer_id,
-                agent_name: peer_name,
-                ..
-            } => {
-                tracing::info!("Connected to peer: {} ({})", peer_name, peer_id);
-            }
-            _ => {
-                anyhow::bail!("Expected handshake message");
+        // Receive handshake from server, then send ours
+        let handshake =
+            protocol::read_handshake(&mut tls_stream, self.protocol.max_frame_size).await?;
+        protocol::write_handshake(
+            &mut tls_stream,
+            &self.local_handshake(),
+            self.protocol.max_frame_size,
+        )
+        .await?;
+
+        let (reader, writer) = tokio::io::split(tls_stream);
+        let session = self.establish_session(handshake, identity, writer, false)?;
+
+        // Serve the peer's requests and match responses in the background
+        let connector = self.clone();
+        let reader_session = session.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connector.serve_session(reader_session, reader).await {
+                tracing::info!("Connection closed: {}", e);
             }
-        }
+        });
+
+        Ok(session)
+    }
 
-        // Send our handshake
-        let handshake = P2PMessage::Handshake {
+    /// Handshake announcing this agent and its protocol support
+    fn local_handshake(&self) -> P2PMessage {
+        P2PMessage::Handshake {
             agent_id: self.agent_id,
             agent_name: self.agent_name.clone(),
             timestamp: Utc::now(),
-        };
-        Self::send_message(&mut tls_stream, &handshake).await?;
+            protocol_version: protocol::PROTOCOL_VERSION,
+            capabilities: self.capabilities(),
+            encodings: self.protocol.encodings.clone(),
+            max_frame_size: Some(self.protocol.max_frame_size),
+        }
+    }
+
+    /// Features this agent offers to peers
+    fn capabilities(&self) -> Vec<String> {
+        let mut capabilities = vec![
+            protocol::CAP_HEARTBEAT.to_string(),
+            protocol::CAP_METRICS.to_string(),
+            protocol::CAP_GOODBYE.to_string(),
+        ];
+        if self.relay_enabled {
+            capabilities.push(protocol::CAP_RELAY.to_string());
+        }
+        if self.collector_proxy.is_some() {
+            capabilities.push(protocol::CAP_COLLECTOR.to_string());
+        }
+        if self.artifacts.is_some() {
+            capabilities.push(protocol::CAP_TRANSFER.to_string());
+        }
+        capabilities
+    }
 
-        Ok(tls_stream)
+    /// Identity of the peer from the certificate it presented during the TLS handshake
+    fn peer_identity(peer_certificates: Option<&[CertificateDer<'_>]>) -> Result<PeerIdentity> {
+        let cert = peer_certificates
+            .and_then(|certs| certs.first())
+            .context("Peer did not present a certificate")?;
+        certs::certificate_identity(cert.as_ref()).context("Peer certificate has no agent identity")
     }
 
-    /// Send a message over any TLS stream
-    async fn send_message<S>(stream: &mut S, message: &P2PMessage) -> Result<()>
+    /// Validates the peer's handshake against its certificate identity and
+    /// negotiates the protocol
+    fn establish_session<W>(
+        &self,
+        handshake: P2PMessage,
+        identity: PeerIdentity,
+        writer: W,
+        is_server: bool,
+    ) -> Result<PeerSession>
     where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
+        W: tokio::io::AsyncWrite + Send + Unpin + 'static,
     {
-        let json = serde_json::to_string(message)?;
-        let len = json.len() as u32;
+        let P2PMessage::Handshake {
+            agent_id: peer_id,
+            agent_name: peer_name,
+            protocol_version,
+            capabilities,
+            encodings,
+            max_frame_size,
+            ..
+        } = handshake
+        else {
+            anyhow::bail!("Expected handshake message, got: {:?}", handshake);
+        };
 
-        // Send length prefix (4 bytes)
-        stream.write_all(&len.to_be_bytes()).await?;
-        // Send JSON data
-        stream.write_all(json.as_bytes()).await?;
-        stream.flush().await?;
+        if peer_id != identity.agent_id {
+            anyhow::bail!(
+                "Peer claims to be agent {} but its certificate identifies agent {}",
+                peer_id,
+                identity.agent_id
+            );
+        }
 
-        Ok(())
+        Self::check_not_denied(&self.denied_agents, &peer_id)?;
+
+        let codec = FrameCodec::negotiate(
+            &self.protocol,
+            protocol_version,
+            &encodings,
+            max_frame_size,
+            is_server,
+        )?;
+
+        let (version, encoding) = (codec.version, codec.encoding);
+        let session = PeerSession::new(identity, peer_name, capabilities, codec, writer);
+
+        tracing::info!(
+            "Connected to peer: {} ({}), protocol v{} ({:?})",
+            session.peer_name(),
+            peer_id,
+            version,
+            encoding
+        );
+
+        Ok(session)
     }
 
-    /// Receive a message from any TLS stream
-    async fn receive_message<S>(stream: &mut S) -> Result<P2PMessage>
-    where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
-    {
-        // Read length prefix (4 bytes)
-        let mut len_bytes = [0u8; 4];
-        stream.read_exact(&mut len_bytes).await?;
-        let len = u32::from_be_bytes(len_bytes) as usize;
+    /// Handle a message a peer sent us (anything but a response)
+    async fn handle_frame(self, session: PeerSession, frame: Frame) {
+        tracing::debug!(
+            "Received message from {}: {:?}",
+            session.peer_name(),
+            frame.message
+        );
 
-        // Read JSON data
-        let mut buffer = vec![0u8; len];
-        stream.read_exact(&mut buffer).await?;
+        // Messages must come from the agent the certificate identifies
+        let sender = frame.message.sender();
+        let response = match frame.message {
+            _ if sender.is_some_and(|sender| sender != session.peer_id()) => {
+                tracing::warn!(
+                    "Rejecting message from {} claiming to be sent by {:?}",
+                    session.peer_id(),
+                    sender
+                );
+                Some(Self::failure("Sender does not match peer identity"))
+            }
+            P2PMessage::RelayForward { from, to, message } => Some(
+                self.handle_relay_forward(&session, from, to, *message)
+                    .await,
+            ),
+            P2PMessage::RelayRegister { .. } => Some(self.register_relay_route(&session)),
+            P2PMessage::Goodbye { reason, .. } => {
+                tracing::info!(
+                    "👋 Peer {} ({}) is closing the session: {}",
+                    session.peer_name(),
+                    session.peer_id(),
+                    reason
+                );
+                session.close().await;
+                None
+            }
+            P2PMessage::TransferOffer { manifest, .. } => {
+                Some(self.handle_transfer_offer(&session, manifest).await)
+            }
+            P2PMessage::TransferManifestRequest { name, .. } => {
+                Some(self.serve_manifest(&session, &name).await)
+            }
+            P2PMessage::TransferChunkRequest {
+                name,
+                sha256,
+                offset,
+                length,
+                ..
+            } => Some(
+                self.serve_chunk(&session, &name, &sha256, offset, length)
+                    .await,
+            ),
+            message => self.handle_message(session.peer_id(), message).await,
+        };
 
-        let message: P2PMessage = serde_json::from_slice(&buffer)?;
-        Ok(message)
+        if let Some(response) = response {
+            if let Err(e) = session.reply(frame.id, response).await {
+                tracing::warn!("Failed to respond to {}: {}", session.peer_id(), e);
+            }
+        }
     }
 
-    /// Send a heartbeat to a peer
-    pub async fn send_heartbeat<S>(&self, stream: &mut S) -> Result<()>
+    /// Handle a message from a verified peer, returning the response if any
+    async fn handle_message(&self, peer_id: Uuid, message: P2PMessage) -> Option<P2PMessage> {
+        // Handle different message types
+        match message {
+            P2PMessage::Heartbeat { .. } => {
+                tracing::debug!("Heartbeat from {}", peer_id);
+
+                Some(P2PMessage::Response {
+                    success: true,
+                    message: "Heartbeat received".to_string(),
+                    data: None,
+                })
+            }
+            P2PMessage::MetricsRequest { .. } => {
+                tracing::debug!("Metrics request from {}", peer_id);
+
+                // TODO: Get current metrics and send them
+                Some(P2PMessage::Response {
+                    success: true,
+                    message: "Metrics data".to_string(),
+                    data: Some(serde_json::json!({"status": "ok"})),
+                })
+            }
+            P2PMessage::MetricsShare { metrics, .. } => {
+                if self.collector_proxy.is_none() {
+                    tracing::info!("Received metrics from {}: {:?}", peer_id, metrics);
+                    return Some(P2PMessage::Response {
+                        success: true,
+                        message: "Metrics received".to_string(),
+                        data: None,
+                    });
+                }
+
+                Some(self.proxy_metrics(peer_id, metrics).await)
+            }
+            P2PMessage::ProxyRegistration { registration, .. } => {
+                Some(self.proxy_registration(peer_id, registration).await)
+            }
+            P2PMessage::ProxyHeartbeat { heartbeat, .. } => {
+                Some(self.proxy_heartbeat(peer_id, heartbeat).await)
+            }
+            P2PMessage::ProxyInventory { report, .. } => {
+                Some(self.proxy_inventory(peer_id, report).await)
+            }
+            message => {
+                tracing::warn!("Unhandled message type: {:?}", message);
+                None
+            }
+        }
+    }
+
+    /// Forwards an isolated peer's registration to the backend
+    async fn proxy_registration(
+        &self,
+        peer_id: Uuid,
+        mut registration: AgentRegistration,
+    ) -> P2PMessage {
+        let Some(client) = &self.collector_proxy else {
+            return Self::failure("This agent is not a collector proxy");
+        };
+        if registration.agent_id != peer_id {
+            return Self::failure("Registration is not for the sending agent");
+        }
+
+        registration.relayed_by = Some(self.agent_id);
+        match client.register(&registration).await {
+            Ok(response) => {
+                tracing::info!("📮 Forwarded registration of {}", peer_id);
+                P2PMessage::Response {
+                    success: true,
+                    message: response.message.clone(),
+                    data: serde_json::to_value(response).ok(),
+                }
+            }
+            Err(e) => {
+                tracing::error!("❌ Failed to forward registration of {}: {}", peer_id, e);
+                Self::failure(&format!("Backend rejected registration: {}", e))
+            }
+        }
+    }
+
+    /// Forwards an isolated peer's heartbeat to the backend
+    async fn proxy_heartbeat(&self, peer_id: Uuid, mut heartbeat: Heartbeat) -> P2PMessage {
+        let Some(client) = &self.collector_proxy else {
+            return Self::failure("This agent is not a collector proxy");
+        };
+        if heartbeat.agent_id != peer_id {
+            return Self::failure("Heartbeat is not for the sending agent");
+        }
+
+        heartbeat.relayed_by = Some(self.agent_id);
+        match client.send_heartbeat(&heartbeat).await {
+            Ok(response) => {
+                tracing::debug!("Forwarded heartbeat of {}", peer_id);
+                P2PMessage::Response {
+                    success: true,
+                    message: "Heartbeat forwarded".to_string(),
+                    data: serde_json::to_value(response).ok(),
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to forward heartbeat of {}: {}", peer_id, e);
+                Self::failure(&format!("Backend rejected heartbeat: {}", e))
+            }
+        }
+    }
+
+    /// Forwards an isolated peer's changed inventory to the backend
+    async fn proxy_inventory(&self, peer_id: Uuid, mut report: InventoryReport) -> P2PMessage {
+        let Some(client) = &self.collector_proxy else {
+            return Self::failure("This agent is not a collector proxy");
+        };
+        if report.agent_id != peer_id {
+            return Self::failure("Inventory is not for the sending agent");
+        }
+
+        report.relayed_by = Some(self.agent_id);
+        match client.send_inventory(&report).await {
+            Ok(_) => {
+                tracing::info!("📮 Forwarded inventory of {}", peer_id);
+                P2PMessage::Response {
+                    success: true,
+                    message: "Inventory forwarded".to_string(),
+                    data: None,
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to forward inventory of {}: {}", peer_id, e);
+                Self::failure(&format!("Backend rejected inventory: {}", e))
+            }
+        }
+    }
+
+    /// Forwards metrics an isolated peer shared with us to the backend
+    async fn proxy_metrics(&self, peer_id: Uuid, metrics: serde_json::Value) -> P2PMessage {
+        let Some(client) = &self.collector_proxy else {
+            return Self::failure("This agent is not a collector proxy");
+        };
+        let mut metrics: SystemMetrics = match serde_json::from_value(metrics) {
+            Ok(metrics) => metrics,
+            Err(e) => return Self::failure(&format!("Invalid metrics: {}", e)),
+        };
+        if metrics.agent_id != peer_id {
+            return Self::failure("Metrics are not for the sending agent");
+        }
+
+        metrics.relayed_by = Some(self.agent_id);
+        match client.send_metrics(&metrics).await {
+            Ok(_) => {
+                tracing::debug!("Forwarded metrics of {}", peer_id);
+                P2PMessage::Response {
+                    success: true,
+                    message: "Metrics forwarded".to_string(),
+                    data: None,
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Failed to forward metrics of {}: {}", peer_id, e);
+                Self::failure(&format!("Backend rejected metrics: {}", e))
+            }
+        }
+    }
+
+    /// Downloads an offered artifact in the background if auto-fetch is enabled
+    async fn handle_transfer_offer(
+        &self,
+        session: &PeerSession,
+        manifest: TransferManifest,
+    ) -> P2PMessage {
+        let Some(store) = self.artifacts.clone().filter(|_| self.transfer.auto_fetch) else {
+            return Self::failure("Not accepting artifacts");
+        };
+
+        let name = manifest.name.clone();
+        let up_to_date = store
+            .manifest(&name, u32::MAX)
+            .await
+            .is_ok_and(|local| local.sha256 == manifest.sha256);
+        if up_to_date {
+            return P2PMessage::Response {
+                success: true,
+                message: "Artifact already present".to_string(),
+                data: None,
+            };
+        }
+        if !store.begin_fetch(&name) {
+            return P2PMessage::Response {
+                success: true,
+                message: "Artifact download in progress".to_string(),
+                data: None,
+            };
+        }
+
+        tracing::info!(
+            "📦 Peer {} offers {} ({} bytes)",
+            session.peer_id(),
+            name,
+            manifest.size
+        );
+        let connector = self.clone();
+        let session = session.clone();
+        tokio::spawn(async move {
+            let mut result = Ok(());
+            for attempt in 1..=ARTIFACT_FETCH_ATTEMPTS {
+                result = transfer::fetch_artifact(&session, connector.agent_id, &name, store.dir())
+                    .await
+                    .map(|_| ());
+                match &result {
+                    Ok(_) => break,
+                    Err(e) if attempt < ARTIFACT_FETCH_ATTEMPTS => {
+                        tracing::warn!("⚠️  Download of {} interrupted, resuming: {}", name, e);
+                    }
+                    Err(_) => {}
+                }
+            }
+            store.end_fetch(&name);
+
+            match result {
+                // Pass the artifact on to peers connecting later
+                Ok(_) => connector.offer_artifact(&name),
+                Err(e) => tracing::error!("❌ Failed to download {}: {}", name, e),
+            }
+        });
+
+        P2PMessage::Response {
+            success: true,
+            message: "Downloading artifact".to_string(),
+            data: None,
+        }
+    }
+
+    /// Answers a manifest request for one of our artifacts
+    async fn serve_manifest(&self, session: &PeerSession, name: &str) -> P2PMessage {
+        let Some(store) = self.artifacts.as_ref().filter(|_| self.transfer.serve) else {
+            return Self::failure("Not serving artifacts");
+        };
+
+        let max_chunk = transfer::max_chunk_for(session.peer_max_frame_size());
+        match store.manifest(name, max_chunk).await {
+            Ok(manifest) => P2PMessage::TransferManifest { manifest },
+            Err(e) => Self::failure(&e.to_string()),
+        }
+    }
+
+    /// Answers a chunk request for one of our artifacts
+    async fn serve_chunk(
+        &self,
+        session: &PeerSession,
+        name: &str,
+        sha256: &str,
+        offset: u64,
+        length: u32,
+    ) -> P2PMessage {
+        let Some(store) = self.artifacts.as_ref().filter(|_| self.transfer.serve) else {
+            return Self::failure("Not serving artifacts");
+        };
+
+        let max_chunk = transfer::max_chunk_for(session.peer_max_frame_size());
+        match store
+            .read_chunk(name, sha256, offset, length, max_chunk)
+            .await
+        {
+            Ok(data) => P2PMessage::TransferChunk {
+                offset,
+                sha256: hex::encode(Sha256::digest(&data)),
+                data,
+            },
+            Err(e) => Self::failure(&e.to_string()),
+        }
+    }
+
+    /// Offer an artifact from our store to peers, starting with the next sessions
+    pub fn offer_artifact(&self, name: &str) {
+        self.offered_artifacts
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string());
+    }
+
+    /// Sends offers for our artifacts to a newly connected peer
+    async fn send_offers(&self, session: PeerSession) {
+        let Some(store) = self.artifacts.as_ref().filter(|_| self.transfer.serve) else {
+            return;
+        };
+        if !session.supports(protocol::CAP_TRANSFER) {
+            return;
+        }
+
+        let offered: Vec<String> = self
+            .offered_artifacts
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect();
+        let max_chunk = transfer::max_chunk_for(session.peer_max_frame_size());
+        for name in offered {
+            let manifest = match store.manifest(&name, max_chunk).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    tracing::warn!("⚠️  Cannot offer artifact {}: {}", name, e);
+                    continue;
+                }
+            };
+
+            let offer = P2PMessage::TransferOffer {
+                agent_id: self.agent_id,
+                manifest,
+            };
+            match session.request(offer).await {
+                Ok(P2PMessage::Response { message, .. }) => {
+                    tracing::debug!("Offered {} to {}: {}", name, session.peer_id(), message)
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::debug!("Failed to offer {} to {}: {}", name, session.peer_id(), e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Failed response with the given message
+    fn failure(message: &str) -> P2PMessage {
+        P2PMessage::Response {
+            success: false,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    /// Adds the peer to the routes of this relay
+    fn register_relay_route(&self, session: &PeerSession) -> P2PMessage {
+        if !self.relay_enabled {
+            return Self::failure("Relaying is not enabled on this agent");
+        }
+
+        let previous = self
+            .relay_routes
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(session.peer_id(), session.clone());
+        if previous.is_none() {
+            tracing::info!(
+                "🔀 Relaying for {} ({})",
+                session.peer_name(),
+                session.peer_id()
+            );
+        }
+
+        P2PMessage::Response {
+            success: true,
+            message: "Registered with relay".to_string(),
+            data: None,
+        }
+    }
+
+    /// Handle a relayed message, either delivering it to us or forwarding it
+    /// to the registered target
+    async fn handle_relay_forward(
+        &self,
+        session: &PeerSession,
+        from: Uuid,
+        to: Uuid,
+        message: P2PMessage,
+    ) -> P2PMessage {
+        if to == self.agent_id {
+            // Only a relay we registered with vouches for the original sender
+            let via_relay = self
+                .relay_links
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains_key(&session.peer_id());
+            if !via_relay && from != session.peer_id() {
+                tracing::warn!(
+                    "Rejecting message for {} relayed by unknown relay {}",
+                    from,
+                    session.peer_id()
+                );
+                return Self::failure("Not a relay of this agent");
+            }
+            if message.sender().is_some_and(|sender| sender != from) {
+                return Self::failure("Sender does not match relayed identity");
+            }
+            if Self::check_not_denied(&self.denied_agents, &from).is_err() {
+                return Self::failure("Sender is denied");
+            }
+
+            return self.handle_message(from, message).await.unwrap_or_else(|| {
+                P2PMessage::Response {
+                    success: true,
+                    message: "Delivered".to_string(),
+                    data: None,
+                }
+            });
+        }
+
+        if !self.relay_enabled {
+            return Self::failure("Relaying is not enabled on this agent");
+        }
+        if from != session.peer_id() {
+            tracing::warn!(
+                "Rejecting relay request from {} on behalf of {}",
+                session.peer_id(),
+                from
+            );
+            return Self::failure("Sender does not match peer identity");
+        }
+
+        let target = self
+            .relay_routes
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&to)
+            .cloned();
+        let Some(target) = target.filter(|target| !target.is_closed()) else {
+            return Self::failure("Target agent is not connected to this relay");
+        };
+
+        tracing::debug!("Relaying message from {} to {}", from, to);
+        let forward = P2PMessage::RelayForward {
+            from,
+            to,
+            message: Box::new(message),
+        };
+        match target.request(forward).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Failed to relay message from {} to {}: {}", from, to, e);
+                Self::failure("Target agent did not respond")
+            }
+        }
+    }
+
+    /// Serve a session until it closes, then drop its relay registrations
+    async fn serve_session<R>(&self, session: PeerSession, reader: R) -> Result<()>
     where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
+        R: tokio::io::AsyncRead + Unpin,
     {
+        self.sessions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(session.clone());
+
+        let connector = self.clone();
+        let offer_session = session.clone();
+        tokio::spawn(async move { connector.send_offers(offer_session).await });
+
+        let connector = self.clone();
+        let result = session
+            .run(reader, move |session, frame| {
+                connector.clone().handle_frame(session, frame)
+            })
+            .await;
+
+        self.sessions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|open| !open.same_session(&session));
+
+        let mut routes = self.relay_routes.write().unwrap_or_else(|e| e.into_inner());
+        if routes
+            .get(&session.peer_id())
+            .is_some_and(|route| route.same_session(&session))
+        {
+            routes.remove(&session.peer_id());
+            tracing::info!("🔀 Stopped relaying for {}", session.peer_id());
+        }
+
+        result
+    }
+
+    /// Tells all connected peers that this agent is going away and closes the sessions
+    ///
+    /// Peers that don't understand goodbyes just see the connection close.
+    pub async fn shutdown(&self, reason: &str) {
+        let sessions =
+            std::mem::take(&mut *self.sessions.write().unwrap_or_else(|e| e.into_inner()));
+        if sessions.is_empty() {
+            return;
+        }
+
+        tracing::info!("👋 Closing {} P2P session(s)", sessions.len());
+        let mut tasks = tokio::task::JoinSet::new();
+        for session in sessions {
+            let goodbye = P2PMessage::Goodbye {
+                agent_id: self.agent_id,
+                reason: reason.to_string(),
+            };
+            tasks.spawn(async move {
+                if session.supports(protocol::CAP_GOODBYE) {
+                    if let Err(e) = session.send(goodbye).await {
+                        tracing::debug!("Failed to say goodbye to {}: {}", session.peer_id(), e);
+                    }
+                }
+                session.close().await;
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// Registers with a relay so peers can reach us through it
+    pub async fn register_with_relay(&self, relay: &PeerSession) -> Result<()> {
+        let response = relay
+            .request(P2PMessage::RelayRegister {
+                agent_id: self.agent_id,
+                timestamp: Utc::now(),
+            })
+            .await?;
+        match response {
+            P2PMessage::Response { success: true, .. } => {
+                self.relay_links
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(relay.peer_id(), relay.clone());
+                Ok(())
+            }
+            P2PMessage::Response { message, .. } => {
+                anyhow::bail!("Relay refused registration: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to relay registration"),
+        }
+    }
+
+    /// Forgets a relay whose session has ended
+    pub fn remove_relay_link(&self, relay: &PeerSession) {
+        let mut links = self.relay_links.write().unwrap_or_else(|e| e.into_inner());
+        if links
+            .get(&relay.peer_id())
+            .is_some_and(|link| link.same_session(relay))
+        {
+            links.remove(&relay.peer_id());
+        }
+    }
+
+    /// Send a request to an agent through one of our relays
+    pub async fn relay_request(&self, to: Uuid, message: P2PMessage) -> Result<P2PMessage> {
+        let relay = self
+            .relay_links
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .find(|relay| !relay.is_closed())
+            .cloned()
+            .context("No relay connected")?;
+
+        relay
+            .request(P2PMessage::RelayForward {
+                from: self.agent_id,
+                to,
+                message: Box::new(message),
+            })
+            .await
+    }
+
+    /// Send a heartbeat to an agent reachable only through a relay
+    pub async fn send_relayed_heartbeat(&self, to: Uuid) -> Result<()> {
         let heartbeat = P2PMessage::Heartbeat {
             agent_id: self.agent_id,
             timestamp: Utc::now(),
         };
 
-        Self::send_message(stream, &heartbeat).await?;
+        match self.relay_request(to, heartbeat).await? {
+            P2PMessage::Response { success: true, .. } => Ok(()),
+            P2PMessage::Response { message, .. } => {
+                anyhow::bail!("Relayed heartbeat failed: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response to relayed heartbeat"),
+        }
+    }
+
+    /// Send a heartbeat to a peer
+    pub async fn send_heartbeat(&self, session: &PeerSession) -> Result<()> {
+        let heartbeat = P2PMessage::Heartbeat {
+            agent_id: self.agent_id,
+            timestamp: Utc::now(),
+        };
 
         // Wait for response
-        let response = Self::receive_message(stream).await?;
+        let response = session.request(heartbeat).await?;
         match response {
             P2PMessage::Response {
                 success, message, ..
@@ -398,19 +1354,14 @@ impl P2PConnector {
 
     /// Request metrics from a peer
     #[allow(dead_code)]
-    pub async fn request_metrics<S>(&self, stream: &mut S) -> Result<serde_json::Value>
-    where
-        S: AsyncReadExt + AsyncWriteExt + Unpin,
-    {
+    pub async fn request_metrics(&self, session: &PeerSession) -> Result<serde_json::Value> {
         let request = P2PMessage::MetricsRequest {
             agent_id: self.agent_id,
             timestamp: Utc::now(),
         };
 
-        Self::send_message(stream, &request).await?;
-
         // Wait for response
-        let response = Self::receive_message(stream).await?;
+        let response = session.request(request).await?;
         match response {
             P2PMessage::Response {
                 success,
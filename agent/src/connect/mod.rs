@@ -1,5 +1,12 @@
 pub mod certs;
 pub mod connector;
+pub mod protocol;
+pub mod relay;
+pub mod renewal;
+pub mod revocation;
+pub mod session;
+pub mod transfer;
+pub mod uplink;
 
 pub use certs::ensure_certificates;
 pub use connector::P2PConnector;
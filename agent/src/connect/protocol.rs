@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::connector::P2PMessage;
+
+/// Current version of the P2P wire protocol
+///
+/// Version 1 is the original protocol: JSON messages without request ids.
+/// Version 2 wraps every message in a [`Frame`] carrying request ids and
+/// supports the encodings listed in [`Encoding`].
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Version assumed for peers whose handshake doesn't announce one
+pub const LEGACY_PROTOCOL_VERSION: u16 = 1;
+
+/// Default upper bound for a single frame (1 MiB)
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 1024 * 1024;
+
+/// Capabilities announced in the handshake
+pub const CAP_HEARTBEAT: &str = "heartbeat";
+pub const CAP_METRICS: &str = "metrics";
+pub const CAP_RELAY: &str = "relay";
+pub const CAP_COLLECTOR: &str = "collector";
+pub const CAP_TRANSFER: &str = "transfer";
+pub const CAP_GOODBYE: &str = "goodbye";
+
+/// Message encoding used after the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Json,
+    /// MessagePack, more compact than JSON
+    Msgpack,
+}
+
+/// Protocol settings of the local agent
+#[derive(Debug, Clone)]
+pub struct ProtocolOptions {
+    /// Largest frame accepted from or sent to a peer (bytes)
+    pub max_frame_size: u32,
+    /// Supported encodings in order of preference
+    pub encodings: Vec<Encoding>,
+}
+
+impl Default for ProtocolOptions {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            encodings: vec![Encoding::Msgpack, Encoding::Json],
+        }
+    }
+}
+
+/// A message on the wire together with its correlation ids
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    /// Sender-assigned id, unique per connection and direction
+    pub id: u64,
+    /// Id of the request this frame answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
+    pub message: P2PMessage,
+}
+
+/// Settings agreed on during the handshake, used to encode frames
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCodec {
+    pub version: u16,
+    pub encoding: Encoding,
+    /// Largest frame we accept
+    max_frame_size: u32,
+    /// Largest frame the peer accepts
+    peer_max_frame_size: u32,
+}
+
+impl FrameCodec {
+    /// Agrees on version and encoding with a peer
+    ///
+    /// Both sides pick the first encoding of the accepting side's list that
+    /// the connecting side supports, so they reach the same result without
+    /// another round trip.
+    pub fn negotiate(
+        local: &ProtocolOptions,
+        peer_version: u16,
+        peer_encodings: &[Encoding],
+        peer_max_frame_size: Option<u32>,
+        is_server: bool,
+    ) -> Result<Self> {
+        let version = PROTOCOL_VERSION.min(peer_version);
+
+        let encoding = if version < 2 {
+            Encoding::Json
+        } else {
+            let (preferred, other): (&[Encoding], &[Encoding]) = if is_server {
+                (&local.encodings, peer_encodings)
+            } else {
+                (peer_encodings, &local.encodings)
+            };
+            *preferred
+                .iter()
+                .find(|encoding| other.contains(encoding))
+                .context("No common message encoding with peer")?
+        };
+
+        Ok(Self {
+            version,
+            encoding,
+            max_frame_size: local.max_frame_size,
+            peer_max_frame_size: peer_max_frame_size.unwrap_or(local.max_frame_size),
+        })
+    }
+
+    /// Largest frame the peer accepts (bytes)
+    pub fn peer_max_frame_size(&self) -> u32 {
+        self.peer_max_frame_size
+    }
+
+    /// Whether the peer speaks the original protocol without request ids
+    pub fn is_legacy(&self) -> bool {
+        self.version < 2
+    }
+
+    /// Writes a frame (only the message for legacy peers)
+    pub async fn write_frame<W>(&self, writer: &mut W, frame: &Frame) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let payload = match (self.is_legacy(), self.encoding) {
+            (true, _) => serde_json::to_vec(&frame.message)?,
+            (false, Encoding::Json) => serde_json::to_vec(frame)?,
+            (false, Encoding::Msgpack) => rmp_serde::to_vec_named(frame)?,
+        };
+
+        write_payload(writer, &payload, self.peer_max_frame_size).await
+    }
+
+    /// Reads a frame, assigning id 0 to messages from legacy peers
+    pub async fn read_frame<R>(&self, reader: &mut R) -> Result<Frame>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let payload = read_payload(reader, self.max_frame_size).await?;
+
+        let frame = match (self.is_legacy(), self.encoding) {
+            (true, _) => Frame {
+                id: 0,
+                reply_to: None,
+                message: serde_json::from_slice(&payload)?,
+            },
+            (false, Encoding::Json) => serde_json::from_slice(&payload)?,
+            (false, Encoding::Msgpack) => rmp_serde::from_slice(&payload)?,
+        };
+        Ok(frame)
+    }
+}
+
+/// Sends a handshake, always as a plain JSON message understood by every version
+pub async fn write_handshake<W>(writer: &mut W, message: &P2PMessage, max_size: u32) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let payload = serde_json::to_vec(message)?;
+    write_payload(writer, &payload, max_size).await
+}
+
+/// Receives a handshake sent with [`write_handshake`]
+pub async fn read_handshake<R>(reader: &mut R, max_size: u32) -> Result<P2PMessage>
+where
+    R: AsyncRead + Unpin,
+{
+    let payload = read_payload(reader, max_size).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// Writes a payload with a u32 length prefix
+async fn write_payload<W>(writer: &mut W, payload: &[u8], max_size: u32) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > max_size as usize {
+        anyhow::bail!(
+            "Frame of {} bytes exceeds the peer's limit of {} bytes",
+            payload.len(),
+            max_size
+        );
+    }
+
+    // Send length prefix (4 bytes)
+    writer
+        .write_all(&(payload.len() as u32).to_be_bytes())
+        .await?;
+    // Send payload
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Reads a length-prefixed payload, refusing lengths above `max_size`
+/// before allocating anything
+async fn read_payload<R>(reader: &mut R, max_size: u32) -> Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    // Read length prefix (4 bytes)
+    let mut len_bytes = [0u8; 4];
+    reader.read_exact(&mut len_bytes).await?;
+    let len = u32::from_be_bytes(len_bytes);
+
+    if len > max_size {
+        anyhow::bail!(
+            "Peer announced a frame of {} bytes, limit is {} bytes",
+            len,
+            max_size
+        );
+    }
+
+    let mut buffer = vec![0u8; len as usize];
+    reader.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+/// Serde helper for binary fields: raw bytes in MessagePack, base64 in JSON
+pub mod binary {
+    use base64::Engine;
+    use serde::de::{self, Deserializer, SeqAccess, Visitor};
+    use serde::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+        } else {
+            serializer.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BinaryVisitor;
+
+        impl<'de> Visitor<'de> for BinaryVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("bytes or a base64 string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Vec<u8>, E> {
+                base64::engine::general_purpose::STANDARD
+                    .decode(v)
+                    .map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+                let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    data.push(byte);
+                }
+                Ok(data)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BinaryVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BinaryVisitor)
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::P2PConnector;
+
+/// Interval of keep-alive heartbeats on relay sessions (also keeps NAT mappings open)
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Delay before reconnecting to a relay
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Keeps an outbound session to each relay so peers can reach this agent
+/// without inbound connections
+pub fn spawn_relay_links(connector: P2PConnector, relays: Vec<String>) {
+    for relay_addr in relays {
+        let connector = connector.clone();
+        tokio::spawn(async move {
+            loop {
+                match connector.connect_to_peer(&relay_addr).await {
+                    Ok(relay) => match connector.register_with_relay(&relay).await {
+                        Ok(_) => {
+                            tracing::info!(
+                                "🔀 Registered with relay {} ({})",
+                                relay_addr,
+                                relay.peer_id()
+                            );
+
+                            let mut interval = tokio::time::interval(KEEPALIVE_INTERVAL);
+                            loop {
+                                interval.tick().await;
+
+                                if let Err(e) = connector.send_heartbeat(&relay).await {
+                                    tracing::warn!(
+                                        "⚠️  Lost relay {}: {}. Reconnecting...",
+                                        relay_addr,
+                                        e
+                                    );
+                                    break;
+                                }
+                            }
+                            connector.remove_relay_link(&relay);
+                        }
+                        Err(e) => {
+                            tracing::error!(
+                                "❌ Failed to register with relay {}: {}",
+                                relay_addr,
+                                e
+                            );
+                        }
+                    },
+                    Err(e) => {
+                        tracing::error!("❌ Failed to connect to relay {}: {}", relay_addr, e);
+                    }
+                }
+
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        });
+    }
+}
+
+/// Keeps heartbeats flowing to a peer reachable only through a relay
+pub fn spawn_relayed_peer(connector: P2PConnector, peer_id: Uuid) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(KEEPALIVE_INTERVAL);
+        let mut reachable = false;
+        loop {
+            interval.tick().await;
+
+            match connector.send_relayed_heartbeat(peer_id).await {
+                Ok(_) => {
+                    if !reachable {
+                        tracing::info!("✅ Reached peer {} through relay", peer_id);
+                        reachable = true;
+                    }
+                }
+                Err(e) => {
+                    if reachable {
+                        tracing::warn!("⚠️  Lost relayed peer {}: {}", peer_id, e);
+                    } else {
+                        tracing::debug!("Relayed peer {} not reachable yet: {}", peer_id, e);
+                    }
+                    reachable = false;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{certs, P2PConnector};
+use crate::client::ServerClient;
+use crate::config::AgentConfig;
+
+/// How often the agent certificate's expiry is checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Spawns a task that renews the agent certificate before it expires
+pub fn spawn_renewal_task(config: AgentConfig, client: ServerClient, connector: P2PConnector) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = renew_if_needed(&config, &client, &connector).await {
+                tracing::error!("❌ Certificate renewal failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Whether a CA in the trusted bundle issued the agent certificate for this agent
+///
+/// With `use_backend_ca` that is the fleet CA, unless there is no certificate
+/// yet or an older version generated it with its local CA.
+pub fn has_fleet_certificate(config: &AgentConfig) -> bool {
+    Path::new(&config.p2p.key_path).exists()
+        && certs::verify_agent_certificate(
+            Path::new(&config.p2p.cert_path),
+            Path::new(&config.p2p.ca_cert_path),
+            Path::new(&config.p2p.crl_path),
+            &config.agent_id,
+        )
+        .is_ok()
+}
+
+/// Renews the agent certificate if it expires within `renew_before_days`
+///
+/// Certificates are re-issued by the fleet CA when `use_backend_ca` is set,
+/// otherwise by the local CA. The connector is reloaded afterwards so new
+/// connections use the renewed certificate without a restart.
+pub async fn renew_if_needed(
+    config: &AgentConfig,
+    client: &ServerClient,
+    connector: &P2PConnector,
+) -> Result<bool> {
+    let cert_path = Path::new(&config.p2p.cert_path);
+
+    // After a CA rotation or an upgrade from the local CA the certificate may
+    // not be trusted anymore, whatever its expiry
+    if config.p2p.use_backend_ca
+        && config.connects_to_backend()
+        && ensure_fleet_certificate(config, client).await?
+    {
+        connector.reload_tls()?;
+        let not_after = certs::certificate_not_after(cert_path)?;
+        tracing::info!(
+            "✅ Agent certificate issued by the fleet CA, valid until {}",
+            not_after
+        );
+        return Ok(true);
+    }
+
+    let not_after = certs::certificate_not_after(cert_path)?;
+    let remaining = not_after - Utc::now();
+
+    if remaining > chrono::Duration::days(config.p2p.renew_before_days as i64) {
+        tracing::debug!("Agent certificate valid until {}", not_after);
+        return Ok(false);
+    }
+
+    // The fleet CA renews it with the next registration through the collector proxy
+    if renews_through_collector(config) {
+        tracing::debug!(
+            "Agent certificate expires at {}, renewed through the collector proxy",
+            not_after
+        );
+        return Ok(false);
+    }
+
+    tracing::info!(
+        "🔄 Agent certificate expires at {} ({} days left), renewing...",
+        not_after,
+        remaining.num_days()
+    );
+
+    renew_certificate(config, client).await?;
+    connector.reload_tls()?;
+
+    let not_after = certs::certificate_not_after(cert_path)?;
+    tracing::info!("✅ Agent certificate renewed, valid until {}", not_after);
+    Ok(true)
+}
+
+/// Issues a new agent certificate regardless of the current one's expiry
+///
+/// The fleet CA only renews its own certificates, any other is requested
+/// through the registration.
+pub async fn renew_certificate(config: &AgentConfig, client: &ServerClient) -> Result<()> {
+    if renews_through_collector(config) {
+        anyhow::bail!(
+            "The fleet CA is only reachable through the collector proxy, the running agent \
+             renews the certificate with its registration (or use `certs request`)"
+        );
+    }
+
+    let cert_dir = config.p2p.cert_dir();
+    let fleet_ca = config.p2p.use_backend_ca && config.connects_to_backend();
+    if fleet_ca && !has_fleet_certificate(config) {
+        request_fleet_certificate(config, client).await?;
+    } else if fleet_ca {
+        let csr = agent_csr(config)?;
+        let response = client
+            .renew_certificate(config.agent_id, &csr)
+            .await
+            .context("Fleet CA did not renew the certificate")?;
+        certs::install_issued_certificate(&cert_dir, &response.certificate, &response.ca_bundle)?;
+    } else {
+        certs::generate_agent_cert(
+            &config.name,
+            &config.agent_id,
+            &config.p2p.subject_alt_names(),
+            &cert_dir.join("ca.crt"),
+            &cert_dir.join("ca.key"),
+            &cert_dir,
+            config.p2p.key_passphrase(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Gets a certificate from the fleet CA unless it issued the current one
+///
+/// The CA bundle is refreshed first. A missing certificate, one of the local
+/// CA older versions generated or one of a CA that is no longer trusted is
+/// requested with a CSR through the registration. Returns whether a new
+/// certificate was installed.
+pub async fn ensure_fleet_certificate(config: &AgentConfig, client: &ServerClient) -> Result<bool> {
+    refresh_ca_bundle(config, client).await;
+    if has_fleet_certificate(config) {
+        return Ok(false);
+    }
+
+    tracing::info!("🔏 Requesting a certificate from the fleet CA...");
+    request_fleet_certificate(config, client).await?;
+    Ok(true)
+}
+
+/// Replaces the trusted CA bundle with the fleet CA's current one
+pub async fn refresh_ca_bundle(config: &AgentConfig, client: &ServerClient) {
+    match client.fetch_ca_bundle().await {
+        Ok(ca_bundle) => {
+            if let Err(e) = certs::install_ca_bundle(&config.p2p.cert_dir(), &ca_bundle) {
+                tracing::error!("❌ Failed to update CA bundle: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("⚠️  Could not refresh CA bundle: {}", e),
+    }
+}
+
+/// CSR for the fleet CA, unless it already issued the agent's certificate
+pub fn fleet_csr(config: &AgentConfig) -> Result<Option<String>> {
+    if has_fleet_certificate(config) {
+        return Ok(None);
+    }
+    agent_csr(config).map(Some)
+}
+
+/// Whether the fleet CA issues the certificate but is only reachable through
+/// a collector proxy
+pub fn renews_through_collector(config: &AgentConfig) -> bool {
+    config.p2p.use_backend_ca && config.p2p.collector.is_some()
+}
+
+/// Whether the agent certificate is missing or expires within `renew_before_days`
+pub fn renewal_due(config: &AgentConfig) -> bool {
+    certs::certificate_not_after(Path::new(&config.p2p.cert_path))
+        .map(|not_after| {
+            not_after - Utc::now() <= chrono::Duration::days(config.p2p.renew_before_days as i64)
+        })
+        .unwrap_or(true)
+}
+
+/// Registers with a CSR, the fleet CA only renews certificates it issued
+async fn request_fleet_certificate(config: &AgentConfig, client: &ServerClient) -> Result<()> {
+    let csr = agent_csr(config)?;
+    let response = client
+        .register(&crate::agent_registration(config, Some(csr), None))
+        .await
+        .context("Registration with the fleet CA failed")?;
+    let (Some(certificate), Some(ca_bundle)) = (response.certificate, response.ca_bundle) else {
+        anyhow::bail!("The backend did not issue a certificate");
+    };
+    certs::install_issued_certificate(&config.p2p.cert_dir(), &certificate, &ca_bundle)
+}
+
+/// CSR for the agent key, so the fleet CA sees the key it certified before
+pub fn agent_csr(config: &AgentConfig) -> Result<String> {
+    certs::generate_csr(
+        &config.name,
+        &config.agent_id,
+        &config.p2p.subject_alt_names(),
+        &config.p2p.cert_dir(),
+        config.p2p.key_passphrase(),
+    )
+}
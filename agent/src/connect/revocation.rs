@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::{certs, P2PConnector};
+use crate::client::ServerClient;
+use crate::config::AgentConfig;
+
+/// File in the certificate directory caching the backend's deny list
+const DENIED_AGENTS_CACHE: &str = "denied_agents.json";
+
+/// Applies the deny list known locally: the configured agents plus the last
+/// list fetched from the backend
+pub fn apply_cached_deny_list(config: &AgentConfig, connector: &P2PConnector) {
+    let mut denied = config.p2p.denied_agents.clone();
+    match load_cached_deny_list(&cache_path(config)) {
+        Ok(cached) => denied.extend(cached),
+        Err(e) => tracing::warn!("⚠️  Could not load cached deny list: {}", e),
+    }
+    connector.set_denied_agents(denied);
+}
+
+/// Spawns a task that keeps the CRLs and the deny list in sync with the backend
+pub fn spawn_revocation_task(config: AgentConfig, client: ServerClient, connector: P2PConnector) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            config.p2p.revocation_refresh_interval.max(60),
+        ));
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = refresh_revocations(&config, &client, &connector).await {
+                tracing::error!("❌ Failed to refresh revocation list: {}", e);
+            }
+        }
+    });
+}
+
+/// Fetches the current CRLs and deny list, caches them on disk and applies
+/// them to new connections
+pub async fn refresh_revocations(
+    config: &AgentConfig,
+    client: &ServerClient,
+    connector: &P2PConnector,
+) -> Result<()> {
+    let revocations = client.fetch_revocations().await?;
+
+    certs::install_crls(Path::new(&config.p2p.crl_path), &revocations.crls)?;
+    std::fs::write(
+        cache_path(config),
+        serde_json::to_vec(&revocations.denied_agents)?,
+    )
+    .context("Failed to cache deny list")?;
+
+    let mut denied = config.p2p.denied_agents.clone();
+    denied.extend(&revocations.denied_agents);
+    connector.set_denied_agents(denied);
+    connector.reload_tls()?;
+
+    tracing::info!(
+        "🛡️  Revocation list updated ({} CRLs, {} denied agents)",
+        revocations.crls.len(),
+        revocations.denied_agents.len()
+    );
+    Ok(())
+}
+
+fn cache_path(config: &AgentConfig) -> PathBuf {
+    config.p2p.cert_dir().join(DENIED_AGENTS_CACHE)
+}
+
+fn load_cached_deny_list(path: &Path) -> Result<Vec<Uuid>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let contents = std::fs::read(path).context(format!("Failed to read {:?}", path))?;
+    serde_json::from_slice(&contents).context("Failed to parse cached deny list")
+}
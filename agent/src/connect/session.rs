@@ -0,0 +1,243 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::certs::PeerIdentity;
+use super::connector::P2PMessage;
+use super::protocol::{Frame, FrameCodec};
+
+/// How long to wait for the response to a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for the peer to acknowledge closing the connection
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type BoxedWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// An established connection to a peer after a successful handshake
+///
+/// Requests are tagged with ids, so any number of them can be in flight at
+/// once; responses are matched by the task running [`PeerSession::run`].
+#[derive(Clone)]
+pub struct PeerSession {
+    inner: Arc<SessionInner>,
+}
+
+struct SessionInner {
+    identity: PeerIdentity,
+    peer_name: String,
+    capabilities: Vec<String>,
+    codec: FrameCodec,
+    writer: tokio::sync::Mutex<BoxedWriter>,
+    pending: Mutex<BTreeMap<u64, oneshot::Sender<P2PMessage>>>,
+    next_id: AtomicU64,
+    closed: AtomicBool,
+}
+
+impl PeerSession {
+    pub fn new<W>(
+        identity: PeerIdentity,
+        peer_name: String,
+        capabilities: Vec<String>,
+        codec: FrameCodec,
+        writer: W,
+    ) -> Self
+    where
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        Self {
+            inner: Arc::new(SessionInner {
+                identity,
+                peer_name,
+                capabilities,
+                codec,
+                writer: tokio::sync::Mutex::new(Box::new(writer)),
+                pending: Mutex::new(BTreeMap::new()),
+                // Id 0 is used for messages from legacy peers
+                next_id: AtomicU64::new(1),
+                closed: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Agent ID verified against the peer's certificate
+    pub fn peer_id(&self) -> Uuid {
+        self.inner.identity.agent_id
+    }
+
+    /// Name from the peer's certificate, or the one it announced in its
+    /// handshake if the certificate has none (informational only)
+    pub fn peer_name(&self) -> &str {
+        self.inner
+            .identity
+            .common_name
+            .as_deref()
+            .unwrap_or(&self.inner.peer_name)
+    }
+
+    /// Whether the peer announced a capability in its handshake
+    pub fn supports(&self, capability: &str) -> bool {
+        self.inner.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Largest frame the peer accepts (bytes)
+    pub fn peer_max_frame_size(&self) -> u32 {
+        self.inner.codec.peer_max_frame_size()
+    }
+
+    /// Whether both handles refer to the same connection
+    pub fn same_session(&self, other: &PeerSession) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::Acquire)
+    }
+
+    /// Sends a message that expects no response
+    pub async fn send(&self, message: P2PMessage) -> Result<()> {
+        let id = self.next_id();
+        self.write(Frame {
+            id,
+            reply_to: None,
+            message,
+        })
+        .await
+    }
+
+    /// Sends a request and waits for the matching response
+    pub async fn request(&self, message: P2PMessage) -> Result<P2PMessage> {
+        if self.is_closed() {
+            anyhow::bail!("Session with {} is closed", self.peer_id());
+        }
+
+        let id = self.next_id();
+        let (tx, rx) = oneshot::channel();
+        self.pending().insert(id, tx);
+
+        if let Err(e) = self
+            .write(Frame {
+                id,
+                reply_to: None,
+                message,
+            })
+            .await
+        {
+            self.pending().remove(&id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => anyhow::bail!("Session with {} closed", self.peer_id()),
+            Err(_) => {
+                self.pending().remove(&id);
+                anyhow::bail!("Request {} to {} timed out", id, self.peer_id())
+            }
+        }
+    }
+
+    /// Answers the request with the given id
+    pub async fn reply(&self, request_id: u64, message: P2PMessage) -> Result<()> {
+        let id = self.next_id();
+        self.write(Frame {
+            id,
+            reply_to: Some(request_id),
+            message,
+        })
+        .await
+    }
+
+    /// Reads frames until the connection closes
+    ///
+    /// Responses complete their pending request; every other frame is passed
+    /// to `handler` on its own task so slow requests don't block the session.
+    /// Ending after either side closed the session on purpose is not an error.
+    pub async fn run<R, F, Fut>(&self, mut reader: R, handler: F) -> Result<()>
+    where
+        R: AsyncRead + Unpin,
+        F: Fn(PeerSession, Frame) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let result = loop {
+            let frame = match self.inner.codec.read_frame(&mut reader).await {
+                Ok(frame) => frame,
+                Err(e) => break Err(e),
+            };
+
+            // Legacy peers answer strictly in order and without ids
+            let reply_to = frame.reply_to.or_else(|| {
+                (self.inner.codec.is_legacy()
+                    && matches!(frame.message, P2PMessage::Response { .. }))
+                .then(|| self.pending().keys().next().copied())
+                .flatten()
+            });
+
+            match reply_to {
+                Some(request_id) => match self.pending().remove(&request_id) {
+                    Some(tx) => {
+                        let _ = tx.send(frame.message);
+                    }
+                    None => tracing::debug!(
+                        "Dropping response to unknown request {} from {}",
+                        request_id,
+                        self.peer_id()
+                    ),
+                },
+                None => {
+                    // The connection closing next is expected, not an error
+                    if matches!(frame.message, P2PMessage::Goodbye { .. }) {
+                        self.inner.closed.store(true, Ordering::Release);
+                    }
+                    tokio::spawn(handler(self.clone(), frame));
+                }
+            }
+        };
+
+        // Fail all requests still waiting for a response
+        let closed_on_purpose = self.inner.closed.swap(true, Ordering::AcqRel);
+        self.pending().clear();
+
+        if closed_on_purpose {
+            return Ok(());
+        }
+        result.context(format!("Session with {} ended", self.peer_id()))
+    }
+
+    /// Closes the connection; pending and later requests fail
+    ///
+    /// The TLS session is shut down cleanly, so the peer sees an orderly
+    /// close instead of a reset.
+    pub async fn close(&self) {
+        self.inner.closed.store(true, Ordering::Release);
+        self.pending().clear();
+
+        let mut writer = self.inner.writer.lock().await;
+        match tokio::time::timeout(CLOSE_TIMEOUT, writer.shutdown()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::debug!("Failed to close the session with {}: {}", self.peer_id(), e)
+            }
+            Err(_) => tracing::debug!("Closing the session with {} timed out", self.peer_id()),
+        }
+    }
+
+    async fn write(&self, frame: Frame) -> Result<()> {
+        let mut writer = self.inner.writer.lock().await;
+        self.inner.codec.write_frame(&mut *writer, &frame).await
+    }
+
+    fn next_id(&self) -> u64 {
+        self.inner.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, oneshot::Sender<P2PMessage>>> {
+        self.inner.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+use super::connector::P2PMessage;
+use super::session::PeerSession;
+
+/// Largest chunk served in one message (before encoding)
+const MAX_CHUNK_SIZE: u32 = 256 * 1024;
+
+/// Room left in a frame for the message around a chunk
+const CHUNK_OVERHEAD: u32 = 4096;
+
+/// Describes an artifact offered for transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub name: String,
+    pub size: u64,
+    /// Hex SHA-256 of the whole file
+    pub sha256: String,
+    /// Size of the chunks the file is served in
+    pub chunk_size: u32,
+}
+
+/// How this agent takes part in artifact distribution
+#[derive(Debug, Clone, Default)]
+pub struct TransferOptions {
+    /// Serve artifacts from the store to peers
+    pub serve: bool,
+    /// Download artifacts peers offer into the store
+    pub auto_fetch: bool,
+}
+
+/// Directory of artifacts this agent serves to and downloads from peers
+pub struct ArtifactStore {
+    dir: PathBuf,
+    /// Manifests by name, with the modification time and size they were computed for
+    manifests: Mutex<HashMap<String, (SystemTime, u64, TransferManifest)>>,
+    /// Artifacts currently being downloaded
+    fetching: Mutex<HashSet<String>>,
+}
+
+impl ArtifactStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            manifests: Mutex::new(HashMap::new()),
+            fetching: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Marks an artifact as being downloaded, false if it already is
+    pub fn begin_fetch(&self, name: &str) -> bool {
+        self.fetching
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string())
+    }
+
+    pub fn end_fetch(&self, name: &str) {
+        self.fetching
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name);
+    }
+
+    /// Manifest of an artifact, hashing the file only when it changed
+    ///
+    /// `max_chunk` limits the chunk size to what the requesting peer accepts.
+    pub async fn manifest(&self, name: &str, max_chunk: u32) -> Result<TransferManifest> {
+        let path = self.artifact_path(name)?;
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .context(format!("Artifact not found: {}", name))?;
+        if !metadata.is_file() {
+            anyhow::bail!("Artifact not found: {}", name);
+        }
+        let modified = metadata.modified()?;
+        let size = metadata.len();
+        let chunk_size = MAX_CHUNK_SIZE.min(max_chunk).max(1);
+
+        let cached = self
+            .manifests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .filter(|(cached_modified, cached_size, _)| {
+                *cached_modified == modified && *cached_size == size
+            })
+            .map(|(_, _, manifest)| manifest.clone());
+        if let Some(manifest) = cached {
+            return Ok(TransferManifest {
+                chunk_size,
+                ..manifest
+            });
+        }
+
+        let sha256 = sha256_file(&path).await?;
+        let manifest = TransferManifest {
+            name: name.to_string(),
+            size,
+            sha256,
+            chunk_size,
+        };
+        self.manifests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), (modified, size, manifest.clone()));
+
+        Ok(manifest)
+    }
+
+    /// Reads a chunk of an artifact, failing if the artifact changed since
+    /// the requester fetched its manifest
+    pub async fn read_chunk(
+        &self,
+        name: &str,
+        sha256: &str,
+        offset: u64,
+        length: u32,
+        max_chunk: u32,
+    ) -> Result<Vec<u8>> {
+        let manifest = self.manifest(name, max_chunk).await?;
+        if manifest.sha256 != sha256 {
+            anyhow::bail!("Artifact {} has changed", name);
+        }
+        if offset > manifest.size {
+            anyhow::bail!("Offset {} is beyond the end of {}", offset, name);
+        }
+
+        let length = (length.min(manifest.chunk_size) as u64).min(manifest.size - offset);
+        let mut file = tokio::fs::File::open(self.artifact_path(name)?).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut data = vec![0u8; length as usize];
+        file.read_exact(&mut data).await?;
+
+        Ok(data)
+    }
+
+    /// Path of an artifact, refusing names that could escape the directory
+    pub fn artifact_path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+        if !valid {
+            anyhow::bail!("Invalid artifact name: {}", name);
+        }
+        Ok(self.dir.join(name))
+    }
+}
+
+/// Largest chunk a peer can receive given its frame limit
+pub fn max_chunk_for(peer_max_frame_size: u32) -> u32 {
+    // Chunks are base64 encoded in JSON, which adds a third
+    (peer_max_frame_size.saturating_sub(CHUNK_OVERHEAD) / 4) * 3
+}
+
+/// Downloads an artifact from a peer into `dest_dir`
+///
+/// Data is written to a `.part` file next to the destination, so an
+/// interrupted download resumes where it stopped as long as the artifact is
+/// unchanged. Every chunk and the complete file are verified against their
+/// SHA-256 before the artifact is moved into place.
+pub async fn fetch_artifact(
+    session: &PeerSession,
+    agent_id: Uuid,
+    name: &str,
+    dest_dir: &Path,
+) -> Result<PathBuf> {
+    let manifest = match session
+        .request(P2PMessage::TransferManifestRequest {
+            agent_id,
+            name: name.to_string(),
+        })
+        .await?
+    {
+        P2PMessage::TransferManifest { manifest } => manifest,
+        P2PMessage::Response { message, .. } => {
+            anyhow::bail!("Peer refused artifact {}: {}", name, message)
+        }
+        _ => anyhow::bail!("Unexpected response to manifest request"),
+    };
+    if manifest.name != name || manifest.chunk_size == 0 {
+        anyhow::bail!("Peer sent an invalid manifest for {}", name);
+    }
+
+    let store = ArtifactStore::new(dest_dir.to_path_buf());
+    let dest = store.artifact_path(name)?;
+    tokio::fs::create_dir_all(dest_dir)
+        .await
+        .context("Failed to create artifact directory")?;
+
+    // Already up to date
+    if tokio::fs::metadata(&dest)
+        .await
+        .is_ok_and(|m| m.len() == manifest.size)
+        && sha256_file(&dest).await? == manifest.sha256
+    {
+        tracing::info!("Artifact {} is already up to date", name);
+        return Ok(dest);
+    }
+
+    let part_path = dest_dir.join(format!(".{}.part", name));
+    let state_path = dest_dir.join(format!(".{}.part.sha256", name));
+
+    // Resume only a partial download of the same artifact version
+    let resumable = tokio::fs::read_to_string(&state_path)
+        .await
+        .is_ok_and(|sha256| sha256.trim() == manifest.sha256);
+    let mut offset = if resumable {
+        let len = tokio::fs::metadata(&part_path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        (len.min(manifest.size) / manifest.chunk_size as u64) * manifest.chunk_size as u64
+    } else {
+        tokio::fs::write(&state_path, &manifest.sha256).await?;
+        0
+    };
+
+    let mut part = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&part_path)
+        .await
+        .context("Failed to open partial download")?;
+    part.set_len(offset).await?;
+    part.seek(std::io::SeekFrom::Start(offset)).await?;
+
+    if offset > 0 {
+        tracing::info!(
+            "📦 Resuming download of {} at {} of {} bytes",
+            name,
+            offset,
+            manifest.size
+        );
+    } else {
+        tracing::info!("📦 Downloading {} ({} bytes)", name, manifest.size);
+    }
+
+    while offset < manifest.size {
+        let length = (manifest.chunk_size as u64).min(manifest.size - offset) as u32;
+        let response = session
+            .request(P2PMessage::TransferChunkRequest {
+                agent_id,
+                name: name.to_string(),
+                sha256: manifest.sha256.clone(),
+                offset,
+                length,
+            })
+            .await?;
+
+        let data = match response {
+            P2PMessage::TransferChunk {
+                offset: chunk_offset,
+                data,
+                sha256,
+            } => {
+                if chunk_offset != offset || data.len() != length as usize {
+                    anyhow::bail!("Peer sent the wrong chunk of {}", name);
+                }
+                if hex::encode(Sha256::digest(&data)) != sha256 {
+                    anyhow::bail!("Checksum mismatch in chunk at {} of {}", offset, name);
+                }
+                data
+            }
+            P2PMessage::Response { message, .. } => {
+                anyhow::bail!("Peer failed to send chunk of {}: {}", name, message)
+            }
+            _ => anyhow::bail!("Unexpected response to chunk request"),
+        };
+
+        part.write_all(&data).await?;
+        offset += data.len() as u64;
+    }
+    part.flush().await?;
+    part.sync_all().await?;
+    drop(part);
+
+    if sha256_file(&part_path).await? != manifest.sha256 {
+        // Corrupt data, start over next time
+        let _ = tokio::fs::remove_file(&part_path).await;
+        let _ = tokio::fs::remove_file(&state_path).await;
+        anyhow::bail!("Checksum mismatch for {}", name);
+    }
+
+    tokio::fs::rename(&part_path, &dest)
+        .await
+        .context("Failed to move artifact into place")?;
+    let _ = tokio::fs::remove_file(&state_path).await;
+
+    tracing::info!("✅ Downloaded {} from {}", name, session.peer_id());
+    Ok(dest)
+}
+
+/// Hex SHA-256 of a file
+pub async fn sha256_file(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path).context(format!("Failed to open {:?}", path))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await?
+}
@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use super::connector::P2PMessage;
+use super::session::PeerSession;
+use super::P2PConnector;
+use crate::client::{
+    AgentRegistration, Heartbeat, HeartbeatResponse, InventoryReport, RegistrationResponse,
+};
+use crate::collector::SystemMetrics;
+
+/// Reports to the backend through a collector proxy agent instead of HTTP
+///
+/// Used on hosts without a route to the backend; the session to the
+/// collector is re-established on demand.
+#[derive(Clone)]
+pub struct CollectorUplink {
+    connector: P2PConnector,
+    collector_addr: String,
+    session: Arc<Mutex<Option<PeerSession>>>,
+}
+
+impl CollectorUplink {
+    pub fn new(connector: P2PConnector, collector_addr: String) -> Self {
+        Self {
+            connector,
+            collector_addr,
+            session: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub async fn register(&self, registration: &AgentRegistration) -> Result<RegistrationResponse> {
+        let data = self
+            .request(P2PMessage::ProxyRegistration {
+                agent_id: self.connector.agent_id(),
+                registration: registration.clone(),
+            })
+            .await?
+            .context("Collector returned no registration response")?;
+
+        Ok(serde_json::from_value(data)?)
+    }
+
+    pub async fn send_heartbeat(&self, heartbeat: &Heartbeat) -> Result<HeartbeatResponse> {
+        let data = self
+            .request(P2PMessage::ProxyHeartbeat {
+                agent_id: self.connector.agent_id(),
+                heartbeat: heartbeat.clone(),
+            })
+            .await?;
+
+        // Collectors running an older version don't pass the backend's response on
+        Ok(match data {
+            Some(data) => serde_json::from_value(data)?,
+            None => HeartbeatResponse::default(),
+        })
+    }
+
+    pub async fn send_inventory(&self, report: &InventoryReport) -> Result<()> {
+        self.request(P2PMessage::ProxyInventory {
+            agent_id: self.connector.agent_id(),
+            report: report.clone(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    pub async fn send_metrics(&self, metrics: &SystemMetrics) -> Result<()> {
+        self.request(P2PMessage::MetricsShare {
+            agent_id: self.connector.agent_id(),
+            timestamp: metrics.timestamp,
+            metrics: serde_json::to_value(metrics)?,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Sends a request to the collector, returning the response data
+    async fn request(&self, message: P2PMessage) -> Result<Option<serde_json::Value>> {
+        let session = self.session().await?;
+        match session.request(message).await? {
+            P2PMessage::Response {
+                success: true,
+                data,
+                ..
+            } => Ok(data),
+            P2PMessage::Response { message, .. } => {
+                anyhow::bail!("Collector proxy failed: {}", message)
+            }
+            _ => anyhow::bail!("Unexpected response from collector proxy"),
+        }
+    }
+
+    /// Current session to the collector, connecting if there is none
+    async fn session(&self) -> Result<PeerSession> {
+        let mut session = self.session.lock().await;
+        if let Some(existing) = session.as_ref().filter(|s| !s.is_closed()) {
+            return Ok(existing.clone());
+        }
+
+        let new_session = self
+            .connector
+            .connect_to_peer(&self.collector_addr)
+            .await
+            .context(format!(
+                "Failed to connect to collector {}",
+                self.collector_addr
+            ))?;
+        if !new_session.supports(super::protocol::CAP_COLLECTOR) {
+            tracing::warn!(
+                "⚠️  Peer {} does not announce collector proxy support",
+                self.collector_addr
+            );
+        }
+
+        *session = Some(new_session.clone());
+        Ok(new_session)
+    }
+}
@@ -0,0 +1,474 @@
+use serde::{Deserialize, Serialize};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, Networks, RefreshKind, System};
+
+/// Hardware and software inventory of the host
+///
+/// Sent with the registration and again whenever it changes. It only holds
+/// values that stay the same between collections unless the host changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    pub cpu: CpuInventory,
+    pub memory: MemoryInventory,
+    pub disks: Vec<DiskInventory>,
+    pub network_interfaces: Vec<NetworkInterface>,
+    pub os: OsInventory,
+    /// Installed packages, if a known package manager was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packages: Option<PackageInventory>,
+    pub virtualization: Virtualization,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CpuInventory {
+    pub model: String,
+    pub vendor: String,
+    pub sockets: u32,
+    pub cores: u32,
+    pub threads: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryInventory {
+    pub total_bytes: u64,
+    pub swap_total_bytes: u64,
+    /// Installed modules, read from SMBIOS (only available to root)
+    pub modules: Vec<MemoryModule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryModule {
+    /// Slot the module sits in, e.g. `DIMM 0`
+    pub locator: String,
+    pub size_bytes: u64,
+    /// Memory type, e.g. `DDR4`
+    pub kind: Option<String>,
+    /// Speed in MT/s
+    pub speed_mts: Option<u32>,
+    pub manufacturer: Option<String>,
+    pub serial: Option<String>,
+    pub part_number: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiskInventory {
+    pub name: String,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub size_bytes: u64,
+    /// Spinning disk rather than flash, if known
+    pub rotational: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac_address: Option<String>,
+    /// Addresses with prefix length, e.g. `10.0.0.5/24`
+    pub ip_addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OsInventory {
+    pub name: String,
+    pub version: String,
+    pub kernel_version: String,
+    pub hostname: String,
+    pub architecture: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageInventory {
+    /// Package manager the count was taken from, e.g. `dpkg`
+    pub manager: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Virtualization {
+    /// Hypervisor the host runs on, `unknown` for a virtual machine of unknown type
+    pub hypervisor: Option<String>,
+    /// Container runtime the agent runs in
+    pub container: Option<String>,
+}
+
+/// Collects the inventory of this host
+///
+/// Reads files and may run the package manager, so call it off the async runtime.
+pub fn collect() -> Inventory {
+    let system = System::new_with_specifics(
+        RefreshKind::new()
+            .with_cpu(CpuRefreshKind::new())
+            .with_memory(MemoryRefreshKind::everything()),
+    );
+
+    let first_cpu = system.cpus().first();
+    let cpu = CpuInventory {
+        model: first_cpu
+            .map(|cpu| cpu.brand().trim().to_string())
+            .unwrap_or_else(|| "Unknown".to_string()),
+        vendor: first_cpu
+            .map(|cpu| cpu.vendor_id().to_string())
+            .unwrap_or_else(|| "Unknown".to_string()),
+        sockets: platform::cpu_sockets().unwrap_or(1),
+        cores: system.physical_core_count().unwrap_or(0) as u32,
+        threads: system.cpus().len() as u32,
+    };
+
+    let memory = MemoryInventory {
+        total_bytes: system.total_memory(),
+        swap_total_bytes: system.total_swap(),
+        modules: platform::memory_modules(),
+    };
+
+    let os = OsInventory {
+        name: System::name().unwrap_or_else(|| "Unknown".to_string()),
+        version: System::os_version().unwrap_or_else(|| "Unknown".to_string()),
+        kernel_version: System::kernel_version().unwrap_or_else(|| "Unknown".to_string()),
+        hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+        architecture: std::env::consts::ARCH.to_string(),
+    };
+
+    Inventory {
+        cpu,
+        memory,
+        disks: platform::disks(),
+        network_interfaces: network_interfaces(),
+        os,
+        packages: platform::packages(),
+        virtualization: platform::virtualization(),
+    }
+}
+
+/// Network interfaces except loopback, sorted by name
+fn network_interfaces() -> Vec<NetworkInterface> {
+    let networks = Networks::new_with_refreshed_list();
+    let mut interfaces: Vec<NetworkInterface> = networks
+        .iter()
+        .filter(|(_, data)| {
+            !data
+                .ip_networks()
+                .iter()
+                .any(|network| network.addr.is_loopback())
+        })
+        .map(|(name, data)| {
+            let mac = data.mac_address();
+            let mut ip_addresses: Vec<String> =
+                data.ip_networks().iter().map(ToString::to_string).collect();
+            ip_addresses.sort();
+            NetworkInterface {
+                name: name.clone(),
+                mac_address: (!mac.is_unspecified()).then(|| mac.to_string()),
+                ip_addresses,
+            }
+        })
+        .collect();
+    interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+    interfaces
+}
+
+/// Trimmed contents of a text file, if it exists and isn't blank
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn read_trimmed(path: impl AsRef<std::path::Path>) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let value = content.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{read_trimmed, DiskInventory, MemoryModule, PackageInventory, Virtualization};
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    /// Number of distinct physical packages in `/proc/cpuinfo`
+    pub fn cpu_sockets() -> Option<u32> {
+        let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+        let sockets: HashSet<&str> = cpuinfo
+            .lines()
+            .filter(|line| line.starts_with("physical id"))
+            .filter_map(|line| line.split(':').nth(1))
+            .map(str::trim)
+            .collect();
+        (!sockets.is_empty()).then_some(sockets.len() as u32)
+    }
+
+    /// Memory devices (SMBIOS type 17) from `/sys/firmware/dmi/entries`
+    pub fn memory_modules() -> Vec<MemoryModule> {
+        let Ok(entries) = std::fs::read_dir("/sys/firmware/dmi/entries") else {
+            return vec![];
+        };
+
+        let mut modules: Vec<MemoryModule> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("17-"))
+            .filter_map(|entry| std::fs::read(entry.path().join("raw")).ok())
+            .filter_map(|raw| parse_memory_device(&raw))
+            .collect();
+        modules.sort_by(|a, b| a.locator.cmp(&b.locator));
+        modules
+    }
+
+    /// Parses an SMBIOS memory device structure, `None` for empty slots
+    fn parse_memory_device(raw: &[u8]) -> Option<MemoryModule> {
+        let length = *raw.get(1)? as usize;
+        if raw.first() != Some(&17) || length < 0x15 || raw.len() < length {
+            return None;
+        }
+        let word = |offset: usize| {
+            (offset + 2 <= length).then(|| u16::from_le_bytes([raw[offset], raw[offset + 1]]))
+        };
+
+        let size_bytes = match word(0x0C)? {
+            0 | 0xFFFF => return None,
+            0x7FFF => {
+                let extended = raw.get(0x1C..0x20)?;
+                let mib = u32::from_le_bytes(extended.try_into().ok()?) & 0x7FFF_FFFF;
+                mib as u64 * 1024 * 1024
+            }
+            size if size & 0x8000 != 0 => (size & 0x7FFF) as u64 * 1024,
+            size => size as u64 * 1024 * 1024,
+        };
+
+        // Strings follow the formatted area, numbered from 1
+        let strings: Vec<String> = raw[length..]
+            .split(|byte| *byte == 0)
+            .take_while(|string| !string.is_empty())
+            .map(|string| String::from_utf8_lossy(string).trim().to_string())
+            .collect();
+        let string = |offset: usize| {
+            let index = *raw.get(offset).filter(|_| offset < length)? as usize;
+            let value = strings.get(index.checked_sub(1)?)?;
+            let placeholder = ["", "Not Specified", "Unknown", "NO DIMM", "None"]
+                .iter()
+                .any(|p| value.eq_ignore_ascii_case(p));
+            (!placeholder).then(|| value.clone())
+        };
+
+        Some(MemoryModule {
+            locator: string(0x10).unwrap_or_default(),
+            size_bytes,
+            kind: memory_type(raw[0x12]).map(str::to_string),
+            speed_mts: word(0x15).filter(|speed| *speed != 0).map(u32::from),
+            manufacturer: string(0x17),
+            serial: string(0x18),
+            part_number: string(0x1A),
+        })
+    }
+
+    fn memory_type(code: u8) -> Option<&'static str> {
+        Some(match code {
+            0x07 => "RAM",
+            0x0F => "SDRAM",
+            0x12 => "DDR",
+            0x13 => "DDR2",
+            0x18 => "DDR3",
+            0x1A => "DDR4",
+            0x1B => "LPDDR",
+            0x1C => "LPDDR2",
+            0x1D => "LPDDR3",
+            0x1E => "LPDDR4",
+            0x22 => "DDR5",
+            0x23 => "LPDDR5",
+            _ => return None,
+        })
+    }
+
+    /// Block devices backed by hardware (no loop, RAM or device mapper devices)
+    pub fn disks() -> Vec<DiskInventory> {
+        let Ok(entries) = std::fs::read_dir("/sys/block") else {
+            return vec![];
+        };
+
+        let mut disks: Vec<DiskInventory> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.join("device").exists())
+            .map(|path| {
+                let sectors: u64 = read_trimmed(path.join("size"))
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or(0);
+                DiskInventory {
+                    name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                    model: read_trimmed(path.join("device/model")),
+                    serial: read_trimmed(path.join("device/serial"))
+                        .or_else(|| read_trimmed(path.join("serial"))),
+                    // Always counted in 512 byte sectors
+                    size_bytes: sectors * 512,
+                    rotational: read_trimmed(path.join("queue/rotational"))
+                        .map(|rotational| rotational == "1"),
+                }
+            })
+            .collect();
+        disks.sort_by(|a, b| a.name.cmp(&b.name));
+        disks
+    }
+
+    /// Number of installed packages of the first package manager found
+    pub fn packages() -> Option<PackageInventory> {
+        let package = |manager: &str, count: usize| PackageInventory {
+            manager: manager.to_string(),
+            count: count as u64,
+        };
+
+        if let Ok(status) = std::fs::read_to_string("/var/lib/dpkg/status") {
+            let count = status
+                .lines()
+                .filter(|line| *line == "Status: install ok installed")
+                .count();
+            return Some(package("dpkg", count));
+        }
+
+        if Path::new("/var/lib/rpm").is_dir() {
+            if let Ok(output) = std::process::Command::new("rpm").arg("-qa").output() {
+                if output.status.success() {
+                    let count = output
+                        .stdout
+                        .split(|b| *b == b'\n')
+                        .filter(|l| !l.is_empty());
+                    return Some(package("rpm", count.count()));
+                }
+            }
+        }
+
+        if let Ok(installed) = std::fs::read_to_string("/lib/apk/db/installed") {
+            let count = installed
+                .lines()
+                .filter(|line| line.starts_with("P:"))
+                .count();
+            return Some(package("apk", count));
+        }
+
+        if let Ok(entries) = std::fs::read_dir("/var/lib/pacman/local") {
+            let count = entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .count();
+            return Some(package("pacman", count));
+        }
+
+        None
+    }
+
+    /// Detects the hypervisor from DMI and the container runtime from
+    /// well-known marker files
+    pub fn virtualization() -> Virtualization {
+        Virtualization {
+            hypervisor: hypervisor(),
+            container: container(),
+        }
+    }
+
+    fn hypervisor() -> Option<String> {
+        if read_trimmed("/sys/hypervisor/type").as_deref() == Some("xen") {
+            return Some("xen".to_string());
+        }
+
+        let dmi: Vec<String> = ["sys_vendor", "product_name", "board_vendor", "bios_vendor"]
+            .iter()
+            .filter_map(|name| read_trimmed(Path::new("/sys/class/dmi/id").join(name)))
+            .collect();
+        let dmi = dmi.join(" ");
+        let known = [
+            ("KVM", "kvm"),
+            ("QEMU", "qemu"),
+            ("VMware", "vmware"),
+            ("VirtualBox", "virtualbox"),
+            ("innotek", "virtualbox"),
+            ("Virtual Machine", "hyper-v"),
+            ("Xen", "xen"),
+            ("Amazon EC2", "amazon"),
+            ("Google Compute Engine", "google"),
+            ("Parallels", "parallels"),
+            ("BHYVE", "bhyve"),
+            ("OpenStack", "openstack"),
+        ];
+        if let Some((_, name)) = known.iter().find(|(marker, _)| dmi.contains(marker)) {
+            return Some(name.to_string());
+        }
+
+        // The CPU tells it runs under a hypervisor even if DMI doesn't say which
+        let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+        cpuinfo
+            .lines()
+            .filter(|line| line.starts_with("flags"))
+            .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"))
+            .then(|| "unknown".to_string())
+    }
+
+    fn container() -> Option<String> {
+        if std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+            return Some("kubernetes".to_string());
+        }
+        if Path::new("/.dockerenv").exists() {
+            return Some("docker".to_string());
+        }
+        if Path::new("/run/.containerenv").exists() {
+            return Some("podman".to_string());
+        }
+
+        // Set by systemd-nspawn, LXC and others for the init process (readable by root)
+        if let Ok(environ) = std::fs::read("/proc/1/environ") {
+            let runtime = environ
+                .split(|b| *b == 0)
+                .filter_map(|var| var.strip_prefix(b"container="))
+                .map(|value| String::from_utf8_lossy(value).to_string())
+                .next();
+            if runtime.is_some() {
+                return runtime;
+            }
+        }
+
+        let cgroup = std::fs::read_to_string("/proc/1/cgroup").unwrap_or_default();
+        if cgroup.contains("kubepods") {
+            Some("kubernetes".to_string())
+        } else if cgroup.contains("docker") {
+            Some("docker".to_string())
+        } else if cgroup.contains("lxc") {
+            Some("lxc".to_string())
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{DiskInventory, MemoryModule, PackageInventory, Virtualization};
+    use sysinfo::Disks;
+
+    pub fn cpu_sockets() -> Option<u32> {
+        None
+    }
+
+    pub fn memory_modules() -> Vec<MemoryModule> {
+        vec![]
+    }
+
+    /// Mounted disks, the only ones sysinfo knows about here
+    pub fn disks() -> Vec<DiskInventory> {
+        let disks = Disks::new_with_refreshed_list();
+        let mut disks: Vec<DiskInventory> = disks
+            .iter()
+            .map(|disk| DiskInventory {
+                name: disk.name().to_string_lossy().to_string(),
+                model: None,
+                serial: None,
+                size_bytes: disk.total_space(),
+                rotational: None,
+            })
+            .collect();
+        disks.sort_by(|a, b| a.name.cmp(&b.name));
+        disks
+    }
+
+    pub fn packages() -> Option<PackageInventory> {
+        None
+    }
+
+    pub fn virtualization() -> Virtualization {
+        Virtualization::default()
+    }
+}
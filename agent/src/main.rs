@@ -1,171 +1,190 @@
+mod cli;
 mod client;
 mod collector;
+mod commands;
 mod config;
 mod connect;
+mod inventory;
+mod schedule;
+mod secrets;
+mod services;
+mod spool;
+mod status;
 
 use anyhow::Result;
-use chrono::Utc;
-use client::{AgentRegistration, Heartbeat, ServerClient};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use cli::{Cli, Command};
+use client::{AgentRegistration, Heartbeat, HeartbeatResponse, InventoryReport, ServerClient};
 use collector::MetricsCollector;
-use config::AgentConfig;
-use connect::{ensure_certificates, P2PConnector};
+use config::{AgentConfig, LoadedConfig};
+use connect::connector::TlsFiles;
+use connect::transfer::{ArtifactStore, TransferOptions};
+use connect::uplink::CollectorUplink;
+use connect::{certs, ensure_certificates, relay, renewal, revocation, P2PConnector};
+use inventory::Inventory;
+use schedule::CollectionSchedule;
+use secrets::SecretSource;
+use spool::{MetricsSink, MetricsSpool};
+use status::StatusTracker;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
+use tokio::sync::watch;
 use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run);
+
     // Initialize rustls crypto provider
     let _ = rustls::crypto::ring::default_provider().install_default();
 
-    // Initialize logging
+    // Initialize logging (commands print their own output, so only warnings by default)
+    let default_level = if matches!(command, Command::Run) {
+        "info"
+    } else {
+        "warn"
+    };
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(default_level)),
         )
         .init();
 
+    let config_path = cli.config.unwrap_or_else(AgentConfig::config_path);
+    let load = || AgentConfig::load_layered(&config_path, &cli.overrides);
+    match command {
+        Command::Run => run(load()?, &config_path).await,
+        Command::Init(args) => commands::init::run(&config_path, args),
+        Command::Check => commands::check::run(load()).await,
+        Command::Config => commands::config::run(&load()?),
+        Command::Status { json } => commands::status::run(&load()?.config, json),
+        Command::Certs(command) => {
+            let loaded = load()?;
+            if loaded.generated_agent_id {
+                loaded.config.persist_agent_id(&config_path)?;
+            }
+            commands::certs::run(&loaded.config, command).await
+        }
+        Command::Collect { once, json } => {
+            commands::collect::run(&load()?.config, once, json).await
+        }
+        Command::Inventory => commands::inventory::run().await,
+        Command::Deregister => commands::deregister::run(&load()?.config).await,
+    }
+}
+
+/// Runs the agent until it is stopped
+async fn run(loaded: LoadedConfig, config_path: &Path) -> Result<()> {
     info!("🚀 CSF Agent starting...");
 
-    // Load or create configuration
-    let config = AgentConfig::load().unwrap_or_else(|e| {
-        warn!("Failed to load config: {}. Using defaults.", e);
-        AgentConfig::default()
-    });
+    let config = loaded.config;
+    for source in &loaded.sources {
+        info!("   Config source: {}", source);
+    }
+    for (name, source) in &loaded.secrets {
+        info!("   Secret {} loaded from {}", name, source);
+        if *source == SecretSource::Inline {
+            warn!(
+                "⚠️  {} is set in plain text, move it to a secret file, {} or a systemd credential",
+                name,
+                secrets::API_KEY_ENV
+            );
+        }
+    }
+
+    // Keep a generated agent ID, the backend and peers know the agent by it
+    if loaded.generated_agent_id {
+        match config.persist_agent_id(config_path) {
+            Ok(_) => info!("🆔 Generated agent ID {} saved to {:?}", config.agent_id, config_path),
+            Err(e) => warn!(
+                "⚠️  Could not save generated agent ID to {:?}: {}. Set agent_id or CSF_AGENT_AGENT_ID to keep it across restarts.",
+                config_path, e
+            ),
+        }
+    }
 
-    // Save config if it's new
-    if config.api_key.is_empty() {
+    if config.api_key.is_empty() && config.connects_to_backend() {
         warn!("⚠️  No API key configured. Agent will not be able to connect to server.");
-        warn!("   Please configure the agent by editing: /etc/csf-agent/config.toml");
-        config.save()?;
+        warn!(
+            "   Please configure the agent by editing {:?} or running `csf-agent init`",
+            config_path
+        );
         return Ok(());
     }
 
-    config.save()?;
     info!("📝 Configuration loaded");
     info!("   Agent ID: {}", config.agent_id);
     info!("   Name: {}", config.name);
-    if !config.p2p_only_mode {
+    if config.connects_to_backend() {
         info!("   Server: {}", config.server_url);
+    } else if let Some(collector) = &config.p2p.collector {
+        info!("   Mode: Reporting through collector proxy {}", collector);
     } else {
         info!("   Mode: P2P Only (no backend connection)");
     }
 
-    // Initialize P2P if enabled
-    let p2p_connector = if config.p2p.enabled {
-        info!("🔐 P2P connections enabled");
+    // Initialize components
+    let client = ServerClient::new(&config);
+    let mut collector = MetricsCollector::new();
+    let status = StatusTracker::new(&config);
+    let inventory = tokio::task::spawn_blocking(inventory::collect).await?;
 
-        // Get certificate directory
-        let cert_dir = if let Some(parent) = std::path::Path::new(&config.p2p.cert_path).parent() {
-            parent.to_path_buf()
-        } else {
-            std::path::PathBuf::from(if cfg!(target_os = "windows") {
-                "C:\\ProgramData\\csf-agent\\certs"
-            } else {
-                "/etc/csf-agent/certs"
-            })
-        };
+    // Inventory the backend has, the changes are sent later on
+    let mut inventory_sent = None;
 
-        // Ensure certificates exist
-        match ensure_certificates(&config.name, &cert_dir, config.p2p.auto_generate_certs) {
-            Ok(_) => info!("✅ Certificates ready"),
-            Err(e) => {
-                error!("❌ Failed to setup certificates: {}", e);
-                return Err(e);
-            }
-        }
+    // Periodic heartbeats, stopped before the final one on shutdown
+    let mut heartbeat_task = None;
 
-        // Create P2P connector
-        match P2PConnector::new(
-            config.agent_id,
-            config.name.clone(),
-            config.p2p.listen_port,
-            std::path::Path::new(&config.p2p.cert_path),
-            std::path::Path::new(&config.p2p.key_path),
-            std::path::Path::new(&config.p2p.ca_cert_path),
-        ) {
-            Ok(connector) => {
-                info!(
-                    "✅ P2P connector initialized on port {}",
-                    config.p2p.listen_port
-                );
-                Some(connector)
-            }
-            Err(e) => {
-                error!("❌ Failed to create P2P connector: {}", e);
-                return Err(e);
-            }
-        }
-    } else {
-        info!("ℹ️  P2P connections disabled");
-        None
-    };
+    // Burst mode asked for by the backend in heartbeat responses
+    let (burst_tx, mut burst_rx) = watch::channel(None);
 
-    // Start P2P server if enabled
-    if let Some(ref connector) = p2p_connector {
-        let connector_clone = connector.clone();
-        tokio::spawn(async move {
-            if let Err(e) = connector_clone.start_server().await {
-                error!("❌ P2P server error: {}", e);
-            }
-        });
+    // Started right away, or once the fleet CA issued a certificate
+    let p2p = Arc::new(OnceLock::new());
 
-        // Connect to configured peers
-        for peer in &config.p2p.peers {
-            let connector_clone = connector.clone();
-            let peer_addr = peer.clone();
-            tokio::spawn(async move {
-                info!("🔗 Connecting to peer: {}", peer_addr);
-                match connector_clone.connect_to_peer(&peer_addr).await {
-                    Ok(mut stream) => {
-                        info!("✅ Connected to peer: {}", peer_addr);
-
-                        // Keep connection alive with heartbeats
-                        let mut interval = tokio::time::interval(Duration::from_secs(30));
-                        loop {
-                            interval.tick().await;
-
-                            if let Err(e) = connector_clone.send_heartbeat(&mut stream).await {
-                                error!(
-                                    "❌ Heartbeat to {} failed: {}. Reconnecting...",
-                                    peer_addr, e
-                                );
-                                break;
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("❌ Failed to connect to peer {}: {}", peer_addr, e);
-                    }
-                }
-            });
-        }
-    }
+    // Certificates of the fleet CA are only issued through the backend
+    let fleet_ca = config.p2p.enabled && config.p2p.use_backend_ca && config.connects_to_backend();
 
-    // Initialize components
-    let client = ServerClient::new(&config);
-    let mut collector = MetricsCollector::new();
-
-    // Register with server (skip if P2P only mode)
-    if !config.p2p_only_mode {
+    // Register with server (skip if P2P only mode or behind a collector proxy)
+    if config.connects_to_backend() {
         info!("📡 Registering with server...");
-        let registration = AgentRegistration {
-            agent_id: config.agent_id,
-            name: config.name.clone(),
-            hostname: hostname::get()
-                .ok()
-                .and_then(|h| h.into_string().ok())
-                .unwrap_or_else(|| "unknown".to_string()),
-            os_type: std::env::consts::OS.to_string(),
-            os_version: sysinfo::System::os_version().unwrap_or_else(|| "Unknown".to_string()),
-            architecture: std::env::consts::ARCH.to_string(),
-            agent_version: env!("CARGO_PKG_VERSION").to_string(),
-            tags: config.tags.clone(),
+
+        // Trust the current fleet CAs (picks up rotations) before checking our certificate
+        let cert_dir = config.p2p.cert_dir();
+        if fleet_ca {
+            renewal::refresh_ca_bundle(&config, &client).await;
+        }
+
+        // Ask the fleet CA for a certificate unless it issued the one we have
+        let csr = if fleet_ca {
+            renewal::fleet_csr(&config).unwrap_or_else(|e| {
+                error!("❌ Failed to create certificate signing request: {}", e);
+                None
+            })
+        } else {
+            None
         };
 
-        match client.register(&registration).await {
+        let registration = agent_registration(&config, csr, Some(inventory.clone()));
+
+        let result = client.register(&registration).await;
+        status.record_registration(&result);
+        match result {
             Ok(response) => {
                 info!("✅ Registration successful: {}", response.message);
+                inventory_sent = registration.inventory;
+
+                if let (Some(certificate), Some(ca_bundle)) =
+                    (&response.certificate, &response.ca_bundle)
+                {
+                    match certs::install_issued_certificate(&cert_dir, certificate, ca_bundle) {
+                        Ok(_) => info!("🔏 Received certificate from fleet CA"),
+                        Err(e) => error!("❌ Failed to install issued certificate: {}", e),
+                    }
+                }
             }
             Err(e) => {
                 error!("❌ Registration failed: {}", e);
@@ -173,57 +192,626 @@ async fn main() -> Result<()> {
             }
         }
 
+        // Without a certificate of the fleet CA, P2P waits for it and the
+        // heartbeats keep asking for one
+        let mut enrollment = (fleet_ca && !renewal::has_fleet_certificate(&config)).then(|| {
+            warn!("⚠️  No certificate from the fleet CA yet, P2P starts once it is issued");
+            config.clone()
+        });
+
         // Spawn heartbeat task
         let heartbeat_client = client.clone();
         let heartbeat_agent_id = config.agent_id;
         let heartbeat_interval = config.heartbeat_interval;
-        tokio::spawn(async move {
+        let heartbeat_cert_path = heartbeat_cert_path(&config);
+        let heartbeat_services = config.watched_services.clone();
+        let heartbeat_status = status.clone();
+        let heartbeat_burst = burst_tx.clone();
+        let heartbeat_p2p = p2p.clone();
+        heartbeat_task = Some(tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(heartbeat_interval));
             loop {
                 interval.tick().await;
 
-                let heartbeat = Heartbeat {
-                    agent_id: heartbeat_agent_id,
-                    timestamp: Utc::now(),
-                    status: "online".to_string(),
-                };
+                if let Some(config) = &enrollment {
+                    match renewal::ensure_fleet_certificate(config, &heartbeat_client).await {
+                        Ok(_) => {
+                            info!("🔏 Received certificate from fleet CA, starting P2P");
+                            match start_p2p(config, &heartbeat_client, &heartbeat_status) {
+                                Ok(connector) => {
+                                    let _ = heartbeat_p2p.set(connector);
+                                }
+                                Err(e) => error!("❌ Failed to start P2P: {}", e),
+                            }
+                            enrollment = None;
+                        }
+                        Err(e) => warn!(
+                            "⚠️  Still no certificate from the fleet CA, retrying with the next heartbeat: {}",
+                            e
+                        ),
+                    }
+                }
 
-                if let Err(e) = heartbeat_client.send_heartbeat(&heartbeat).await {
-                    error!("Failed to send heartbeat: {}", e);
-                } else {
-                    info!("💓 Heartbeat sent");
+                let heartbeat = agent_heartbeat(
+                    heartbeat_agent_id,
+                    heartbeat_cert_path.as_deref(),
+                    &heartbeat_services,
+                );
+
+                let result = heartbeat_client.send_heartbeat(&heartbeat).await;
+                heartbeat_status.record_heartbeat(&result);
+                match result {
+                    Ok(response) => {
+                        info!("💓 Heartbeat sent");
+                        forward_burst(&heartbeat_burst, &response);
+                    }
+                    Err(e) => error!("Failed to send heartbeat: {}", e),
                 }
             }
-        });
+        }));
+    } else if config.p2p.collector.is_some() {
+        info!("ℹ️  Backend reached through collector proxy");
     } else {
         info!("ℹ️  Backend connection disabled (P2P only mode)");
     }
 
+    // Initialize P2P if enabled
+    if !config.p2p.enabled {
+        info!("ℹ️  P2P connections disabled");
+    } else if !fleet_ca || renewal::has_fleet_certificate(&config) {
+        let connector = start_p2p(&config, &client, &status)?;
+        let _ = p2p.set(connector);
+    }
+
+    // Report through the collector proxy if the backend isn't reachable directly
+    let uplink = match (&config.p2p.collector, p2p.get()) {
+        (Some(collector), Some(connector)) => {
+            let uplink = CollectorUplink::new(connector.clone(), collector.clone());
+            heartbeat_task = Some(spawn_uplink_heartbeats(
+                &config,
+                connector.clone(),
+                uplink.clone(),
+                status.clone(),
+                burst_tx.clone(),
+                inventory.clone(),
+            ));
+            // Sent with the registration through the collector proxy
+            inventory_sent = Some(inventory.clone());
+            Some(uplink)
+        }
+        (Some(_), None) => {
+            warn!("⚠️  A collector proxy is configured but P2P is disabled");
+            None
+        }
+        _ => None,
+    };
+
+    // Metrics that can't be sent are kept and delivered once the backend is reachable
+    let sink = match &uplink {
+        Some(uplink) => Some(MetricsSink::Uplink(Box::new(uplink.clone()))),
+        None if config.connects_to_backend() => Some(MetricsSink::Backend(client.clone())),
+        None => None,
+    };
+    if let Some(sink) = &sink {
+        if config.inventory_interval > 0 {
+            spawn_inventory_task(&config, sink.clone(), inventory_sent);
+        }
+    }
+
+    let mut spool = MetricsSpool::load(
+        Path::new(&config.metrics_spool_path),
+        config.metrics_spool_size,
+    );
+
     // Main metrics collection loop
     info!("📊 Starting metrics collection...");
-    let mut interval = tokio::time::interval(Duration::from_secs(config.collection_interval));
+    let mut schedule = CollectionSchedule::new(&config);
+    let shutdown_signal = shutdown_signal();
+    tokio::pin!(shutdown_signal);
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = tokio::time::sleep_until(schedule.next_due()) => {}
+            Ok(()) = burst_rx.changed() => {
+                schedule.apply_requested_burst(*burst_rx.borrow_and_update());
+                continue;
+            }
+            signal = &mut shutdown_signal => {
+                info!("🛑 Received {}, shutting down...", signal);
+                break;
+            }
+        }
+
+        // Collect the metric groups that are due
+        let groups = schedule.due(tokio::time::Instant::now());
+        if groups.is_empty() {
+            continue;
+        }
+        let metrics = collector.collect(config.agent_id, groups);
+        schedule.check_thresholds(&metrics);
+
+        info!("📈 Metrics - {}", metrics.summary());
+
+        // Send to server (skip if P2P only mode)
+        let Some(sink) = &sink else {
+            // Nothing is sent, but `csf-agent status` should still see the agent is alive
+            status.touch();
+            continue;
+        };
+
+        let spooled = spool.len();
+        if spooled > 0 {
+            let sent = spool.flush(sink).await;
+            if sent > 0 {
+                info!(
+                    "📤 Sent {} of {} spooled metrics to {}",
+                    sent, spooled, sink
+                );
+            }
+        }
+
+        let result = sink.send(&metrics).await;
+        status.record_metrics(&result);
+        match result {
+            Ok(_) => info!("✅ Metrics sent to {}", sink),
+            Err(e) => {
+                error!("❌ Failed to send metrics to {}: {}", sink, e);
+                spool.push(metrics);
+            }
+        }
+
+        if spool.len() != spooled {
+            if let Err(e) = spool.persist() {
+                warn!("⚠️  Could not save the metrics spool: {}", e);
+            }
+        }
+    }
+
+    // Don't let a periodic heartbeat overtake the final one
+    if let Some(task) = heartbeat_task {
+        task.abort();
+    }
 
-        // Collect metrics
-        let metrics = collector.collect(config.agent_id);
+    let stopping = shutdown(
+        &config,
+        &client,
+        uplink.as_ref(),
+        sink.as_ref(),
+        &mut spool,
+        p2p.get(),
+        &status,
+    );
+    if tokio::time::timeout(Duration::from_secs(config.shutdown_timeout), stopping)
+        .await
+        .is_err()
+    {
+        warn!(
+            "⚠️  Shutdown did not finish within {}s",
+            config.shutdown_timeout
+        );
+    }
 
+    if let Err(e) = spool.persist() {
+        warn!("⚠️  Could not save the metrics spool: {}", e);
+    } else if !spool.is_empty() {
         info!(
-            "📈 Metrics - CPU: {:.1}% | RAM: {:.1}% | Disk: {:.1}%",
-            metrics.cpu_usage_percent, metrics.memory_usage_percent, metrics.disk_usage_percent
+            "💾 {} unsent metrics saved to {}",
+            spool.len(),
+            config.metrics_spool_path
         );
+    }
 
-        // Send to server (skip if P2P only mode)
-        if !config.p2p_only_mode {
-            match client.send_metrics(&metrics).await {
-                Ok(_) => {
-                    info!("✅ Metrics sent to server");
+    info!("👋 CSF Agent stopped");
+    Ok(())
+}
+
+/// Resolves with the name of the signal once the agent is asked to stop
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = terminate.recv() => "SIGTERM",
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+            },
+            Err(e) => {
+                warn!("⚠️  Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl+C"
+    }
+}
+
+/// Delivers spooled metrics, tells the backend the agent is stopping on
+/// purpose and says goodbye to peers
+///
+/// The heartbeat goes out before the P2P sessions close, an uplink needs them.
+async fn shutdown(
+    config: &AgentConfig,
+    client: &ServerClient,
+    uplink: Option<&CollectorUplink>,
+    sink: Option<&MetricsSink>,
+    spool: &mut MetricsSpool,
+    connector: Option<&P2PConnector>,
+    status: &StatusTracker,
+) {
+    if let Some(sink) = sink {
+        if !spool.is_empty() {
+            let spooled = spool.len();
+            let sent = spool.flush(sink).await;
+            info!(
+                "📤 Sent {} of {} spooled metrics to {}",
+                sent, spooled, sink
+            );
+        }
+    }
+
+    let mut heartbeat = agent_heartbeat(
+        config.agent_id,
+        heartbeat_cert_path(config).as_deref(),
+        &config.watched_services,
+    );
+    heartbeat.status = "stopping".to_string();
+    let result = match uplink {
+        Some(uplink) => Some(uplink.send_heartbeat(&heartbeat).await),
+        None if config.connects_to_backend() => Some(client.send_heartbeat(&heartbeat).await),
+        None => None,
+    };
+    if let Some(result) = result {
+        status.record_heartbeat(&result);
+        match result {
+            Ok(_) => info!("💓 Sent final heartbeat"),
+            Err(e) => warn!("⚠️  Failed to send final heartbeat: {}", e),
+        }
+    }
+
+    if let Some(connector) = connector {
+        connector.shutdown("agent stopping").await;
+    }
+}
+
+/// Sets up certificates, starts the P2P server and connects to peers
+fn start_p2p(
+    config: &AgentConfig,
+    client: &ServerClient,
+    status: &StatusTracker,
+) -> Result<P2PConnector> {
+    info!("🔐 P2P connections enabled");
+
+    // Ensure certificates exist
+    let certificates = if config.p2p.use_backend_ca && config.connects_to_backend() {
+        if renewal::has_fleet_certificate(config) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "No certificate has been issued by the fleet CA yet (registration required)"
+            ))
+        }
+    } else if renewal::renews_through_collector(config) {
+        // The collector proxy only accepts certificates of the fleet CA
+        if renewal::has_fleet_certificate(config) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "No certificate has been issued by the fleet CA yet, request one with \
+                 `csf-agent certs request` from a host that reaches the backend"
+            ))
+        }
+    } else {
+        ensure_certificates(
+            &config.name,
+            &config.agent_id,
+            &config.p2p.subject_alt_names(),
+            &config.p2p.cert_dir(),
+            config.p2p.auto_generate_certs,
+            config.p2p.key_passphrase(),
+        )
+    };
+
+    match certificates {
+        Ok(_) => info!("✅ Certificates ready"),
+        Err(e) => {
+            error!("❌ Failed to setup certificates: {}", e);
+            return Err(e);
+        }
+    }
+
+    // Create P2P connector
+    let connector = match P2PConnector::new(
+        config.agent_id,
+        config.name.clone(),
+        config.p2p.listen_port,
+        TlsFiles::from_config(&config.p2p),
+    ) {
+        Ok(connector) => {
+            let connector = connector
+                .with_protocol(config.p2p.protocol_options())
+                .with_relay_enabled(config.p2p.relay_enabled);
+            let connector = if config.p2p.collector_proxy && config.connects_to_backend() {
+                info!("📮 Forwarding data of isolated peers to the backend");
+                connector.with_collector_proxy(client.clone())
+            } else {
+                connector
+            };
+            let connector = if config.p2p.serve_artifacts || config.p2p.auto_fetch_artifacts {
+                info!(
+                    "📦 Artifact transfer directory: {}",
+                    config.p2p.transfer_dir
+                );
+                connector.with_artifacts(
+                    ArtifactStore::new(config.p2p.transfer_dir.clone().into()),
+                    TransferOptions {
+                        serve: config.p2p.serve_artifacts,
+                        auto_fetch: config.p2p.auto_fetch_artifacts,
+                    },
+                    config.p2p.offered_artifacts.clone(),
+                )
+            } else {
+                connector
+            };
+            info!(
+                "✅ P2P connector initialized on port {}",
+                config.p2p.listen_port
+            );
+            revocation::apply_cached_deny_list(config, &connector);
+            connector
+        }
+        Err(e) => {
+            error!("❌ Failed to create P2P connector: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Start P2P server
+    let connector_clone = connector.clone();
+    tokio::spawn(async move {
+        if let Err(e) = connector_clone.start_server().await {
+            error!("❌ P2P server error: {}", e);
+        }
+    });
+
+    // Keep the agent certificate renewed
+    renewal::spawn_renewal_task(config.clone(), client.clone(), connector.clone());
+
+    // Keep CRLs and the deny list up to date
+    if config.connects_to_backend() {
+        revocation::spawn_revocation_task(config.clone(), client.clone(), connector.clone());
+    }
+
+    // Stay reachable through relays and reach peers behind NAT
+    if config.p2p.relay_enabled {
+        info!("🔀 Relaying messages between peers");
+    }
+    relay::spawn_relay_links(connector.clone(), config.p2p.relays.clone());
+    for peer_id in &config.p2p.relayed_peers {
+        relay::spawn_relayed_peer(connector.clone(), *peer_id);
+    }
+
+    // Connect to configured peers
+    for peer in &config.p2p.peers {
+        let connector_clone = connector.clone();
+        let peer_addr = peer.clone();
+        let peer_status = status.clone();
+        tokio::spawn(async move {
+            info!("🔗 Connecting to peer: {}", peer_addr);
+            match connector_clone.connect_to_peer(&peer_addr).await {
+                Ok(session) => {
+                    info!("✅ Connected to peer: {}", peer_addr);
+                    peer_status.peer_connected(&peer_addr, session.peer_id());
+
+                    // Keep connection alive with heartbeats
+                    let mut interval = tokio::time::interval(Duration::from_secs(30));
+                    loop {
+                        interval.tick().await;
+
+                        let result = connector_clone.send_heartbeat(&session).await;
+                        peer_status.peer_heartbeat(&peer_addr, &result);
+                        if let Err(e) = result {
+                            error!(
+                                "❌ Heartbeat to {} failed: {}. Reconnecting...",
+                                peer_addr, e
+                            );
+                            peer_status.peer_disconnected(&peer_addr, &e);
+                            break;
+                        }
+                    }
                 }
                 Err(e) => {
-                    error!("❌ Failed to send metrics: {}", e);
+                    error!("❌ Failed to connect to peer {}: {}", peer_addr, e);
+                    peer_status.peer_disconnected(&peer_addr, &e);
                 }
             }
-        }
+        });
     }
+
+    Ok(connector)
+}
+
+/// Registration describing this agent
+fn agent_registration(
+    config: &AgentConfig,
+    csr: Option<String>,
+    inventory: Option<Inventory>,
+) -> AgentRegistration {
+    AgentRegistration {
+        agent_id: config.agent_id,
+        name: config.name.clone(),
+        hostname: hostname::get()
+            .ok()
+            .and_then(|h| h.into_string().ok())
+            .unwrap_or_else(|| "unknown".to_string()),
+        os_type: std::env::consts::OS.to_string(),
+        os_version: sysinfo::System::os_version().unwrap_or_else(|| "Unknown".to_string()),
+        architecture: std::env::consts::ARCH.to_string(),
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        tags: config.tags.clone(),
+        csr,
+        relayed_by: None,
+        inventory,
+    }
+}
+
+/// Certificate whose expiry is reported in heartbeats
+fn heartbeat_cert_path(config: &AgentConfig) -> Option<std::path::PathBuf> {
+    config
+        .p2p
+        .enabled
+        .then(|| std::path::PathBuf::from(&config.p2p.cert_path))
+}
+
+fn agent_heartbeat(
+    agent_id: uuid::Uuid,
+    cert_path: Option<&std::path::Path>,
+    watched_services: &[String],
+) -> Heartbeat {
+    Heartbeat {
+        agent_id,
+        timestamp: Utc::now(),
+        status: "online".to_string(),
+        cert_not_after: cert_path.and_then(|path| certs::certificate_not_after(path).ok()),
+        relayed_by: None,
+        services: services::check(watched_services),
+    }
+}
+
+/// Registers through the collector proxy, then keeps sending heartbeats
+fn spawn_uplink_heartbeats(
+    config: &AgentConfig,
+    connector: P2PConnector,
+    uplink: CollectorUplink,
+    status: StatusTracker,
+    burst: watch::Sender<Option<DateTime<Utc>>>,
+    inventory: Inventory,
+) -> tokio::task::JoinHandle<()> {
+    let config = config.clone();
+    let registration = agent_registration(&config, None, Some(inventory));
+    let agent_id = config.agent_id;
+    let heartbeat_interval = config.heartbeat_interval;
+    let cert_path = heartbeat_cert_path(&config);
+    let watched_services = config.watched_services.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(heartbeat_interval));
+        let mut registered = false;
+        loop {
+            interval.tick().await;
+
+            // The fleet CA renews the certificate with a registration carrying a CSR
+            let renew = renewal::renews_through_collector(&config) && renewal::renewal_due(&config);
+            if !registered || renew {
+                let mut registration = registration.clone();
+                if renew {
+                    match renewal::agent_csr(&config) {
+                        Ok(csr) => registration.csr = Some(csr),
+                        Err(e) => error!("❌ Failed to create certificate signing request: {}", e),
+                    }
+                }
+
+                let result = uplink.register(&registration).await;
+                status.record_registration(&result);
+                match result {
+                    Ok(response) => {
+                        info!(
+                            "✅ Registration through collector proxy successful: {}",
+                            response.message
+                        );
+                        registered = true;
+
+                        if let (Some(certificate), Some(ca_bundle)) =
+                            (&response.certificate, &response.ca_bundle)
+                        {
+                            let installed = certs::install_issued_certificate(
+                                &config.p2p.cert_dir(),
+                                certificate,
+                                ca_bundle,
+                            )
+                            .and_then(|_| connector.reload_tls());
+                            match installed {
+                                Ok(_) => info!("🔏 Certificate renewed through collector proxy"),
+                                Err(e) => error!("❌ Failed to install renewed certificate: {}", e),
+                            }
+                        }
+                    }
+                    Err(e) if registered => {
+                        error!(
+                            "❌ Certificate renewal through collector proxy failed: {}",
+                            e
+                        )
+                    }
+                    Err(e) => {
+                        error!("❌ Registration through collector proxy failed: {}", e);
+                        continue;
+                    }
+                }
+            }
+
+            let heartbeat = agent_heartbeat(agent_id, cert_path.as_deref(), &watched_services);
+            let result = uplink.send_heartbeat(&heartbeat).await;
+            status.record_heartbeat(&result);
+            match result {
+                Ok(response) => {
+                    info!("💓 Heartbeat sent through collector proxy");
+                    forward_burst(&burst, &response);
+                }
+                Err(e) => error!("Failed to send heartbeat through collector proxy: {}", e),
+            }
+        }
+    })
+}
+
+/// Passes a burst mode request from the backend on to the collection loop
+fn forward_burst(burst: &watch::Sender<Option<DateTime<Utc>>>, response: &HeartbeatResponse) {
+    burst.send_if_modified(|burst_until| {
+        let changed = *burst_until != response.burst_until;
+        *burst_until = response.burst_until;
+        changed
+    });
+}
+
+/// Checks the inventory for changes and sends it when it differs from what
+/// the backend has (or it doesn't have one yet)
+fn spawn_inventory_task(config: &AgentConfig, sink: MetricsSink, mut sent: Option<Inventory>) {
+    let agent_id = config.agent_id;
+    let period = Duration::from_secs(config.inventory_interval);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+
+            let inventory = match tokio::task::spawn_blocking(inventory::collect).await {
+                Ok(inventory) => inventory,
+                Err(e) => {
+                    error!("❌ Failed to collect the inventory: {}", e);
+                    continue;
+                }
+            };
+            if sent.as_ref() == Some(&inventory) {
+                continue;
+            }
+
+            let report = InventoryReport {
+                agent_id,
+                collected_at: Utc::now(),
+                inventory,
+                relayed_by: None,
+            };
+            match sink.send_inventory(&report).await {
+                Ok(_) => {
+                    info!("🗂️  Inventory sent to {}", sink);
+                    sent = Some(report.inventory);
+                }
+                Err(e) => warn!("⚠️  Failed to send the inventory to {}: {}", sink, e),
+            }
+        }
+    });
 }
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::collector::{MetricGroups, SystemMetrics};
+use crate::config::AgentConfig;
+
+/// When a metric group is collected next
+struct GroupTimer {
+    interval: Duration,
+    next: Instant,
+}
+
+impl GroupTimer {
+    /// Whether the group is due at `now`, scheduling the next collection if so
+    fn poll(&mut self, now: Instant, max_interval: Option<Duration>) -> bool {
+        if self.next > now {
+            return false;
+        }
+
+        let interval = max_interval.map_or(self.interval, |max| self.interval.min(max));
+        // Keep a fixed rate so groups with related intervals stay in the same sample
+        self.next += interval;
+        // Don't try to catch up on collections that were missed
+        if self.next <= now {
+            self.next = now + interval;
+        }
+        true
+    }
+}
+
+/// Decides which metric groups are due and when to collect next
+///
+/// In burst mode no group is collected less often than `burst_interval`.
+/// Burst mode starts when a sample crosses a usage threshold, lasting
+/// `burst_duration` after the last crossing, or when the backend asks for it.
+pub struct CollectionSchedule {
+    cpu: GroupTimer,
+    memory: GroupTimer,
+    disk: GroupTimer,
+    network: GroupTimer,
+    burst_interval: Duration,
+    burst_duration: Duration,
+    burst_cpu_percent: f32,
+    burst_memory_percent: f32,
+    burst_disk_percent: f32,
+    burst_until: Option<Instant>,
+    /// The current burst was asked for by the backend, so it may cancel it
+    burst_requested: bool,
+}
+
+impl CollectionSchedule {
+    /// Schedule with every group due right away
+    pub fn new(config: &AgentConfig) -> Self {
+        let now = Instant::now();
+        let collection = &config.collection;
+        let timer = |interval: Option<u64>| GroupTimer {
+            interval: Duration::from_secs(interval.unwrap_or(config.collection_interval).max(1)),
+            next: now,
+        };
+
+        Self {
+            cpu: timer(collection.cpu_interval),
+            memory: timer(collection.memory_interval),
+            disk: timer(collection.disk_interval),
+            network: timer(collection.network_interval),
+            burst_interval: Duration::from_secs(collection.burst_interval.max(1)),
+            burst_duration: Duration::from_secs(collection.burst_duration),
+            burst_cpu_percent: collection.burst_cpu_percent,
+            burst_memory_percent: collection.burst_memory_percent,
+            burst_disk_percent: collection.burst_disk_percent,
+            burst_until: None,
+            burst_requested: false,
+        }
+    }
+
+    /// When the next group is due
+    pub fn next_due(&self) -> Instant {
+        self.cpu
+            .next
+            .min(self.memory.next)
+            .min(self.disk.next)
+            .min(self.network.next)
+    }
+
+    /// Groups due at `now`, scheduling their next collection
+    pub fn due(&mut self, now: Instant) -> MetricGroups {
+        if self.burst_until.is_some_and(|until| until <= now) {
+            info!("📉 Burst mode ended");
+            self.burst_until = None;
+            self.burst_requested = false;
+        }
+
+        let max_interval = self.burst_until.map(|_| self.burst_interval);
+        MetricGroups {
+            cpu: self.cpu.poll(now, max_interval),
+            memory: self.memory.poll(now, max_interval),
+            disk: self.disk.poll(now, max_interval),
+            network: self.network.poll(now, max_interval),
+        }
+    }
+
+    /// Starts or extends burst mode if a usage threshold was crossed
+    pub fn check_thresholds(&mut self, metrics: &SystemMetrics) {
+        let crossed = [
+            ("CPU", metrics.cpu_usage_percent, self.burst_cpu_percent),
+            (
+                "Memory",
+                metrics.memory_usage_percent,
+                self.burst_memory_percent,
+            ),
+            ("Disk", metrics.disk_usage_percent, self.burst_disk_percent),
+        ]
+        .into_iter()
+        .find(|(_, usage, threshold)| *threshold > 0.0 && usage.is_some_and(|u| u >= *threshold));
+
+        if let Some((name, Some(usage), threshold)) = crossed {
+            self.start_burst(
+                Instant::now() + self.burst_duration,
+                &format!("{} usage {:.1}% reached {:.1}%", name, usage, threshold),
+                false,
+            );
+        }
+    }
+
+    /// Applies the burst mode the backend asked for with the last heartbeat
+    pub fn apply_requested_burst(&mut self, burst_until: Option<DateTime<Utc>>) {
+        let remaining = burst_until.and_then(|until| (until - Utc::now()).to_std().ok());
+        match remaining {
+            Some(remaining) => {
+                self.start_burst(Instant::now() + remaining, "requested by the backend", true)
+            }
+            None if self.burst_requested => {
+                info!("📉 Burst mode cancelled by the backend");
+                self.burst_until = None;
+                self.burst_requested = false;
+            }
+            None => {}
+        }
+    }
+
+    fn start_burst(&mut self, until: Instant, reason: &str, requested: bool) {
+        if self.burst_until.is_some_and(|current| current >= until) {
+            return;
+        }
+        if self.burst_until.is_none() {
+            info!(
+                "📈 Burst mode for {}s: {}",
+                until.saturating_duration_since(Instant::now()).as_secs(),
+                reason
+            );
+
+            // Don't wait for the regular intervals to run out, and collect all
+            // groups together while bursting
+            let next = self.next_due().min(Instant::now() + self.burst_interval);
+            for timer in [
+                &mut self.cpu,
+                &mut self.memory,
+                &mut self.disk,
+                &mut self.network,
+            ] {
+                timer.next = next;
+            }
+        }
+        self.burst_until = Some(until);
+        self.burst_requested = requested;
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Environment variable holding the API key
+pub const API_KEY_ENV: &str = "CSF_AGENT_API_KEY";
+
+/// Environment variable holding the passphrase of the agent's private keys
+pub const KEY_PASSPHRASE_ENV: &str = "CSF_AGENT_KEY_PASSPHRASE";
+
+/// Credential name of the API key in the systemd credentials directory
+pub const API_KEY_CREDENTIAL: &str = "api_key";
+
+/// Credential name of the private key passphrase in the systemd credentials directory
+pub const KEY_PASSPHRASE_CREDENTIAL: &str = "key_passphrase";
+
+/// Directory systemd passes credentials in (`LoadCredential=`, `SetCredential=`)
+const CREDENTIALS_DIRECTORY_ENV: &str = "CREDENTIALS_DIRECTORY";
+
+/// A secret value, wiped from memory on drop and never printed
+#[derive(Clone)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(Zeroizing::new(value))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// Where a secret was loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    Environment(&'static str),
+    Credential(PathBuf),
+    File(PathBuf),
+    /// Given directly in the config file or on the command line
+    Inline,
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Environment(name) => write!(f, "environment {}", name),
+            Self::Credential(path) => write!(f, "systemd credential {}", path.display()),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Inline => write!(f, "config"),
+        }
+    }
+}
+
+/// Resolves a secret from the first source that provides it:
+///
+/// 1. the environment variable `env`
+/// 2. the systemd credential `credential` in `$CREDENTIALS_DIRECTORY`
+/// 3. the secret file at `file`
+/// 4. the `inline` value
+pub fn resolve(
+    env: &'static str,
+    credential: &str,
+    file: Option<&str>,
+    inline: Option<&str>,
+) -> Result<Option<(Secret, SecretSource)>> {
+    if let Ok(value) = std::env::var(env) {
+        if !value.is_empty() {
+            return Ok(Some((Secret::new(value), SecretSource::Environment(env))));
+        }
+    }
+
+    if let Some(dir) = std::env::var_os(CREDENTIALS_DIRECTORY_ENV) {
+        let path = Path::new(&dir).join(credential);
+        if path.exists() {
+            let secret = read_secret_file(&path)?;
+            return Ok(Some((secret, SecretSource::Credential(path))));
+        }
+    }
+
+    if let Some(file) = file {
+        let path = PathBuf::from(file);
+        let secret = read_secret_file(&path)?;
+        return Ok(Some((secret, SecretSource::File(path))));
+    }
+
+    Ok(inline
+        .filter(|value| !value.is_empty())
+        .map(|value| (Secret::new(value.to_string()), SecretSource::Inline)))
+}
+
+/// Reads a secret from a file only its owner can access
+///
+/// Trailing line breaks are removed so the file can be written with `echo`.
+pub fn read_secret_file(path: &Path) -> Result<Secret> {
+    check_permissions(path)?;
+
+    let content = Zeroizing::new(
+        std::fs::read_to_string(path).context(format!("Failed to read secret file {:?}", path))?,
+    );
+    let value = content.trim_end_matches(['\r', '\n']);
+    if value.is_empty() {
+        anyhow::bail!("Secret file {:?} is empty", path);
+    }
+
+    Ok(Secret::new(value.to_string()))
+}
+
+/// Writes a secret to a file only its owner can access
+pub fn write_secret_file(path: &Path, value: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_private_file(path, format!("{}\n", value).as_bytes())
+        .context(format!("Failed to write secret file {:?}", path))
+}
+
+/// Writes a file readable only by its owner, without a window where others could open it
+pub fn write_private_file(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    // The mode only applies to new files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)
+}
+
+/// Rejects secret files that group members or other users can access
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata =
+        std::fs::metadata(path).context(format!("Failed to open secret file {:?}", path))?;
+    let mode = metadata.permissions().mode() & 0o777;
+    if mode & 0o077 != 0 {
+        anyhow::bail!(
+            "Secret file {:?} is accessible by other users (mode {:o}), restrict it with `chmod 600`",
+            path,
+            mode
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// State of a watched service as reported with the heartbeat
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceState {
+    pub name: String,
+    /// systemd active state, e.g. `active`, `inactive` or `failed`
+    /// (`unknown` where it can't be checked)
+    pub state: String,
+}
+
+/// Current states of the given services
+pub fn check(names: &[String]) -> Vec<ServiceState> {
+    if names.is_empty() {
+        return Vec::new();
+    }
+
+    let states = platform::active_states(names);
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| ServiceState {
+            name: name.clone(),
+            state: states
+                .as_ref()
+                .and_then(|states| states.get(i).cloned())
+                .unwrap_or_else(|| "unknown".to_string()),
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    /// Active state of each unit, in order, from a single `systemctl is-active` call
+    pub fn active_states(names: &[String]) -> Option<Vec<String>> {
+        // Exits non-zero when any unit isn't active, the states are printed regardless
+        let output = std::process::Command::new("systemctl")
+            .arg("is-active")
+            .arg("--")
+            .args(names)
+            .output()
+            .ok()?;
+
+        let states: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        (states.len() == names.len()).then_some(states)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    pub fn active_states(_names: &[String]) -> Option<Vec<String>> {
+        None
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use crate::client::{InventoryReport, ServerClient};
+use crate::collector::SystemMetrics;
+use crate::connect::uplink::CollectorUplink;
+
+/// Where metrics and inventory changes are delivered to
+#[derive(Clone)]
+pub enum MetricsSink {
+    Backend(ServerClient),
+    Uplink(Box<CollectorUplink>),
+}
+
+impl std::fmt::Display for MetricsSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Backend(_) => write!(f, "server"),
+            Self::Uplink(_) => write!(f, "collector proxy"),
+        }
+    }
+}
+
+impl MetricsSink {
+    pub async fn send(&self, metrics: &SystemMetrics) -> Result<()> {
+        match self {
+            Self::Backend(client) => client.send_metrics(metrics).await,
+            Self::Uplink(uplink) => uplink.send_metrics(metrics).await,
+        }
+    }
+
+    pub async fn send_inventory(&self, report: &InventoryReport) -> Result<()> {
+        match self {
+            Self::Backend(client) => client.send_inventory(report).await,
+            Self::Uplink(uplink) => uplink.send_inventory(report).await,
+        }
+    }
+}
+
+/// Metrics that could not be delivered yet, oldest first
+///
+/// Written to disk whenever it changes, so samples taken during a backend
+/// outage are sent once it is back, even across restarts.
+pub struct MetricsSpool {
+    path: PathBuf,
+    capacity: usize,
+    queue: VecDeque<SystemMetrics>,
+}
+
+impl MetricsSpool {
+    /// Opens the spool, picking up metrics left over by the previous run
+    pub fn load(path: &Path, capacity: usize) -> Self {
+        let mut spool = Self {
+            path: path.to_path_buf(),
+            capacity,
+            queue: VecDeque::new(),
+        };
+
+        match std::fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<Vec<SystemMetrics>>(&content) {
+                Ok(metrics) => {
+                    tracing::info!("📥 {} spooled metrics from the last run", metrics.len());
+                    for metrics in metrics {
+                        spool.push(metrics);
+                    }
+                }
+                Err(e) => tracing::warn!("⚠️  Ignoring unreadable metrics spool {:?}: {}", path, e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("⚠️  Could not read metrics spool {:?}: {}", path, e),
+        }
+
+        spool
+    }
+
+    /// Queues metrics for a later attempt, dropping the oldest when full
+    pub fn push(&mut self, metrics: SystemMetrics) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.queue.len() == self.capacity {
+            self.queue.pop_front();
+            tracing::warn!(
+                "⚠️  Metrics spool full ({} entries), dropping the oldest",
+                self.capacity
+            );
+        }
+        self.queue.push_back(metrics);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Sends queued metrics in order until one fails, returning how many were sent
+    pub async fn flush(&mut self, sink: &MetricsSink) -> usize {
+        let mut sent = 0;
+        while let Some(metrics) = self.queue.front() {
+            if let Err(e) = sink.send(metrics).await {
+                tracing::debug!("Spooled metrics not sent yet: {}", e);
+                break;
+            }
+            self.queue.pop_front();
+            sent += 1;
+        }
+        sent
+    }
+
+    /// Writes the queued metrics to disk, or removes the file if nothing is queued
+    pub fn persist(&self) -> Result<()> {
+        if self.queue.is_empty() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a partial spool
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&self.queue)?)
+            .context(format!("Failed to write {:?}", tmp_path))?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::config::AgentConfig;
+
+/// Status of the running agent, written to the state file for `csf-agent status`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub agent_id: Uuid,
+    pub name: String,
+    pub version: String,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Last registration with the backend
+    pub registration: Option<SendResult>,
+    /// Last heartbeat sent to the backend
+    pub heartbeat: Option<SendResult>,
+    /// Last metrics sent to the backend
+    pub metrics: Option<SendResult>,
+    /// Configured peers by address
+    pub peers: BTreeMap<String, PeerStatus>,
+}
+
+/// Outcome of sending something to the backend or a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendResult {
+    pub at: DateTime<Utc>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SendResult {
+    pub fn from_result<T>(result: &Result<T>) -> Self {
+        Self {
+            at: Utc::now(),
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerStatus {
+    /// Agent ID verified during the last handshake
+    pub agent_id: Option<Uuid>,
+    pub connected: bool,
+    /// When the peer last connected or disconnected
+    pub since: DateTime<Utc>,
+    pub last_heartbeat: Option<SendResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Records the agent's status and persists it after every change
+#[derive(Clone)]
+pub struct StatusTracker {
+    path: PathBuf,
+    status: Arc<Mutex<AgentStatus>>,
+}
+
+impl StatusTracker {
+    pub fn new(config: &AgentConfig) -> Self {
+        let now = Utc::now();
+        let tracker = Self {
+            path: PathBuf::from(&config.state_path),
+            status: Arc::new(Mutex::new(AgentStatus {
+                agent_id: config.agent_id,
+                name: config.name.clone(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                pid: std::process::id(),
+                started_at: now,
+                updated_at: now,
+                registration: None,
+                heartbeat: None,
+                metrics: None,
+                peers: BTreeMap::new(),
+            })),
+        };
+        tracker.touch();
+        tracker
+    }
+
+    pub fn record_registration<T>(&self, result: &Result<T>) {
+        self.update(|status| status.registration = Some(SendResult::from_result(result)));
+    }
+
+    pub fn record_heartbeat<T>(&self, result: &Result<T>) {
+        self.update(|status| status.heartbeat = Some(SendResult::from_result(result)));
+    }
+
+    pub fn record_metrics<T>(&self, result: &Result<T>) {
+        self.update(|status| status.metrics = Some(SendResult::from_result(result)));
+    }
+
+    /// Marks the status as current without changing it
+    pub fn touch(&self) {
+        self.update(|_| {});
+    }
+
+    pub fn peer_connected(&self, address: &str, agent_id: Uuid) {
+        self.update(|status| {
+            status.peers.insert(
+                address.to_string(),
+                PeerStatus {
+                    agent_id: Some(agent_id),
+                    connected: true,
+                    since: Utc::now(),
+                    last_heartbeat: None,
+                    error: None,
+                },
+            );
+        });
+    }
+
+    pub fn peer_heartbeat<T>(&self, address: &str, result: &Result<T>) {
+        self.update(|status| {
+            if let Some(peer) = status.peers.get_mut(address) {
+                peer.last_heartbeat = Some(SendResult::from_result(result));
+            }
+        });
+    }
+
+    pub fn peer_disconnected(&self, address: &str, error: &anyhow::Error) {
+        self.update(|status| {
+            let peer = status
+                .peers
+                .entry(address.to_string())
+                .or_insert_with(|| PeerStatus {
+                    agent_id: None,
+                    connected: false,
+                    since: Utc::now(),
+                    last_heartbeat: None,
+                    error: None,
+                });
+            if peer.connected {
+                peer.since = Utc::now();
+            }
+            peer.connected = false;
+            peer.error = Some(format!("{:#}", error));
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut AgentStatus)) {
+        let status = {
+            let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut status);
+            status.updated_at = Utc::now();
+            status.clone()
+        };
+
+        if let Err(e) = write_status(&self.path, &status) {
+            tracing::debug!("Failed to write agent status to {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Reads the status last written by a running agent
+pub fn read_status(path: &Path) -> Result<AgentStatus> {
+    let content =
+        std::fs::read_to_string(path).context(format!("No agent status found at {:?}", path))?;
+    serde_json::from_str(&content).context("Failed to parse agent status")
+}
+
+fn write_status(path: &Path, status: &AgentStatus) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Write to a temporary file first so readers never see a partial file
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(status)?)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
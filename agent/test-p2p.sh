@@ -64,7 +64,7 @@ EOF
 # Start Agent 1
 echo "🚀 Starting Agent 1 (port 8443)..."
 cd test-agent1
-RUST_LOG=info ../target/release/csf-agent > agent.log 2>&1 &
+RUST_LOG=info ../target/release/csf-agent --config config.toml > agent.log 2>&1 &
 AGENT1_PID=$!
 cd ..
 echo "   PID: $AGENT1_PID"
@@ -89,7 +89,7 @@ fi
 # Start Agent 2
 echo "🚀 Starting Agent 2 (port 8444)..."
 cd test-agent2
-RUST_LOG=info ../target/release/csf-agent > agent.log 2>&1 &
+RUST_LOG=info ../target/release/csf-agent --config config.toml > agent.log 2>&1 &
 AGENT2_PID=$!
 cd ..
 echo "   PID: $AGENT2_PID"
@@ -0,0 +1,46 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "agent_anomalies")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub agent_id: Uuid,
+    /// cpu, memory, disk, network_rx or network_tx
+    pub metric: String,
+    /// deviation or disk_full
+    pub kind: String,
+    /// Observed value: usage in percent, or bytes per second for network
+    pub value: f64,
+    // Baseline the value deviates from
+    pub baseline: Option<f64>,
+    pub std_dev: Option<f64>,
+    pub z_score: Option<f64>,
+    // Forecast of a disk_full anomaly
+    pub days_until_full: Option<f64>,
+    pub first_detected_at: DateTime,
+    pub last_detected_at: DateTime,
+    /// Set once the analysis no longer finds the anomaly
+    pub resolved_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agents::Entity",
+        from = "Column::AgentId",
+        to = "super::agents::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Agent,
+}
+
+impl Related<super::agents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agent.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
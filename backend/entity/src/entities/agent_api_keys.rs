@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "agent_api_keys")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub name: String,
+    /// SHA-256 of the key, the key itself is only shown once
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    /// User who created the key, `None` for the key from `AGENT_API_KEY`
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime,
+    pub last_used_at: Option<DateTime>,
+    /// Set when the key was revoked, agents can't use it anymore
+    pub revoked_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "agent_certificates")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    /// `None` once the agent was deleted, its revoked certificates stay listed
+    pub agent_id: Option<Uuid>,
+    pub ca_id: Uuid,
+    pub serial_number: String,
+    pub certificate_pem: String,
+    pub not_before: DateTime,
+    pub not_after: DateTime,
+    pub created_at: DateTime,
+    pub revoked_at: Option<DateTime>,
+    pub revocation_reason: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agents::Entity",
+        from = "Column::AgentId",
+        to = "super::agents::Column::Id",
+        on_update = "NoAction",
+        on_delete = "SetNull"
+    )]
+    Agent,
+    #[sea_orm(
+        belongs_to = "super::certificate_authorities::Entity",
+        from = "Column::CaId",
+        to = "super::certificate_authorities::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    CertificateAuthority,
+}
+
+impl Related<super::agents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agent.def()
+    }
+}
+
+impl Related<super::certificate_authorities::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::CertificateAuthority.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,63 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "agent_inventory")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub agent_id: Uuid,
+
+    // CPU
+    pub cpu_model: Option<String>,
+    pub cpu_vendor: Option<String>,
+    pub cpu_sockets: Option<i32>,
+    pub cpu_cores: Option<i32>,
+    pub cpu_threads: Option<i32>,
+
+    // Memory
+    pub memory_total_bytes: Option<i64>,
+    pub memory_module_count: Option<i32>,
+
+    // Disks
+    pub disk_count: Option<i32>,
+    pub disk_total_bytes: Option<i64>,
+
+    // Network (JSON arrays of strings)
+    pub mac_addresses: Option<Json>,
+    pub ip_addresses: Option<Json>,
+
+    // Software
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub architecture: Option<String>,
+    pub package_manager: Option<String>,
+    pub package_count: Option<i64>,
+    pub hypervisor: Option<String>,
+    pub container: Option<String>,
+
+    // Full inventory as reported by the agent
+    pub inventory: Json,
+    pub collected_at: DateTime,
+    pub updated_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agents::Entity",
+        from = "Column::AgentId",
+        to = "super::agents::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Agent,
+}
+
+impl Related<super::agents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agent.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
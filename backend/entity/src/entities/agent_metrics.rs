@@ -38,6 +38,9 @@ pub struct Model {
 
     // Custom metrics (JSON)
     pub custom_metrics: Option<Json>,
+
+    // Agent that forwarded this sample, if not sent directly
+    pub relayed_by: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -20,6 +20,13 @@ pub struct Model {
     pub organization_id: Option<Uuid>,
     pub tags: Option<Json>,
     pub capabilities: Option<Json>,
+    pub cert_expires_at: Option<DateTime>,
+    pub relayed_by: Option<Uuid>,
+    pub burst_until: Option<DateTime>,
+    pub resource_group_id: Option<Uuid>,
+    pub services: Option<Json>,
+    /// Until when a certificate may be issued for a new key
+    pub reissue_approved_until: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -32,8 +39,20 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     Organization,
+    #[sea_orm(
+        belongs_to = "super::resource_groups::Entity",
+        from = "Column::ResourceGroupId",
+        to = "super::resource_groups::Column::Id",
+        on_update = "NoAction",
+        on_delete = "SetNull"
+    )]
+    ResourceGroups,
     #[sea_orm(has_many = "super::agent_metrics::Entity")]
     AgentMetrics,
+    #[sea_orm(has_one = "super::agent_inventory::Entity")]
+    AgentInventory,
+    #[sea_orm(has_many = "super::agent_anomalies::Entity")]
+    AgentAnomalies,
 }
 
 impl Related<super::organization::Entity> for Entity {
@@ -42,10 +61,28 @@ impl Related<super::organization::Entity> for Entity {
     }
 }
 
+impl Related<super::resource_groups::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ResourceGroups.def()
+    }
+}
+
 impl Related<super::agent_metrics::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::AgentMetrics.def()
     }
 }
 
+impl Related<super::agent_inventory::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AgentInventory.def()
+    }
+}
+
+impl Related<super::agent_anomalies::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AgentAnomalies.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,37 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[sea_orm(table_name = "certificate_authorities")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub common_name: String,
+    pub certificate_pem: String,
+    #[serde(skip_serializing)]
+    pub private_key_pem: String,
+    pub status: String, // active, retiring, retired
+    pub not_before: DateTime,
+    pub not_after: DateTime,
+    pub retire_at: Option<DateTime>,
+    pub created_at: DateTime,
+    /// Last CRL signed by this CA
+    #[serde(skip_serializing)]
+    pub crl_pem: Option<String>,
+    /// Set to the revocation time when one of its certificates is revoked
+    pub crl_next_update: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::agent_certificates::Entity")]
+    AgentCertificates,
+}
+
+impl Related<super::agent_certificates::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AgentCertificates.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
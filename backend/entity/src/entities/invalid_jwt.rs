@@ -6,7 +6,10 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: Uuid,
-    pub token: String,
+    /// `jti` claim of the revoked token
+    #[sea_orm(unique)]
+    pub jti: Uuid,
+    /// Expiry of the token, the entry can be dropped after it
     pub exp: DateTime,
 }
 
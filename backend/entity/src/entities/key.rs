@@ -8,6 +8,11 @@ pub struct Model {
     pub id: Uuid,
     pub name: String,
     pub private_key: String,
+    /// JWT algorithm of signing keys, None for other keys
+    pub algorithm: Option<String>,
+    pub created_at: DateTime,
+    /// When a signing key stopped signing, it still verifies until tokens expire
+    pub retired_at: Option<DateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
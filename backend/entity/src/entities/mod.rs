@@ -1,31 +1,51 @@
+pub mod agent_anomalies;
+pub mod agent_api_keys;
+pub mod agent_certificates;
+pub mod agent_inventory;
 pub mod agent_metrics;
 pub mod agents;
+pub mod audit_events;
+pub mod certificate_authorities;
 pub mod config;
 pub mod docker_resources;
 pub mod expenses;
 pub mod invalid_jwt;
 pub mod key;
+pub mod login_throttles;
 pub mod marketplace_templates;
 pub mod organization;
 pub mod permission;
+pub mod recovery_codes;
+pub mod refresh_tokens;
 pub mod resource_groups;
+pub mod revoked_agents;
 pub mod role;
 pub mod role_permission;
 pub mod subscription;
 pub mod user;
 pub mod user_organization;
 
+pub use agent_anomalies::Entity as AgentAnomalies;
+pub use agent_api_keys::Entity as AgentApiKeys;
+pub use agent_certificates::Entity as AgentCertificates;
+pub use agent_inventory::Entity as AgentInventory;
 pub use agent_metrics::Entity as AgentMetrics;
 pub use agents::Entity as Agents;
+pub use audit_events::Entity as AuditEvents;
+pub use certificate_authorities::Entity as CertificateAuthorities;
 pub use config::Entity as Config;
 pub use docker_resources::Entity as DockerResources;
 pub use expenses::Entity as Expenses;
 pub use invalid_jwt::Entity as InvalidJwt;
 pub use key::Entity as Key;
+pub use login_throttles::Entity as LoginThrottles;
 pub use marketplace_templates::Entity as MarketplaceTemplates;
 pub use organization::Entity as Organization;
 pub use permission::Entity as Permission;
+pub use recovery_codes::Entity as RecoveryCodes;
+pub use refresh_tokens::Entity as RefreshTokens;
 pub use resource_groups::Entity as ResourceGroups;
+pub use revoked_agents::Entity as RevokedAgents;
 pub use role::Entity as Role;
 pub use role_permission::Entity as RolePermission;
 pub use subscription::Entity as Subscription;
@@ -0,0 +1,45 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "refresh_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Session the token belongs to, shared by all tokens it was rotated into
+    pub family_id: Uuid,
+    /// SHA-256 of the token, the token itself is only known to the client
+    #[sea_orm(unique)]
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    // Device the session was started or last refreshed from
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub session_started_at: DateTime,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    /// Set when the token was exchanged for a new one; presenting it again revokes the family
+    pub rotated_at: Option<DateTime>,
+    pub revoked_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
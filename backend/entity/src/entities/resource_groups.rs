@@ -34,6 +34,8 @@ pub enum Relation {
         on_delete = "SetNull"
     )]
     User,
+    #[sea_orm(has_many = "super::agents::Entity")]
+    Agents,
 }
 
 impl Related<super::organization::Entity> for Entity {
@@ -48,4 +50,10 @@ impl Related<super::user::Entity> for Entity {
     }
 }
 
+impl Related<super::agents::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agents.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -13,6 +13,10 @@ pub struct Model {
     pub two_factor_secret: Option<String>,
     pub two_factor_enabled: bool,
     pub force_password_change: bool,
+    /// Tokens issued before this are invalid, set when logging out everywhere
+    pub tokens_valid_after: Option<DateTime>,
+    /// TOTP time step of the last accepted code, codes of it and earlier steps are rejected
+    pub two_factor_last_step: Option<i64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -21,6 +25,10 @@ pub enum Relation {
     Expenses,
     #[sea_orm(has_many = "super::subscription::Entity")]
     Subscriptions,
+    #[sea_orm(has_many = "super::refresh_tokens::Entity")]
+    RefreshTokens,
+    #[sea_orm(has_many = "super::recovery_codes::Entity")]
+    RecoveryCodes,
 }
 
 impl Related<super::expenses::Entity> for Entity {
@@ -35,4 +43,16 @@ impl Related<super::subscription::Entity> for Entity {
     }
 }
 
+impl Related<super::refresh_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RefreshTokens.def()
+    }
+}
+
+impl Related<super::recovery_codes::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RecoveryCodes.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -11,6 +11,24 @@ mod m20251215_180000_add_rbac_tables;
 mod m20251216_190000_add_agents_and_metrics;
 mod m20251228_120000_add_resource_groups;
 mod m20251228_140000_add_docker_resources;
+mod m20261018_120000_add_certificate_authority;
+mod m20261018_130000_add_agent_cert_expiry;
+mod m20261018_140000_add_certificate_revocation;
+mod m20261018_150000_add_relayed_by;
+mod m20261018_160000_add_agent_burst;
+mod m20261018_170000_add_agent_inventory;
+mod m20261018_180000_add_agent_resource_group;
+mod m20261018_190000_add_agent_services;
+mod m20261018_200000_add_agent_anomalies;
+mod m20261018_210000_add_refresh_tokens;
+mod m20261018_220000_add_signing_keys;
+mod m20261018_230000_add_token_revocation;
+mod m20261018_240000_add_login_protection;
+mod m20261018_250000_add_recovery_codes;
+mod m20261018_260000_add_agent_api_keys;
+mod m20261018_270000_add_single_active_ca;
+mod m20261018_280000_add_ca_crl_cache;
+mod m20261018_290000_keep_certificates_of_deleted_agents;
 
 pub struct Migrator;
 
@@ -29,6 +47,24 @@ impl MigratorTrait for Migrator {
             Box::new(m20251216_190000_add_agents_and_metrics::Migration),
             Box::new(m20251228_120000_add_resource_groups::Migration),
             Box::new(m20251228_140000_add_docker_resources::Migration),
+            Box::new(m20261018_120000_add_certificate_authority::Migration),
+            Box::new(m20261018_130000_add_agent_cert_expiry::Migration),
+            Box::new(m20261018_140000_add_certificate_revocation::Migration),
+            Box::new(m20261018_150000_add_relayed_by::Migration),
+            Box::new(m20261018_160000_add_agent_burst::Migration),
+            Box::new(m20261018_170000_add_agent_inventory::Migration),
+            Box::new(m20261018_180000_add_agent_resource_group::Migration),
+            Box::new(m20261018_190000_add_agent_services::Migration),
+            Box::new(m20261018_200000_add_agent_anomalies::Migration),
+            Box::new(m20261018_210000_add_refresh_tokens::Migration),
+            Box::new(m20261018_220000_add_signing_keys::Migration),
+            Box::new(m20261018_230000_add_token_revocation::Migration),
+            Box::new(m20261018_240000_add_login_protection::Migration),
+            Box::new(m20261018_250000_add_recovery_codes::Migration),
+            Box::new(m20261018_260000_add_agent_api_keys::Migration),
+            Box::new(m20261018_270000_add_single_active_ca::Migration),
+            Box::new(m20261018_280000_add_ca_crl_cache::Migration),
+            Box::new(m20261018_290000_keep_certificates_of_deleted_agents::Migration),
         ]
     }
 }
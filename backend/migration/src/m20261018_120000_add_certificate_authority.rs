@@ -0,0 +1,124 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Create certificate_authorities table
+        manager
+            .create_table(
+                Table::create()
+                    .table(CertificateAuthorities::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(CertificateAuthorities::Id))
+                    .col(string(CertificateAuthorities::CommonName))
+                    .col(text(CertificateAuthorities::CertificatePem))
+                    .col(text(CertificateAuthorities::PrivateKeyPem))
+                    .col(string(CertificateAuthorities::Status))
+                    .col(date_time(CertificateAuthorities::NotBefore))
+                    .col(date_time(CertificateAuthorities::NotAfter))
+                    .col(date_time_null(CertificateAuthorities::RetireAt))
+                    .col(date_time(CertificateAuthorities::CreatedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create agent_certificates table
+        manager
+            .create_table(
+                Table::create()
+                    .table(AgentCertificates::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(AgentCertificates::Id))
+                    .col(uuid(AgentCertificates::AgentId))
+                    .col(uuid(AgentCertificates::CaId))
+                    .col(string(AgentCertificates::SerialNumber))
+                    .col(text(AgentCertificates::CertificatePem))
+                    .col(date_time(AgentCertificates::NotBefore))
+                    .col(date_time(AgentCertificates::NotAfter))
+                    .col(date_time(AgentCertificates::CreatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_agent_certificates_agent_id")
+                            .from(AgentCertificates::Table, AgentCertificates::AgentId)
+                            .to(Agents::Table, Agents::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_agent_certificates_ca_id")
+                            .from(AgentCertificates::Table, AgentCertificates::CaId)
+                            .to(CertificateAuthorities::Table, CertificateAuthorities::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create index for per-agent certificate lookup
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_agent_certificates_agent")
+                    .table(AgentCertificates::Table)
+                    .col(AgentCertificates::AgentId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AgentCertificates::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(CertificateAuthorities::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum CertificateAuthorities {
+    Table,
+    Id,
+    CommonName,
+    CertificatePem,
+    PrivateKeyPem,
+    Status,
+    NotBefore,
+    NotAfter,
+    RetireAt,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum AgentCertificates {
+    Table,
+    Id,
+    AgentId,
+    CaId,
+    SerialNumber,
+    CertificatePem,
+    NotBefore,
+    NotAfter,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    Id,
+}
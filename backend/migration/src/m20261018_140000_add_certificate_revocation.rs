@@ -0,0 +1,70 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Track revocation of issued certificates
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentCertificates::Table)
+                    .add_column(date_time_null(AgentCertificates::RevokedAt))
+                    .add_column(string_null(AgentCertificates::RevocationReason))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create revoked_agents deny list (kept even if the agent is deleted)
+        manager
+            .create_table(
+                Table::create()
+                    .table(RevokedAgents::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(RevokedAgents::AgentId))
+                    .col(string_null(RevokedAgents::Reason))
+                    .col(uuid_null(RevokedAgents::RevokedBy))
+                    .col(date_time(RevokedAgents::RevokedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RevokedAgents::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentCertificates::Table)
+                    .drop_column(AgentCertificates::RevokedAt)
+                    .drop_column(AgentCertificates::RevocationReason)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AgentCertificates {
+    Table,
+    RevokedAt,
+    RevocationReason,
+}
+
+#[derive(DeriveIden)]
+enum RevokedAgents {
+    Table,
+    AgentId,
+    Reason,
+    RevokedBy,
+    RevokedAt,
+}
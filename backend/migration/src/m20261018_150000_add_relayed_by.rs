@@ -0,0 +1,65 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Agent that last forwarded data for an isolated agent
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agents::Table)
+                    .add_column(uuid_null(Agents::RelayedBy))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Agent that forwarded a metrics sample
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentMetrics::Table)
+                    .add_column(uuid_null(AgentMetrics::RelayedBy))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentMetrics::Table)
+                    .drop_column(AgentMetrics::RelayedBy)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agents::Table)
+                    .drop_column(Agents::RelayedBy)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    RelayedBy,
+}
+
+#[derive(DeriveIden)]
+enum AgentMetrics {
+    Table,
+    RelayedBy,
+}
@@ -0,0 +1,100 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Latest hardware and software inventory reported by each agent
+        manager
+            .create_table(
+                Table::create()
+                    .table(AgentInventory::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(AgentInventory::AgentId))
+                    // CPU
+                    .col(string_null(AgentInventory::CpuModel))
+                    .col(string_null(AgentInventory::CpuVendor))
+                    .col(integer_null(AgentInventory::CpuSockets))
+                    .col(integer_null(AgentInventory::CpuCores))
+                    .col(integer_null(AgentInventory::CpuThreads))
+                    // Memory
+                    .col(big_integer_null(AgentInventory::MemoryTotalBytes))
+                    .col(integer_null(AgentInventory::MemoryModuleCount))
+                    // Disks
+                    .col(integer_null(AgentInventory::DiskCount))
+                    .col(big_integer_null(AgentInventory::DiskTotalBytes))
+                    // Network
+                    .col(json_null(AgentInventory::MacAddresses))
+                    .col(json_null(AgentInventory::IpAddresses))
+                    // Software
+                    .col(string_null(AgentInventory::OsName))
+                    .col(string_null(AgentInventory::OsVersion))
+                    .col(string_null(AgentInventory::KernelVersion))
+                    .col(string_null(AgentInventory::Architecture))
+                    .col(string_null(AgentInventory::PackageManager))
+                    .col(big_integer_null(AgentInventory::PackageCount))
+                    .col(string_null(AgentInventory::Hypervisor))
+                    .col(string_null(AgentInventory::Container))
+                    // Everything the agent reported
+                    .col(json(AgentInventory::Inventory))
+                    .col(date_time(AgentInventory::CollectedAt))
+                    .col(date_time(AgentInventory::UpdatedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_agent_inventory_agent_id")
+                            .from(AgentInventory::Table, AgentInventory::AgentId)
+                            .to(Agents::Table, Agents::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AgentInventory::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AgentInventory {
+    Table,
+    AgentId,
+    CpuModel,
+    CpuVendor,
+    CpuSockets,
+    CpuCores,
+    CpuThreads,
+    MemoryTotalBytes,
+    MemoryModuleCount,
+    DiskCount,
+    DiskTotalBytes,
+    MacAddresses,
+    IpAddresses,
+    OsName,
+    OsVersion,
+    KernelVersion,
+    Architecture,
+    PackageManager,
+    PackageCount,
+    Hypervisor,
+    Container,
+    Inventory,
+    CollectedAt,
+    UpdatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    Id,
+}
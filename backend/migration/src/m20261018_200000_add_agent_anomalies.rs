@@ -0,0 +1,84 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Deviations from an agent's metric baselines and disk-full forecasts,
+        // open until the analysis no longer finds them
+        manager
+            .create_table(
+                Table::create()
+                    .table(AgentAnomalies::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(AgentAnomalies::Id))
+                    .col(uuid(AgentAnomalies::AgentId))
+                    .col(string(AgentAnomalies::Metric))
+                    .col(string(AgentAnomalies::Kind))
+                    .col(double(AgentAnomalies::Value))
+                    .col(double_null(AgentAnomalies::Baseline))
+                    .col(double_null(AgentAnomalies::StdDev))
+                    .col(double_null(AgentAnomalies::ZScore))
+                    .col(double_null(AgentAnomalies::DaysUntilFull))
+                    .col(date_time(AgentAnomalies::FirstDetectedAt))
+                    .col(date_time(AgentAnomalies::LastDetectedAt))
+                    .col(date_time_null(AgentAnomalies::ResolvedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_agent_anomalies_agent_id")
+                            .from(AgentAnomalies::Table, AgentAnomalies::AgentId)
+                            .to(Agents::Table, Agents::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_agent_anomalies_agent_resolved")
+                    .table(AgentAnomalies::Table)
+                    .col(AgentAnomalies::AgentId)
+                    .col(AgentAnomalies::ResolvedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AgentAnomalies::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AgentAnomalies {
+    Table,
+    Id,
+    AgentId,
+    Metric,
+    Kind,
+    Value,
+    Baseline,
+    StdDev,
+    ZScore,
+    DaysUntilFull,
+    FirstDetectedAt,
+    LastDetectedAt,
+    ResolvedAt,
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    Id,
+}
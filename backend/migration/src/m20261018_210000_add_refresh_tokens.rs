@@ -0,0 +1,91 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Refresh tokens of login sessions. Each refresh replaces the token with
+        // a new one of the same family, only hashes are stored.
+        manager
+            .create_table(
+                Table::create()
+                    .table(RefreshTokens::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(RefreshTokens::Id))
+                    .col(uuid(RefreshTokens::UserId))
+                    .col(uuid(RefreshTokens::FamilyId))
+                    .col(string_uniq(RefreshTokens::TokenHash))
+                    .col(string_null(RefreshTokens::UserAgent))
+                    .col(string_null(RefreshTokens::IpAddress))
+                    .col(date_time(RefreshTokens::SessionStartedAt))
+                    .col(date_time(RefreshTokens::CreatedAt))
+                    .col(date_time(RefreshTokens::ExpiresAt))
+                    .col(date_time_null(RefreshTokens::RotatedAt))
+                    .col(date_time_null(RefreshTokens::RevokedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_refresh_tokens_user_id")
+                            .from(RefreshTokens::Table, RefreshTokens::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_refresh_tokens_family_id")
+                    .table(RefreshTokens::Table)
+                    .col(RefreshTokens::FamilyId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_refresh_tokens_user_id")
+                    .table(RefreshTokens::Table)
+                    .col(RefreshTokens::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(RefreshTokens::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum RefreshTokens {
+    Table,
+    Id,
+    UserId,
+    FamilyId,
+    TokenHash,
+    UserAgent,
+    IpAddress,
+    SessionStartedAt,
+    CreatedAt,
+    ExpiresAt,
+    RotatedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
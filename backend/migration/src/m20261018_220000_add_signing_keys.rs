@@ -0,0 +1,66 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // JWT signing keys live in the key table next to the password key.
+        // Keys with an algorithm sign tokens, retired ones only verify them.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Key::Table)
+                    .add_column(string_null(Key::Algorithm))
+                    .add_column(date_time(Key::CreatedAt).default(Expr::current_timestamp()))
+                    .add_column(date_time_null(Key::RetiredAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_key_algorithm")
+                    .table(Key::Table)
+                    .col(Key::Algorithm)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_key_algorithm")
+                    .table(Key::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Key::Table)
+                    .drop_column(Key::Algorithm)
+                    .drop_column(Key::CreatedAt)
+                    .drop_column(Key::RetiredAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Key {
+    Table,
+    Algorithm,
+    CreatedAt,
+    RetiredAt,
+}
@@ -0,0 +1,111 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Revoked tokens are identified by their jti claim instead of the whole
+        // token. Old entries can't be matched anymore and expire soon anyway.
+        manager
+            .exec_stmt(Query::delete().from_table(InvalidJwt::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InvalidJwt::Table)
+                    .drop_column(InvalidJwt::Token)
+                    .add_column(uuid_uniq(InvalidJwt::Jti))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_invalid_jwt_exp")
+                    .table(InvalidJwt::Table)
+                    .col(InvalidJwt::Exp)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Tokens of a user issued before this are invalid (logout everywhere)
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(date_time_null(User::TokensValidAfter))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Logged out tokens were only listed in the entries deleted above, so
+        // every token issued until now is invalid, including legacy HS256 ones
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(User::Table)
+                    .value(
+                        User::TokensValidAfter,
+                        Expr::cust("CURRENT_TIMESTAMP AT TIME ZONE 'UTC'"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::TokensValidAfter)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_invalid_jwt_exp")
+                    .table(InvalidJwt::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .exec_stmt(Query::delete().from_table(InvalidJwt::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InvalidJwt::Table)
+                    .drop_column(InvalidJwt::Jti)
+                    .add_column(string(InvalidJwt::Token))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum InvalidJwt {
+    Table,
+    Token,
+    Jti,
+    Exp,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    TokensValidAfter,
+}
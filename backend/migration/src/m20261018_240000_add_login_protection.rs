@@ -0,0 +1,101 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Failed credential checks per account name and per client IP
+        manager
+            .create_table(
+                Table::create()
+                    .table(LoginThrottles::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(LoginThrottles::Id))
+                    .col(string(LoginThrottles::Scope))
+                    .col(string(LoginThrottles::Subject))
+                    .col(integer(LoginThrottles::Failures).default(0))
+                    .col(date_time(LoginThrottles::LastFailureAt))
+                    .col(date_time_null(LoginThrottles::LockedUntil))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_login_throttles_scope_subject")
+                    .table(LoginThrottles::Table)
+                    .col(LoginThrottles::Scope)
+                    .col(LoginThrottles::Subject)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        // Security relevant events, e.g. lockouts and who lifted them
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvents::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(AuditEvents::Id))
+                    .col(string(AuditEvents::Event))
+                    .col(uuid_null(AuditEvents::UserId))
+                    .col(uuid_null(AuditEvents::ActorId))
+                    .col(string_null(AuditEvents::IpAddress))
+                    .col(json_null(AuditEvents::Details))
+                    .col(date_time(AuditEvents::CreatedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_events_created_at")
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvents::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(LoginThrottles::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum LoginThrottles {
+    Table,
+    Id,
+    Scope,
+    Subject,
+    Failures,
+    LastFailureAt,
+    LockedUntil,
+}
+
+#[derive(DeriveIden)]
+enum AuditEvents {
+    Table,
+    Id,
+    Event,
+    UserId,
+    ActorId,
+    IpAddress,
+    Details,
+    CreatedAt,
+}
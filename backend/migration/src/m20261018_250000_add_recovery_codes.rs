@@ -0,0 +1,89 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // One-time codes that replace a TOTP code when the device is lost,
+        // only hashes are stored
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCodes::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(RecoveryCodes::Id))
+                    .col(uuid(RecoveryCodes::UserId))
+                    .col(string_uniq(RecoveryCodes::CodeHash))
+                    .col(date_time(RecoveryCodes::CreatedAt))
+                    .col(date_time_null(RecoveryCodes::UsedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_recovery_codes_user_id")
+                            .from(RecoveryCodes::Table, RecoveryCodes::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::NoAction),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_recovery_codes_user_id")
+                    .table(RecoveryCodes::Table)
+                    .col(RecoveryCodes::UserId)
+                    .to_owned(),
+            )
+            .await?;
+
+        // TOTP time step of the last accepted code, a code is only accepted once
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(big_integer_null(User::TwoFactorLastStep))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::TwoFactorLastStep)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(RecoveryCodes::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum RecoveryCodes {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    CreatedAt,
+    UsedAt,
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    TwoFactorLastStep,
+}
@@ -0,0 +1,73 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Keys agents authenticate with, only hashes are stored
+        manager
+            .create_table(
+                Table::create()
+                    .table(AgentApiKeys::Table)
+                    .if_not_exists()
+                    .col(pk_uuid(AgentApiKeys::Id))
+                    .col(string(AgentApiKeys::Name))
+                    .col(string_uniq(AgentApiKeys::KeyHash))
+                    .col(uuid_null(AgentApiKeys::CreatedBy))
+                    .col(date_time(AgentApiKeys::CreatedAt))
+                    .col(date_time_null(AgentApiKeys::LastUsedAt))
+                    .col(date_time_null(AgentApiKeys::RevokedAt))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Until when the agent may get a certificate for a new key, set by an admin
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agents::Table)
+                    .add_column(date_time_null(Agents::ReissueApprovedUntil))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Agents::Table)
+                    .drop_column(Agents::ReissueApprovedUntil)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(AgentApiKeys::Table).to_owned())
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AgentApiKeys {
+    Table,
+    Id,
+    Name,
+    KeyHash,
+    CreatedBy,
+    CreatedAt,
+    LastUsedAt,
+    RevokedAt,
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    ReissueApprovedUntil,
+}
@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Concurrent first registrations could create more than one active CA,
+        // all but the newest keep being trusted for the default overlap period
+        db.execute_unprepared(
+            r#"UPDATE "certificate_authorities"
+               SET "status" = 'retiring', "retire_at" = NOW() + INTERVAL '30 days'
+               WHERE "status" = 'active'
+                 AND "id" <> (SELECT "id" FROM "certificate_authorities"
+                              WHERE "status" = 'active'
+                              ORDER BY "created_at" DESC LIMIT 1)"#,
+        )
+        .await?;
+
+        // At most one CA signs, the query builder has no partial indexes
+        db.execute_unprepared(
+            r#"CREATE UNIQUE INDEX IF NOT EXISTS "idx_certificate_authorities_single_active"
+               ON "certificate_authorities" ("status") WHERE "status" = 'active'"#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_certificate_authorities_single_active")
+                    .table(CertificateAuthorities::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum CertificateAuthorities {
+    Table,
+}
@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Last CRL signed by each CA, re-signed only on revocations or before it expires
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CertificateAuthorities::Table)
+                    .add_column(text_null(CertificateAuthorities::CrlPem))
+                    .add_column(date_time_null(CertificateAuthorities::CrlNextUpdate))
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(CertificateAuthorities::Table)
+                    .drop_column(CertificateAuthorities::CrlPem)
+                    .drop_column(CertificateAuthorities::CrlNextUpdate)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum CertificateAuthorities {
+    Table,
+    CrlPem,
+    CrlNextUpdate,
+}
@@ -0,0 +1,98 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Revoked certificates must stay on the CRLs after their agent is deleted
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name("fk_agent_certificates_agent_id")
+                    .table(AgentCertificates::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentCertificates::Table)
+                    .modify_column(uuid_null(AgentCertificates::AgentId))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk_agent_certificates_agent_id")
+                    .from(AgentCertificates::Table, AgentCertificates::AgentId)
+                    .to(Agents::Table, Agents::Id)
+                    .on_delete(ForeignKeyAction::SetNull)
+                    .on_update(ForeignKeyAction::NoAction)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_foreign_key(
+                ForeignKey::drop()
+                    .name("fk_agent_certificates_agent_id")
+                    .table(AgentCertificates::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        // Certificates of deleted agents can't be kept without their agent
+        manager
+            .exec_stmt(
+                Query::delete()
+                    .from_table(AgentCertificates::Table)
+                    .and_where(Expr::col(AgentCertificates::AgentId).is_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(AgentCertificates::Table)
+                    .modify_column(uuid(AgentCertificates::AgentId))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_foreign_key(
+                ForeignKey::create()
+                    .name("fk_agent_certificates_agent_id")
+                    .from(AgentCertificates::Table, AgentCertificates::AgentId)
+                    .to(Agents::Table, Agents::Id)
+                    .on_delete(ForeignKeyAction::Cascade)
+                    .on_update(ForeignKeyAction::NoAction)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum AgentCertificates {
+    Table,
+    AgentId,
+}
+
+#[derive(DeriveIden)]
+enum Agents {
+    Table,
+    Id,
+}
@@ -0,0 +1,166 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use entity::{agent_api_keys, AgentApiKeys};
+use rand::RngCore;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, QueryFilter, QueryOrder,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::session_service::hash_token;
+
+/// Environment variable with a key agents may use, e.g. the one a fleet was set up with
+pub const AGENT_API_KEY_ENV: &str = "AGENT_API_KEY";
+
+/// Prefix of generated keys, makes them easy to recognize in configs and logs
+const KEY_PREFIX: &str = "csf_";
+
+/// How often the last use of a key is written, agents use it with every request (minutes)
+const LAST_USED_RESOLUTION_MINUTES: i64 = 5;
+
+#[derive(Debug, Error)]
+pub enum AgentKeyError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Invalid or revoked API key")]
+    InvalidKey,
+    #[error("API key {0} not found")]
+    NotFound(Uuid),
+    #[error("Invalid value: {0}")]
+    InvalidValue(String),
+}
+
+pub type AgentKeyResult<T> = Result<T, AgentKeyError>;
+
+/// Manages the API keys agents authenticate with
+#[derive(Clone)]
+pub struct AgentKeyService {
+    db: DatabaseConnection,
+}
+
+impl AgentKeyService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Creates a key, returned only this once
+    pub async fn create(
+        &self,
+        name: &str,
+        created_by: Uuid,
+    ) -> AgentKeyResult<(agent_api_keys::Model, String)> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AgentKeyError::InvalidValue(
+                "name must not be empty".to_string(),
+            ));
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let key = format!("{}{}", KEY_PREFIX, URL_SAFE_NO_PAD.encode(bytes));
+
+        let model = self.insert(name, &key, Some(created_by)).await?;
+        tracing::info!("Created agent API key {} ({})", model.name, model.id);
+        Ok((model, key))
+    }
+
+    /// Accepts the key from `AGENT_API_KEY` so agents set up before keys were
+    /// checked keep working
+    pub async fn import_from_env(&self) -> AgentKeyResult<()> {
+        let Ok(key) = std::env::var(AGENT_API_KEY_ENV) else {
+            return Ok(());
+        };
+        let key = key.trim();
+        if key.is_empty() {
+            return Ok(());
+        }
+
+        // A key that was revoked stays revoked
+        if AgentApiKeys::find()
+            .filter(agent_api_keys::Column::KeyHash.eq(hash_token(key)))
+            .one(&self.db)
+            .await?
+            .is_none()
+        {
+            self.insert(AGENT_API_KEY_ENV, key, None).await?;
+            tracing::info!("Imported agent API key from {}", AGENT_API_KEY_ENV);
+        }
+        Ok(())
+    }
+
+    /// All keys, newest first
+    pub async fn list(&self) -> AgentKeyResult<Vec<agent_api_keys::Model>> {
+        Ok(AgentApiKeys::find()
+            .order_by_desc(agent_api_keys::Column::CreatedAt)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Revokes a key, agents using it are refused from now on
+    pub async fn revoke(&self, id: Uuid) -> AgentKeyResult<()> {
+        let key = AgentApiKeys::find_by_id(id)
+            .one(&self.db)
+            .await?
+            .ok_or(AgentKeyError::NotFound(id))?;
+        if key.revoked_at.is_some() {
+            return Ok(());
+        }
+
+        let name = key.name.clone();
+        let mut key_active: agent_api_keys::ActiveModel = key.into();
+        key_active.revoked_at = ActiveValue::Set(Some(Utc::now().naive_utc()));
+        key_active.update(&self.db).await?;
+
+        tracing::warn!("Revoked agent API key {} ({})", name, id);
+        Ok(())
+    }
+
+    /// Looks up an unrevoked key
+    pub async fn verify(&self, key: &str) -> AgentKeyResult<agent_api_keys::Model> {
+        let model = AgentApiKeys::find()
+            .filter(agent_api_keys::Column::KeyHash.eq(hash_token(key)))
+            .filter(agent_api_keys::Column::RevokedAt.is_null())
+            .one(&self.db)
+            .await?
+            .ok_or(AgentKeyError::InvalidKey)?;
+
+        let now = Utc::now().naive_utc();
+        let stale = now - Duration::minutes(LAST_USED_RESOLUTION_MINUTES);
+        if model.last_used_at.is_none_or(|last_used| last_used < stale) {
+            AgentApiKeys::update_many()
+                .col_expr(agent_api_keys::Column::LastUsedAt, Expr::value(now))
+                .filter(agent_api_keys::Column::Id.eq(model.id))
+                .filter(
+                    Condition::any()
+                        .add(agent_api_keys::Column::LastUsedAt.is_null())
+                        .add(agent_api_keys::Column::LastUsedAt.lt(stale)),
+                )
+                .exec(&self.db)
+                .await?;
+        }
+
+        Ok(model)
+    }
+
+    async fn insert(
+        &self,
+        name: &str,
+        key: &str,
+        created_by: Option<Uuid>,
+    ) -> AgentKeyResult<agent_api_keys::Model> {
+        Ok(agent_api_keys::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            name: ActiveValue::Set(name.to_string()),
+            key_hash: ActiveValue::Set(hash_token(key)),
+            created_by: ActiveValue::Set(created_by),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            last_used_at: ActiveValue::Set(None),
+            revoked_at: ActiveValue::Set(None),
+        }
+        .insert(&self.db)
+        .await?)
+    }
+}
@@ -0,0 +1,322 @@
+use entity::{agent_metrics, agents, AgentMetrics, Agents, Organization, ResourceGroups};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::ca_service::revoke_agent_certificates;
+
+/// Status of an agent that was taken out of service; its reports are refused
+pub const STATUS_DECOMMISSIONED: &str = "decommissioned";
+
+/// Longest tag accepted
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Page size when a request doesn't give one
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+
+/// Largest page that can be requested
+pub const MAX_PAGE_SIZE: u64 = 500;
+
+#[derive(Debug, Error)]
+pub enum AgentError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Agent {0} not found")]
+    AgentNotFound(Uuid),
+    #[error("Organization {0} not found")]
+    OrganizationNotFound(Uuid),
+    #[error("Resource group {0} not found")]
+    ResourceGroupNotFound(Uuid),
+    #[error("Resource group {0} belongs to another organization")]
+    ResourceGroupMismatch(Uuid),
+    #[error("Invalid name or tag: {0}")]
+    InvalidValue(String),
+}
+
+pub type AgentResult<T> = Result<T, AgentError>;
+
+/// Criteria to list agents by, all optional
+#[derive(Debug, Default, Deserialize)]
+pub struct AgentFilter {
+    pub status: Option<String>,
+    pub tag: Option<String>,
+    pub os_type: Option<String>,
+    pub organization_id: Option<Uuid>,
+    pub resource_group_id: Option<Uuid>,
+}
+
+/// Changes to an agent, fields left out stay as they are
+#[derive(Debug, Default, Deserialize)]
+pub struct AgentUpdate {
+    pub name: Option<String>,
+    /// Replaces all tags
+    pub tags: Option<Vec<String>>,
+}
+
+/// Manages the agents of the fleet
+#[derive(Clone)]
+pub struct AgentService {
+    db: DatabaseConnection,
+}
+
+impl AgentService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// One page of the agents matching the filter (pages start at 1), with the total count
+    pub async fn list(
+        &self,
+        filter: &AgentFilter,
+        page: u64,
+        per_page: u64,
+    ) -> AgentResult<(Vec<agents::Model>, u64)> {
+        let mut query = Agents::find()
+            .order_by_desc(agents::Column::RegisteredAt)
+            .order_by_asc(agents::Column::Id);
+        if let Some(status) = &filter.status {
+            query = query.filter(agents::Column::Status.eq(status));
+        }
+        if let Some(os_type) = &filter.os_type {
+            query = query.filter(agents::Column::OsType.eq(os_type));
+        }
+        if let Some(organization_id) = filter.organization_id {
+            query = query.filter(agents::Column::OrganizationId.eq(organization_id));
+        }
+        if let Some(resource_group_id) = filter.resource_group_id {
+            query = query.filter(agents::Column::ResourceGroupId.eq(resource_group_id));
+        }
+        if let Some(tag) = &filter.tag {
+            query = query.filter(Expr::cust_with_values(
+                "agents.tags::jsonb @> $1::jsonb",
+                [serde_json::json!([tag]).to_string()],
+            ));
+        }
+
+        let paginator = query.paginate(&self.db, per_page.clamp(1, MAX_PAGE_SIZE));
+        let total = paginator.num_items().await?;
+        let agents = paginator.fetch_page(page.max(1) - 1).await?;
+
+        Ok((agents, total))
+    }
+
+    /// Renames an agent or replaces its tags
+    pub async fn update(&self, agent_id: Uuid, update: AgentUpdate) -> AgentResult<agents::Model> {
+        let agent = self.find(agent_id).await?;
+        let mut active_model: agents::ActiveModel = agent.into();
+
+        if let Some(name) = update.name {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(AgentError::InvalidValue(
+                    "name must not be empty".to_string(),
+                ));
+            }
+            active_model.name = ActiveValue::Set(name.to_string());
+        }
+        if let Some(tags) = update.tags {
+            active_model.tags = ActiveValue::Set(Some(tags_json(normalize_tags(tags)?)));
+        }
+
+        active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+        Ok(active_model.update(&self.db).await?)
+    }
+
+    /// Adds tags an agent doesn't have yet
+    pub async fn add_tags(&self, agent_id: Uuid, tags: Vec<String>) -> AgentResult<agents::Model> {
+        let agent = self.find(agent_id).await?;
+        let merged = merge_tags(tag_list(&agent.tags), normalize_tags(tags)?);
+        self.set_tags(agent, merged).await
+    }
+
+    /// Removes a tag from an agent
+    pub async fn remove_tag(&self, agent_id: Uuid, tag: &str) -> AgentResult<agents::Model> {
+        let agent = self.find(agent_id).await?;
+        let remaining = tag_list(&agent.tags)
+            .into_iter()
+            .filter(|existing| existing != tag.trim())
+            .collect();
+        self.set_tags(agent, remaining).await
+    }
+
+    /// Assigns an agent to an organization and resource group, `None` removes the assignment
+    ///
+    /// An agent put into a resource group without an organization joins the group's organization.
+    pub async fn assign(
+        &self,
+        agent_id: Uuid,
+        organization_id: Option<Uuid>,
+        resource_group_id: Option<Uuid>,
+    ) -> AgentResult<agents::Model> {
+        let agent = self.find(agent_id).await?;
+
+        if let Some(organization_id) = organization_id {
+            Organization::find_by_id(organization_id)
+                .one(&self.db)
+                .await?
+                .ok_or(AgentError::OrganizationNotFound(organization_id))?;
+        }
+
+        let organization_id = match resource_group_id {
+            Some(resource_group_id) => {
+                let group = ResourceGroups::find_by_id(resource_group_id)
+                    .one(&self.db)
+                    .await?
+                    .ok_or(AgentError::ResourceGroupNotFound(resource_group_id))?;
+                match organization_id {
+                    Some(organization_id) if organization_id != group.organization_id => {
+                        return Err(AgentError::ResourceGroupMismatch(resource_group_id));
+                    }
+                    _ => Some(group.organization_id),
+                }
+            }
+            None => organization_id,
+        };
+
+        let mut active_model: agents::ActiveModel = agent.into();
+        active_model.organization_id = ActiveValue::Set(organization_id);
+        active_model.resource_group_id = ActiveValue::Set(resource_group_id);
+        active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+        Ok(active_model.update(&self.db).await?)
+    }
+
+    /// Takes an agent out of service, keeping its record but dropping its metrics
+    ///
+    /// Its certificates are revoked and the agent is denied, so it can neither
+    /// connect to peers nor get new certificates until the denial is lifted.
+    /// Returns the number of deleted metric samples.
+    pub async fn decommission(&self, agent_id: Uuid, user_id: Uuid) -> AgentResult<u64> {
+        let agent = self.find(agent_id).await?;
+
+        let txn = self.db.begin().await?;
+        let revoked = revoke_agent_certificates(
+            &txn,
+            agent_id,
+            Some("Agent decommissioned".to_string()),
+            Some(user_id),
+            true,
+        )
+        .await?;
+        let deleted = AgentMetrics::delete_many()
+            .filter(agent_metrics::Column::AgentId.eq(agent_id))
+            .exec(&txn)
+            .await?
+            .rows_affected;
+
+        let mut active_model: agents::ActiveModel = agent.into();
+        active_model.status = ActiveValue::Set(STATUS_DECOMMISSIONED.to_string());
+        active_model.burst_until = ActiveValue::Set(None);
+        active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+        active_model.update(&txn).await?;
+        txn.commit().await?;
+
+        tracing::warn!(
+            "Revoked {} certificate(s) of decommissioned agent {} and denied it",
+            revoked,
+            agent_id
+        );
+        Ok(deleted)
+    }
+
+    /// Deletes an agent with its metrics and inventory
+    ///
+    /// Its certificates are revoked and kept for the CRLs, and the agent is
+    /// denied; it may register again once the denial is lifted. Returns the
+    /// number of deleted metric samples.
+    pub async fn delete(&self, agent_id: Uuid, user_id: Uuid) -> AgentResult<u64> {
+        self.find(agent_id).await?;
+
+        let txn = self.db.begin().await?;
+        let revoked = revoke_agent_certificates(
+            &txn,
+            agent_id,
+            Some("Agent deleted".to_string()),
+            Some(user_id),
+            true,
+        )
+        .await?;
+        let deleted = AgentMetrics::delete_many()
+            .filter(agent_metrics::Column::AgentId.eq(agent_id))
+            .exec(&txn)
+            .await?
+            .rows_affected;
+        // Inventory is removed with the agent, certificates lose their link to it
+        Agents::delete_by_id(agent_id).exec(&txn).await?;
+        txn.commit().await?;
+
+        tracing::warn!(
+            "Revoked {} certificate(s) of deleted agent {} and denied it",
+            revoked,
+            agent_id
+        );
+        Ok(deleted)
+    }
+
+    async fn find(&self, agent_id: Uuid) -> AgentResult<agents::Model> {
+        Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(AgentError::AgentNotFound(agent_id))
+    }
+
+    async fn set_tags(
+        &self,
+        agent: agents::Model,
+        tags: Vec<String>,
+    ) -> AgentResult<agents::Model> {
+        let mut active_model: agents::ActiveModel = agent.into();
+        active_model.tags = ActiveValue::Set(Some(tags_json(tags)));
+        active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+        Ok(active_model.update(&self.db).await?)
+    }
+}
+
+/// Tags stored for an agent, ignoring anything that isn't a string
+pub fn tag_list(tags: &Option<serde_json::Value>) -> Vec<String> {
+    tags.as_ref()
+        .and_then(|tags| tags.as_array())
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|tag| tag.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tags of `existing` followed by the new ones from `added`
+pub fn merge_tags(existing: Vec<String>, added: Vec<String>) -> Vec<String> {
+    let mut merged = existing;
+    for tag in added {
+        if !merged.contains(&tag) {
+            merged.push(tag);
+        }
+    }
+    merged
+}
+
+pub fn tags_json(tags: Vec<String>) -> serde_json::Value {
+    serde_json::Value::from(tags)
+}
+
+/// Trimmed tags without duplicates
+fn normalize_tags(tags: Vec<String>) -> AgentResult<Vec<String>> {
+    let mut normalized = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+            return Err(AgentError::InvalidValue(format!(
+                "tags must have 1 to {} characters",
+                MAX_TAG_LENGTH
+            )));
+        }
+        if !normalized.iter().any(|existing| existing == tag) {
+            normalized.push(tag.to_string());
+        }
+    }
+    Ok(normalized)
+}
@@ -0,0 +1,470 @@
+use chrono::{NaiveDateTime, Utc};
+use entity::{agent_anomalies, agent_metrics, AgentAnomalies, AgentMetrics, Agents};
+use sea_orm::sea_query::{Expr, Order};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// How often the whole fleet is analysed
+const ANALYSIS_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Metrics the baselines are learned from (seconds)
+const BASELINE_WINDOW_SECONDS: i64 = 86_400;
+
+/// Latest metrics that are compared against the baseline (seconds)
+const RECENT_WINDOW_SECONDS: i64 = 600;
+
+/// Time constant of the moving average; older samples fade out over a few of these (seconds)
+const BASELINE_TIME_CONSTANT_SECONDS: f64 = 6.0 * 3600.0;
+
+/// Baseline samples needed before deviations are reported
+const MIN_BASELINE_SAMPLES: usize = 30;
+
+/// Standard deviations from the baseline that make an anomaly
+const Z_SCORE_THRESHOLD: f64 = 3.0;
+
+/// Smallest deviation of a usage percentage that is reported, so flat series don't flag noise
+const MIN_PERCENT_DEVIATION: f64 = 10.0;
+
+/// Smallest relative deviation of a network rate that is reported
+const MIN_RATE_DEVIATION_RATIO: f64 = 0.5;
+
+/// Smallest deviation of a network rate that is reported (bytes per second)
+const MIN_RATE_DEVIATION: f64 = 64.0 * 1024.0;
+
+/// Disk usage history the forecast is fitted to (seconds)
+const FORECAST_WINDOW_SECONDS: i64 = 7 * 86_400;
+
+/// Hours of disk usage needed for a forecast
+const MIN_FORECAST_HOURS: usize = 6;
+
+/// How well the trend has to fit the disk usage to be trusted (R²)
+const MIN_FORECAST_FIT: f64 = 0.6;
+
+/// Disks filling up within this many days are reported
+const DISK_FULL_WARNING_DAYS: f64 = 14.0;
+
+/// A disk filling up within this many days is critical
+pub const DISK_FULL_CRITICAL_DAYS: f64 = 3.0;
+
+/// Most anomalies returned by a listing
+const MAX_LISTED_ANOMALIES: u64 = 500;
+
+pub const KIND_DEVIATION: &str = "deviation";
+pub const KIND_DISK_FULL: &str = "disk_full";
+
+#[derive(Debug, Error)]
+pub enum AnomalyError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Agent {0} not found")]
+    AgentNotFound(Uuid),
+}
+
+pub type AnomalyResult<T> = Result<T, AnomalyError>;
+
+/// Criteria to list anomalies by, only open ones unless `include_resolved` is set
+#[derive(Debug, Default, Deserialize)]
+pub struct AnomalyFilter {
+    pub agent_id: Option<Uuid>,
+    /// cpu, memory, disk, network_rx or network_tx
+    pub metric: Option<String>,
+    /// deviation or disk_full
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub include_resolved: bool,
+}
+
+/// Values of a metric over time, oldest first
+type Series = Vec<(NaiveDateTime, f64)>;
+
+/// Columns of a metrics sample the baselines are built from
+#[derive(Debug, FromQueryResult)]
+struct SeriesSample {
+    timestamp: NaiveDateTime,
+    cpu_usage_percent: Option<f32>,
+    memory_usage_percent: Option<f32>,
+    disk_usage_percent: Option<f32>,
+    network_rx_bytes: Option<i64>,
+    network_tx_bytes: Option<i64>,
+}
+
+/// Disk usage averaged over an hour
+#[derive(Debug, FromQueryResult)]
+struct DiskHour {
+    hour: NaiveDateTime,
+    used_bytes: f64,
+    total_bytes: f64,
+}
+
+/// An anomaly found by one analysis
+#[derive(Debug, Clone, PartialEq)]
+struct Detection {
+    metric: &'static str,
+    kind: &'static str,
+    value: f64,
+    baseline: Option<f64>,
+    std_dev: Option<f64>,
+    z_score: Option<f64>,
+    days_until_full: Option<f64>,
+}
+
+/// Learns metric baselines per agent and records deviations and disk-full forecasts
+#[derive(Clone)]
+pub struct AnomalyService {
+    db: DatabaseConnection,
+}
+
+impl AnomalyService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Analyses every agent, returning the number of open anomalies
+    ///
+    /// An agent that fails to be analysed is logged and skipped.
+    pub async fn analyze_all(&self) -> AnomalyResult<usize> {
+        let agent_ids: Vec<Uuid> = Agents::find()
+            .select_only()
+            .column(entity::agents::Column::Id)
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+
+        let mut open = 0;
+        for agent_id in agent_ids {
+            match self.analyze_agent(agent_id).await {
+                Ok(anomalies) => open += anomalies.len(),
+                Err(e) => tracing::warn!("Failed to analyse metrics of agent {}: {}", agent_id, e),
+            }
+        }
+        Ok(open)
+    }
+
+    /// Analyses an agent's recent metrics and returns its open anomalies
+    ///
+    /// Anomalies found again are updated, ones no longer found are resolved.
+    pub async fn analyze_agent(
+        &self,
+        agent_id: Uuid,
+    ) -> AnomalyResult<Vec<agent_anomalies::Model>> {
+        Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(AnomalyError::AgentNotFound(agent_id))?;
+
+        let now = Utc::now().naive_utc();
+        let detections = self.detect(agent_id, now).await?;
+        let open = AgentAnomalies::find()
+            .filter(agent_anomalies::Column::AgentId.eq(agent_id))
+            .filter(agent_anomalies::Column::ResolvedAt.is_null())
+            .all(&self.db)
+            .await?;
+
+        let txn = self.db.begin().await?;
+        let mut still_open = Vec::new();
+        for detection in detections {
+            let existing = open
+                .iter()
+                .find(|a| a.metric == detection.metric && a.kind == detection.kind);
+            let mut active_model = match existing {
+                Some(existing) => existing.clone().into(),
+                None => agent_anomalies::ActiveModel {
+                    id: ActiveValue::Set(Uuid::new_v4()),
+                    agent_id: ActiveValue::Set(agent_id),
+                    metric: ActiveValue::Set(detection.metric.to_string()),
+                    kind: ActiveValue::Set(detection.kind.to_string()),
+                    first_detected_at: ActiveValue::Set(now),
+                    resolved_at: ActiveValue::Set(None),
+                    ..Default::default()
+                },
+            };
+            active_model.value = ActiveValue::Set(detection.value);
+            active_model.baseline = ActiveValue::Set(detection.baseline);
+            active_model.std_dev = ActiveValue::Set(detection.std_dev);
+            active_model.z_score = ActiveValue::Set(detection.z_score);
+            active_model.days_until_full = ActiveValue::Set(detection.days_until_full);
+            active_model.last_detected_at = ActiveValue::Set(now);
+            let anomaly = match existing {
+                Some(_) => active_model.update(&txn).await?,
+                None => active_model.insert(&txn).await?,
+            };
+            still_open.push(anomaly);
+        }
+
+        for anomaly in open {
+            if still_open.iter().any(|a| a.id == anomaly.id) {
+                continue;
+            }
+            let mut active_model: agent_anomalies::ActiveModel = anomaly.into();
+            active_model.resolved_at = ActiveValue::Set(Some(now));
+            active_model.update(&txn).await?;
+        }
+        txn.commit().await?;
+
+        Ok(still_open)
+    }
+
+    /// Anomalies matching the filter, most recently detected first
+    pub async fn list(&self, filter: &AnomalyFilter) -> AnomalyResult<Vec<agent_anomalies::Model>> {
+        let mut query = AgentAnomalies::find()
+            .order_by_desc(agent_anomalies::Column::LastDetectedAt)
+            .limit(MAX_LISTED_ANOMALIES);
+        if let Some(agent_id) = filter.agent_id {
+            query = query.filter(agent_anomalies::Column::AgentId.eq(agent_id));
+        }
+        if let Some(metric) = &filter.metric {
+            query = query.filter(agent_anomalies::Column::Metric.eq(metric.as_str()));
+        }
+        if let Some(kind) = &filter.kind {
+            query = query.filter(agent_anomalies::Column::Kind.eq(kind.as_str()));
+        }
+        if !filter.include_resolved {
+            query = query.filter(agent_anomalies::Column::ResolvedAt.is_null());
+        }
+
+        Ok(query.all(&self.db).await?)
+    }
+
+    /// Anomalies in an agent's metrics at `now`
+    async fn detect(&self, agent_id: Uuid, now: NaiveDateTime) -> AnomalyResult<Vec<Detection>> {
+        let since = now - chrono::Duration::seconds(BASELINE_WINDOW_SECONDS);
+        let recent_since = now - chrono::Duration::seconds(RECENT_WINDOW_SECONDS);
+
+        let samples = AgentMetrics::find()
+            .select_only()
+            .columns([
+                agent_metrics::Column::Timestamp,
+                agent_metrics::Column::CpuUsagePercent,
+                agent_metrics::Column::MemoryUsagePercent,
+                agent_metrics::Column::DiskUsagePercent,
+                agent_metrics::Column::NetworkRxBytes,
+                agent_metrics::Column::NetworkTxBytes,
+            ])
+            .filter(agent_metrics::Column::AgentId.eq(agent_id))
+            .filter(agent_metrics::Column::Timestamp.gt(since))
+            .order_by_asc(agent_metrics::Column::Timestamp)
+            .into_model::<SeriesSample>()
+            .all(&self.db)
+            .await?;
+
+        // Nothing to compare without current metrics, so open anomalies get resolved
+        if samples
+            .last()
+            .is_none_or(|last| last.timestamp < recent_since)
+        {
+            return Ok(Vec::new());
+        }
+
+        let percent = |value: fn(&SeriesSample) -> Option<f32>| -> Series {
+            samples
+                .iter()
+                .filter_map(|s| value(s).map(|v| (s.timestamp, v as f64)))
+                .collect()
+        };
+        let counter = |value: fn(&SeriesSample) -> Option<i64>| -> Series {
+            rates(
+                samples
+                    .iter()
+                    .filter_map(|s| value(s).map(|v| (s.timestamp, v))),
+            )
+        };
+
+        let mut detections: Vec<Detection> = [
+            (
+                "cpu",
+                percent(|s| s.cpu_usage_percent),
+                percent_floor as fn(f64) -> f64,
+            ),
+            ("memory", percent(|s| s.memory_usage_percent), percent_floor),
+            ("disk", percent(|s| s.disk_usage_percent), percent_floor),
+            ("network_rx", counter(|s| s.network_rx_bytes), rate_floor),
+            ("network_tx", counter(|s| s.network_tx_bytes), rate_floor),
+        ]
+        .into_iter()
+        .filter_map(|(metric, points, floor)| deviation(metric, &points, recent_since, floor))
+        .collect();
+        if let Some(forecast) = disk_forecast(&self.disk_hours(agent_id, now).await?) {
+            detections.push(forecast);
+        }
+        Ok(detections)
+    }
+
+    /// Hourly disk usage over the forecast window, oldest first
+    async fn disk_hours(&self, agent_id: Uuid, now: NaiveDateTime) -> AnomalyResult<Vec<DiskHour>> {
+        let since = now - chrono::Duration::seconds(FORECAST_WINDOW_SECONDS);
+        Ok(AgentMetrics::find()
+            .select_only()
+            .column_as(
+                Expr::cust(r#"date_trunc('hour', "agent_metrics"."timestamp")"#),
+                "hour",
+            )
+            .column_as(
+                Expr::cust(r#"avg("agent_metrics"."disk_used_bytes")::float8"#),
+                "used_bytes",
+            )
+            .column_as(
+                Expr::cust(r#"max("agent_metrics"."disk_total_bytes")::float8"#),
+                "total_bytes",
+            )
+            .filter(agent_metrics::Column::AgentId.eq(agent_id))
+            .filter(agent_metrics::Column::Timestamp.gt(since))
+            .filter(agent_metrics::Column::DiskUsedBytes.is_not_null())
+            .filter(agent_metrics::Column::DiskTotalBytes.is_not_null())
+            .group_by(Expr::cust("hour"))
+            .order_by(Expr::cust("hour"), Order::Asc)
+            .into_model::<DiskHour>()
+            .all(&self.db)
+            .await?)
+    }
+}
+
+/// Runs the analysis for the whole fleet in the background
+pub fn start_anomaly_detection(db: DatabaseConnection) {
+    let service = AnomalyService::new(db);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ANALYSIS_INTERVAL);
+        loop {
+            interval.tick().await;
+            match service.analyze_all().await {
+                Ok(open) => tracing::debug!("Anomaly analysis done, {} open anomalies", open),
+                Err(e) => tracing::error!("Anomaly analysis failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Smallest reported deviation of a usage percentage
+fn percent_floor(_baseline: f64) -> f64 {
+    MIN_PERCENT_DEVIATION
+}
+
+/// Smallest reported deviation of a network rate with the given baseline
+fn rate_floor(baseline: f64) -> f64 {
+    (baseline * MIN_RATE_DEVIATION_RATIO).max(MIN_RATE_DEVIATION)
+}
+
+/// Per second rates of a byte counter, skipping counter resets
+fn rates(counter: impl Iterator<Item = (NaiveDateTime, i64)>) -> Series {
+    let mut rates = Vec::new();
+    let mut previous: Option<(NaiveDateTime, i64)> = None;
+    for (timestamp, bytes) in counter {
+        if let Some((last_timestamp, last_bytes)) = previous {
+            let seconds = (timestamp - last_timestamp).num_milliseconds() as f64 / 1000.0;
+            if seconds > 0.0 && bytes >= last_bytes {
+                rates.push((timestamp, (bytes - last_bytes) as f64 / seconds));
+            }
+        }
+        previous = Some((timestamp, bytes));
+    }
+    rates
+}
+
+/// Compares the mean of the recent points with a moving baseline of the ones before
+///
+/// The baseline is an exponentially weighted mean and variance with a time
+/// constant, so irregular sample intervals weigh correctly and a slow leak
+/// still stands out against the hours before.
+fn deviation(
+    metric: &'static str,
+    points: &[(NaiveDateTime, f64)],
+    recent_since: NaiveDateTime,
+    min_deviation: fn(f64) -> f64,
+) -> Option<Detection> {
+    let split = points.partition_point(|(timestamp, _)| *timestamp < recent_since);
+    let (history, recent) = points.split_at(split);
+    if history.len() < MIN_BASELINE_SAMPLES || recent.is_empty() {
+        return None;
+    }
+
+    let (mut last_timestamp, mut mean) = history[0];
+    let mut variance = 0.0;
+    for &(timestamp, value) in &history[1..] {
+        let seconds = (timestamp - last_timestamp).num_seconds().max(0) as f64;
+        let alpha = 1.0 - (-seconds / BASELINE_TIME_CONSTANT_SECONDS).exp();
+        let diff = value - mean;
+        let increment = alpha * diff;
+        mean += increment;
+        variance = (1.0 - alpha) * (variance + diff * increment);
+        last_timestamp = timestamp;
+    }
+
+    let current = recent.iter().map(|(_, value)| value).sum::<f64>() / recent.len() as f64;
+    let std_dev = variance.sqrt();
+    let diff = current - mean;
+    if diff.abs() < (Z_SCORE_THRESHOLD * std_dev).max(min_deviation(mean)) {
+        return None;
+    }
+
+    Some(Detection {
+        metric,
+        kind: KIND_DEVIATION,
+        value: current,
+        baseline: Some(mean),
+        std_dev: Some(std_dev),
+        z_score: (std_dev > 0.0).then(|| diff / std_dev),
+        days_until_full: None,
+    })
+}
+
+/// Days until the disk is full from a linear fit of its hourly usage
+fn disk_forecast(hours: &[DiskHour]) -> Option<Detection> {
+    if hours.len() < MIN_FORECAST_HOURS {
+        return None;
+    }
+
+    let start = hours[0].hour;
+    let points: Vec<(f64, f64)> = hours
+        .iter()
+        .map(|h| {
+            (
+                (h.hour - start).num_seconds() as f64 / 86_400.0,
+                h.used_bytes,
+            )
+        })
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (sxy, sxx, syy) = points
+        .iter()
+        .fold((0.0, 0.0, 0.0), |(sxy, sxx, syy), (x, y)| {
+            let (dx, dy) = (x - mean_x, y - mean_y);
+            (sxy + dx * dy, sxx + dx * dx, syy + dy * dy)
+        });
+    if sxx == 0.0 || syy == 0.0 {
+        return None;
+    }
+
+    // Bytes per day, only growing disks fill up
+    let slope = sxy / sxx;
+    let fit = sxy * sxy / (sxx * syy);
+    if slope <= 0.0 || fit < MIN_FORECAST_FIT {
+        return None;
+    }
+
+    let latest = hours.last()?;
+    let days = (latest.total_bytes - latest.used_bytes).max(0.0) / slope;
+    if days > DISK_FULL_WARNING_DAYS {
+        return None;
+    }
+
+    Some(Detection {
+        metric: "disk",
+        kind: KIND_DISK_FULL,
+        value: if latest.total_bytes > 0.0 {
+            latest.used_bytes / latest.total_bytes * 100.0
+        } else {
+            0.0
+        },
+        baseline: None,
+        std_dev: None,
+        z_score: None,
+        days_until_full: Some(days),
+    })
+}
@@ -0,0 +1,85 @@
+use chrono::Utc;
+use entity::{audit_events, AuditEvents};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// An account or client IP was locked after too many failed attempts
+pub const EVENT_LOGIN_LOCKOUT: &str = "login.lockout";
+
+/// An admin lifted a lockout
+pub const EVENT_LOGIN_UNLOCK: &str = "login.unlock";
+
+/// A recovery code replaced a TOTP code
+pub const EVENT_RECOVERY_CODE_USED: &str = "2fa.recovery_code_used";
+
+/// An admin turned off 2FA of a user who lost their device
+pub const EVENT_TWO_FACTOR_RESET: &str = "2fa.reset";
+
+/// Most events returned at once
+pub const MAX_AUDIT_EVENTS: u64 = 500;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+}
+
+pub type AuditResult<T> = Result<T, AuditError>;
+
+/// Something to keep in the audit log
+#[derive(Debug, Default)]
+pub struct AuditEvent {
+    pub event: &'static str,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
+/// Records security relevant events
+#[derive(Clone)]
+pub struct AuditService {
+    db: DatabaseConnection,
+}
+
+impl AuditService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    pub async fn record(&self, event: AuditEvent) -> AuditResult<()> {
+        AuditEvents::insert(audit_events::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            event: ActiveValue::Set(event.event.to_string()),
+            user_id: ActiveValue::Set(event.user_id),
+            actor_id: ActiveValue::Set(event.actor_id),
+            ip_address: ActiveValue::Set(event.ip_address),
+            details: ActiveValue::Set(event.details),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+        })
+        .exec_without_returning(&self.db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The latest events, optionally of one kind
+    pub async fn list(
+        &self,
+        event: Option<&str>,
+        limit: u64,
+    ) -> AuditResult<Vec<audit_events::Model>> {
+        let mut query = AuditEvents::find();
+        if let Some(event) = event {
+            query = query.filter(audit_events::Column::Event.eq(event));
+        }
+        Ok(query
+            .order_by_desc(audit_events::Column::CreatedAt)
+            .limit(limit.min(MAX_AUDIT_EVENTS))
+            .all(&self.db)
+            .await?)
+    }
+}
@@ -1,9 +1,41 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
+    TokenData, Validation,
+};
+use rsa::{pkcs1::DecodeRsaPrivateKey, traits::PublicKeyParts, RsaPrivateKey};
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
 use uuid::Uuid;
 
+/// Lifetime of an access token, sessions last longer through refresh tokens
+pub const ACCESS_TOKEN_MINUTES: i64 = 15;
+
+/// Algorithm tokens are signed with
+pub const SIGNING_ALGORITHM: Algorithm = Algorithm::RS256;
+
+/// How long clients may cache the JWKS
+pub const JWKS_MAX_AGE_SECONDS: i64 = 300;
+
+/// Least time between reloads of the keys for tokens with an unknown `kid`
+const UNKNOWN_KID_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Secrets from example configs and old defaults that must never sign tokens
+const DEFAULT_SECRETS: [&str; 4] = [
+    "your-secret-key",
+    "your-super-secret-jwt-key-change-this-in-production",
+    "changeme_generate_secure_secret",
+    "changeme_generate_secure_secret_with_openssl_rand_hex_32",
+];
+
+/// Shortest `JWT_SECRET` that is accepted
+const MIN_SECRET_LENGTH: usize = 32;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // Subject (user ID)
@@ -11,12 +43,18 @@ pub struct Claims {
     pub iat: i64,    // Issued at
     pub user_id: Uuid,
     pub username: String,
+    /// Session (refresh token family) the token was issued for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    /// Unique token ID, revoked tokens are listed by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
 }
 
 impl Claims {
-    pub fn new(user_id: Uuid, username: String) -> Self {
+    pub fn new(user_id: Uuid, username: String, session_id: Uuid) -> Self {
         let now = Utc::now();
-        let exp = now + Duration::hours(24); // Token expires in 24 hours
+        let exp = now + Duration::minutes(ACCESS_TOKEN_MINUTES);
 
         Claims {
             sub: user_id.to_string(),
@@ -24,29 +62,182 @@ impl Claims {
             iat: now.timestamp(),
             user_id,
             username,
+            sid: Some(session_id),
+            jti: Some(Uuid::new_v4()),
         }
     }
 }
 
-pub fn create_jwt(user_id: Uuid, username: String) -> Result<String, jsonwebtoken::errors::Error> {
-    let claims = Claims::new(user_id, username);
-    let secret = get_jwt_secret();
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
+/// Public part of a signing key as published in the JWKS
+#[derive(Debug, Clone, Serialize)]
+pub struct Jwk {
+    pub kty: &'static str,
+    #[serde(rename = "use")]
+    pub use_: &'static str,
+    pub alg: &'static str,
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
 }
 
-pub fn verify_jwt(token: &str) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
-    let secret = get_jwt_secret();
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )
+/// A key from the key table that signs or verifies tokens
+pub struct SigningKey {
+    kid: String,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    jwk: Jwk,
 }
 
-fn get_jwt_secret() -> String {
-    env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string())
+impl SigningKey {
+    /// Reads an RSA private key stored as PKCS#1 PEM
+    pub fn from_rsa_pem(kid: Uuid, private_pem: &str) -> Result<Self, jsonwebtoken::errors::Error> {
+        let private_key = RsaPrivateKey::from_pkcs1_pem(private_pem).map_err(|_| {
+            jsonwebtoken::errors::Error::from(ErrorKind::InvalidRsaKey(
+                "not a PKCS#1 RSA private key".to_string(),
+            ))
+        })?;
+        let n = URL_SAFE_NO_PAD.encode(private_key.n().to_bytes_be());
+        let e = URL_SAFE_NO_PAD.encode(private_key.e().to_bytes_be());
+
+        Ok(Self {
+            kid: kid.to_string(),
+            encoding: EncodingKey::from_rsa_pem(private_pem.as_bytes())?,
+            decoding: DecodingKey::from_rsa_components(&n, &e)?,
+            jwk: Jwk {
+                kty: "RSA",
+                use_: "sig",
+                alg: "RS256",
+                kid: kid.to_string(),
+                n,
+                e,
+            },
+        })
+    }
+}
+
+#[derive(Default)]
+struct KeySet {
+    /// Kid of the key new tokens are signed with
+    current: Option<String>,
+    keys: HashMap<String, SigningKey>,
+}
+
+/// Keys tokens are signed and verified with, shared by all requests
+///
+/// Loaded from the key table and replaced when keys are rotated.
+#[derive(Clone)]
+pub struct JwtKeys {
+    keys: Arc<RwLock<KeySet>>,
+    /// Verifies tokens signed with `JWT_SECRET` before signing keys were introduced
+    legacy_secret: Option<Arc<DecodingKey>>,
+    /// Last reload for a token with an unknown `kid`
+    last_reload: Arc<Mutex<Option<Instant>>>,
+}
+
+impl JwtKeys {
+    /// Empty key set, refuses to start with a default `JWT_SECRET`
+    pub fn new() -> Result<Self, String> {
+        let legacy_secret = match std::env::var("JWT_SECRET") {
+            Ok(secret) if !secret.is_empty() => {
+                if DEFAULT_SECRETS.contains(&secret.as_str()) || secret.starts_with("changeme") {
+                    return Err(
+                        "JWT_SECRET is set to a default value, generate one with `openssl rand -hex 32` or remove it"
+                            .to_string(),
+                    );
+                }
+                if secret.len() < MIN_SECRET_LENGTH {
+                    return Err(format!(
+                        "JWT_SECRET must be at least {} characters long",
+                        MIN_SECRET_LENGTH
+                    ));
+                }
+                Some(Arc::new(DecodingKey::from_secret(secret.as_bytes())))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            keys: Arc::new(RwLock::new(KeySet::default())),
+            legacy_secret,
+            last_reload: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Replaces the keys, tokens are signed with `current` from now on
+    pub fn replace(&self, current: Uuid, keys: Vec<SigningKey>) {
+        let keys = keys.into_iter().map(|key| (key.kid.clone(), key)).collect();
+        let mut set = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        *set = KeySet {
+            current: Some(current.to_string()),
+            keys,
+        };
+    }
+
+    /// Signs claims with the current key, its ID goes into the `kid` header
+    pub fn sign(&self, claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+        let set = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let key = set
+            .current
+            .as_ref()
+            .and_then(|kid| set.keys.get(kid))
+            .ok_or_else(|| jsonwebtoken::errors::Error::from(ErrorKind::InvalidKeyFormat))?;
+
+        let mut header = Header::new(SIGNING_ALGORITHM);
+        header.kid = Some(key.kid.clone());
+        encode(&header, claims, &key.encoding)
+    }
+
+    /// Verifies a token with the key named in its `kid` header
+    pub fn verify(&self, token: &str) -> Result<TokenData<Claims>, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        match header.kid {
+            Some(kid) => {
+                let set = self.keys.read().unwrap_or_else(|e| e.into_inner());
+                let key = set.keys.get(&kid).ok_or_else(|| {
+                    jsonwebtoken::errors::Error::from(ErrorKind::InvalidSignature)
+                })?;
+                decode::<Claims>(token, &key.decoding, &Validation::new(SIGNING_ALGORITHM))
+            }
+            None => {
+                let secret = self.legacy_secret.as_ref().ok_or_else(|| {
+                    jsonwebtoken::errors::Error::from(ErrorKind::InvalidSignature)
+                })?;
+                decode::<Claims>(token, secret, &Validation::new(Algorithm::HS256))
+            }
+        }
+    }
+
+    /// Whether the token names a key that isn't loaded, e.g. one another
+    /// instance published since the last reload
+    pub fn has_unknown_kid(&self, token: &str) -> bool {
+        let Ok(Header { kid: Some(kid), .. }) = decode_header(token) else {
+            return false;
+        };
+        let set = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        !set.keys.contains_key(&kid)
+    }
+
+    /// Whether the keys may be reloaded for an unknown `kid`, at most once
+    /// per [`UNKNOWN_KID_RELOAD_INTERVAL`] so forged tokens can't flood the database
+    pub fn claim_reload(&self) -> bool {
+        let mut last_reload = self.last_reload.lock().unwrap_or_else(|e| e.into_inner());
+        if last_reload.is_some_and(|last| last.elapsed() < UNKNOWN_KID_RELOAD_INTERVAL) {
+            return false;
+        }
+        *last_reload = Some(Instant::now());
+        true
+    }
+
+    /// Public keys of all keys that verify tokens
+    pub fn jwks(&self) -> JwkSet {
+        let set = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let mut keys: Vec<Jwk> = set.keys.values().map(|key| key.jwk.clone()).collect();
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+        JwkSet { keys }
+    }
 }
@@ -1,15 +1,16 @@
-use crate::{
-    auth::jwt::{verify_jwt, Claims},
-    AppState,
-};
+use crate::agent_key_service::{AgentKeyError, AgentKeyService};
+use crate::key_service::KeyService;
+use crate::lockout_service::LockoutService;
+use crate::routes::{source_ip, users::lockout_error_status};
+use crate::{auth::jwt::Claims, AppState};
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{ConnectInfo, FromRequestParts},
     http::{request::Parts, StatusCode},
 };
 use chrono::Utc;
-use entity::InvalidJwt;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use entity::agent_api_keys;
+use std::net::SocketAddr;
 
 // Custom extractor for authenticated requests
 pub struct AuthenticatedUser(pub Claims);
@@ -36,7 +37,8 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
                     .and_then(|cookies| {
                         cookies.split(';').find_map(|cookie| {
                             let mut parts = cookie.trim().splitn(2, '=');
-                            if parts.next()? == "token" {
+                            // Login sets `auth_token`, which is all EventSource clients can send
+                            if matches!(parts.next()?, "token" | "auth_token") {
                                 parts.next()
                             } else {
                                 None
@@ -46,23 +48,31 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
             });
 
         if let Some(token) = token {
-            let is_blacklisted = InvalidJwt::find()
-                .filter(entity::invalid_jwt::Column::Token.eq(token))
-                .one(&state.db_conn)
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-                .is_some();
-
-            if is_blacklisted {
-                return Err(StatusCode::UNAUTHORIZED);
+            let mut verified = state.jwt_keys.verify(token);
+            // Another instance may already sign with a key published after our last sync
+            if verified.is_err()
+                && state.jwt_keys.has_unknown_kid(token)
+                && state.jwt_keys.claim_reload()
+            {
+                match KeyService::new(state.db_conn.clone())
+                    .load(&state.jwt_keys)
+                    .await
+                {
+                    Ok(()) => verified = state.jwt_keys.verify(token),
+                    Err(e) => tracing::error!("Failed to reload JWT signing keys: {}", e),
+                }
             }
 
-            match verify_jwt(token) {
+            match verified {
                 Ok(token_data) => {
                     // Check if token is expired
                     if token_data.claims.exp < Utc::now().timestamp() {
                         return Err(StatusCode::UNAUTHORIZED);
                     }
+                    // Logged out tokens, checked in memory
+                    if state.revoked_tokens.is_revoked(&token_data.claims) {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
                     Ok(AuthenticatedUser(token_data.claims))
                 }
                 Err(_) => Err(StatusCode::UNAUTHORIZED),
@@ -72,3 +82,59 @@ impl FromRequestParts<AppState> for AuthenticatedUser {
         }
     }
 }
+
+/// Requests of agents, authenticated with an API key in `X-API-Key`
+pub struct AuthenticatedAgent(pub agent_api_keys::Model);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedAgent {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let key = parts
+            .headers
+            .get("X-API-Key")
+            .and_then(|header| header.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        // Guessing keys is throttled per client IP like logins
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| source_ip(*peer, &parts.headers))
+            .unwrap_or_else(|| "unknown".to_string());
+        let lockout_service = LockoutService::new(state.db_conn.clone());
+        lockout_service
+            .reserve_api_key(&ip)
+            .await
+            .map_err(lockout_error_status)?;
+
+        let verified = AgentKeyService::new(state.db_conn.clone())
+            .verify(key)
+            .await;
+        let recorded = match &verified {
+            Err(AgentKeyError::InvalidKey) => lockout_service.record_api_key_failure(&ip).await,
+            _ => lockout_service.release_api_key(&ip).await,
+        };
+        if let Err(e) = recorded {
+            tracing::error!("Failed to record API key check: {}", e);
+        }
+
+        match verified {
+            Ok(key) => Ok(AuthenticatedAgent(key)),
+            Err(AgentKeyError::InvalidKey) => {
+                tracing::warn!("Refused agent request from {} with an invalid API key", ip);
+                Err(StatusCode::UNAUTHORIZED)
+            }
+            Err(e) => {
+                tracing::error!("Failed to check agent API key: {}", e);
+                Err(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        }
+    }
+}
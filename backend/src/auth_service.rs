@@ -1,18 +1,31 @@
-use chrono::{DateTime, Utc};
-use entity::{invalid_jwt, key, user, InvalidJwt, Key, User};
+use chrono::Utc;
+use entity::{key, recovery_codes, user, Key, RecoveryCodes, User};
+use rand::Rng;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set,
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, Condition, DatabaseConnection,
+    EntityTrait, PaginatorTrait, QueryFilter, Set, TransactionTrait,
 };
 use thiserror::Error;
 use totp_rs::{Algorithm, Secret, TOTP};
 use uuid::Uuid;
 
-use crate::auth::{
-    crypto::{
-        decrypt_password, generate_salt, hash_password, verify_password, CryptoError, RsaKeyPair,
-    },
-    jwt::create_jwt,
+use crate::audit_service::{AuditEvent, AuditService, EVENT_RECOVERY_CODE_USED};
+use crate::auth::crypto::{
+    decrypt_password, generate_salt, hash_password, verify_password, CryptoError, RsaKeyPair,
 };
+use crate::session_service::hash_token;
+
+/// Seconds a TOTP code is valid for
+const TOTP_STEP_SECONDS: u64 = 30;
+
+/// Recovery codes handed out when 2FA is enabled
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Characters of a recovery code, 16 of them make 80 random bits
+const RECOVERY_CODE_LENGTH: usize = 16;
+
+/// Lowercase letters and digits without the easily confused ones
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
 
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -49,11 +62,12 @@ impl AuthService {
         Self { db }
     }
 
+    /// Creates a user, sessions are started by the caller
     pub async fn register_user(
         &self,
         username: String,
         encrypted_password: String,
-    ) -> AuthResult<String> {
+    ) -> AuthResult<user::Model> {
         // Check if user already exists
         let existing_user = User::find()
             .filter(user::Column::Name.eq(&username))
@@ -85,6 +99,8 @@ impl AuthService {
             two_factor_secret: ActiveValue::NotSet,
             two_factor_enabled: ActiveValue::Set(false),
             force_password_change: ActiveValue::Set(false),
+            tokens_valid_after: ActiveValue::Set(None),
+            two_factor_last_step: ActiveValue::Set(None),
         };
 
         // Insert without retrieving last_insert_id (which doesn't work with UUID PKs in SQLite)
@@ -92,17 +108,16 @@ impl AuthService {
             .exec_without_returning(&self.db)
             .await?;
 
-        // Create JWT token using our pre-generated user ID
-        let token = create_jwt(user_id, username)?;
-        Ok(token)
+        self.get_user_by_id(user_id).await
     }
 
+    /// Checks the credentials of a user, sessions are started by the caller
     pub async fn login_user(
         &self,
         username: String,
         encrypted_password: String,
         two_factor_code: Option<String>,
-    ) -> AuthResult<(String, bool, bool)> {
+    ) -> AuthResult<user::Model> {
         // Find user
         let user = User::find()
             .filter(user::Column::Name.eq(&username))
@@ -124,7 +139,7 @@ impl AuthService {
         // Check if 2FA is enabled
         if user.two_factor_enabled {
             if let Some(code) = two_factor_code {
-                if !self.verify_2fa_code(&user, &code)? {
+                if !self.verify_second_factor(&user, &code).await? {
                     return Err(AuthError::InvalidTwoFactorCode);
                 }
             } else {
@@ -132,25 +147,7 @@ impl AuthService {
             }
         }
 
-        // Create JWT token
-        let token = create_jwt(user.id, user.name)?;
-        Ok((token, user.two_factor_enabled, user.force_password_change))
-    }
-
-    pub async fn logout_user(&self, token: String, exp: DateTime<Utc>) -> AuthResult<()> {
-        // Add token to blacklist with pre-generated UUID
-        let jwt_id = Uuid::new_v4();
-        let invalid_jwt = invalid_jwt::ActiveModel {
-            id: ActiveValue::Set(jwt_id),
-            token: ActiveValue::Set(token),
-            exp: ActiveValue::Set(exp.naive_utc()),
-        };
-
-        // Insert without retrieving last_insert_id (which doesn't work with UUID PKs in SQLite)
-        InvalidJwt::insert(invalid_jwt)
-            .exec_without_returning(&self.db)
-            .await?;
-        Ok(())
+        Ok(user)
     }
 
     pub async fn get_user_by_id(&self, user_id: Uuid) -> AuthResult<user::Model> {
@@ -179,6 +176,9 @@ impl AuthService {
             id: ActiveValue::Set(key_id),
             name: ActiveValue::Set(key_name.to_string()),
             private_key: ActiveValue::Set(key_pair.private_key.clone()),
+            algorithm: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(Utc::now().naive_utc()),
+            retired_at: ActiveValue::Set(None),
         };
 
         // Insert without retrieving last_insert_id (which doesn't work with UUID PKs in SQLite)
@@ -194,17 +194,6 @@ impl AuthService {
         Ok(key_pair.public_key)
     }
 
-    #[allow(dead_code)]
-    pub async fn cleanup_expired_tokens(&self) -> AuthResult<()> {
-        let now = Utc::now().naive_utc();
-        InvalidJwt::delete_many()
-            .filter(invalid_jwt::Column::Exp.lt(now))
-            .exec(&self.db)
-            .await?;
-
-        Ok(())
-    }
-
     // 2FA Methods
     pub async fn generate_2fa_secret(&self, user_id: Uuid) -> AuthResult<(String, String)> {
         let user = self.get_user_by_id(user_id).await?;
@@ -235,7 +224,8 @@ impl AuthService {
         Ok((secret_string, qr_code_url))
     }
 
-    pub async fn enable_2fa(&self, user_id: Uuid, code: String) -> AuthResult<()> {
+    /// Enables 2FA once a code from the new secret checks out, returns the recovery codes
+    pub async fn enable_2fa(&self, user_id: Uuid, code: String) -> AuthResult<Vec<String>> {
         let user = self.get_user_by_id(user_id).await?;
 
         if user.two_factor_secret.is_none() {
@@ -243,7 +233,7 @@ impl AuthService {
         }
 
         // Verify the code before enabling
-        if !self.verify_2fa_code(&user, &code)? {
+        if !self.verify_2fa_code(&user, &code).await? {
             return Err(AuthError::InvalidTwoFactorCode);
         }
 
@@ -252,7 +242,7 @@ impl AuthService {
         user_active.two_factor_enabled = Set(true);
         user_active.update(&self.db).await?;
 
-        Ok(())
+        self.replace_recovery_codes(user_id).await
     }
 
     pub async fn disable_2fa(&self, user_id: Uuid, code: String) -> AuthResult<()> {
@@ -262,37 +252,167 @@ impl AuthService {
             return Ok(());
         }
 
-        // Verify the code before disabling
-        if !self.verify_2fa_code(&user, &code)? {
+        // Verify the code before disabling, a recovery code will do when the device is lost
+        if !self.verify_second_factor(&user, &code).await? {
             return Err(AuthError::InvalidTwoFactorCode);
         }
 
-        // Disable 2FA and remove secret
+        self.reset_2fa(user_id).await
+    }
+
+    /// Turns 2FA off and drops the secret and recovery codes, without asking for a code
+    pub async fn reset_2fa(&self, user_id: Uuid) -> AuthResult<()> {
+        let user = self.get_user_by_id(user_id).await?;
+
+        let txn = self.db.begin().await?;
         let mut user_active: user::ActiveModel = user.into();
         user_active.two_factor_enabled = Set(false);
         user_active.two_factor_secret = Set(None);
-        user_active.update(&self.db).await?;
+        user_active.two_factor_last_step = Set(None);
+        user_active.update(&txn).await?;
+
+        RecoveryCodes::delete_many()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
 
         Ok(())
     }
 
-    pub fn verify_2fa_code(&self, user: &user::Model, code: &str) -> AuthResult<bool> {
-        if let Some(secret) = &user.two_factor_secret {
-            let totp = TOTP::new(
-                Algorithm::SHA1,
-                6,
-                1,
-                30,
-                Secret::Encoded(secret.clone()).to_bytes().unwrap(),
-                Some("CSF-Core".to_string()),
-                user.name.clone(),
-            )
-            .unwrap();
+    /// Replaces the recovery codes of a user after checking a code
+    pub async fn regenerate_recovery_codes(
+        &self,
+        user_id: Uuid,
+        code: String,
+    ) -> AuthResult<Vec<String>> {
+        let user = self.get_user_by_id(user_id).await?;
+
+        if !user.two_factor_enabled || !self.verify_second_factor(&user, &code).await? {
+            return Err(AuthError::InvalidTwoFactorCode);
+        }
+
+        self.replace_recovery_codes(user_id).await
+    }
 
-            Ok(totp.check_current(code).unwrap_or(false))
+    /// Recovery codes the user has not used yet
+    pub async fn remaining_recovery_codes(&self, user_id: Uuid) -> AuthResult<u64> {
+        Ok(RecoveryCodes::find()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .filter(recovery_codes::Column::UsedAt.is_null())
+            .count(&self.db)
+            .await?)
+    }
+
+    /// Accepts a TOTP code or, for users who lost their device, a recovery code
+    pub async fn verify_second_factor(&self, user: &user::Model, code: &str) -> AuthResult<bool> {
+        let code = code.trim();
+        if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+            self.verify_2fa_code(user, code).await
         } else {
-            Ok(false)
+            self.use_recovery_code(user, code).await
+        }
+    }
+
+    /// Checks a TOTP code of the current or an adjacent time step
+    ///
+    /// The step of an accepted code is stored, so neither it nor a code of an
+    /// earlier step is accepted again.
+    pub async fn verify_2fa_code(&self, user: &user::Model, code: &str) -> AuthResult<bool> {
+        let Some(totp) = totp(user) else {
+            return Ok(false);
+        };
+
+        let current = Utc::now().timestamp() as u64 / TOTP_STEP_SECONDS;
+        // Adjacent steps allow for clock drift between the server and the device
+        let Some(step) = [current - 1, current, current + 1]
+            .into_iter()
+            .find(|step| totp.check(code, step * TOTP_STEP_SECONDS))
+        else {
+            return Ok(false);
+        };
+
+        // Claimed in one statement, so concurrent requests can't both use the code
+        let step = step as i64;
+        let claimed = User::update_many()
+            .col_expr(user::Column::TwoFactorLastStep, Expr::value(step))
+            .filter(user::Column::Id.eq(user.id))
+            .filter(
+                Condition::any()
+                    .add(user::Column::TwoFactorLastStep.is_null())
+                    .add(user::Column::TwoFactorLastStep.lt(step)),
+            )
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if claimed == 0 {
+            tracing::warn!("Rejected reused 2FA code of user {}", user.id);
+        }
+
+        Ok(claimed > 0)
+    }
+
+    /// Marks a recovery code used, each one works once
+    async fn use_recovery_code(&self, user: &user::Model, code: &str) -> AuthResult<bool> {
+        let Some(code) = normalize_recovery_code(code) else {
+            return Ok(false);
+        };
+
+        let used = RecoveryCodes::update_many()
+            .col_expr(
+                recovery_codes::Column::UsedAt,
+                Expr::value(Utc::now().naive_utc()),
+            )
+            .filter(recovery_codes::Column::UserId.eq(user.id))
+            .filter(recovery_codes::Column::CodeHash.eq(hash_token(&code)))
+            .filter(recovery_codes::Column::UsedAt.is_null())
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if used == 0 {
+            return Ok(false);
         }
+
+        let remaining = self.remaining_recovery_codes(user.id).await?;
+        tracing::info!("User {} used a recovery code, {} left", user.id, remaining);
+        let event = AuditEvent {
+            event: EVENT_RECOVERY_CODE_USED,
+            user_id: Some(user.id),
+            actor_id: Some(user.id),
+            details: Some(serde_json::json!({ "remaining": remaining })),
+            ..Default::default()
+        };
+        if let Err(e) = AuditService::new(self.db.clone()).record(event).await {
+            tracing::error!("Failed to record audit event: {}", e);
+        }
+
+        Ok(true)
+    }
+
+    /// Generates new recovery codes, the previous ones stop working
+    async fn replace_recovery_codes(&self, user_id: Uuid) -> AuthResult<Vec<String>> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let now = Utc::now().naive_utc();
+
+        let txn = self.db.begin().await?;
+        RecoveryCodes::delete_many()
+            .filter(recovery_codes::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        RecoveryCodes::insert_many(codes.iter().map(|code| recovery_codes::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            user_id: ActiveValue::Set(user_id),
+            code_hash: ActiveValue::Set(hash_token(&code.replace('-', ""))),
+            created_at: ActiveValue::Set(now),
+            used_at: ActiveValue::Set(None),
+        }))
+        .exec_without_returning(&txn)
+        .await?;
+        txn.commit().await?;
+
+        Ok(codes)
     }
 
     // Password and Email Management
@@ -347,3 +467,41 @@ impl AuthService {
         Ok(())
     }
 }
+
+/// TOTP of a user's secret, checking a single time step
+fn totp(user: &user::Model) -> Option<TOTP> {
+    let secret = user.two_factor_secret.as_ref()?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP_SECONDS,
+        Secret::Encoded(secret.clone()).to_bytes().ok()?,
+        Some("CSF-Core".to_string()),
+        user.name.clone(),
+    )
+    .ok()
+}
+
+/// A recovery code like `abcd-efgh-jkmn-pqrs`
+fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..RECOVERY_CODE_LENGTH)
+        .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+        .collect();
+    chars
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Drops dashes and spaces and lowercases a recovery code as typed by the user
+fn normalize_recovery_code(code: &str) -> Option<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    (code.len() == RECOVERY_CODE_LENGTH).then_some(code)
+}
@@ -0,0 +1,709 @@
+use chrono::{DateTime, Duration, Utc};
+use entity::{
+    agent_certificates, agents, certificate_authorities, revoked_agents, AgentCertificates, Agents,
+    CertificateAuthorities, RevokedAgents,
+};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertificateRevocationListParams,
+    CertificateSigningRequestParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyIdMethod, KeyPair, KeyUsagePurpose, PublicKeyData, RevocationReason, RevokedCertParams,
+    SanType, SerialNumber,
+};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, SqlErr, TransactionTrait,
+};
+use std::net::IpAddr;
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// DNS suffix binding an agent certificate to its agent ID (must match the agent)
+pub const AGENT_IDENTITY_DOMAIN: &str = "agents.csf.internal";
+
+/// Validity of the fleet CA certificate
+const CA_VALIDITY_DAYS: i64 = 3650;
+
+/// Validity of certificates issued to agents
+const AGENT_CERT_VALIDITY_DAYS: i64 = 365;
+
+/// How long a replaced CA stays in the trust bundle by default
+pub const DEFAULT_ROTATION_OVERLAP_DAYS: i64 = 30;
+
+/// How long a published CRL is valid; agents refresh well before that
+const CRL_VALIDITY_DAYS: i64 = 7;
+
+/// How long a signed CRL is served before it is signed again, unless a revocation changes it
+const CRL_REISSUE_HOURS: i64 = 24;
+
+/// How long an admin's approval to certify a new agent key lasts
+const REISSUE_APPROVAL_HOURS: i64 = 24;
+
+#[derive(Debug, Error)]
+pub enum CaError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Certificate error: {0}")]
+    CertificateError(#[from] rcgen::Error),
+    #[error("Invalid certificate signing request: {0}")]
+    InvalidCsr(String),
+    #[error("Agent {0} has been revoked")]
+    AgentRevoked(Uuid),
+    #[error("Agent {0} has a certificate for another key, re-issuing needs an admin's approval")]
+    KeyNotApproved(Uuid),
+    #[error("Agent {0} has no current certificate for this key")]
+    KeyNotCurrent(Uuid),
+    #[error("Agent {0} not found")]
+    AgentNotFound(Uuid),
+}
+
+pub type CaResult<T> = Result<T, CaError>;
+
+/// Why a certificate is requested, decides which keys are certified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Issuance {
+    /// Enrolls agents without a certificate, a new key needs an admin's approval
+    Registration,
+    /// Only the key of the agent's current certificate
+    Renewal,
+}
+
+/// A certificate issued to an agent together with the current trust bundle
+pub struct SignedCertificate {
+    pub certificate_pem: String,
+    pub ca_bundle: Vec<String>,
+}
+
+/// Current revocation state published to agents
+pub struct RevocationList {
+    /// One PEM CRL per CA in the trust bundle
+    pub crls: Vec<String>,
+    /// Agents that must be refused regardless of their certificate
+    pub denied_agents: Vec<Uuid>,
+}
+
+/// Operates the fleet CA that issues agent certificates for P2P mTLS
+#[derive(Clone)]
+pub struct CaService {
+    db: DatabaseConnection,
+}
+
+impl CaService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Returns the CA currently used for signing, creating one on first use
+    ///
+    /// A unique index allows a single active CA, so when concurrent requests
+    /// both create one, the loser uses the CA of the winner.
+    pub async fn get_or_create_active_ca(&self) -> CaResult<certificate_authorities::Model> {
+        if let Some(ca) = self.active_ca().await? {
+            return Ok(ca);
+        }
+
+        tracing::info!("Creating fleet certificate authority...");
+        match Self::generate_ca()?.insert(&self.db).await {
+            Ok(ca) => {
+                tracing::info!("Fleet certificate authority created: {}", ca.common_name);
+                Ok(ca)
+            }
+            Err(e) if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) => {
+                tracing::info!("Fleet certificate authority was created concurrently");
+                self.active_ca().await?.ok_or(CaError::DatabaseError(e))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn active_ca(&self) -> CaResult<Option<certificate_authorities::Model>> {
+        Ok(CertificateAuthorities::find()
+            .filter(certificate_authorities::Column::Status.eq("active"))
+            .one(&self.db)
+            .await?)
+    }
+
+    /// PEM certificates agents must trust: the active CA plus CAs still in their overlap period
+    pub async fn ca_bundle(&self) -> CaResult<Vec<String>> {
+        let active = self.get_or_create_active_ca().await?;
+        let now = Utc::now().naive_utc();
+
+        let mut bundle = vec![active.certificate_pem];
+        let retiring = CertificateAuthorities::find()
+            .filter(certificate_authorities::Column::Status.eq("retiring"))
+            .order_by_desc(certificate_authorities::Column::CreatedAt)
+            .all(&self.db)
+            .await?;
+
+        for ca in retiring {
+            if ca.retire_at.is_some_and(|retire_at| retire_at <= now) {
+                // Overlap period is over, drop the CA from the bundle for good
+                let mut ca_active: certificate_authorities::ActiveModel = ca.into();
+                ca_active.status = ActiveValue::Set("retired".to_string());
+                ca_active.update(&self.db).await?;
+            } else {
+                bundle.push(ca.certificate_pem);
+            }
+        }
+
+        Ok(bundle)
+    }
+
+    /// Signs an agent's CSR with the active CA
+    ///
+    /// The subject, SANs and validity are set by the CA, see
+    /// [`Self::subject_alt_names`]. An agent that already has a certificate
+    /// only gets one for the same key, see [`Self::authorize_key`].
+    pub async fn sign_agent_csr(
+        &self,
+        agent_id: Uuid,
+        agent_name: &str,
+        csr_pem: &str,
+        issuance: Issuance,
+    ) -> CaResult<SignedCertificate> {
+        if RevokedAgents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .is_some()
+        {
+            return Err(CaError::AgentRevoked(agent_id));
+        }
+
+        let agent = Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(CaError::AgentNotFound(agent_id))?;
+
+        let ca = self.get_or_create_active_ca().await?;
+        let ca_key = KeyPair::from_pem(&ca.private_key_pem)?;
+        let ca_cert =
+            CertificateParams::from_ca_cert_pem(&ca.certificate_pem)?.self_signed(&ca_key)?;
+
+        // Parsing checks the CSR's signature, so the requester holds the key
+        let mut csr = CertificateSigningRequestParams::from_pem(csr_pem)
+            .map_err(|e| CaError::InvalidCsr(e.to_string()))?;
+        self.authorize_key(agent_id, csr.public_key.der_bytes(), issuance)
+            .await?;
+
+        let subject_alt_names = self
+            .subject_alt_names(&agent, &csr.params.subject_alt_names)
+            .await?;
+
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, agent_name);
+        dn.push(DnType::OrganizationName, "CSF Agent Network");
+
+        let now = Utc::now();
+        let not_after = now + Duration::days(AGENT_CERT_VALIDITY_DAYS);
+        let serial_number = Self::random_serial();
+
+        let params = &mut csr.params;
+        params.distinguished_name = dn;
+        params.subject_alt_names = subject_alt_names;
+        params.serial_number = Some(serial_number.clone());
+        params.not_before = to_offset_date_time(now);
+        params.not_after = to_offset_date_time(not_after);
+        params.is_ca = IsCa::NoCa;
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyEncipherment,
+        ];
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        params.use_authority_key_identifier_extension = true;
+
+        let cert = csr.signed_by(&ca_cert, &ca_key)?;
+        let certificate_pem = cert.pem();
+
+        let issued = agent_certificates::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            agent_id: ActiveValue::Set(Some(agent_id)),
+            ca_id: ActiveValue::Set(ca.id),
+            serial_number: ActiveValue::Set(serial_number.to_string()),
+            certificate_pem: ActiveValue::Set(certificate_pem.clone()),
+            not_before: ActiveValue::Set(now.naive_utc()),
+            not_after: ActiveValue::Set(not_after.naive_utc()),
+            created_at: ActiveValue::Set(now.naive_utc()),
+            revoked_at: ActiveValue::Set(None),
+            revocation_reason: ActiveValue::Set(None),
+        };
+        AgentCertificates::insert(issued)
+            .exec_without_returning(&self.db)
+            .await?;
+
+        tracing::info!(
+            "Issued certificate {} for agent {} ({})",
+            serial_number,
+            agent_name,
+            agent_id
+        );
+
+        Ok(SignedCertificate {
+            certificate_pem,
+            ca_bundle: self.ca_bundle().await?,
+        })
+    }
+
+    /// SANs to certify for an agent, starting with its identity name
+    ///
+    /// Of the requested names only the hostname and IP address the backend
+    /// recorded for the agent are kept, and only while no other agent has
+    /// the same recorded. Wildcards are refused, other names left out.
+    async fn subject_alt_names(
+        &self,
+        agent: &agents::Model,
+        requested: &[SanType],
+    ) -> CaResult<Vec<SanType>> {
+        let identity_suffix = format!(".{}", AGENT_IDENTITY_DOMAIN);
+        let mut subject_alt_names = vec![SanType::DnsName(
+            format!("{}{}", agent.id, identity_suffix).try_into()?,
+        )];
+        let recorded_ip = agent
+            .ip_address
+            .as_deref()
+            .and_then(|ip| ip.parse::<IpAddr>().ok());
+
+        for san in requested {
+            if subject_alt_names.contains(san) {
+                continue;
+            }
+            let allowed = match san {
+                SanType::DnsName(name) if name.as_str().contains('*') => {
+                    return Err(CaError::InvalidCsr(format!(
+                        "wildcard name {} is not allowed",
+                        name.as_str()
+                    )));
+                }
+                SanType::DnsName(name) => {
+                    name.as_str().eq_ignore_ascii_case(&agent.hostname)
+                        && !agent.hostname.ends_with(&identity_suffix)
+                        && !self
+                            .recorded_for_other_agent(
+                                agent.id,
+                                agents::Column::Hostname,
+                                &agent.hostname,
+                            )
+                            .await?
+                }
+                SanType::IpAddress(ip) => match (recorded_ip, &agent.ip_address) {
+                    (Some(recorded), Some(ip_address)) if recorded == *ip => {
+                        !self
+                            .recorded_for_other_agent(
+                                agent.id,
+                                agents::Column::IpAddress,
+                                ip_address,
+                            )
+                            .await?
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            if allowed {
+                subject_alt_names.push(san.clone());
+            } else {
+                tracing::debug!(
+                    "Leaving {:?} out of the certificate of agent {}",
+                    san,
+                    agent.id
+                );
+            }
+        }
+
+        Ok(subject_alt_names)
+    }
+
+    /// Whether another agent has the same hostname or IP address recorded
+    async fn recorded_for_other_agent(
+        &self,
+        agent_id: Uuid,
+        column: agents::Column,
+        value: &str,
+    ) -> CaResult<bool> {
+        Ok(Agents::find()
+            .filter(column.eq(value))
+            .filter(agents::Column::Id.ne(agent_id))
+            .one(&self.db)
+            .await?
+            .is_some())
+    }
+
+    /// Revokes all unexpired certificates issued to an agent
+    ///
+    /// With `deny` the agent ID is also put on the deny list, so peers refuse
+    /// it outright and the CA no longer issues certificates for it.
+    /// Returns the number of certificates revoked.
+    pub async fn revoke_agent(
+        &self,
+        agent_id: Uuid,
+        reason: Option<String>,
+        revoked_by: Option<Uuid>,
+        deny: bool,
+    ) -> CaResult<usize> {
+        let txn = self.db.begin().await?;
+        let count = revoke_agent_certificates(&txn, agent_id, reason, revoked_by, deny).await?;
+        txn.commit().await?;
+
+        tracing::warn!(
+            "Revoked {} certificate(s) of agent {}{}",
+            count,
+            agent_id,
+            if deny { " and denied the agent" } else { "" }
+        );
+
+        Ok(count)
+    }
+
+    /// Lets the agent get a certificate for a new key once, e.g. after it lost its key
+    pub async fn approve_reissue(&self, agent_id: Uuid) -> CaResult<DateTime<Utc>> {
+        let agent = Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(CaError::AgentNotFound(agent_id))?;
+
+        let approved_until = Utc::now() + Duration::hours(REISSUE_APPROVAL_HOURS);
+        let mut agent_active: agents::ActiveModel = agent.into();
+        agent_active.reissue_approved_until = ActiveValue::Set(Some(approved_until.naive_utc()));
+        agent_active.update(&self.db).await?;
+
+        tracing::info!(
+            "Approved a certificate for a new key of agent {} until {}",
+            agent_id,
+            approved_until
+        );
+        Ok(approved_until)
+    }
+
+    /// Removes an agent from the deny list so it can be issued certificates again
+    ///
+    /// Certificates revoked earlier stay revoked.
+    pub async fn lift_denial(&self, agent_id: Uuid) -> CaResult<bool> {
+        let result = RevokedAgents::delete_by_id(agent_id).exec(&self.db).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// CRLs for every trusted CA together with the agent deny list
+    ///
+    /// A CA's CRL is signed again only after a revocation or once it has been
+    /// served for [`CRL_REISSUE_HOURS`], otherwise the stored one is returned.
+    pub async fn revocation_list(&self) -> CaResult<RevocationList> {
+        // Make sure the retiring CAs are up to date before listing them
+        self.ca_bundle().await?;
+
+        let now = Utc::now();
+        let authorities = CertificateAuthorities::find()
+            .filter(certificate_authorities::Column::Status.is_in(["active", "retiring"]))
+            .order_by_desc(certificate_authorities::Column::CreatedAt)
+            .all(&self.db)
+            .await?;
+
+        // Stored CRLs expiring before this are signed again
+        let reissue_before =
+            now + Duration::days(CRL_VALIDITY_DAYS) - Duration::hours(CRL_REISSUE_HOURS);
+
+        let mut crls = Vec::with_capacity(authorities.len());
+        for ca in authorities {
+            if let (Some(crl_pem), Some(next_update)) = (&ca.crl_pem, ca.crl_next_update) {
+                if next_update.and_utc() > reissue_before {
+                    crls.push(crl_pem.clone());
+                    continue;
+                }
+            }
+
+            let revoked = AgentCertificates::find()
+                .filter(agent_certificates::Column::CaId.eq(ca.id))
+                .filter(agent_certificates::Column::RevokedAt.is_not_null())
+                .filter(agent_certificates::Column::NotAfter.gt(now.naive_utc()))
+                .all(&self.db)
+                .await?;
+
+            let revoked_certs = revoked
+                .into_iter()
+                .filter_map(|certificate| {
+                    let serial_number = parse_serial(&certificate.serial_number)?;
+                    let revoked_at = certificate.revoked_at?.and_utc();
+                    Some(RevokedCertParams {
+                        serial_number,
+                        revocation_time: to_offset_date_time(revoked_at),
+                        reason_code: Some(RevocationReason::Unspecified),
+                        invalidity_date: None,
+                    })
+                })
+                .collect();
+
+            let ca_key = KeyPair::from_pem(&ca.private_key_pem)?;
+            let ca_cert =
+                CertificateParams::from_ca_cert_pem(&ca.certificate_pem)?.self_signed(&ca_key)?;
+
+            let next_update = now + Duration::days(CRL_VALIDITY_DAYS);
+            let params = CertificateRevocationListParams {
+                this_update: to_offset_date_time(now),
+                next_update: to_offset_date_time(next_update),
+                crl_number: SerialNumber::from(now.timestamp().to_be_bytes().to_vec()),
+                issuing_distribution_point: None,
+                revoked_certs,
+                key_identifier_method: KeyIdMethod::Sha256,
+            };
+            let crl_pem = params.signed_by(&ca_cert, &ca_key)?.pem()?;
+
+            // Only stored if no certificate was revoked in the meantime
+            let unchanged = match ca.crl_next_update {
+                Some(stored) => certificate_authorities::Column::CrlNextUpdate.eq(stored),
+                None => certificate_authorities::Column::CrlNextUpdate.is_null(),
+            };
+            CertificateAuthorities::update_many()
+                .col_expr(
+                    certificate_authorities::Column::CrlPem,
+                    Expr::value(crl_pem.clone()),
+                )
+                .col_expr(
+                    certificate_authorities::Column::CrlNextUpdate,
+                    Expr::value(next_update.naive_utc()),
+                )
+                .filter(certificate_authorities::Column::Id.eq(ca.id))
+                .filter(unchanged)
+                .exec(&self.db)
+                .await?;
+            crls.push(crl_pem);
+        }
+
+        let denied_agents = RevokedAgents::find()
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|denied| denied.agent_id)
+            .collect();
+
+        Ok(RevocationList {
+            crls,
+            denied_agents,
+        })
+    }
+
+    /// Replaces the active CA, keeping the old one trusted for `overlap_days`
+    pub async fn rotate(&self, overlap_days: i64) -> CaResult<certificate_authorities::Model> {
+        let previous = self.get_or_create_active_ca().await?;
+        let new_ca = Self::generate_ca()?;
+
+        // The old CA steps down first, only one may be active
+        let retire_at = Utc::now() + Duration::days(overlap_days.max(0));
+        let txn = self.db.begin().await?;
+        let mut previous_active: certificate_authorities::ActiveModel = previous.into();
+        previous_active.status = ActiveValue::Set("retiring".to_string());
+        previous_active.retire_at = ActiveValue::Set(Some(retire_at.naive_utc()));
+        let previous = previous_active.update(&txn).await?;
+        let new_ca = new_ca.insert(&txn).await?;
+        txn.commit().await?;
+
+        tracing::info!(
+            "Rotated fleet CA: {} replaces {} (trusted until {})",
+            new_ca.common_name,
+            previous.common_name,
+            retire_at
+        );
+
+        Ok(new_ca)
+    }
+
+    /// Checks the agent may get a certificate for `public_key`
+    ///
+    /// Only the key of a current (unrevoked, unexpired) certificate is
+    /// certified again, the CSR's signature proves the agent holds it. On
+    /// registration an agent without certificates is enrolled, and any other
+    /// key needs an approval from [`Self::approve_reissue`], which is used up
+    /// and revokes the certificates of the old key.
+    async fn authorize_key(
+        &self,
+        agent_id: Uuid,
+        public_key: &[u8],
+        issuance: Issuance,
+    ) -> CaResult<()> {
+        let certificates = AgentCertificates::find()
+            .filter(agent_certificates::Column::AgentId.eq(agent_id))
+            .all(&self.db)
+            .await?;
+        if certificates.is_empty() && issuance == Issuance::Registration {
+            return Ok(());
+        }
+
+        let now = Utc::now().naive_utc();
+        let current_key = certificates
+            .iter()
+            .filter(|certificate| certificate.revoked_at.is_none() && certificate.not_after > now)
+            .any(|certificate| {
+                certificate_public_key(&certificate.certificate_pem).as_deref() == Some(public_key)
+            });
+        if current_key {
+            return Ok(());
+        }
+        if issuance == Issuance::Renewal {
+            tracing::warn!(
+                "Refused certificate renewal for agent {}: the key is not the one of a current certificate",
+                agent_id
+            );
+            return Err(CaError::KeyNotCurrent(agent_id));
+        }
+
+        // Only one request gets to use the approval
+        let approved = Agents::update_many()
+            .col_expr(
+                agents::Column::ReissueApprovedUntil,
+                Expr::value(Option::<chrono::NaiveDateTime>::None),
+            )
+            .filter(agents::Column::Id.eq(agent_id))
+            .filter(agents::Column::ReissueApprovedUntil.gt(now))
+            .exec(&self.db)
+            .await?
+            .rows_affected
+            > 0;
+        if approved {
+            tracing::info!("Certifying a new key of agent {} as approved", agent_id);
+            // Whoever has the replaced key must not keep using it
+            self.revoke_agent(
+                agent_id,
+                Some("Replaced by a new key".to_string()),
+                None,
+                false,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "Refused certificate for agent {}: the key is not the one of its current certificate",
+            agent_id
+        );
+        Err(CaError::KeyNotApproved(agent_id))
+    }
+
+    /// Generates a new self-signed CA ready to be inserted as the active one
+    fn generate_ca() -> CaResult<certificate_authorities::ActiveModel> {
+        let common_name = format!("CSF-Fleet-CA-{}", Uuid::new_v4());
+        let now = Utc::now();
+        let not_after = now + Duration::days(CA_VALIDITY_DAYS);
+
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, common_name.as_str());
+        dn.push(DnType::OrganizationName, "CSF Agent Network");
+        params.distinguished_name = dn;
+
+        params.serial_number = Some(Self::random_serial());
+        params.not_before = to_offset_date_time(now);
+        params.not_after = to_offset_date_time(not_after);
+        params.key_usages = vec![
+            KeyUsagePurpose::DigitalSignature,
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+        ];
+
+        let key_pair = KeyPair::generate()?;
+        let cert = params.self_signed(&key_pair)?;
+
+        Ok(certificate_authorities::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            common_name: ActiveValue::Set(common_name),
+            certificate_pem: ActiveValue::Set(cert.pem()),
+            private_key_pem: ActiveValue::Set(key_pair.serialize_pem()),
+            status: ActiveValue::Set("active".to_string()),
+            not_before: ActiveValue::Set(now.naive_utc()),
+            not_after: ActiveValue::Set(not_after.naive_utc()),
+            retire_at: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(now.naive_utc()),
+            crl_pem: ActiveValue::Set(None),
+            crl_next_update: ActiveValue::Set(None),
+        })
+    }
+
+    /// Random positive 128-bit serial number
+    fn random_serial() -> SerialNumber {
+        let mut bytes: Vec<u8> = (0..16).map(|_| rand::random::<u8>()).collect();
+        bytes[0] &= 0x7f;
+        SerialNumber::from(bytes)
+    }
+}
+
+/// Revokes the unexpired certificates of an agent and with `deny` puts it on
+/// the deny list, on a connection or within a caller's transaction
+///
+/// Returns the number of certificates revoked.
+pub async fn revoke_agent_certificates<C: ConnectionTrait>(
+    conn: &C,
+    agent_id: Uuid,
+    reason: Option<String>,
+    revoked_by: Option<Uuid>,
+    deny: bool,
+) -> Result<usize, sea_orm::DbErr> {
+    let now = Utc::now().naive_utc();
+    let certificates = AgentCertificates::find()
+        .filter(agent_certificates::Column::AgentId.eq(agent_id))
+        .filter(agent_certificates::Column::RevokedAt.is_null())
+        .filter(agent_certificates::Column::NotAfter.gt(now))
+        .all(conn)
+        .await?;
+
+    let count = certificates.len();
+    let ca_ids: Vec<Uuid> = certificates.iter().map(|c| c.ca_id).collect();
+    for certificate in certificates {
+        let mut certificate_active: agent_certificates::ActiveModel = certificate.into();
+        certificate_active.revoked_at = ActiveValue::Set(Some(now));
+        certificate_active.revocation_reason = ActiveValue::Set(reason.clone());
+        certificate_active.update(conn).await?;
+    }
+
+    // The stored CRLs of the issuing CAs miss these certificates, expiring
+    // them also keeps CRLs built concurrently from being stored
+    if !ca_ids.is_empty() {
+        CertificateAuthorities::update_many()
+            .col_expr(
+                certificate_authorities::Column::CrlNextUpdate,
+                Expr::value(now),
+            )
+            .filter(certificate_authorities::Column::Id.is_in(ca_ids))
+            .exec(conn)
+            .await?;
+    }
+
+    if deny
+        && RevokedAgents::find_by_id(agent_id)
+            .one(conn)
+            .await?
+            .is_none()
+    {
+        let denied = revoked_agents::ActiveModel {
+            agent_id: ActiveValue::Set(agent_id),
+            reason: ActiveValue::Set(reason),
+            revoked_by: ActiveValue::Set(revoked_by),
+            revoked_at: ActiveValue::Set(now),
+        };
+        RevokedAgents::insert(denied)
+            .exec_without_returning(conn)
+            .await?;
+    }
+
+    Ok(count)
+}
+
+/// Raw subject public key of a PEM certificate, as rcgen reads it from a CSR
+fn certificate_public_key(certificate_pem: &str) -> Option<Vec<u8>> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(certificate_pem.as_bytes()).ok()?;
+    let certificate = pem.parse_x509().ok()?;
+    Some(certificate.public_key().subject_public_key.data.to_vec())
+}
+
+/// Parses a serial number stored in its colon-separated hex form
+fn parse_serial(serial: &str) -> Option<SerialNumber> {
+    serial
+        .split(':')
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .map(SerialNumber::from)
+}
+
+fn to_offset_date_time(dt: DateTime<Utc>) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(dt.timestamp()).unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
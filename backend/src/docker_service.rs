@@ -165,6 +165,36 @@ impl DockerService {
         Ok(result)
     }
 
+    /// Lifecycle events of all containers (`start`, `die`, ...) as they happen
+    ///
+    /// Yields the container ID with the action. Ends when the connection to Docker is lost.
+    pub fn container_events(
+        &self,
+    ) -> impl futures_util::Stream<Item = Result<(String, String), DockerError>> {
+        use bollard::system::EventsOptions;
+        use futures_util::stream::StreamExt;
+        use std::collections::HashMap;
+
+        let mut filters = HashMap::new();
+        filters.insert("type".to_string(), vec!["container".to_string()]);
+        let options = EventsOptions {
+            filters,
+            ..Default::default()
+        };
+
+        self.client
+            .events(Some(options))
+            .filter_map(|event| async move {
+                match event {
+                    Ok(event) => {
+                        let id = event.actor.and_then(|actor| actor.id)?;
+                        Some(Ok((id, event.action?)))
+                    }
+                    Err(e) => Some(Err(DockerError::from(e))),
+                }
+            })
+    }
+
     /// Pull a Docker image
     pub async fn pull_image(&self, image: &str) -> Result<(), DockerError> {
         use bollard::image::CreateImageOptions;
@@ -0,0 +1,62 @@
+use entity::{agent_metrics, docker_resources};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Events a subscriber can fall behind by before it misses some
+const EVENT_BUS_CAPACITY: usize = 1024;
+
+/// Something that happened in the backend that live views are interested in
+#[derive(Debug, Clone)]
+pub enum LiveEvent {
+    /// A metrics sample was stored for an agent
+    AgentMetrics(Arc<agent_metrics::Model>),
+    /// The status of a Docker resource changed
+    ResourceStatus(ResourceStatus),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceStatus {
+    pub resource_id: Uuid,
+    pub name: String,
+    pub resource_group_id: Uuid,
+    /// pending, running, stopped, error or deleted
+    pub status: String,
+    pub container_id: Option<String>,
+}
+
+impl ResourceStatus {
+    pub fn of(resource: &docker_resources::Model) -> Self {
+        Self {
+            resource_id: resource.id,
+            name: resource.name.clone(),
+            resource_group_id: resource.resource_group_id,
+            status: resource.status.clone(),
+            container_id: resource.container_id.clone(),
+        }
+    }
+}
+
+/// In-process broadcast of live events to every subscriber
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LiveEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Passes the event to all current subscribers, if there are any
+    pub fn publish(&self, event: LiveEvent) {
+        // Failing only means nobody is listening right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiveEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,494 @@
+use chrono::{DateTime, Utc};
+use entity::{agent_anomalies, agent_metrics, agents, AgentAnomalies, AgentMetrics, Agents};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, FromQueryResult, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::agent_service::STATUS_DECOMMISSIONED;
+use crate::anomaly_service::{DISK_FULL_CRITICAL_DAYS, KIND_DISK_FULL};
+
+/// A heartbeat older than this is late (seconds)
+const HEARTBEAT_LATE_SECONDS: i64 = 180;
+
+/// Without a heartbeat for this long an agent is offline (seconds)
+const HEARTBEAT_OFFLINE_SECONDS: i64 = 600;
+
+/// Utilisation from metrics older than this is not considered current (seconds)
+const METRICS_WINDOW_SECONDS: i64 = 900;
+
+const UTILISATION_WARNING_PERCENT: f32 = 85.0;
+const UTILISATION_CRITICAL_PERCENT: f32 = 95.0;
+
+/// Warn when an agent certificate expires within this many days
+pub const CERT_EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// A certificate expiring within this many days is critical
+const CERT_EXPIRY_CRITICAL_DAYS: i64 = 3;
+
+/// Score lost per warning and per critical issue
+const WARNING_PENALTY: u8 = 10;
+const CRITICAL_PENALTY: u8 = 30;
+
+/// Statuses of agents that were stopped or removed on purpose
+const INACTIVE_STATUSES: [&str; 3] = ["stopped", "deregistered", STATUS_DECOMMISSIONED];
+
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Agent {0} not found")]
+    AgentNotFound(Uuid),
+}
+
+pub type HealthResult<T> = Result<T, HealthError>;
+
+/// Overall health of an agent, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    Healthy,
+    Warning,
+    Critical,
+    Offline,
+    /// Stopped, deregistered or decommissioned on purpose
+    Inactive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthIssue {
+    /// Check that found the issue: heartbeat, status, cpu, memory, disk, service, certificate,
+    /// version, anomaly or forecast
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentHealth {
+    pub agent_id: Uuid,
+    pub name: String,
+    pub state: HealthState,
+    /// 100 without issues, lower for each issue; absent for inactive agents
+    pub score: Option<u8>,
+    pub issues: Vec<HealthIssue>,
+}
+
+/// Agent counts for the dashboard
+#[derive(Debug, Serialize)]
+pub struct FleetSummary {
+    pub total: u64,
+    pub by_state: BTreeMap<HealthState, u64>,
+    pub by_os: BTreeMap<String, u64>,
+    pub by_version: BTreeMap<String, u64>,
+    /// Newest agent version in the fleet, agents on older ones get a warning
+    pub latest_version: Option<String>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Watched service state reported with a heartbeat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceState {
+    pub name: String,
+    pub state: String,
+}
+
+/// Usage columns of a metrics sample
+#[derive(Debug, FromQueryResult)]
+struct UsageSample {
+    agent_id: Uuid,
+    cpu_usage_percent: Option<f32>,
+    memory_usage_percent: Option<f32>,
+    disk_usage_percent: Option<f32>,
+}
+
+/// Latest usage of each kind, which may come from different samples
+#[derive(Debug, Default, Clone, Copy)]
+struct Utilisation {
+    cpu: Option<f32>,
+    memory: Option<f32>,
+    disk: Option<f32>,
+}
+
+/// Computes agent health from heartbeats, recent metrics, watched services,
+/// certificate expiry and agent versions
+#[derive(Clone)]
+pub struct HealthService {
+    db: DatabaseConnection,
+}
+
+impl HealthService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Health of a single agent
+    pub async fn agent_health(&self, agent_id: Uuid) -> HealthResult<AgentHealth> {
+        let agent = Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(HealthError::AgentNotFound(agent_id))?;
+
+        let latest_version = self.latest_version().await?;
+        let utilisation = self.utilisation(Some(agent_id)).await?;
+        let anomalies = self.anomalies(Some(agent_id)).await?;
+        Ok(evaluate(
+            &agent,
+            utilisation.get(&agent_id).copied().unwrap_or_default(),
+            anomalies
+                .get(&agent_id)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            latest_version.as_deref(),
+            Utc::now(),
+        ))
+    }
+
+    /// Health of every agent, optionally only those in the given state
+    pub async fn fleet_health(&self, state: Option<HealthState>) -> HealthResult<Vec<AgentHealth>> {
+        let agents = Agents::find()
+            .order_by_asc(agents::Column::Name)
+            .all(&self.db)
+            .await?;
+        let health = self.evaluate_all(&agents).await?;
+
+        Ok(health
+            .into_iter()
+            .filter(|health| state.is_none_or(|state| health.state == state))
+            .collect())
+    }
+
+    /// Agent counts by health state, OS and agent version
+    pub async fn summary(&self) -> HealthResult<FleetSummary> {
+        let agents = Agents::find().all(&self.db).await?;
+        let health = self.evaluate_all(&agents).await?;
+
+        let mut by_state = BTreeMap::new();
+        for health in &health {
+            *by_state.entry(health.state).or_insert(0) += 1;
+        }
+        let mut by_os = BTreeMap::new();
+        let mut by_version = BTreeMap::new();
+        for agent in &agents {
+            *by_os.entry(agent.os_type.clone()).or_insert(0) += 1;
+            *by_version.entry(agent.agent_version.clone()).or_insert(0) += 1;
+        }
+
+        Ok(FleetSummary {
+            total: agents.len() as u64,
+            by_state,
+            by_os,
+            by_version,
+            latest_version: newest_version(agents.iter().map(|a| a.agent_version.as_str())),
+            generated_at: Utc::now(),
+        })
+    }
+
+    async fn evaluate_all(&self, agents: &[agents::Model]) -> HealthResult<Vec<AgentHealth>> {
+        let latest_version = newest_version(agents.iter().map(|a| a.agent_version.as_str()));
+        let utilisation = self.utilisation(None).await?;
+        let anomalies = self.anomalies(None).await?;
+        let now = Utc::now();
+
+        Ok(agents
+            .iter()
+            .map(|agent| {
+                evaluate(
+                    agent,
+                    utilisation.get(&agent.id).copied().unwrap_or_default(),
+                    anomalies
+                        .get(&agent.id)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                    latest_version.as_deref(),
+                    now,
+                )
+            })
+            .collect())
+    }
+
+    async fn latest_version(&self) -> HealthResult<Option<String>> {
+        let versions: Vec<String> = Agents::find()
+            .select_only()
+            .column(agents::Column::AgentVersion)
+            .distinct()
+            .into_tuple()
+            .all(&self.db)
+            .await?;
+        Ok(newest_version(versions.iter().map(String::as_str)))
+    }
+
+    /// Latest usage per agent from recent metrics, only reading the usage columns
+    async fn utilisation(
+        &self,
+        agent_id: Option<Uuid>,
+    ) -> HealthResult<HashMap<Uuid, Utilisation>> {
+        let since = (Utc::now() - chrono::Duration::seconds(METRICS_WINDOW_SECONDS)).naive_utc();
+        let mut query = AgentMetrics::find()
+            .select_only()
+            .columns([
+                agent_metrics::Column::AgentId,
+                agent_metrics::Column::CpuUsagePercent,
+                agent_metrics::Column::MemoryUsagePercent,
+                agent_metrics::Column::DiskUsagePercent,
+            ])
+            .filter(agent_metrics::Column::Timestamp.gt(since))
+            .order_by_desc(agent_metrics::Column::Timestamp);
+        if let Some(agent_id) = agent_id {
+            query = query.filter(agent_metrics::Column::AgentId.eq(agent_id));
+        }
+
+        let samples = query.into_model::<UsageSample>().all(&self.db).await?;
+
+        // Samples only hold the metric groups that were due, so take the newest value of each
+        let mut utilisation: HashMap<Uuid, Utilisation> = HashMap::new();
+        for sample in samples {
+            let latest = utilisation.entry(sample.agent_id).or_default();
+            latest.cpu = latest.cpu.or(sample.cpu_usage_percent);
+            latest.memory = latest.memory.or(sample.memory_usage_percent);
+            latest.disk = latest.disk.or(sample.disk_usage_percent);
+        }
+        Ok(utilisation)
+    }
+
+    /// Open anomalies per agent from the last analysis
+    async fn anomalies(
+        &self,
+        agent_id: Option<Uuid>,
+    ) -> HealthResult<HashMap<Uuid, Vec<agent_anomalies::Model>>> {
+        let mut query = AgentAnomalies::find()
+            .filter(agent_anomalies::Column::ResolvedAt.is_null())
+            .order_by_asc(agent_anomalies::Column::FirstDetectedAt);
+        if let Some(agent_id) = agent_id {
+            query = query.filter(agent_anomalies::Column::AgentId.eq(agent_id));
+        }
+
+        let mut anomalies: HashMap<Uuid, Vec<agent_anomalies::Model>> = HashMap::new();
+        for anomaly in query.all(&self.db).await? {
+            anomalies.entry(anomaly.agent_id).or_default().push(anomaly);
+        }
+        Ok(anomalies)
+    }
+}
+
+/// Health of an agent at `now`
+fn evaluate(
+    agent: &agents::Model,
+    utilisation: Utilisation,
+    anomalies: &[agent_anomalies::Model],
+    latest_version: Option<&str>,
+    now: DateTime<Utc>,
+) -> AgentHealth {
+    let health = |state: HealthState, score: Option<u8>, issues: Vec<HealthIssue>| AgentHealth {
+        agent_id: agent.id,
+        name: agent.name.clone(),
+        state,
+        score,
+        issues,
+    };
+
+    if INACTIVE_STATUSES.contains(&agent.status.as_str()) {
+        return health(HealthState::Inactive, None, Vec::new());
+    }
+
+    let silence = agent
+        .last_heartbeat
+        .map(|last| (now - last.and_utc()).num_seconds());
+    if silence.is_none_or(|silence| silence >= HEARTBEAT_OFFLINE_SECONDS) {
+        let message = match silence {
+            Some(silence) => format!("No heartbeat for {} minutes", silence / 60),
+            None => "No heartbeat received yet".to_string(),
+        };
+        return health(
+            HealthState::Offline,
+            Some(0),
+            vec![issue("heartbeat", Severity::Critical, message)],
+        );
+    }
+
+    let mut issues = Vec::new();
+    if let Some(silence) = silence.filter(|silence| *silence >= HEARTBEAT_LATE_SECONDS) {
+        issues.push(issue(
+            "heartbeat",
+            Severity::Warning,
+            format!("Last heartbeat {} seconds ago", silence),
+        ));
+    }
+
+    if agent.status != "online" {
+        issues.push(issue(
+            "status",
+            Severity::Warning,
+            format!("Agent reports status {}", agent.status),
+        ));
+    }
+
+    for (check, usage) in [
+        ("cpu", utilisation.cpu),
+        ("memory", utilisation.memory),
+        ("disk", utilisation.disk),
+    ] {
+        let Some(usage) = usage else { continue };
+        let severity = if usage >= UTILISATION_CRITICAL_PERCENT {
+            Severity::Critical
+        } else if usage >= UTILISATION_WARNING_PERCENT {
+            Severity::Warning
+        } else {
+            continue;
+        };
+        issues.push(issue(
+            check,
+            severity,
+            format!("{} usage at {:.1}%", check, usage),
+        ));
+    }
+
+    for anomaly in anomalies {
+        if anomaly.kind == KIND_DISK_FULL {
+            let days = anomaly.days_until_full.unwrap_or_default();
+            let severity = if days < DISK_FULL_CRITICAL_DAYS {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            issues.push(issue(
+                "forecast",
+                severity,
+                format!("Disk full in ~{:.0} days", days),
+            ));
+        } else {
+            issues.push(issue(
+                "anomaly",
+                Severity::Warning,
+                format!(
+                    "{} at {} deviates from its baseline of {}",
+                    anomaly.metric,
+                    metric_value(&anomaly.metric, anomaly.value),
+                    metric_value(&anomaly.metric, anomaly.baseline.unwrap_or_default()),
+                ),
+            ));
+        }
+    }
+
+    for service in service_states(&agent.services) {
+        if service.state == "active" {
+            continue;
+        }
+        let severity = if service.state == "failed" {
+            Severity::Critical
+        } else {
+            Severity::Warning
+        };
+        issues.push(issue(
+            "service",
+            severity,
+            format!("Service {} is {}", service.name, service.state),
+        ));
+    }
+
+    if let Some(expires_at) = agent.cert_expires_at {
+        let remaining = expires_at.and_utc() - now;
+        if remaining <= chrono::Duration::zero() {
+            issues.push(issue(
+                "certificate",
+                Severity::Critical,
+                format!("Certificate expired at {}", expires_at),
+            ));
+        } else if remaining < chrono::Duration::days(CERT_EXPIRY_WARNING_DAYS) {
+            let severity = if remaining < chrono::Duration::days(CERT_EXPIRY_CRITICAL_DAYS) {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            };
+            issues.push(issue(
+                "certificate",
+                severity,
+                format!("Certificate expires in {} days", remaining.num_days()),
+            ));
+        }
+    }
+
+    if let Some(latest) = latest_version {
+        if version_key(&agent.agent_version) < version_key(latest) {
+            issues.push(issue(
+                "version",
+                Severity::Warning,
+                format!("Agent version {} is behind {}", agent.agent_version, latest),
+            ));
+        }
+    }
+
+    let (warnings, criticals) =
+        issues
+            .iter()
+            .fold((0u8, 0u8), |(w, c), issue| match issue.severity {
+                Severity::Warning => (w.saturating_add(1), c),
+                Severity::Critical => (w, c.saturating_add(1)),
+            });
+    let state = if criticals > 0 {
+        HealthState::Critical
+    } else if warnings > 0 {
+        HealthState::Warning
+    } else {
+        HealthState::Healthy
+    };
+    let score = 100u8
+        .saturating_sub(warnings.saturating_mul(WARNING_PENALTY))
+        .saturating_sub(criticals.saturating_mul(CRITICAL_PENALTY));
+
+    health(state, Some(score), issues)
+}
+
+fn issue(check: &'static str, severity: Severity, message: String) -> HealthIssue {
+    HealthIssue {
+        check,
+        severity,
+        message,
+    }
+}
+
+/// Usage in percent, network rates in bytes per second
+fn metric_value(metric: &str, value: f64) -> String {
+    if metric.starts_with("network") {
+        format!("{:.0} B/s", value)
+    } else {
+        format!("{:.1}%", value)
+    }
+}
+
+/// Watched service states stored with the agent
+pub fn service_states(services: &Option<serde_json::Value>) -> Vec<ServiceState> {
+    services
+        .as_ref()
+        .and_then(|services| serde_json::from_value(services.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Numeric parts of a version like `1.4.2-beta`, for ordering
+fn version_key(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
+
+fn newest_version<'a>(versions: impl Iterator<Item = &'a str>) -> Option<String> {
+    versions
+        .max_by_key(|version| version_key(version))
+        .map(str::to_string)
+}
@@ -30,6 +30,9 @@ pub async fn initialize_database(
             id: ActiveValue::Set(key_id),
             name: ActiveValue::Set("main".to_string()),
             private_key: ActiveValue::Set(key_pair.private_key),
+            algorithm: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
+            retired_at: ActiveValue::Set(None),
         };
         Key::insert(new_key).exec_without_returning(db).await?;
         tracing::info!("RSA key pair created successfully");
@@ -197,6 +200,8 @@ pub async fn initialize_database(
             two_factor_secret: ActiveValue::NotSet,
             two_factor_enabled: ActiveValue::Set(false),
             force_password_change: ActiveValue::Set(true),
+            tokens_valid_after: ActiveValue::Set(None),
+            two_factor_last_step: ActiveValue::Set(None),
         };
 
         User::insert(admin_user).exec_without_returning(db).await?;
@@ -0,0 +1,251 @@
+use chrono::{DateTime, Utc};
+use entity::{agent_inventory, AgentInventory, Agents};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::agent_service::STATUS_DECOMMISSIONED;
+
+#[derive(Debug, Error)]
+pub enum InventoryError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Invalid inventory: {0}")]
+    InvalidInventory(#[from] serde_json::Error),
+    #[error("Agent {0} not found")]
+    AgentNotFound(Uuid),
+    #[error("Agent {0} is decommissioned")]
+    AgentDecommissioned(Uuid),
+}
+
+pub type InventoryResult<T> = Result<T, InventoryError>;
+
+/// Criteria to find agents by their inventory, all optional
+#[derive(Debug, Default, Deserialize)]
+pub struct InventoryFilter {
+    pub os_name: Option<String>,
+    pub kernel_version: Option<String>,
+    pub architecture: Option<String>,
+    /// Part of the CPU model name
+    pub cpu_model: Option<String>,
+    pub hypervisor: Option<String>,
+    pub container: Option<String>,
+    pub package_manager: Option<String>,
+    /// MAC or IP address of any network interface
+    pub address: Option<String>,
+}
+
+/// Parts of an agent's inventory that are kept in their own columns
+///
+/// Everything is optional, so inventories of older and newer agents are accepted.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct InventoryColumns {
+    cpu: CpuColumns,
+    memory: MemoryColumns,
+    disks: Vec<DiskColumns>,
+    network_interfaces: Vec<NetworkInterfaceColumns>,
+    os: OsColumns,
+    packages: Option<PackageColumns>,
+    virtualization: VirtualizationColumns,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CpuColumns {
+    model: Option<String>,
+    vendor: Option<String>,
+    sockets: Option<i32>,
+    cores: Option<i32>,
+    threads: Option<i32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct MemoryColumns {
+    total_bytes: Option<i64>,
+    modules: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct DiskColumns {
+    size_bytes: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct NetworkInterfaceColumns {
+    mac_address: Option<String>,
+    ip_addresses: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct OsColumns {
+    name: Option<String>,
+    version: Option<String>,
+    kernel_version: Option<String>,
+    architecture: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PackageColumns {
+    manager: Option<String>,
+    count: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct VirtualizationColumns {
+    hypervisor: Option<String>,
+    container: Option<String>,
+}
+
+/// Keeps the latest hardware and software inventory reported by each agent
+#[derive(Clone)]
+pub struct InventoryService {
+    db: DatabaseConnection,
+}
+
+impl InventoryService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Replaces the stored inventory of an agent
+    pub async fn store(
+        &self,
+        agent_id: Uuid,
+        inventory: serde_json::Value,
+        collected_at: DateTime<Utc>,
+    ) -> InventoryResult<()> {
+        let agent = Agents::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .ok_or(InventoryError::AgentNotFound(agent_id))?;
+        if agent.status == STATUS_DECOMMISSIONED {
+            return Err(InventoryError::AgentDecommissioned(agent_id));
+        }
+
+        let columns: InventoryColumns = serde_json::from_value(inventory.clone())?;
+        let mac_addresses: Vec<String> = columns
+            .network_interfaces
+            .iter()
+            .filter_map(|interface| interface.mac_address.clone())
+            .collect();
+        let ip_addresses: Vec<String> = columns
+            .network_interfaces
+            .iter()
+            .flat_map(|interface| interface.ip_addresses.iter().cloned())
+            .collect();
+        let (package_manager, package_count) = columns
+            .packages
+            .map(|packages| (packages.manager, packages.count))
+            .unwrap_or_default();
+
+        let model = agent_inventory::ActiveModel {
+            agent_id: ActiveValue::Set(agent_id),
+            cpu_model: ActiveValue::Set(columns.cpu.model),
+            cpu_vendor: ActiveValue::Set(columns.cpu.vendor),
+            cpu_sockets: ActiveValue::Set(columns.cpu.sockets),
+            cpu_cores: ActiveValue::Set(columns.cpu.cores),
+            cpu_threads: ActiveValue::Set(columns.cpu.threads),
+            memory_total_bytes: ActiveValue::Set(columns.memory.total_bytes),
+            memory_module_count: ActiveValue::Set(Some(columns.memory.modules.len() as i32)),
+            disk_count: ActiveValue::Set(Some(columns.disks.len() as i32)),
+            disk_total_bytes: ActiveValue::Set(Some(
+                columns.disks.iter().map(|disk| disk.size_bytes).sum(),
+            )),
+            mac_addresses: ActiveValue::Set(Some(serde_json::to_value(mac_addresses)?)),
+            ip_addresses: ActiveValue::Set(Some(serde_json::to_value(ip_addresses)?)),
+            os_name: ActiveValue::Set(columns.os.name),
+            os_version: ActiveValue::Set(columns.os.version),
+            kernel_version: ActiveValue::Set(columns.os.kernel_version),
+            architecture: ActiveValue::Set(columns.os.architecture),
+            package_manager: ActiveValue::Set(package_manager),
+            package_count: ActiveValue::Set(package_count),
+            hypervisor: ActiveValue::Set(columns.virtualization.hypervisor),
+            container: ActiveValue::Set(columns.virtualization.container),
+            inventory: ActiveValue::Set(inventory),
+            collected_at: ActiveValue::Set(collected_at.naive_utc()),
+            updated_at: ActiveValue::Set(Utc::now().naive_utc()),
+        };
+
+        if AgentInventory::find_by_id(agent_id)
+            .one(&self.db)
+            .await?
+            .is_some()
+        {
+            model.update(&self.db).await?;
+        } else {
+            model.insert(&self.db).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Latest inventory of an agent, if it reported one
+    pub async fn get(&self, agent_id: Uuid) -> InventoryResult<Option<agent_inventory::Model>> {
+        Ok(AgentInventory::find_by_id(agent_id).one(&self.db).await?)
+    }
+
+    /// Inventories matching all given criteria
+    pub async fn find(
+        &self,
+        filter: &InventoryFilter,
+    ) -> InventoryResult<Vec<agent_inventory::Model>> {
+        let mut query = AgentInventory::find().order_by_asc(agent_inventory::Column::AgentId);
+        if let Some(os_name) = &filter.os_name {
+            query = query.filter(agent_inventory::Column::OsName.eq(os_name));
+        }
+        if let Some(kernel_version) = &filter.kernel_version {
+            query = query.filter(agent_inventory::Column::KernelVersion.eq(kernel_version));
+        }
+        if let Some(architecture) = &filter.architecture {
+            query = query.filter(agent_inventory::Column::Architecture.eq(architecture));
+        }
+        if let Some(cpu_model) = &filter.cpu_model {
+            query = query.filter(agent_inventory::Column::CpuModel.contains(cpu_model));
+        }
+        if let Some(hypervisor) = &filter.hypervisor {
+            query = query.filter(agent_inventory::Column::Hypervisor.eq(hypervisor));
+        }
+        if let Some(container) = &filter.container {
+            query = query.filter(agent_inventory::Column::Container.eq(container));
+        }
+        if let Some(package_manager) = &filter.package_manager {
+            query = query.filter(agent_inventory::Column::PackageManager.eq(package_manager));
+        }
+
+        let mut inventories = query.all(&self.db).await?;
+        // Addresses are kept as JSON arrays, compared here to stay independent of the database
+        if let Some(address) = &filter.address {
+            inventories.retain(|inventory| has_address(inventory, address));
+        }
+
+        Ok(inventories)
+    }
+}
+
+/// Whether any interface has the MAC address or IP address (with or without prefix length)
+fn has_address(inventory: &agent_inventory::Model, address: &str) -> bool {
+    let values = |json: &Option<serde_json::Value>| -> Vec<String> {
+        json.as_ref()
+            .and_then(|json| serde_json::from_value(json.clone()).ok())
+            .unwrap_or_default()
+    };
+
+    let mac = values(&inventory.mac_addresses)
+        .iter()
+        .any(|mac| mac.eq_ignore_ascii_case(address));
+    let ip = values(&inventory.ip_addresses)
+        .iter()
+        .any(|ip| ip == address || ip.split('/').next() == Some(address));
+    mac || ip
+}
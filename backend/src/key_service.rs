@@ -0,0 +1,229 @@
+use chrono::{Duration, Utc};
+use entity::{key, Key};
+use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::crypto::{CryptoError, RsaKeyPair};
+use crate::auth::jwt::{JwtKeys, SigningKey, ACCESS_TOKEN_MINUTES, JWKS_MAX_AGE_SECONDS};
+
+/// Name of JWT signing keys in the key table
+const SIGNING_KEY_NAME: &str = "jwt-signing";
+
+/// Algorithm stored with RSA signing keys
+const RSA_ALGORITHM: &str = "RS256";
+
+/// Days a signing key is used before it is replaced, unless `JWT_KEY_ROTATION_DAYS` is set
+const DEFAULT_ROTATION_DAYS: i64 = 30;
+
+/// How often keys are checked for rotation and reloaded from the database (minutes)
+const ROTATION_CHECK_MINUTES: i64 = 15;
+
+const ROTATION_CHECK_INTERVAL: std::time::Duration =
+    std::time::Duration::from_secs(ROTATION_CHECK_MINUTES as u64 * 60);
+
+/// Extra minutes a retired key keeps verifying. Other instances sign with it
+/// until their next check, plus some clock skew between services.
+const RETIRED_KEY_GRACE_MINUTES: i64 = 20;
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+    #[error("Invalid signing key {0}: {1}")]
+    InvalidKey(Uuid, jsonwebtoken::errors::Error),
+}
+
+pub type KeyResult<T> = Result<T, KeyError>;
+
+/// Manages the keys JWTs are signed with
+///
+/// A new key is published first and signs only once every instance and
+/// every JWKS client has seen it; the keys it replaces keep verifying until
+/// the tokens they signed have expired. All instances share the keys through
+/// the database.
+#[derive(Clone)]
+pub struct KeyService {
+    db: DatabaseConnection,
+}
+
+impl KeyService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Publishes the next key once the signing key is older than `rotation`,
+    /// retires the keys a published key replaced, drops expired keys and
+    /// loads the remaining ones into `keys`
+    pub async fn sync(&self, keys: &JwtKeys, rotation: Duration) -> KeyResult<()> {
+        let now = Utc::now().naive_utc();
+        let unretired = Key::find()
+            .filter(key::Column::Algorithm.eq(RSA_ALGORITHM))
+            .filter(key::Column::RetiredAt.is_null())
+            .order_by_desc(key::Column::CreatedAt)
+            .all(&self.db)
+            .await?;
+
+        match signing_key(&unretired) {
+            // The first key signs right away, no token can be signed with another one
+            None => {
+                let kid = self.rotate().await?;
+                tracing::info!("Created JWT signing key {}", kid);
+            }
+            Some(current) => {
+                // Keys replaced by the signing key only verify from now on
+                let retired = Key::update_many()
+                    .col_expr(key::Column::RetiredAt, sea_orm::sea_query::Expr::value(now))
+                    .filter(key::Column::Algorithm.is_not_null())
+                    .filter(key::Column::RetiredAt.is_null())
+                    .filter(key::Column::CreatedAt.lt(current.created_at))
+                    .exec(&self.db)
+                    .await?
+                    .rows_affected;
+                if retired > 0 {
+                    tracing::info!("JWT signing key {} replaced {} key(s)", current.id, retired);
+                }
+
+                let pending = unretired[0].created_at > current.created_at;
+                if !pending && now - current.created_at >= rotation {
+                    let kid = self.rotate().await?;
+                    tracing::info!(
+                        "Published JWT signing key {}, it signs from {}",
+                        kid,
+                        now + publish_delay()
+                    );
+                }
+            }
+        }
+
+        let purged = Key::delete_many()
+            .filter(key::Column::Algorithm.is_not_null())
+            .filter(key::Column::RetiredAt.lt(verify_cutoff()))
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if purged > 0 {
+            tracing::info!("Removed {} expired JWT signing keys", purged);
+        }
+
+        self.load(keys).await
+    }
+
+    /// Creates the next signing key, it is published right away and signs
+    /// after [`publish_delay`], when the next sync retires the previous ones
+    pub async fn rotate(&self) -> KeyResult<Uuid> {
+        let key_pair = RsaKeyPair::generate()?;
+        let kid = Uuid::new_v4();
+        let now = Utc::now().naive_utc();
+
+        Key::insert(key::ActiveModel {
+            id: ActiveValue::Set(kid),
+            name: ActiveValue::Set(SIGNING_KEY_NAME.to_string()),
+            private_key: ActiveValue::Set(key_pair.private_key),
+            algorithm: ActiveValue::Set(Some(RSA_ALGORITHM.to_string())),
+            created_at: ActiveValue::Set(now),
+            retired_at: ActiveValue::Set(None),
+        })
+        .exec_without_returning(&self.db)
+        .await?;
+
+        Ok(kid)
+    }
+
+    /// Loads the signing key, the published next key and the retired keys
+    /// that still verify
+    pub async fn load(&self, keys: &JwtKeys) -> KeyResult<()> {
+        let stored = Key::find()
+            .filter(key::Column::Algorithm.eq(RSA_ALGORITHM))
+            .filter(
+                key::Column::RetiredAt
+                    .is_null()
+                    .or(key::Column::RetiredAt.gte(verify_cutoff())),
+            )
+            .order_by_desc(key::Column::CreatedAt)
+            .all(&self.db)
+            .await?;
+
+        // Another instance may have rotated at the same time, the newest published key wins
+        let unretired: Vec<key::Model> = stored
+            .iter()
+            .filter(|key| key.retired_at.is_none())
+            .cloned()
+            .collect();
+        let Some(current) = signing_key(&unretired) else {
+            return Ok(());
+        };
+        let current = current.id;
+
+        let signing_keys = stored
+            .iter()
+            .map(|key| {
+                SigningKey::from_rsa_pem(key.id, &key.private_key)
+                    .map_err(|e| KeyError::InvalidKey(key.id, e))
+            })
+            .collect::<KeyResult<Vec<_>>>()?;
+        keys.replace(current, signing_keys);
+
+        Ok(())
+    }
+}
+
+/// How long a new key is only published: every instance loads it within a
+/// check and JWKS clients refetch the set once their copy expires
+fn publish_delay() -> Duration {
+    Duration::minutes(ROTATION_CHECK_MINUTES) + Duration::seconds(JWKS_MAX_AGE_SECONDS)
+}
+
+/// The key that signs among unretired keys ordered newest first
+///
+/// That is the newest key published for at least [`publish_delay`], or the
+/// first key if none has been published that long.
+fn signing_key(unretired: &[key::Model]) -> Option<&key::Model> {
+    let published_before = Utc::now().naive_utc() - publish_delay();
+    unretired
+        .iter()
+        .find(|key| key.created_at <= published_before)
+        .or_else(|| unretired.last())
+}
+
+/// Keys retired before this can't have signed a token that is still valid
+fn verify_cutoff() -> chrono::NaiveDateTime {
+    (Utc::now() - Duration::minutes(ACCESS_TOKEN_MINUTES + RETIRED_KEY_GRACE_MINUTES)).naive_utc()
+}
+
+/// Rotation interval from `JWT_KEY_ROTATION_DAYS`
+pub fn rotation_interval() -> Duration {
+    let days = match std::env::var("JWT_KEY_ROTATION_DAYS") {
+        Ok(value) => match value.parse::<i64>() {
+            Ok(days) if days > 0 => days,
+            _ => {
+                tracing::warn!(
+                    "Invalid JWT_KEY_ROTATION_DAYS {:?}, using {} days",
+                    value,
+                    DEFAULT_ROTATION_DAYS
+                );
+                DEFAULT_ROTATION_DAYS
+            }
+        },
+        Err(_) => DEFAULT_ROTATION_DAYS,
+    };
+    Duration::days(days)
+}
+
+/// Rotates signing keys when they are due and picks up keys rotated by other instances
+pub fn start_key_rotation(db: DatabaseConnection, keys: JwtKeys, rotation: Duration) {
+    let service = KeyService::new(db);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ROTATION_CHECK_INTERVAL);
+        // The first tick fires immediately, keys were just loaded at startup
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = service.sync(&keys, rotation).await {
+                tracing::error!("JWT signing key rotation failed: {}", e);
+            }
+        }
+    });
+}
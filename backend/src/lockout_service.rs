@@ -0,0 +1,408 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use entity::{login_throttles, user, LoginThrottles, User};
+use sea_orm::{
+    sea_query::{Expr, OnConflict, Value},
+    ActiveValue, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde_json::json;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::audit_service::{AuditEvent, AuditService, EVENT_LOGIN_LOCKOUT, EVENT_LOGIN_UNLOCK};
+
+pub const SCOPE_ACCOUNT: &str = "account";
+pub const SCOPE_IP: &str = "ip";
+/// Client IPs sending invalid agent API keys, apart from logins so agents can't lock users out
+pub const SCOPE_API_KEY: &str = "api_key";
+
+/// Failures are forgotten after this long without another one (minutes)
+const FAILURE_WINDOW_MINUTES: i64 = 60;
+
+/// How long a lockout lasts (minutes)
+const LOCKOUT_MINUTES: i64 = 15;
+
+/// Longest wait between attempts before a lockout (seconds)
+const MAX_DELAY_SECONDS: i64 = 30;
+
+/// Failures reset after the window or once a lockout ended, otherwise count up
+const COUNT_FAILURE: &str = r#"CASE WHEN "login_throttles"."last_failure_at" < $1 OR "login_throttles"."locked_until" < $2 THEN 1 ELSE "login_throttles"."failures" + 1 END"#;
+
+/// Lockouts that ended are cleared
+const CLEAR_ENDED_LOCKOUT: &str = r#"CASE WHEN "login_throttles"."locked_until" < $1 THEN NULL ELSE "login_throttles"."locked_until" END"#;
+
+/// When attempts get delayed and locked, per scope
+struct Policy {
+    /// Failures allowed without a delay
+    free_attempts: i32,
+    /// Failures that lock the subject
+    lockout_failures: i32,
+}
+
+impl Policy {
+    fn of(scope: &str) -> Self {
+        match scope {
+            // Many users or agents may share an address, so it takes more failures
+            SCOPE_IP | SCOPE_API_KEY => Policy {
+                free_attempts: 20,
+                lockout_failures: 50,
+            },
+            _ => Policy {
+                free_attempts: 3,
+                lockout_failures: 10,
+            },
+        }
+    }
+
+    /// Wait after the last failure before the next attempt, doubling with every failure
+    fn delay(&self, failures: i32) -> Duration {
+        if failures < self.free_attempts {
+            return Duration::zero();
+        }
+        let exponent = (failures - self.free_attempts).min(5) as u32;
+        Duration::seconds(2i64.pow(exponent).min(MAX_DELAY_SECONDS))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LockoutError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Too many failed attempts, retry in {0} seconds")]
+    TooManyAttempts(i64),
+    #[error("Lockout {0} not found")]
+    NotFound(Uuid),
+    #[error("User {0} not found")]
+    UserNotFound(Uuid),
+}
+
+pub type LockoutResult<T> = Result<T, LockoutError>;
+
+/// Slows down and locks out repeated failed credential checks
+///
+/// Failures are counted per account name and per client IP, invalid agent
+/// API keys per client IP. Attempts are delayed more with every failure, too
+/// many lock the account or IP for a while.
+///
+/// Every attempt is counted as a failure before the credentials are checked,
+/// so concurrent attempts can't all pass before the first failure counts.
+/// Attempts that succeed are taken back afterwards.
+#[derive(Clone)]
+pub struct LockoutService {
+    db: DatabaseConnection,
+}
+
+impl LockoutService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// Counts an attempt, refused while the account or IP is locked or has to wait
+    pub async fn reserve(&self, account: &str, ip: &str) -> LockoutResult<()> {
+        self.reserve_subjects(&[(SCOPE_ACCOUNT, account), (SCOPE_IP, ip)])
+            .await
+    }
+
+    /// Locks the account or IP once a failed attempt made too many
+    pub async fn record_failure(&self, account: &str, ip: &str) -> LockoutResult<()> {
+        for (scope, subject) in [(SCOPE_ACCOUNT, account), (SCOPE_IP, ip)] {
+            self.record(scope, subject, ip).await?;
+        }
+        Ok(())
+    }
+
+    /// Forgets the failures of an account after it authenticated
+    ///
+    /// The IP only gets the attempt back, or a valid account would reset its
+    /// failures between guesses.
+    pub async fn record_success(&self, account: &str, ip: &str) -> LockoutResult<()> {
+        LoginThrottles::delete_many()
+            .filter(subject(SCOPE_ACCOUNT, account))
+            .exec(&self.db)
+            .await?;
+        self.undo(SCOPE_IP, ip).await
+    }
+
+    /// Takes back an attempt that neither failed nor succeeded, e.g. on an internal error
+    pub async fn release(&self, account: &str, ip: &str) -> LockoutResult<()> {
+        self.undo(SCOPE_ACCOUNT, account).await?;
+        self.undo(SCOPE_IP, ip).await
+    }
+
+    /// Counts an agent request, refused while its IP is locked for invalid API keys or has to wait
+    pub async fn reserve_api_key(&self, ip: &str) -> LockoutResult<()> {
+        self.reserve_subjects(&[(SCOPE_API_KEY, ip)]).await
+    }
+
+    /// Locks the IP once an invalid API key made too many
+    pub async fn record_api_key_failure(&self, ip: &str) -> LockoutResult<()> {
+        self.record(SCOPE_API_KEY, ip, ip).await
+    }
+
+    /// Takes back an agent request whose API key was valid or couldn't be checked
+    pub async fn release_api_key(&self, ip: &str) -> LockoutResult<()> {
+        self.undo(SCOPE_API_KEY, ip).await
+    }
+
+    /// Counts an attempt on all subjects, or on none if one of them has to wait
+    ///
+    /// Each subject's row is locked until the attempt is counted, so
+    /// concurrent attempts queue up and each sees the ones before it.
+    async fn reserve_subjects(&self, subjects: &[(&str, &str)]) -> LockoutResult<()> {
+        let txn = self.db.begin().await?;
+
+        let mut wait = Duration::zero();
+        for (scope, name) in subjects {
+            let previous = LoginThrottles::find()
+                .filter(subject(scope, name))
+                .lock_exclusive()
+                .one(&txn)
+                .await?;
+            // Only now, the attempt before may have been counted while waiting for the lock
+            let now = Utc::now().naive_utc();
+            let counted = count_failure(&txn, scope, name, now).await?;
+            // Without a row to lock, the attempts before were concurrent with this one
+            let previous = previous.unwrap_or(login_throttles::Model {
+                failures: counted.failures - 1,
+                ..counted
+            });
+            wait = wait.max(wait_until(&previous, now) - now);
+        }
+
+        if wait > Duration::zero() {
+            // A refused attempt doesn't count
+            txn.rollback().await?;
+            // Round up, retrying a moment early would just fail again
+            return Err(LockoutError::TooManyAttempts(
+                (wait.num_milliseconds() + 999) / 1000,
+            ));
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    /// Locked subjects and those with recent failures, most recent first
+    pub async fn list(&self) -> LockoutResult<Vec<login_throttles::Model>> {
+        let now = Utc::now().naive_utc();
+        Ok(LoginThrottles::find()
+            .filter(
+                Condition::any()
+                    .add(login_throttles::Column::LockedUntil.gt(now))
+                    .add(login_throttles::Column::LastFailureAt.gte(window_start(now))),
+            )
+            .order_by_desc(login_throttles::Column::LastFailureAt)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Lifts a lockout and forgets the failures
+    pub async fn unlock(&self, id: Uuid, actor_id: Uuid) -> LockoutResult<()> {
+        let throttle = LoginThrottles::find_by_id(id)
+            .one(&self.db)
+            .await?
+            .ok_or(LockoutError::NotFound(id))?;
+        LoginThrottles::delete_by_id(id).exec(&self.db).await?;
+
+        let user_id = match throttle.scope.as_str() {
+            SCOPE_ACCOUNT => self.user_id(&throttle.subject).await?,
+            _ => None,
+        };
+        self.audit(AuditEvent {
+            event: EVENT_LOGIN_UNLOCK,
+            user_id,
+            actor_id: Some(actor_id),
+            ip_address: matches!(throttle.scope.as_str(), SCOPE_IP | SCOPE_API_KEY)
+                .then(|| throttle.subject.clone()),
+            details: Some(json!({
+                "scope": throttle.scope,
+                "subject": throttle.subject,
+                "failures": throttle.failures,
+            })),
+        })
+        .await;
+        Ok(())
+    }
+
+    /// Lifts the lockout of a user's account
+    pub async fn unlock_user(&self, user_id: Uuid, actor_id: Uuid) -> LockoutResult<()> {
+        let user = User::find_by_id(user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(LockoutError::UserNotFound(user_id))?;
+        let throttle = LoginThrottles::find()
+            .filter(subject(SCOPE_ACCOUNT, &user.name))
+            .one(&self.db)
+            .await?;
+
+        match throttle {
+            Some(throttle) => self.unlock(throttle.id, actor_id).await,
+            // Nothing to lift, the account has no failures
+            None => Ok(()),
+        }
+    }
+
+    async fn record(&self, scope: &str, name: &str, ip: &str) -> LockoutResult<()> {
+        let throttle = LoginThrottles::find()
+            .filter(subject(scope, name))
+            .one(&self.db)
+            .await?;
+        if let Some(throttle) = throttle {
+            if throttle.locked_until.is_none()
+                && throttle.failures >= Policy::of(scope).lockout_failures
+            {
+                self.lock(throttle, ip).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes back one counted attempt, dropping the subject once none are left
+    async fn undo(&self, scope: &str, name: &str) -> LockoutResult<()> {
+        let txn = self.db.begin().await?;
+        let throttle = LoginThrottles::find()
+            .filter(subject(scope, name))
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+
+        match throttle {
+            Some(throttle) if throttle.failures <= 1 && throttle.locked_until.is_none() => {
+                LoginThrottles::delete_by_id(throttle.id).exec(&txn).await?;
+            }
+            Some(throttle) => {
+                LoginThrottles::update_many()
+                    .col_expr(
+                        login_throttles::Column::Failures,
+                        Expr::value((throttle.failures - 1).max(0)),
+                    )
+                    .filter(login_throttles::Column::Id.eq(throttle.id))
+                    .exec(&txn)
+                    .await?;
+            }
+            None => {}
+        }
+        txn.commit().await?;
+        Ok(())
+    }
+
+    async fn lock(&self, throttle: login_throttles::Model, ip: &str) -> LockoutResult<()> {
+        let locked_until = Utc::now().naive_utc() + Duration::minutes(LOCKOUT_MINUTES);
+        // Only one of concurrent failures locks, so the lockout is recorded once
+        let locked = LoginThrottles::update_many()
+            .col_expr(
+                login_throttles::Column::LockedUntil,
+                Expr::value(locked_until),
+            )
+            .filter(login_throttles::Column::Id.eq(throttle.id))
+            .filter(login_throttles::Column::LockedUntil.is_null())
+            .exec(&self.db)
+            .await?
+            .rows_affected;
+        if locked == 0 {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "Locked {} {} after {} failed attempts",
+            throttle.scope,
+            throttle.subject,
+            throttle.failures
+        );
+        let user_id = match throttle.scope.as_str() {
+            SCOPE_ACCOUNT => self.user_id(&throttle.subject).await?,
+            _ => None,
+        };
+        self.audit(AuditEvent {
+            event: EVENT_LOGIN_LOCKOUT,
+            user_id,
+            actor_id: None,
+            ip_address: Some(ip.to_string()),
+            details: Some(json!({
+                "scope": throttle.scope,
+                "subject": throttle.subject,
+                "failures": throttle.failures,
+                "locked_until": locked_until.and_utc(),
+            })),
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn user_id(&self, name: &str) -> LockoutResult<Option<Uuid>> {
+        Ok(User::find()
+            .filter(user::Column::Name.eq(name))
+            .one(&self.db)
+            .await?
+            .map(|user| user.id))
+    }
+
+    /// A lost audit event must not turn a lockout into an error
+    async fn audit(&self, event: AuditEvent) {
+        if let Err(e) = AuditService::new(self.db.clone()).record(event).await {
+            tracing::error!("Failed to record audit event: {}", e);
+        }
+    }
+}
+
+/// Counts a failure, starting over after the window or a lockout that ended
+async fn count_failure<C: ConnectionTrait>(
+    conn: &C,
+    scope: &str,
+    subject: &str,
+    now: NaiveDateTime,
+) -> LockoutResult<login_throttles::Model> {
+    Ok(LoginThrottles::insert(login_throttles::ActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        scope: ActiveValue::Set(scope.to_string()),
+        subject: ActiveValue::Set(subject.to_string()),
+        failures: ActiveValue::Set(1),
+        last_failure_at: ActiveValue::Set(now),
+        locked_until: ActiveValue::Set(None),
+    })
+    .on_conflict(
+        OnConflict::columns([
+            login_throttles::Column::Scope,
+            login_throttles::Column::Subject,
+        ])
+        .value(
+            login_throttles::Column::Failures,
+            Expr::cust_with_values(
+                COUNT_FAILURE,
+                [Value::from(window_start(now)), Value::from(now)],
+            ),
+        )
+        .value(
+            login_throttles::Column::LockedUntil,
+            Expr::cust_with_values(CLEAR_ENDED_LOCKOUT, [Value::from(now)]),
+        )
+        .value(login_throttles::Column::LastFailureAt, now)
+        .to_owned(),
+    )
+    .exec_with_returning(conn)
+    .await?)
+}
+
+fn subject(scope: &str, subject: &str) -> Condition {
+    Condition::all()
+        .add(login_throttles::Column::Scope.eq(scope))
+        .add(login_throttles::Column::Subject.eq(subject))
+}
+
+fn window_start(now: NaiveDateTime) -> NaiveDateTime {
+    now - Duration::minutes(FAILURE_WINDOW_MINUTES)
+}
+
+/// When the subject may try again, in the past if it may right away
+fn wait_until(throttle: &login_throttles::Model, now: NaiveDateTime) -> NaiveDateTime {
+    if let Some(locked_until) = throttle.locked_until {
+        if locked_until > now {
+            return locked_until;
+        }
+        // The lockout ended, the failures that caused it no longer count
+        return now;
+    }
+    if throttle.last_failure_at < window_start(now) {
+        return now;
+    }
+    throttle.last_failure_at + Policy::of(&throttle.scope).delay(throttle.failures)
+}
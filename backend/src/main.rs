@@ -3,14 +3,28 @@ use std::net::SocketAddr;
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+mod agent_key_service;
+mod agent_service;
+mod anomaly_service;
+mod audit_service;
 mod auth;
 mod auth_service;
+mod ca_service;
 mod db;
 mod docker_service;
+mod event_bus;
+mod health_service;
 mod init;
+mod inventory_service;
+mod key_service;
+mod lockout_service;
+mod metrics_service;
 mod rbac_service;
+mod resource_watcher;
+mod revocation_service;
 mod routes;
 mod self_monitor;
+mod session_service;
 mod system_collector;
 mod utils;
 
@@ -25,7 +39,13 @@ use routes::users::{
         routes::users::register_user,
         routes::users::login_user,
         routes::users::logout_user,
+        routes::users::logout_everywhere,
+        routes::users::refresh_session,
+        routes::users::list_sessions,
+        routes::users::revoke_session,
+        routes::users::revoke_other_sessions,
         routes::users::get_public_key,
+        routes::users::get_jwks,
         routes::users::get_user_profile,
         routes::expenses::get_expenses,
         routes::expenses::get_expense,
@@ -67,6 +87,12 @@ pub struct ApiDoc;
 pub struct AppState {
     pub db_conn: DbConn,
     pub docker: Option<docker_service::DockerService>,
+    /// Live events for streaming clients
+    pub events: event_bus::EventBus,
+    /// Keys access tokens are signed and verified with
+    pub jwt_keys: auth::jwt::JwtKeys,
+    /// Tokens revoked before they expire
+    pub revoked_tokens: revocation_service::RevokedTokens,
 }
 
 impl Default for AppState {
@@ -87,6 +113,15 @@ async fn main() {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    // Refuse to run with a well-known JWT secret
+    let jwt_keys = match auth::jwt::JwtKeys::new() {
+        Ok(keys) => keys,
+        Err(e) => {
+            tracing::error!("Invalid JWT configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Initialize database connection and run migrations
     let db_conn = match db::establish_connection().await {
         Ok(conn) => {
@@ -105,6 +140,38 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // Accept the agent API key from the environment
+    if let Err(e) = agent_key_service::AgentKeyService::new(db_conn.clone())
+        .import_from_env()
+        .await
+    {
+        tracing::error!("Failed to import agent API key: {}", e);
+        std::process::exit(1);
+    }
+
+    // Load the JWT signing keys, creating the first one on a fresh install
+    let key_rotation = key_service::rotation_interval();
+    if let Err(e) = key_service::KeyService::new(db_conn.clone())
+        .sync(&jwt_keys, key_rotation)
+        .await
+    {
+        tracing::error!("Failed to load JWT signing keys: {}", e);
+        std::process::exit(1);
+    }
+    key_service::start_key_rotation(db_conn.clone(), jwt_keys.clone(), key_rotation);
+
+    // Load revoked tokens, requests are checked against this copy
+    let revoked_tokens = revocation_service::RevokedTokens::new();
+    if let Err(e) =
+        revocation_service::RevocationService::new(db_conn.clone(), revoked_tokens.clone())
+            .sync()
+            .await
+    {
+        tracing::error!("Failed to load revoked tokens: {}", e);
+        std::process::exit(1);
+    }
+    revocation_service::start_revocation_sync(db_conn.clone(), revoked_tokens.clone());
+
     // Initialize Docker service
     let docker = match docker_service::DockerService::new() {
         Ok(docker) => {
@@ -125,15 +192,28 @@ async fn main() {
         }
     };
 
+    let events = event_bus::EventBus::new();
+
+    // Follow container status changes made outside of the API
+    if let Some(docker) = &docker {
+        resource_watcher::start_resource_watcher(db_conn.clone(), docker.clone(), events.clone());
+    }
+
+    // Learn metric baselines and flag anomalies in the background
+    anomaly_service::start_anomaly_detection(db_conn.clone());
+
     // Create application state
     let state = AppState {
         db_conn: db_conn.clone(),
         docker,
+        events: events.clone(),
+        jwt_keys,
+        revoked_tokens,
     };
 
     // Start self-monitoring service
     tracing::info!("🔄 Starting self-monitoring service...");
-    self_monitor::start_self_monitoring(std::sync::Arc::new(db_conn)).await;
+    self_monitor::start_self_monitoring(std::sync::Arc::new(db_conn), events).await;
 
     // build our application with a route
     let app = routes::create_router()
@@ -144,5 +224,11 @@ async fn main() {
     let addr = SocketAddr::from(([0, 0, 0, 0], 8000));
     tracing::info!("listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Agent routes record the address agents connect from
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -0,0 +1,310 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use entity::{agent_metrics, AgentMetrics};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use sea_orm::sea_query::{Expr, Value};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select,
+};
+use serde::Deserialize;
+use std::fmt::Write;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Samples returned by the metrics endpoint when no limit is given
+pub const DEFAULT_METRICS_LIMIT: u64 = 100;
+
+/// Most samples the metrics endpoint returns at once, larger amounts are exported
+pub const MAX_METRICS_LIMIT: u64 = 1000;
+
+/// Most agents that can be listed explicitly in one export
+pub const MAX_EXPORT_AGENTS: usize = 1000;
+
+/// Rows fetched from the database per export chunk
+const EXPORT_PAGE_SIZE: u64 = 1000;
+
+/// Samples after a cursor in export order
+const AFTER_CURSOR: &str = r#"("agent_metrics"."agent_id", "agent_metrics"."timestamp", "agent_metrics"."id") > ($1, $2, $3)"#;
+
+const CSV_COLUMNS: [&str; 22] = [
+    "id",
+    "agent_id",
+    "timestamp",
+    "cpu_model",
+    "cpu_cores",
+    "cpu_threads",
+    "cpu_usage_percent",
+    "memory_total_bytes",
+    "memory_used_bytes",
+    "memory_usage_percent",
+    "disk_total_bytes",
+    "disk_used_bytes",
+    "disk_usage_percent",
+    "network_rx_bytes",
+    "network_tx_bytes",
+    "os_name",
+    "os_version",
+    "kernel_version",
+    "hostname",
+    "uptime_seconds",
+    "custom_metrics",
+    "relayed_by",
+];
+
+#[derive(Debug, Error)]
+pub enum MetricsError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("Time range starts after it ends")]
+    InvalidRange,
+    #[error("Invalid agent selection: {0}")]
+    InvalidAgents(String),
+}
+
+pub type MetricsResult<T> = Result<T, MetricsError>;
+
+/// Time range of metric samples, `from` inclusive and `to` exclusive
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct MetricsRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl MetricsRange {
+    fn apply(&self, mut query: Select<AgentMetrics>) -> MetricsResult<Select<AgentMetrics>> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err(MetricsError::InvalidRange);
+            }
+        }
+        if let Some(from) = self.from {
+            query = query.filter(agent_metrics::Column::Timestamp.gte(from.naive_utc()));
+        }
+        if let Some(to) = self.to {
+            query = query.filter(agent_metrics::Column::Timestamp.lt(to.naive_utc()));
+        }
+        Ok(query)
+    }
+}
+
+/// Agents to export metrics of
+#[derive(Debug, Clone)]
+pub enum AgentSelection {
+    All,
+    Only(Vec<Uuid>),
+}
+
+impl AgentSelection {
+    /// Parses `all` or comma separated agent IDs
+    pub fn parse(value: &str) -> MetricsResult<Self> {
+        let value = value.trim();
+        if value == "all" {
+            return Ok(AgentSelection::All);
+        }
+
+        let ids = value
+            .split(',')
+            .map(|id| id.trim().parse::<Uuid>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| MetricsError::InvalidAgents(value.to_string()))?;
+        if ids.len() > MAX_EXPORT_AGENTS {
+            return Err(MetricsError::InvalidAgents(format!(
+                "at most {} agents, use `all` instead",
+                MAX_EXPORT_AGENTS
+            )));
+        }
+        Ok(AgentSelection::Only(ids))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    fn header(self) -> String {
+        match self {
+            ExportFormat::Csv => format!("{}\r\n", CSV_COLUMNS.join(",")),
+            ExportFormat::Ndjson => String::new(),
+        }
+    }
+
+    fn encode(self, rows: &[agent_metrics::Model]) -> String {
+        let mut out = String::new();
+        for row in rows {
+            match self {
+                ExportFormat::Csv => csv_row(&mut out, row),
+                ExportFormat::Ndjson => {
+                    // Serializing a model can't fail
+                    out.push_str(&serde_json::to_string(row).unwrap_or_default());
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Position after the last exported sample
+struct ExportCursor {
+    agent_id: Uuid,
+    timestamp: chrono::NaiveDateTime,
+    id: Uuid,
+}
+
+/// Reads stored agent metrics
+#[derive(Clone)]
+pub struct MetricsService {
+    db: DatabaseConnection,
+}
+
+impl MetricsService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+
+    /// The latest samples of an agent within the range, newest first
+    pub async fn recent(
+        &self,
+        agent_id: Uuid,
+        range: &MetricsRange,
+        limit: u64,
+    ) -> MetricsResult<Vec<agent_metrics::Model>> {
+        let query = AgentMetrics::find().filter(agent_metrics::Column::AgentId.eq(agent_id));
+        Ok(range
+            .apply(query)?
+            .order_by_desc(agent_metrics::Column::Timestamp)
+            .limit(limit.min(MAX_METRICS_LIMIT))
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Streams all samples of the agents within the range, encoded in chunks
+    ///
+    /// Samples are ordered by agent, then time. Only one page of rows is held at
+    /// a time, the next one is read when the previous chunk was taken.
+    pub fn export(
+        &self,
+        agents: AgentSelection,
+        range: MetricsRange,
+        format: ExportFormat,
+    ) -> MetricsResult<BoxStream<'static, MetricsResult<String>>> {
+        let mut query = range.apply(AgentMetrics::find())?;
+        if let AgentSelection::Only(ids) = agents {
+            query = query.filter(agent_metrics::Column::AgentId.is_in(ids));
+        }
+        let query = query
+            .order_by_asc(agent_metrics::Column::AgentId)
+            .order_by_asc(agent_metrics::Column::Timestamp)
+            .order_by_asc(agent_metrics::Column::Id)
+            .limit(EXPORT_PAGE_SIZE);
+
+        let db = self.db.clone();
+        let header = stream::once(async move { Ok(format.header()) });
+        let pages = stream::unfold(Some(None), move |cursor: Option<Option<ExportCursor>>| {
+            let db = db.clone();
+            let query = query.clone();
+            async move {
+                // The outer None ends the export once the last page was sent
+                let cursor = cursor?;
+                let page = match &cursor {
+                    Some(after) => query.filter(Expr::cust_with_values(
+                        AFTER_CURSOR,
+                        [
+                            Value::from(after.agent_id),
+                            Value::from(after.timestamp),
+                            Value::from(after.id),
+                        ],
+                    )),
+                    None => query,
+                };
+
+                let rows = match page.all(&db).await {
+                    Ok(rows) => rows,
+                    Err(e) => return Some((Err(e.into()), None)),
+                };
+                let next = match rows.last() {
+                    Some(last) if rows.len() as u64 == EXPORT_PAGE_SIZE => {
+                        Some(Some(ExportCursor {
+                            agent_id: last.agent_id,
+                            timestamp: last.timestamp,
+                            id: last.id,
+                        }))
+                    }
+                    _ => None,
+                };
+                Some((Ok(format.encode(&rows)), next))
+            }
+        });
+
+        Ok(header.chain(pages).boxed())
+    }
+}
+
+fn csv_row(out: &mut String, row: &agent_metrics::Model) {
+    let fields = [
+        row.id.to_string(),
+        row.agent_id.to_string(),
+        row.timestamp
+            .and_utc()
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        optional(&row.cpu_model),
+        optional(&row.cpu_cores),
+        optional(&row.cpu_threads),
+        optional(&row.cpu_usage_percent),
+        optional(&row.memory_total_bytes),
+        optional(&row.memory_used_bytes),
+        optional(&row.memory_usage_percent),
+        optional(&row.disk_total_bytes),
+        optional(&row.disk_used_bytes),
+        optional(&row.disk_usage_percent),
+        optional(&row.network_rx_bytes),
+        optional(&row.network_tx_bytes),
+        optional(&row.os_name),
+        optional(&row.os_version),
+        optional(&row.kernel_version),
+        optional(&row.hostname),
+        optional(&row.uptime_seconds),
+        optional(&row.custom_metrics),
+        optional(&row.relayed_by),
+    ];
+
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        csv_field(out, field);
+    }
+    out.push_str("\r\n");
+}
+
+fn optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+/// Quotes a field if it contains a separator, quote or line break (RFC 4180)
+fn csv_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\n', '\r']) {
+        let _ = write!(out, "\"{}\"", field.replace('"', "\"\""));
+    } else {
+        out.push_str(field);
+    }
+}
@@ -0,0 +1,74 @@
+use entity::{docker_resources, DockerResources};
+use futures_util::stream::StreamExt;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, DbConn, EntityTrait, QueryFilter};
+use std::time::Duration;
+
+use crate::docker_service::DockerService;
+use crate::event_bus::{EventBus, LiveEvent, ResourceStatus};
+
+/// Wait before listening to Docker again after the connection was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Keeps the status of container resources in line with Docker and
+/// publishes every change, also when a container stops on its own
+pub fn start_resource_watcher(db_conn: DbConn, docker: DockerService, events: EventBus) {
+    tokio::spawn(async move {
+        loop {
+            let mut container_events = Box::pin(docker.container_events());
+            while let Some(event) = container_events.next().await {
+                match event {
+                    Ok((container_id, action)) => {
+                        if let Err(e) = apply(&db_conn, &events, &container_id, &action).await {
+                            tracing::warn!("Failed to update resource status: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Docker event stream failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+/// Updates the resource of a container after a lifecycle event
+async fn apply(
+    db_conn: &DbConn,
+    events: &EventBus,
+    container_id: &str,
+    action: &str,
+) -> Result<(), sea_orm::DbErr> {
+    // Same mapping as the status sync when a resource is fetched
+    let status = match action {
+        "start" | "unpause" => "running",
+        "die" => "stopped",
+        _ => return Ok(()),
+    };
+
+    let Some(resource) = DockerResources::find()
+        .filter(docker_resources::Column::ContainerId.eq(container_id))
+        .one(db_conn)
+        .await?
+    else {
+        return Ok(());
+    };
+    if resource.status == status {
+        return Ok(());
+    }
+
+    let mut resource_active: docker_resources::ActiveModel = resource.into();
+    resource_active.status = ActiveValue::Set(status.to_string());
+    resource_active.updated_at = ActiveValue::Set(chrono::Utc::now().naive_utc());
+    let updated = resource_active.update(db_conn).await?;
+
+    tracing::info!(
+        "Container {} of resource {} is {}",
+        container_id,
+        updated.name,
+        status
+    );
+    events.publish(LiveEvent::ResourceStatus(ResourceStatus::of(&updated)));
+    Ok(())
+}
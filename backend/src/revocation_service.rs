@@ -0,0 +1,192 @@
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use entity::{invalid_jwt, user, InvalidJwt, User};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QuerySelect,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::jwt::Claims;
+
+/// How often revocations made by other instances are picked up
+const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often entries of expired tokens are removed from the table
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+
+#[derive(Debug, Error)]
+pub enum RevocationError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+}
+
+pub type RevocationResult<T> = Result<T, RevocationError>;
+
+#[derive(Default)]
+struct Revocations {
+    /// jti of revoked tokens that have not expired, with their expiry
+    tokens: HashMap<Uuid, i64>,
+    /// Users whose tokens issued before the second are invalid
+    users: HashMap<Uuid, NaiveDateTime>,
+}
+
+/// In-memory copy of the revoked tokens, checked on every authenticated request
+///
+/// Entries only live as long as the revoked access token, so the whole table
+/// fits in memory and the check needs no database lookup.
+#[derive(Clone, Default)]
+pub struct RevokedTokens {
+    inner: Arc<RwLock<Revocations>>,
+}
+
+impl RevokedTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the token was revoked or its user logged out everywhere after it was issued
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let revocations = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(jti) = claims.jti {
+            if revocations.tokens.contains_key(&jti) {
+                return true;
+            }
+        }
+        revocations
+            .users
+            .get(&claims.user_id)
+            .is_some_and(|valid_after| claims.iat < valid_after.and_utc().timestamp())
+    }
+
+    fn insert_token(&self, jti: Uuid, exp: i64) {
+        let mut revocations = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        revocations.tokens.insert(jti, exp);
+    }
+
+    fn insert_user(&self, user_id: Uuid, valid_after: NaiveDateTime) {
+        let mut revocations = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        revocations.users.insert(user_id, valid_after);
+    }
+
+    fn replace(&self, tokens: HashMap<Uuid, i64>, users: HashMap<Uuid, NaiveDateTime>) {
+        let mut revocations = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        *revocations = Revocations { tokens, users };
+    }
+}
+
+/// Revokes access tokens before they expire
+#[derive(Clone)]
+pub struct RevocationService {
+    db: DatabaseConnection,
+    revoked: RevokedTokens,
+}
+
+impl RevocationService {
+    pub fn new(db: DatabaseConnection, revoked: RevokedTokens) -> Self {
+        Self { db, revoked }
+    }
+
+    /// Revokes a single token, e.g. on logout
+    ///
+    /// Returns false for tokens without a `jti`, they stay valid until they expire.
+    pub async fn revoke(&self, claims: &Claims) -> RevocationResult<bool> {
+        let Some(jti) = claims.jti else {
+            return Ok(false);
+        };
+        let exp = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+
+        InvalidJwt::insert(invalid_jwt::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            jti: ActiveValue::Set(jti),
+            exp: ActiveValue::Set(exp.naive_utc()),
+        })
+        .on_conflict(
+            OnConflict::column(invalid_jwt::Column::Jti)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(&self.db)
+        .await?;
+        self.revoked.insert_token(jti, claims.exp);
+
+        Ok(true)
+    }
+
+    /// Invalidates all tokens of a user issued before the current second
+    ///
+    /// `iat` only has whole seconds, so tokens of this second stay valid, or
+    /// a login right after would be refused as well.
+    pub async fn revoke_user_tokens(&self, user_id: Uuid) -> RevocationResult<()> {
+        let now = Utc::now().naive_utc().trunc_subsecs(0);
+        User::update_many()
+            .col_expr(user::Column::TokensValidAfter, Expr::value(now))
+            .filter(user::Column::Id.eq(user_id))
+            .exec(&self.db)
+            .await?;
+        self.revoked.insert_user(user_id, now);
+
+        Ok(())
+    }
+
+    /// Reloads the revocations, including those made by other instances
+    pub async fn sync(&self) -> RevocationResult<()> {
+        let now = Utc::now().naive_utc();
+        let tokens = InvalidJwt::find()
+            .filter(invalid_jwt::Column::Exp.gt(now))
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.jti, entry.exp.and_utc().timestamp()))
+            .collect();
+        let users = User::find()
+            .select_only()
+            .column(user::Column::Id)
+            .column(user::Column::TokensValidAfter)
+            .filter(user::Column::TokensValidAfter.is_not_null())
+            .into_tuple::<(Uuid, NaiveDateTime)>()
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .collect();
+
+        self.revoked.replace(tokens, users);
+        Ok(())
+    }
+
+    /// Removes entries of tokens that have expired, returning how many were removed
+    pub async fn purge_expired(&self) -> RevocationResult<u64> {
+        Ok(InvalidJwt::delete_many()
+            .filter(invalid_jwt::Column::Exp.lt(Utc::now().naive_utc()))
+            .exec(&self.db)
+            .await?
+            .rows_affected)
+    }
+}
+
+/// Keeps the revoked tokens in sync with the database and purges expired entries
+pub fn start_revocation_sync(db: DatabaseConnection, revoked: RevokedTokens) {
+    let service = RevocationService::new(db, revoked);
+    tokio::spawn(async move {
+        let mut sync = tokio::time::interval(SYNC_INTERVAL);
+        let mut purge = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = sync.tick() => {
+                    if let Err(e) = service.sync().await {
+                        tracing::error!("Failed to sync revoked tokens: {}", e);
+                    }
+                }
+                _ = purge.tick() => match service.purge_expired().await {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::debug!("Purged {} expired token revocations", purged),
+                    Err(e) => tracing::error!("Failed to purge expired token revocations: {}", e),
+                },
+            }
+        }
+    });
+}
@@ -1,20 +1,47 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    body::Body,
+    extract::{ConnectInfo, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Json},
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Router,
 };
-use entity::entities::{agent_metrics, agents};
-use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
-};
+use entity::entities::{agent_api_keys, agent_metrics, agents};
+use entity::ResourceGroups;
+use futures_util::TryStreamExt;
+use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::auth::middleware::AuthenticatedUser;
+use crate::agent_key_service::{AgentKeyError, AgentKeyService};
+use crate::agent_service::{
+    merge_tags, tag_list, tags_json, AgentError, AgentFilter, AgentService, AgentUpdate,
+    DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, STATUS_DECOMMISSIONED,
+};
+use crate::anomaly_service::{AnomalyError, AnomalyFilter, AnomalyService};
+use crate::auth::middleware::{AuthenticatedAgent, AuthenticatedUser};
+use crate::ca_service::{CaError, CaService, Issuance, DEFAULT_ROTATION_OVERLAP_DAYS};
+use crate::event_bus::LiveEvent;
+use crate::health_service::{
+    HealthError, HealthService, HealthState, ServiceState, CERT_EXPIRY_WARNING_DAYS,
+};
+use crate::inventory_service::{InventoryError, InventoryFilter, InventoryService};
+use crate::metrics_service::{
+    AgentSelection, ExportFormat, MetricsError, MetricsRange, MetricsService, DEFAULT_METRICS_LIMIT,
+};
+use crate::rbac_service::RbacService;
+use crate::routes::organizations::require_permission;
+use crate::routes::source_ip;
 use crate::AppState;
 
+/// Burst length when a request doesn't give one (seconds)
+const DEFAULT_BURST_SECONDS: i64 = 300;
+
+/// Longest burst that can be requested (seconds)
+const MAX_BURST_SECONDS: i64 = 3600;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AgentRegistration {
     pub agent_id: Uuid,
@@ -25,12 +52,106 @@ pub struct AgentRegistration {
     pub architecture: String,
     pub agent_version: String,
     pub tags: Option<serde_json::Value>,
+    /// PEM certificate signing request to be signed by the fleet CA
+    pub csr: Option<String>,
+    /// Agent that forwarded the registration for an isolated agent
+    #[serde(default)]
+    pub relayed_by: Option<Uuid>,
+    /// Hardware and software inventory of the host
+    #[serde(default)]
+    pub inventory: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InventoryReport {
+    pub agent_id: Uuid,
+    pub collected_at: chrono::DateTime<chrono::Utc>,
+    pub inventory: serde_json::Value,
+    /// Agent that forwarded the inventory for an isolated agent
+    #[serde(default)]
+    pub relayed_by: Option<Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegistrationResponse {
     pub success: bool,
     pub message: String,
+    /// Signed agent certificate (PEM), present when a CSR was submitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+    /// Fleet CA certificates (PEM) the agent should trust
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaBundleResponse {
+    pub ca_bundle: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateCaRequest {
+    /// Days the previous CA stays trusted (defaults to 30)
+    pub overlap_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateCaResponse {
+    pub common_name: String,
+    pub not_after: String,
+    pub ca_bundle: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevocationResponse {
+    /// PEM CRLs, one per trusted fleet CA
+    pub crls: Vec<String>,
+    /// Agents peers must refuse regardless of their certificate
+    pub denied_agents: Vec<Uuid>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RevokeAgentRequest {
+    pub reason: Option<String>,
+    /// Also deny the agent ID (defaults to true)
+    pub deny: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokeAgentResponse {
+    pub agent_id: Uuid,
+    pub revoked_certificates: usize,
+    pub denied: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReissueApprovalResponse {
+    pub agent_id: Uuid,
+    /// The agent has to request its certificate before then
+    pub approved_until: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateAgentApiKeyRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedAgentApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: AgentApiKeyResponse,
+    /// Shown only once, agents send it in `X-API-Key`
+    pub key: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,6 +159,61 @@ pub struct Heartbeat {
     pub agent_id: Uuid,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub status: String,
+    /// Expiry of the agent's P2P certificate
+    pub cert_not_after: Option<chrono::DateTime<chrono::Utc>>,
+    /// Agent that forwarded the heartbeat for an isolated agent
+    #[serde(default)]
+    pub relayed_by: Option<Uuid>,
+    /// States of the services the agent watches
+    #[serde(default)]
+    pub services: Vec<ServiceState>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    /// Collect metrics at the burst rate until then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BurstRequest {
+    /// How long the agent should collect at its burst rate (defaults to 300 seconds)
+    pub duration_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BurstResponse {
+    pub agent_id: Uuid,
+    /// Picked up by the agent with its next heartbeat
+    pub burst_until: chrono::DateTime<chrono::Utc>,
+}
+
+/// Heartbeat status an agent sends when it stops on purpose
+const STATUS_STOPPING: &str = "stopping";
+
+/// Status of an agent that shut down cleanly, as opposed to one that stopped reporting
+const STATUS_STOPPED: &str = "stopped";
+
+/// Status of an agent that was uninstalled
+const STATUS_DEREGISTERED: &str = "deregistered";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Deregistration {
+    pub agent_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CertificateRenewal {
+    pub agent_id: Uuid,
+    /// PEM certificate signing request
+    pub csr: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CertificateResponse {
+    pub certificate: String,
+    pub ca_bundle: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,6 +247,10 @@ pub struct SystemMetrics {
     pub kernel_version: Option<String>,
     pub hostname: Option<String>,
     pub uptime_seconds: Option<u64>,
+
+    /// Agent that forwarded the metrics for an isolated agent
+    #[serde(default)]
+    pub relayed_by: Option<Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -86,6 +266,80 @@ pub struct AgentResponse {
     pub status: String,
     pub last_heartbeat: Option<String>,
     pub registered_at: String,
+    pub cert_expires_at: Option<String>,
+    pub relayed_by: Option<Uuid>,
+    pub burst_until: Option<String>,
+    pub organization_id: Option<Uuid>,
+    pub resource_group_id: Option<Uuid>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentListQuery {
+    #[serde(flatten)]
+    pub filter: AgentFilter,
+    /// Starts at 1
+    pub page: Option<u64>,
+    /// Defaults to 50, at most 500
+    pub per_page: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentListResponse {
+    pub agents: Vec<AgentResponse>,
+    pub total: u64,
+    pub page: u64,
+    pub per_page: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsQuery {
+    #[serde(flatten)]
+    pub range: MetricsRange,
+    /// Defaults to 100, at most 1000
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsExportQuery {
+    /// `all` or comma separated agent IDs
+    pub agents: String,
+    #[serde(flatten)]
+    pub range: MetricsRange,
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentAnomalyQuery {
+    /// Also list anomalies that were resolved
+    #[serde(default)]
+    pub include_resolved: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HealthQuery {
+    /// Only agents in this state
+    pub state: Option<HealthState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagsRequest {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignmentRequest {
+    /// Organization of the agent, `null` removes it
+    pub organization_id: Option<Uuid>,
+    /// Resource group of the agent, `null` removes it
+    pub resource_group_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemovalResponse {
+    pub agent_id: Uuid,
+    pub deleted_metrics: u64,
 }
 
 impl From<agents::Model> for AgentResponse {
@@ -102,13 +356,126 @@ impl From<agents::Model> for AgentResponse {
             status: model.status,
             last_heartbeat: model.last_heartbeat.map(|dt| dt.to_string()),
             registered_at: model.registered_at.to_string(),
+            cert_expires_at: model.cert_expires_at.map(|dt| dt.to_string()),
+            relayed_by: model.relayed_by,
+            burst_until: model.burst_until.map(|dt| dt.to_string()),
+            organization_id: model.organization_id,
+            resource_group_id: model.resource_group_id,
+            tags: tag_list(&model.tags),
+        }
+    }
+}
+
+impl From<agent_api_keys::Model> for AgentApiKeyResponse {
+    fn from(model: agent_api_keys::Model) -> Self {
+        Self {
+            id: model.id,
+            name: model.name,
+            created_by: model.created_by,
+            created_at: model.created_at.to_string(),
+            last_used_at: model.last_used_at.map(|dt| dt.to_string()),
+            revoked_at: model.revoked_at.map(|dt| dt.to_string()),
+        }
+    }
+}
+
+/// Maps agent management errors to a response status
+fn agent_error_status(e: AgentError) -> StatusCode {
+    tracing::error!("Failed to manage agent: {}", e);
+    match e {
+        AgentError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+        AgentError::OrganizationNotFound(_)
+        | AgentError::ResourceGroupNotFound(_)
+        | AgentError::InvalidValue(_) => StatusCode::BAD_REQUEST,
+        AgentError::ResourceGroupMismatch(_) => StatusCode::CONFLICT,
+        AgentError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn ca_error_status(e: CaError) -> StatusCode {
+    match e {
+        CaError::InvalidCsr(_) => StatusCode::BAD_REQUEST,
+        CaError::AgentRevoked(_) | CaError::KeyNotApproved(_) | CaError::KeyNotCurrent(_) => {
+            StatusCode::FORBIDDEN
+        }
+        CaError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+        CaError::DatabaseError(_) | CaError::CertificateError(_) => {
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn agent_key_error_status(e: AgentKeyError) -> StatusCode {
+    tracing::error!("Failed to manage agent API key: {}", e);
+    match e {
+        AgentKeyError::NotFound(_) => StatusCode::NOT_FOUND,
+        AgentKeyError::InvalidValue(_) => StatusCode::BAD_REQUEST,
+        AgentKeyError::InvalidKey => StatusCode::UNAUTHORIZED,
+        AgentKeyError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Checks that the user may `action` the fleet (CA, API keys, revocations)
+async fn require_fleet_permission(
+    state: &AppState,
+    user_id: Uuid,
+    action: &str,
+) -> Result<(), StatusCode> {
+    require_permission(state, user_id, "organization", action).await
+}
+
+/// Refuses assignments to organizations the user can't update, e.g. isn't a member of
+async fn require_assignable(
+    state: &AppState,
+    user_id: Uuid,
+    request: &AssignmentRequest,
+) -> Result<(), StatusCode> {
+    let mut organization_ids: Vec<Uuid> = request.organization_id.into_iter().collect();
+    if let Some(resource_group_id) = request.resource_group_id {
+        let group = ResourceGroups::find_by_id(resource_group_id)
+            .one(&state.db_conn)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        organization_ids.push(group.organization_id);
+    }
+
+    let rbac = RbacService::new(state.db_conn.clone());
+    for organization_id in organization_ids {
+        let has_perm = rbac
+            .has_permission(user_id, organization_id, "organization", "update")
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !has_perm {
+            return Err(StatusCode::FORBIDDEN);
         }
     }
+    Ok(())
+}
+
+fn metrics_error_status(e: MetricsError) -> StatusCode {
+    tracing::error!("Failed to read metrics: {}", e);
+    match e {
+        MetricsError::InvalidRange | MetricsError::InvalidAgents(_) => StatusCode::BAD_REQUEST,
+        MetricsError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Refuses reports of decommissioned agents
+fn ensure_in_service(agent: &agents::Model) -> Result<(), StatusCode> {
+    if agent.status == STATUS_DECOMMISSIONED {
+        tracing::debug!("Refusing report of decommissioned agent {}", agent.id);
+        return Err(StatusCode::GONE);
+    }
+    Ok(())
 }
 
 /// Register a new agent or update existing one
 pub async fn register_agent(
     State(state): State<AppState>,
+    AuthenticatedAgent(api_key): AuthenticatedAgent,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(registration): Json<AgentRegistration>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // Check if agent already exists
@@ -121,7 +488,26 @@ pub async fn register_agent(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    if let Some(agent) = existing_agent {
+    let agent_id = registration.agent_id;
+    let agent_name = registration.name.clone();
+    // A relayed registration comes from the relaying agent's address
+    let ip_address = if let Some(relayed_by) = registration.relayed_by {
+        tracing::info!(
+            "Registration of agent {} relayed by agent {}",
+            agent_id,
+            relayed_by
+        );
+        None
+    } else {
+        Some(source_ip(peer, &headers))
+    };
+
+    let message = if let Some(agent) = existing_agent {
+        ensure_in_service(&agent)?;
+
+        // Tags from the agent's config are added to the ones set in the backend
+        let tags = merge_tags(tag_list(&agent.tags), tag_list(&registration.tags));
+
         // Update existing agent
         let mut active_model: agents::ActiveModel = agent.into();
         active_model.name = ActiveValue::Set(registration.name);
@@ -131,10 +517,12 @@ pub async fn register_agent(
         active_model.architecture = ActiveValue::Set(registration.architecture);
         active_model.agent_version = ActiveValue::Set(registration.agent_version);
         active_model.status = ActiveValue::Set("online".to_string());
+        active_model.relayed_by = ActiveValue::Set(registration.relayed_by);
         active_model.last_heartbeat = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
         active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
-        if let Some(tags) = registration.tags {
-            active_model.tags = ActiveValue::Set(Some(tags));
+        active_model.tags = ActiveValue::Set(Some(tags_json(tags)));
+        if ip_address.is_some() {
+            active_model.ip_address = ActiveValue::Set(ip_address);
         }
 
         active_model.update(&state.db_conn).await.map_err(|e| {
@@ -142,17 +530,14 @@ pub async fn register_agent(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-        Ok(Json(RegistrationResponse {
-            success: true,
-            message: "Agent updated successfully".to_string(),
-        }))
+        "Agent updated successfully"
     } else {
         // Create new agent
         let new_agent = agents::ActiveModel {
             id: ActiveValue::Set(registration.agent_id),
             name: ActiveValue::Set(registration.name),
             hostname: ActiveValue::Set(registration.hostname),
-            ip_address: ActiveValue::Set(None),
+            ip_address: ActiveValue::Set(ip_address),
             agent_version: ActiveValue::Set(registration.agent_version),
             os_type: ActiveValue::Set(registration.os_type),
             os_version: ActiveValue::Set(registration.os_version),
@@ -162,25 +547,70 @@ pub async fn register_agent(
             registered_at: ActiveValue::Set(chrono::Utc::now().naive_utc()),
             updated_at: ActiveValue::Set(None),
             organization_id: ActiveValue::Set(None),
-            tags: ActiveValue::Set(registration.tags),
+            tags: ActiveValue::Set(Some(tags_json(tag_list(&registration.tags)))),
             capabilities: ActiveValue::Set(None),
+            cert_expires_at: ActiveValue::Set(None),
+            relayed_by: ActiveValue::Set(registration.relayed_by),
+            burst_until: ActiveValue::Set(None),
+            resource_group_id: ActiveValue::Set(None),
+            services: ActiveValue::Set(None),
+            reissue_approved_until: ActiveValue::Set(None),
         };
 
         new_agent.insert(&state.db_conn).await.map_err(|e| {
             tracing::error!("Failed to create agent: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+        tracing::info!(
+            "Agent {} registered with API key {} ({})",
+            agent_id,
+            api_key.name,
+            api_key.id
+        );
 
-        Ok(Json(RegistrationResponse {
-            success: true,
-            message: "Agent registered successfully".to_string(),
-        }))
+        "Agent registered successfully"
+    };
+
+    // A bad inventory shouldn't keep the agent from registering
+    if let Some(inventory) = registration.inventory {
+        let inventory_service = InventoryService::new(state.db_conn.clone());
+        if let Err(e) = inventory_service
+            .store(agent_id, inventory, chrono::Utc::now())
+            .await
+        {
+            tracing::error!("Failed to store inventory of agent {}: {}", agent_id, e);
+        }
     }
+
+    // Issue a fleet certificate if the agent asked for one
+    let (certificate, ca_bundle) = if let Some(csr) = registration.csr {
+        let ca_service = CaService::new(state.db_conn.clone());
+        let signed = ca_service
+            .sign_agent_csr(agent_id, &agent_name, &csr, Issuance::Registration)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to sign certificate for agent {}: {}", agent_id, e);
+                ca_error_status(e)
+            })?;
+        (Some(signed.certificate_pem), Some(signed.ca_bundle))
+    } else {
+        (None, None)
+    };
+
+    Ok(Json(RegistrationResponse {
+        success: true,
+        message: message.to_string(),
+        certificate,
+        ca_bundle,
+    }))
 }
 
 /// Receive heartbeat from agent
 pub async fn heartbeat(
     State(state): State<AppState>,
+    _agent: AuthenticatedAgent,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(heartbeat): Json<Heartbeat>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let agent = agents::Entity::find()
@@ -193,8 +623,49 @@ pub async fn heartbeat(
         })?;
 
     if let Some(agent) = agent {
+        ensure_in_service(&agent)?;
+
+        if let Some(cert_not_after) = heartbeat.cert_not_after {
+            let remaining = cert_not_after - chrono::Utc::now();
+            if remaining < chrono::Duration::days(CERT_EXPIRY_WARNING_DAYS) {
+                tracing::warn!(
+                    "⚠️  Certificate of agent {} ({}) expires at {} ({} days left)",
+                    agent.name,
+                    agent.id,
+                    cert_not_after,
+                    remaining.num_days()
+                );
+            }
+        }
+
+        // Pass on a pending burst request
+        let now = chrono::Utc::now();
+        let burst_until = agent
+            .burst_until
+            .map(|until| until.and_utc())
+            .filter(|until| *until > now);
+
+        let status = if heartbeat.status == STATUS_STOPPING {
+            tracing::info!("Agent {} ({}) is shutting down", agent.name, agent.id);
+            STATUS_STOPPED.to_string()
+        } else {
+            heartbeat.status
+        };
+
         let mut active_model: agents::ActiveModel = agent.into();
-        active_model.status = ActiveValue::Set(heartbeat.status);
+        active_model.status = ActiveValue::Set(status);
+        if let Some(cert_not_after) = heartbeat.cert_not_after {
+            active_model.cert_expires_at = ActiveValue::Set(Some(cert_not_after.naive_utc()));
+        }
+        if heartbeat.relayed_by.is_none() {
+            active_model.ip_address = ActiveValue::Set(Some(source_ip(peer, &headers)));
+        }
+        active_model.relayed_by = ActiveValue::Set(heartbeat.relayed_by);
+        active_model.services = ActiveValue::Set(if heartbeat.services.is_empty() {
+            None
+        } else {
+            serde_json::to_value(&heartbeat.services).ok()
+        });
         active_model.last_heartbeat = ActiveValue::Set(Some(heartbeat.timestamp.naive_utc()));
         active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
 
@@ -203,17 +674,156 @@ pub async fn heartbeat(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-        Ok(StatusCode::OK)
+        Ok(Json(HeartbeatResponse { burst_until }))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+/// Ask an agent to collect metrics at its burst rate for a while
+pub async fn request_burst(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    request: Option<Json<BurstRequest>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let duration = request.duration_seconds.unwrap_or(DEFAULT_BURST_SECONDS);
+    if !(1..=MAX_BURST_SECONDS).contains(&duration) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let burst_until = chrono::Utc::now() + chrono::Duration::seconds(duration);
+    set_burst_until(&state, agent_id, Some(burst_until.naive_utc())).await?;
+
+    Ok(Json(BurstResponse {
+        agent_id,
+        burst_until,
+    }))
+}
+
+/// Cancel a burst request the agent has not picked up or finished yet
+pub async fn cancel_burst(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    set_burst_until(&state, agent_id, None).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_burst_until(
+    state: &AppState,
+    agent_id: Uuid,
+    burst_until: Option<chrono::NaiveDateTime>,
+) -> Result<(), StatusCode> {
+    let agent = agents::Entity::find_by_id(agent_id)
+        .one(&state.db_conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut active_model: agents::ActiveModel = agent.into();
+    active_model.burst_until = ActiveValue::Set(burst_until);
+    active_model.update(&state.db_conn).await.map_err(|e| {
+        tracing::error!(
+            "Failed to update burst request of agent {}: {}",
+            agent_id,
+            e
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(())
+}
+
+/// Mark an agent as uninstalled, keeping its history
+pub async fn deregister_agent(
+    State(state): State<AppState>,
+    _agent: AuthenticatedAgent,
+    Json(request): Json<Deregistration>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let agent = agents::Entity::find_by_id(request.agent_id)
+        .one(&state.db_conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Uninstalling a decommissioned agent doesn't bring it back
+    if agent.status == STATUS_DECOMMISSIONED {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    tracing::info!("Agent {} ({}) deregistered", agent.name, agent.id);
+
+    let mut active_model: agents::ActiveModel = agent.into();
+    active_model.status = ActiveValue::Set(STATUS_DEREGISTERED.to_string());
+    active_model.updated_at = ActiveValue::Set(Some(chrono::Utc::now().naive_utc()));
+    active_model.update(&state.db_conn).await.map_err(|e| {
+        tracing::error!("Failed to deregister agent: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Receive a changed inventory from agent
+pub async fn receive_inventory(
+    State(state): State<AppState>,
+    _agent: AuthenticatedAgent,
+    Json(report): Json<InventoryReport>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if let Some(relayed_by) = report.relayed_by {
+        tracing::debug!(
+            "Inventory of agent {} relayed by agent {}",
+            report.agent_id,
+            relayed_by
+        );
+    }
+
+    let inventory_service = InventoryService::new(state.db_conn.clone());
+    inventory_service
+        .store(report.agent_id, report.inventory, report.collected_at)
+        .await
+        .map_err(|e| {
+            tracing::error!(
+                "Failed to store inventory of agent {}: {}",
+                report.agent_id,
+                e
+            );
+            match e {
+                InventoryError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+                InventoryError::AgentDecommissioned(_) => StatusCode::GONE,
+                InventoryError::InvalidInventory(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            }
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Receive metrics from agent
 pub async fn receive_metrics(
     State(state): State<AppState>,
+    _agent: AuthenticatedAgent,
     Json(metrics): Json<SystemMetrics>,
 ) -> Result<impl IntoResponse, StatusCode> {
+    let agent = agents::Entity::find_by_id(metrics.agent_id)
+        .one(&state.db_conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    ensure_in_service(&agent)?;
+
     // Store metrics in database
     let new_metrics = agent_metrics::ActiveModel {
         id: ActiveValue::Set(Uuid::new_v4()),
@@ -237,32 +847,47 @@ pub async fn receive_metrics(
         hostname: ActiveValue::Set(metrics.hostname),
         uptime_seconds: ActiveValue::Set(metrics.uptime_seconds.map(|v| v as i64)),
         custom_metrics: ActiveValue::Set(None),
+        relayed_by: ActiveValue::Set(metrics.relayed_by),
     };
 
-    new_metrics.insert(&state.db_conn).await.map_err(|e| {
+    let stored = new_metrics.insert(&state.db_conn).await.map_err(|e| {
         tracing::error!("Failed to store metrics: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
+    state
+        .events
+        .publish(LiveEvent::AgentMetrics(Arc::new(stored)));
 
     Ok(StatusCode::CREATED)
 }
 
-/// List all agents
+/// List agents by status, tag, OS, organization and resource group, one page at a time
 pub async fn list_agents(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
+    Query(query): Query<AgentListQuery>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let agents = agents::Entity::find()
-        .order_by_desc(agents::Column::RegisteredAt)
-        .all(&state.db_conn)
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let (agents, total) = agent_service
+        .list(&query.filter, page, per_page)
         .await
         .map_err(|e| {
             tracing::error!("Failed to fetch agents: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    let response: Vec<AgentResponse> = agents.into_iter().map(Into::into).collect();
-    Ok(Json(response))
+    Ok(Json(AgentListResponse {
+        agents: agents.into_iter().map(Into::into).collect(),
+        total,
+        page,
+        per_page,
+    }))
 }
 
 /// Get agent by ID
@@ -283,34 +908,608 @@ pub async fn get_agent(
     Ok(Json(AgentResponse::from(agent)))
 }
 
-/// Get latest metrics for an agent
-pub async fn get_agent_metrics(
+/// Get the computed health of an agent
+pub async fn get_agent_health(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let health_service = HealthService::new(state.db_conn.clone());
+    let health = health_service
+        .agent_health(agent_id)
+        .await
+        .map_err(|e| match e {
+            HealthError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to compute health of agent {}: {}", agent_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(health))
+}
+
+/// List anomalies in agent metrics, only open ones unless resolved ones are included
+pub async fn list_anomalies(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(filter): Query<AnomalyFilter>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let anomaly_service = AnomalyService::new(state.db_conn.clone());
+    let anomalies = anomaly_service.list(&filter).await.map_err(|e| {
+        tracing::error!("Failed to fetch anomalies: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(anomalies))
+}
+
+/// List the anomalies of an agent
+pub async fn get_agent_anomalies(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    Query(query): Query<AgentAnomalyQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let anomaly_service = AnomalyService::new(state.db_conn.clone());
+    let filter = AnomalyFilter {
+        agent_id: Some(agent_id),
+        include_resolved: query.include_resolved,
+        ..Default::default()
+    };
+    let anomalies = anomaly_service.list(&filter).await.map_err(|e| {
+        tracing::error!("Failed to fetch anomalies of agent {}: {}", agent_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(anomalies))
+}
+
+/// Analyse an agent's metrics now instead of waiting for the next run, returns its open anomalies
+pub async fn analyze_agent_anomalies(
     State(state): State<AppState>,
     _user: AuthenticatedUser,
     axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
 ) -> Result<impl IntoResponse, StatusCode> {
-    let metrics: Vec<agent_metrics::Model> = agent_metrics::Entity::find()
-        .filter(agent_metrics::Column::AgentId.eq(agent_id))
-        .order_by_desc(agent_metrics::Column::Timestamp)
-        .limit(100)
-        .all(&state.db_conn)
+    let anomaly_service = AnomalyService::new(state.db_conn.clone());
+    let anomalies = anomaly_service
+        .analyze_agent(agent_id)
+        .await
+        .map_err(|e| match e {
+            AnomalyError::AgentNotFound(_) => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to analyse metrics of agent {}: {}", agent_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(anomalies))
+}
+
+/// List the computed health of all agents, optionally only those in one state
+pub async fn list_agent_health(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<HealthQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let health_service = HealthService::new(state.db_conn.clone());
+    let health = health_service
+        .fleet_health(query.state)
         .await
         .map_err(|e| {
-            tracing::error!("Failed to fetch metrics: {}", e);
+            tracing::error!("Failed to compute fleet health: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+    Ok(Json(health))
+}
+
+/// Get agent counts by health state, OS and version
+pub async fn get_fleet_summary(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+) -> Result<impl IntoResponse, StatusCode> {
+    let health_service = HealthService::new(state.db_conn.clone());
+    let summary = health_service.summary().await.map_err(|e| {
+        tracing::error!("Failed to build fleet summary: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(summary))
+}
+
+/// Rename an agent or replace its tags
+pub async fn update_agent(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    Json(update): Json<AgentUpdate>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let agent = agent_service
+        .update(agent_id, update)
+        .await
+        .map_err(agent_error_status)?;
+
+    Ok(Json(AgentResponse::from(agent)))
+}
+
+/// Add tags to an agent
+pub async fn add_agent_tags(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    Json(request): Json<TagsRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let agent = agent_service
+        .add_tags(agent_id, request.tags)
+        .await
+        .map_err(agent_error_status)?;
+
+    Ok(Json(AgentResponse::from(agent)))
+}
+
+/// Remove a tag from an agent
+pub async fn remove_agent_tag(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path((agent_id, tag)): axum::extract::Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let agent = agent_service
+        .remove_tag(agent_id, &tag)
+        .await
+        .map_err(agent_error_status)?;
+
+    Ok(Json(AgentResponse::from(agent)))
+}
+
+/// Assign an agent to an organization and resource group
+pub async fn assign_agent(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    Json(request): Json<AssignmentRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+    require_assignable(&state, user.0.user_id, &request).await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let agent = agent_service
+        .assign(agent_id, request.organization_id, request.resource_group_id)
+        .await
+        .map_err(agent_error_status)?;
+
+    Ok(Json(AgentResponse::from(agent)))
+}
+
+/// Take an agent out of service, dropping its metrics
+pub async fn decommission_agent(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let deleted_metrics = agent_service
+        .decommission(agent_id, user.0.user_id)
+        .await
+        .map_err(agent_error_status)?;
+
+    tracing::info!(
+        "Agent {} decommissioned, {} metric samples deleted",
+        agent_id,
+        deleted_metrics
+    );
+
+    Ok(Json(RemovalResponse {
+        agent_id,
+        deleted_metrics,
+    }))
+}
+
+/// Delete an agent with all its data
+pub async fn delete_agent(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "delete").await?;
+
+    let agent_service = AgentService::new(state.db_conn.clone());
+    let deleted_metrics = agent_service
+        .delete(agent_id, user.0.user_id)
+        .await
+        .map_err(agent_error_status)?;
+
+    tracing::info!(
+        "Agent {} deleted with {} metric samples",
+        agent_id,
+        deleted_metrics
+    );
+
+    Ok(Json(RemovalResponse {
+        agent_id,
+        deleted_metrics,
+    }))
+}
+
+/// Get the latest inventory of an agent
+pub async fn get_agent_inventory(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let inventory_service = InventoryService::new(state.db_conn.clone());
+    let inventory = inventory_service
+        .get(agent_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch inventory: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(inventory))
+}
+
+/// Find agents by their inventory
+pub async fn list_inventory(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(filter): Query<InventoryFilter>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let inventory_service = InventoryService::new(state.db_conn.clone());
+    let inventories = inventory_service.find(&filter).await.map_err(|e| {
+        tracing::error!("Failed to fetch inventories: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(inventories))
+}
+
+/// Get latest metrics for an agent, optionally within a time range
+pub async fn get_agent_metrics(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    Query(query): Query<MetricsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let metrics_service = MetricsService::new(state.db_conn.clone());
+    let metrics = metrics_service
+        .recent(
+            agent_id,
+            &query.range,
+            query.limit.unwrap_or(DEFAULT_METRICS_LIMIT),
+        )
+        .await
+        .map_err(metrics_error_status)?;
+
     Ok(Json(metrics))
 }
 
+/// Export the metrics of a set of agents as CSV or NDJSON
+///
+/// The file is streamed while it is read from the database, so any range can be exported.
+pub async fn export_metrics(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<MetricsExportQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let agents = AgentSelection::parse(&query.agents).map_err(metrics_error_status)?;
+    let metrics_service = MetricsService::new(state.db_conn.clone());
+    let rows = metrics_service
+        .export(agents, query.range, query.format)
+        .map_err(metrics_error_status)?
+        .inspect_err(|e| tracing::error!("Metrics export aborted: {}", e));
+
+    let filename = format!(
+        "metrics-{}.{}",
+        chrono::Utc::now().format("%Y%m%dT%H%M%SZ"),
+        query.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                query.format.content_type().to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(rows),
+    ))
+}
+
+/// Renew an agent's certificate by signing a fresh CSR
+///
+/// The CSR has to be signed with the key of the agent's current certificate.
+/// The agent's address is updated first, since the certificate may name it.
+pub async fn renew_certificate(
+    State(state): State<AppState>,
+    _agent: AuthenticatedAgent,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(renewal): Json<CertificateRenewal>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let agent = agents::Entity::find_by_id(renewal.agent_id)
+        .one(&state.db_conn)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut active_model: agents::ActiveModel = agent.into();
+    active_model.ip_address = ActiveValue::Set(Some(source_ip(peer, &headers)));
+    let agent = active_model.update(&state.db_conn).await.map_err(|e| {
+        tracing::error!("Failed to update agent: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let ca_service = CaService::new(state.db_conn.clone());
+    let signed = ca_service
+        .sign_agent_csr(agent.id, &agent.name, &renewal.csr, Issuance::Renewal)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to renew certificate for agent {}: {}", agent.id, e);
+            ca_error_status(e)
+        })?;
+
+    Ok(Json(CertificateResponse {
+        certificate: signed.certificate_pem,
+        ca_bundle: signed.ca_bundle,
+    }))
+}
+
+/// Get the fleet CA bundle agents should trust
+pub async fn get_ca_bundle(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let ca_service = CaService::new(state.db_conn.clone());
+    let ca_bundle = ca_service.ca_bundle().await.map_err(|e| {
+        tracing::error!("Failed to load CA bundle: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(CaBundleResponse { ca_bundle }))
+}
+
+/// Rotate the fleet CA, keeping the previous CA trusted during the overlap period
+pub async fn rotate_ca(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(request): Json<RotateCaRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let ca_service = CaService::new(state.db_conn.clone());
+    let overlap_days = request
+        .overlap_days
+        .unwrap_or(DEFAULT_ROTATION_OVERLAP_DAYS);
+
+    let new_ca = ca_service.rotate(overlap_days).await.map_err(|e| {
+        tracing::error!("Failed to rotate CA: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let ca_bundle = ca_service.ca_bundle().await.map_err(|e| {
+        tracing::error!("Failed to load CA bundle: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(RotateCaResponse {
+        common_name: new_ca.common_name,
+        not_after: new_ca.not_after.to_string(),
+        ca_bundle,
+    }))
+}
+
+/// Get the current CRLs and agent deny list
+pub async fn get_revocations(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let ca_service = CaService::new(state.db_conn.clone());
+    let revocations = ca_service.revocation_list().await.map_err(|e| {
+        tracing::error!("Failed to build revocation list: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(RevocationResponse {
+        crls: revocations.crls,
+        denied_agents: revocations.denied_agents,
+    }))
+}
+
+/// Revoke an agent's certificates and optionally deny the agent
+pub async fn revoke_agent(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+    request: Option<Json<RevokeAgentRequest>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let deny = request.deny.unwrap_or(true);
+
+    let ca_service = CaService::new(state.db_conn.clone());
+    let revoked_certificates = ca_service
+        .revoke_agent(agent_id, request.reason, Some(user.0.user_id), deny)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to revoke agent {}: {}", agent_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(RevokeAgentResponse {
+        agent_id,
+        revoked_certificates,
+        denied: deny,
+    }))
+}
+
+/// Let an agent get a certificate for a new key, e.g. after it lost its key
+pub async fn approve_certificate_reissue(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let ca_service = CaService::new(state.db_conn.clone());
+    let approved_until = ca_service.approve_reissue(agent_id).await.map_err(|e| {
+        tracing::error!("Failed to approve certificate of agent {}: {}", agent_id, e);
+        ca_error_status(e)
+    })?;
+
+    Ok(Json(ReissueApprovalResponse {
+        agent_id,
+        approved_until,
+    }))
+}
+
+/// List the API keys agents authenticate with
+pub async fn list_agent_api_keys(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "view").await?;
+
+    let keys = AgentKeyService::new(state.db_conn.clone())
+        .list()
+        .await
+        .map_err(agent_key_error_status)?;
+
+    Ok(Json(
+        keys.into_iter()
+            .map(AgentApiKeyResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Create an API key for agents, the key is only returned this once
+pub async fn create_agent_api_key(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    Json(request): Json<CreateAgentApiKeyRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let (model, key) = AgentKeyService::new(state.db_conn.clone())
+        .create(&request.name, user.0.user_id)
+        .await
+        .map_err(agent_key_error_status)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedAgentApiKeyResponse {
+            api_key: AgentApiKeyResponse::from(model),
+            key,
+        }),
+    ))
+}
+
+/// Revoke an agent API key, agents using it are refused
+pub async fn revoke_agent_api_key(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(key_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    AgentKeyService::new(state.db_conn.clone())
+        .revoke(key_id)
+        .await
+        .map_err(agent_key_error_status)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove an agent from the deny list
+pub async fn lift_agent_denial(
+    State(state): State<AppState>,
+    user: AuthenticatedUser,
+    axum::extract::Path(agent_id): axum::extract::Path<Uuid>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_fleet_permission(&state, user.0.user_id, "update").await?;
+
+    let ca_service = CaService::new(state.db_conn.clone());
+    let lifted = ca_service.lift_denial(agent_id).await.map_err(|e| {
+        tracing::error!("Failed to lift denial of agent {}: {}", agent_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if lifted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
 pub fn agents_routes() -> Router<AppState> {
     Router::new()
         // Public endpoints (for agents)
         .route("/agents/register", post(register_agent))
         .route("/agents/heartbeat", post(heartbeat))
+        .route("/agents/deregister", post(deregister_agent))
         .route("/agents/metrics", post(receive_metrics))
+        // Agents report changes, the frontend searches them
+        .route(
+            "/agents/inventory",
+            get(list_inventory).post(receive_inventory),
+        )
+        .route("/agents/certificate", post(renew_certificate))
+        .route("/agents/ca", get(get_ca_bundle))
+        .route("/agents/revocations", get(get_revocations))
+        // Protected CA management
+        .route("/agents/ca/rotate", post(rotate_ca))
+        .route(
+            "/agents/api-keys",
+            get(list_agent_api_keys).post(create_agent_api_key),
+        )
+        .route("/agents/api-keys/:key_id", delete(revoke_agent_api_key))
         // Protected endpoints (for frontend)
         .route("/agents", get(list_agents))
-        .route("/agents/:id", get(get_agent))
+        .route("/agents/summary", get(get_fleet_summary))
+        .route("/agents/health", get(list_agent_health))
+        .route("/agents/metrics/export", get(export_metrics))
+        .route("/agents/anomalies", get(list_anomalies))
+        .route(
+            "/agents/:id",
+            get(get_agent).patch(update_agent).delete(delete_agent),
+        )
+        .route("/agents/:id/tags", post(add_agent_tags))
+        .route("/agents/:id/tags/:tag", delete(remove_agent_tag))
+        .route("/agents/:id/assignment", put(assign_agent))
+        .route("/agents/:id/decommission", post(decommission_agent))
         .route("/agents/:id/metrics", get(get_agent_metrics))
+        .route("/agents/:id/health", get(get_agent_health))
+        .route("/agents/:id/anomalies", get(get_agent_anomalies))
+        .route(
+            "/agents/:id/anomalies/analyze",
+            post(analyze_agent_anomalies),
+        )
+        .route("/agents/:id/inventory", get(get_agent_inventory))
+        .route(
+            "/agents/:id/revoke",
+            post(revoke_agent).delete(lift_agent_denial),
+        )
+        .route(
+            "/agents/:id/certificate/approve",
+            post(approve_certificate_reissue),
+        )
+        .route(
+            "/agents/:id/burst",
+            post(request_burst).delete(cancel_burst),
+        )
 }
@@ -3,8 +3,9 @@ use crate::utils::router_ext::RouterExt;
 use axum::body::Body;
 use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
 use axum::http::Method;
-use axum::http::{HeaderValue, Request, Response};
+use axum::http::{HeaderMap, HeaderValue, Request, Response};
 use axum::Router;
+use std::net::SocketAddr;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{info_span, Span};
@@ -15,11 +16,29 @@ pub mod marketplace;
 pub mod organizations;
 pub mod resource_groups;
 pub mod resources;
+pub mod stream;
 pub mod subscriptions;
 pub mod system;
 pub mod updates;
 pub mod users;
 
+/// Address the request came from
+///
+/// Behind a reverse proxy on the same host the peer is the proxy, so the
+/// address it forwards is used instead.
+pub(crate) fn source_ip(peer: SocketAddr, headers: &HeaderMap) -> String {
+    if peer.ip().is_loopback() {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        // The proxy appends the address it saw to X-Forwarded-For, earlier entries come from the client
+        let forwarded = header("x-real-ip")
+            .or_else(|| header("x-forwarded-for").and_then(|value| value.rsplit(',').next()));
+        if let Some(forwarded) = forwarded.map(str::trim).filter(|value| !value.is_empty()) {
+            return forwarded.to_string();
+        }
+    }
+    peer.ip().to_string()
+}
+
 /// Creates the main application router and logs all registered routes.
 pub fn create_router() -> Router<AppState> {
     let frontend_url =
@@ -60,6 +79,7 @@ pub fn create_router() -> Router<AppState> {
         .merge(organizations::routes())
         .merge(resource_groups::resource_groups_routes())
         .merge(resources::resources_routes())
+        .merge(stream::stream_routes())
         .merge(subscriptions::subscriptions_routes())
         .merge(system::routes())
         .merge(updates::router())
@@ -1,14 +1,28 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     Json, Router,
 };
 use entity::{organization, role, user, Organization, Role, User};
 use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 use uuid::Uuid;
 
-use crate::{auth::middleware::AuthenticatedUser, rbac_service::RbacService, AppState};
+use crate::{
+    audit_service::{AuditEvent, AuditService, EVENT_TWO_FACTOR_RESET},
+    auth::middleware::AuthenticatedUser,
+    auth_service::{AuthError, AuthService},
+    lockout_service::LockoutService,
+    rbac_service::RbacService,
+    revocation_service::RevocationService,
+    routes::{source_ip, users::lockout_error_status},
+    session_service::SessionService,
+    AppState,
+};
+
+/// Audit events returned when no limit is given
+const DEFAULT_AUDIT_LIMIT: u64 = 100;
 
 pub fn routes() -> Router<AppState> {
     Router::new()
@@ -32,6 +46,24 @@ pub fn routes() -> Router<AppState> {
             "/organization/users/:user_id/role",
             axum::routing::put(update_user_role),
         )
+        .route(
+            "/organization/users/:user_id/unlock",
+            axum::routing::post(unlock_user),
+        )
+        .route(
+            "/organization/users/:user_id/reset-2fa",
+            axum::routing::post(reset_user_2fa),
+        )
+        // Login protection endpoints
+        .route("/organization/lockouts", axum::routing::get(list_lockouts))
+        .route(
+            "/organization/lockouts/:id",
+            axum::routing::delete(delete_lockout),
+        )
+        .route(
+            "/organization/audit-events",
+            axum::routing::get(list_audit_events),
+        )
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -77,6 +109,34 @@ pub struct UpdateUserRoleRequest {
     pub role_id: Uuid,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LockoutResponse {
+    pub id: Uuid,
+    /// account or ip
+    pub scope: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure_at: String,
+    pub locked_until: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventQuery {
+    pub event: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEventResponse {
+    pub id: Uuid,
+    pub event: String,
+    pub user_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserResponse {
     pub id: Uuid,
@@ -350,6 +410,8 @@ async fn create_user(
         two_factor_secret: ActiveValue::NotSet,
         two_factor_enabled: ActiveValue::Set(false),
         force_password_change: ActiveValue::Set(req.force_password_change),
+        tokens_valid_after: ActiveValue::Set(None),
+        two_factor_last_step: ActiveValue::Set(None),
     };
 
     let new_user = new_user
@@ -536,3 +598,181 @@ async fn update_user_role(
 
     Ok(StatusCode::OK)
 }
+
+/// Checks that the user may `action` users in the organization
+async fn require_users_permission(
+    state: &AppState,
+    user_id: Uuid,
+    action: &str,
+) -> Result<(), StatusCode> {
+    require_permission(state, user_id, "users", action).await
+}
+
+/// Checks that the user may `action` the `resource` in the organization
+pub(crate) async fn require_permission(
+    state: &AppState,
+    user_id: Uuid,
+    resource: &str,
+    action: &str,
+) -> Result<(), StatusCode> {
+    let org = Organization::find()
+        .one(&state.db_conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let has_perm = RbacService::new(state.db_conn.clone())
+        .has_permission(user_id, org.id, resource, action)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !has_perm {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+// Unlock a user's account after failed logins
+async fn unlock_user(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    require_users_permission(&state, claims.user_id, "update").await?;
+
+    LockoutService::new(state.db_conn.clone())
+        .unlock_user(user_id, claims.user_id)
+        .await
+        .map_err(lockout_error_status)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Turn off 2FA of a user who lost their device and sign them out everywhere
+async fn reset_user_2fa(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    require_users_permission(&state, claims.user_id, "update").await?;
+
+    AuthService::new(state.db_conn.clone())
+        .reset_2fa(user_id)
+        .await
+        .map_err(|e| match e {
+            AuthError::UserNotFound => StatusCode::NOT_FOUND,
+            e => {
+                tracing::error!("Failed to reset 2FA: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    // Whoever has the device may still be signed in
+    let sessions = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone())
+        .revoke_all(user_id, None)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to end sessions after 2FA reset: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    RevocationService::new(state.db_conn.clone(), state.revoked_tokens.clone())
+        .revoke_user_tokens(user_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to revoke tokens after 2FA reset: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let event = AuditEvent {
+        event: EVENT_TWO_FACTOR_RESET,
+        user_id: Some(user_id),
+        actor_id: Some(claims.user_id),
+        ip_address: Some(source_ip(peer, &headers)),
+        details: Some(serde_json::json!({ "sessions_ended": sessions })),
+    };
+    if let Err(e) = AuditService::new(state.db_conn.clone()).record(event).await {
+        tracing::error!("Failed to record audit event: {}", e);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// List locked accounts and IPs and those with recent failed attempts
+async fn list_lockouts(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LockoutResponse>>, StatusCode> {
+    require_users_permission(&state, claims.user_id, "view").await?;
+
+    let lockouts = LockoutService::new(state.db_conn.clone())
+        .list()
+        .await
+        .map_err(lockout_error_status)?;
+
+    let response = lockouts
+        .into_iter()
+        .map(|throttle| LockoutResponse {
+            id: throttle.id,
+            scope: throttle.scope,
+            subject: throttle.subject,
+            failures: throttle.failures,
+            last_failure_at: throttle.last_failure_at.to_string(),
+            locked_until: throttle.locked_until.map(|until| until.to_string()),
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+// Lift a lockout of an account or IP
+async fn delete_lockout(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    require_users_permission(&state, claims.user_id, "update").await?;
+
+    LockoutService::new(state.db_conn.clone())
+        .unlock(id, claims.user_id)
+        .await
+        .map_err(lockout_error_status)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// List audit events, newest first
+async fn list_audit_events(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    Query(query): Query<AuditEventQuery>,
+) -> Result<Json<Vec<AuditEventResponse>>, StatusCode> {
+    require_users_permission(&state, claims.user_id, "view").await?;
+
+    let events = AuditService::new(state.db_conn.clone())
+        .list(
+            query.event.as_deref(),
+            query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT),
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to list audit events: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let response = events
+        .into_iter()
+        .map(|event| AuditEventResponse {
+            id: event.id,
+            event: event.event,
+            user_id: event.user_id,
+            actor_id: event.actor_id,
+            ip_address: event.ip_address,
+            details: event.details,
+            created_at: event.created_at.to_string(),
+        })
+        .collect();
+
+    Ok(Json(response))
+}
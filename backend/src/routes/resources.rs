@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::auth::middleware::AuthenticatedUser;
+use crate::event_bus::{LiveEvent, ResourceStatus};
 use crate::AppState;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -282,6 +283,9 @@ async fn get_resource(
                                         container_id,
                                         docker_status
                                     );
+                                    state.events.publish(LiveEvent::ResourceStatus(
+                                        ResourceStatus::of(&updated),
+                                    ));
                                     updated
                                 }
                                 Err(e) => {
@@ -486,6 +490,9 @@ async fn create_resource(
 
     match new_resource.insert(db).await {
         Ok(resource) => {
+            state
+                .events
+                .publish(LiveEvent::ResourceStatus(ResourceStatus::of(&resource)));
             let response = ResourceResponse {
                 id: resource.id,
                 name: resource.name,
@@ -572,6 +579,9 @@ async fn update_resource(
 
     match resource_model.update(db).await {
         Ok(updated) => {
+            state
+                .events
+                .publish(LiveEvent::ResourceStatus(ResourceStatus::of(&updated)));
             let rg_name = if let Ok(Some(rg)) =
                 ResourceGroups::find_by_id(updated.resource_group_id)
                     .one(db)
@@ -624,9 +634,17 @@ async fn delete_resource(
 
     match DockerResources::find_by_id(id).one(db).await {
         Ok(Some(resource)) => {
-            let resource_model: docker_resources::ActiveModel = resource.into();
+            let resource_model: docker_resources::ActiveModel = resource.clone().into();
             match resource_model.delete(db).await {
-                Ok(_) => (StatusCode::NO_CONTENT).into_response(),
+                Ok(_) => {
+                    state
+                        .events
+                        .publish(LiveEvent::ResourceStatus(ResourceStatus {
+                            status: "deleted".to_string(),
+                            ..ResourceStatus::of(&resource)
+                        }));
+                    (StatusCode::NO_CONTENT).into_response()
+                }
                 Err(e) => {
                     tracing::error!("Failed to delete resource: {}", e);
                     (
@@ -835,6 +853,9 @@ async fn perform_resource_action(
 
     match resource_active.update(db).await {
         Ok(updated) => {
+            state
+                .events
+                .publish(LiveEvent::ResourceStatus(ResourceStatus::of(&updated)));
             // Get resource group name
             let rg_name = ResourceGroups::find_by_id(updated.resource_group_id)
                 .one(db)
@@ -1006,6 +1027,9 @@ async fn deploy_container(
 
     match new_resource.insert(db).await {
         Ok(resource) => {
+            state
+                .events
+                .publish(LiveEvent::ResourceStatus(ResourceStatus::of(&resource)));
             tracing::info!(
                 "Deployed container resource: {} ({})",
                 resource.name,
@@ -0,0 +1,171 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::auth::middleware::AuthenticatedUser;
+use crate::event_bus::LiveEvent;
+use crate::system_collector::LocalSystemCollector;
+use crate::AppState;
+
+/// How often local system metrics are sent to subscribers
+const SYSTEM_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// `all` or comma separated agent IDs to receive metrics of
+    pub agents: Option<String>,
+    /// `all` or comma separated resource IDs to receive status changes of
+    pub resources: Option<String>,
+    /// Also send the backend host's system metrics
+    #[serde(default)]
+    pub system: bool,
+}
+
+/// Which agents or resources a client subscribed to
+enum Selection {
+    Nothing,
+    All,
+    Only(HashSet<Uuid>),
+}
+
+impl Selection {
+    fn parse(value: Option<&str>) -> Result<Self, StatusCode> {
+        match value.map(str::trim) {
+            None | Some("") => Ok(Selection::Nothing),
+            Some("all") => Ok(Selection::All),
+            Some(ids) => ids
+                .split(',')
+                .map(|id| id.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(Selection::Only)
+                .map_err(|_| StatusCode::BAD_REQUEST),
+        }
+    }
+
+    fn contains(&self, id: Uuid) -> bool {
+        match self {
+            Selection::Nothing => false,
+            Selection::All => true,
+            Selection::Only(ids) => ids.contains(&id),
+        }
+    }
+}
+
+type EventStream = BoxStream<'static, Result<Event, Infallible>>;
+
+/// Stream new agent metrics, resource status changes and local system metrics
+///
+/// Sends server-sent events named `metrics` (a stored agent metrics sample),
+/// `resource` (a Docker resource status) and `system` (backend host metrics).
+/// A `lagged` event tells a slow client how many events it missed.
+pub async fn stream_events(
+    State(state): State<AppState>,
+    _user: AuthenticatedUser,
+    Query(query): Query<StreamQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let agents = Selection::parse(query.agents.as_deref())?;
+    let resources = Selection::parse(query.resources.as_deref())?;
+    let live_events = !matches!(
+        (&agents, &resources),
+        (Selection::Nothing, Selection::Nothing)
+    );
+    if !live_events && !query.system {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut streams: Vec<EventStream> = Vec::new();
+    if live_events {
+        streams.push(bus_events(state.events.subscribe(), agents, resources));
+    }
+    if query.system {
+        streams.push(system_metrics());
+    }
+
+    // Tell reverse proxies not to hold the events back
+    Ok((
+        [("x-accel-buffering", "no")],
+        Sse::new(stream::select_all(streams)).keep_alive(KeepAlive::default()),
+    ))
+}
+
+/// Events from the bus the client subscribed to
+fn bus_events(
+    receiver: tokio::sync::broadcast::Receiver<LiveEvent>,
+    agents: Selection,
+    resources: Selection,
+) -> EventStream {
+    stream::unfold(
+        (receiver, agents, resources),
+        |(mut receiver, agents, resources)| async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(LiveEvent::AgentMetrics(metrics)) if agents.contains(metrics.agent_id) => {
+                        Event::default()
+                            .event("metrics")
+                            .json_data(metrics.as_ref())
+                    }
+                    Ok(LiveEvent::ResourceStatus(status))
+                        if resources.contains(status.resource_id) =>
+                    {
+                        Event::default().event("resource").json_data(&status)
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(skipped)) => Event::default()
+                        .event("lagged")
+                        .json_data(serde_json::json!({ "skipped": skipped })),
+                    Err(RecvError::Closed) => return None,
+                };
+
+                match event {
+                    Ok(event) => return Some((Ok(event), (receiver, agents, resources))),
+                    Err(e) => tracing::warn!("Failed to encode live event: {}", e),
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Local system metrics at a fixed interval
+fn system_metrics() -> EventStream {
+    let collector = Arc::new(LocalSystemCollector::new());
+    let interval = tokio::time::interval(SYSTEM_METRICS_INTERVAL);
+
+    stream::unfold(
+        (interval, collector),
+        |(mut interval, collector)| async move {
+            loop {
+                interval.tick().await;
+
+                let sampler = collector.clone();
+                let metrics = tokio::task::spawn_blocking(move || sampler.collect())
+                    .await
+                    .ok()?;
+                match Event::default().event("system").json_data(&metrics) {
+                    Ok(event) => return Some((Ok(event), (interval, collector))),
+                    Err(e) => tracing::warn!("Failed to encode system metrics: {}", e),
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+pub fn stream_routes() -> Router<AppState> {
+    Router::new().route("/stream", get(stream_events))
+}
@@ -1,17 +1,33 @@
 use axum::{
-    extract::State,
-    http::{header, StatusCode},
-    response::{IntoResponse, Json},
-    routing::{get, post},
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Json},
+    routing::{delete, get, post},
     Router,
 };
-use chrono::{DateTime, Utc};
+use chrono::Utc;
 use cookie::{Cookie, SameSite};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::net::SocketAddr;
 use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::{jwt::JWKS_MAX_AGE_SECONDS, middleware::AuthenticatedUser},
+    auth_service::AuthService,
+    lockout_service::{LockoutError, LockoutService},
+    revocation_service::RevocationService,
+    routes::source_ip,
+    session_service::{DeviceInfo, SessionError, SessionInfo, SessionService, SessionTokens},
+    AppState,
+};
+
+/// Cookie with the access token, read by `AuthenticatedUser`
+const ACCESS_COOKIE: &str = "auth_token";
 
-use crate::{auth::middleware::AuthenticatedUser, auth_service::AuthService, AppState};
+/// Cookie with the refresh token, only sent to the API
+const REFRESH_COOKIE: &str = "refresh_token";
 
 #[derive(Deserialize, ToSchema)]
 pub struct RegisterRequest {
@@ -27,7 +43,7 @@ pub struct LoginRequest {
     pub username: String,
     /// RSA encrypted password
     pub encrypted_password: String,
-    /// Optional 2FA code
+    /// Optional 2FA code, or a recovery code if the device is lost
     pub two_factor_code: Option<String>,
 }
 
@@ -43,6 +59,8 @@ pub struct AuthResponse {
     pub two_factor_enabled: bool,
     /// Whether the user must change their password
     pub force_password_change: bool,
+    /// Seconds until the token expires, refresh it with `/api/refresh` before
+    pub expires_in: i64,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -71,16 +89,28 @@ pub fn users_routes() -> Router<AppState> {
         .route("/profile", get(get_user_profile))
         .route("/validate-session", get(validate_session))
         .route("/logout", post(logout_user))
+        .route("/logout-all", post(logout_everywhere))
+        .route(
+            "/sessions",
+            get(list_sessions).delete(revoke_other_sessions),
+        )
+        .route("/sessions/:id", delete(revoke_session))
         .route("/2fa/setup", post(setup_2fa))
         .route("/2fa/enable", post(enable_2fa))
         .route("/2fa/disable", post(disable_2fa))
+        .route(
+            "/2fa/recovery-codes",
+            get(get_recovery_codes_status).post(regenerate_recovery_codes),
+        )
         .route("/change-password", post(change_password))
         .route("/change-email", post(change_email));
 
     Router::new()
         .route("/register", post(register_user))
         .route("/login", post(login_user))
+        .route("/refresh", post(refresh_session))
         .route("/public-key", get(get_public_key))
+        .route("/.well-known/jwks.json", get(get_jwks))
         .merge(auth_routes)
 }
 
@@ -98,6 +128,8 @@ pub fn users_routes() -> Router<AppState> {
 )]
 pub async fn register_user(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<RegisterRequest>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let auth_service = AuthService::new(state.db_conn.clone());
@@ -106,31 +138,14 @@ pub async fn register_user(
         .register_user(payload.username.clone(), payload.encrypted_password)
         .await
     {
-        Ok(token) => {
-            // Extract user info from token for response
-            match crate::auth::jwt::verify_jwt(&token) {
-                Ok(token_data) => {
-                    let cookie = Cookie::build(("auth_token", token.clone()))
-                        .path("/")
-                        .http_only(true)
-                        .same_site(SameSite::Strict)
-                        .build();
-
-                    let response = (
-                        StatusCode::OK,
-                        [(header::SET_COOKIE, cookie.to_string())],
-                        Json(AuthResponse {
-                            token,
-                            user_id: token_data.claims.user_id.to_string(),
-                            username: token_data.claims.username,
-                            two_factor_enabled: false,
-                            force_password_change: false,
-                        }),
-                    );
-                    Ok(response)
-                }
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
+        Ok(user) => {
+            let session_service =
+                SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+            let tokens = session_service
+                .start(user, device_info(peer, &headers))
+                .await
+                .map_err(session_error_status)?;
+            Ok(session_response(tokens))
         }
         Err(err) => {
             tracing::error!("Registration failed: {}", err);
@@ -151,15 +166,26 @@ pub async fn register_user(
         (status = 200, description = "User logged in successfully", body = AuthResponse),
         (status = 401, description = "Invalid credentials"),
         (status = 403, description = "2FA required"),
+        (status = 429, description = "Too many failed attempts, account or IP locked"),
         (status = 500, description = "Internal server error")
     ),
     tag = "Authentication"
 )]
 pub async fn login_user(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let auth_service = AuthService::new(state.db_conn.clone());
+    let lockout_service = LockoutService::new(state.db_conn.clone());
+    let ip = source_ip(peer, &headers);
+    let account = payload.username.clone();
+
+    lockout_service
+        .reserve(&account, &ip)
+        .await
+        .map_err(lockout_error_status)?;
 
     match auth_service
         .login_user(
@@ -169,49 +195,82 @@ pub async fn login_user(
         )
         .await
     {
-        Ok((token, two_factor_enabled, force_password_change)) => {
-            // Extract user info from token for response
-            match crate::auth::jwt::verify_jwt(&token) {
-                Ok(token_data) => {
-                    let cookie = Cookie::build(("auth_token", token.clone()))
-                        .path("/")
-                        .http_only(true)
-                        .same_site(SameSite::Strict)
-                        .build();
-
-                    let response = (
-                        StatusCode::OK,
-                        [(header::SET_COOKIE, cookie.to_string())],
-                        Json(AuthResponse {
-                            token,
-                            user_id: token_data.claims.user_id.to_string(),
-                            username: token_data.claims.username,
-                            two_factor_enabled,
-                            force_password_change,
-                        }),
-                    );
-                    Ok(response)
-                }
-                Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
+        Ok(user) => {
+            lockout_service
+                .record_success(&account, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            let session_service =
+                SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+            let tokens = session_service
+                .start(user, device_info(peer, &headers))
+                .await
+                .map_err(session_error_status)?;
+            Ok(session_response(tokens))
         }
         Err(err) => {
             tracing::error!("Login failed: {}", err);
             match err {
                 crate::auth_service::AuthError::UserNotFound
-                | crate::auth_service::AuthError::InvalidCredentials => {
+                | crate::auth_service::AuthError::InvalidCredentials
+                | crate::auth_service::AuthError::InvalidTwoFactorCode => {
+                    // Unknown accounts count too, or probing for names would be free
+                    lockout_service
+                        .record_failure(&account, &ip)
+                        .await
+                        .map_err(lockout_error_status)?;
                     Err(StatusCode::UNAUTHORIZED)
                 }
-                crate::auth_service::AuthError::TwoFactorRequired => Err(StatusCode::FORBIDDEN),
-                crate::auth_service::AuthError::InvalidTwoFactorCode => {
-                    Err(StatusCode::UNAUTHORIZED)
+                // The password was right, so the attempt doesn't count
+                crate::auth_service::AuthError::TwoFactorRequired => {
+                    lockout_service
+                        .release(&account, &ip)
+                        .await
+                        .map_err(lockout_error_status)?;
+                    Err(StatusCode::FORBIDDEN)
+                }
+                _ => {
+                    lockout_service
+                        .release(&account, &ip)
+                        .await
+                        .map_err(lockout_error_status)?;
+                    Err(StatusCode::INTERNAL_SERVER_ERROR)
                 }
-                _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
             }
         }
     }
 }
 
+/// Exchange the refresh token cookie for a new access token
+///
+/// The refresh token is rotated with every call. Using an old one again ends the session.
+#[utoipa::path(
+    post,
+    path = "/api/refresh",
+    responses(
+        (status = 200, description = "Session refreshed", body = AuthResponse),
+        (status = 401, description = "Refresh token is invalid, expired, revoked or was reused"),
+        (status = 409, description = "Refresh token was just rotated by a concurrent request, retry"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "Authentication"
+)]
+pub async fn refresh_session(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let refresh_token = request_cookie(&headers, REFRESH_COOKIE).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+    let tokens = session_service
+        .refresh(&refresh_token, device_info(peer, &headers))
+        .await
+        .map_err(session_error_status)?;
+
+    Ok(session_response(tokens))
+}
+
 /// Logout user (protected)
 #[utoipa::path(
     post,
@@ -229,16 +288,28 @@ pub async fn login_user(
 pub async fn logout_user(
     AuthenticatedUser(claims): AuthenticatedUser,
     State(state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let auth_service = AuthService::new(state.db_conn.clone());
-    let exp_datetime = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
-
-    // Extract token from request header would be better, but for simplicity
-    // we'll use a placeholder. In a real implementation, you'd extract the actual token.
-    let token = "".to_string(); // This should be extracted from the Authorization header
+) -> Result<impl IntoResponse, StatusCode> {
+    // End the session so its refresh token can't be used anymore
+    if let Some(session_id) = claims.sid {
+        let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+        match session_service.revoke(claims.user_id, session_id).await {
+            Ok(()) | Err(SessionError::SessionNotFound(_)) => {}
+            Err(err) => return Err(session_error_status(err)),
+        }
+    }
 
-    match auth_service.logout_user(token, exp_datetime).await {
-        Ok(_) => Ok(Json(json!({ "message": "Logged out successfully" }))),
+    let revocation_service =
+        RevocationService::new(state.db_conn.clone(), state.revoked_tokens.clone());
+    match revocation_service.revoke(&claims).await {
+        Ok(revoked) => {
+            if !revoked {
+                tracing::debug!("Token without jti stays valid until it expires");
+            }
+            Ok((
+                cleared_cookies(),
+                Json(json!({ "message": "Logged out successfully" })),
+            ))
+        }
         Err(err) => {
             tracing::error!("Logout failed: {}", err);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -246,6 +317,229 @@ pub async fn logout_user(
     }
 }
 
+/// Logout user on all devices (protected)
+///
+/// Invalidates every token issued to the user so far and ends all sessions.
+#[utoipa::path(
+    post,
+    path = "/api/logout-all",
+    responses(
+        (status = 200, description = "User logged out everywhere"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn logout_everywhere(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+    let sessions = session_service
+        .revoke_all(claims.user_id, None)
+        .await
+        .map_err(session_error_status)?;
+
+    let revocation_service =
+        RevocationService::new(state.db_conn.clone(), state.revoked_tokens.clone());
+    if let Err(err) = revocation_service.revoke_user_tokens(claims.user_id).await {
+        tracing::error!("Logout everywhere failed: {}", err);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    Ok((
+        cleared_cookies(),
+        Json(json!({ "message": "Logged out everywhere", "sessions": sessions })),
+    ))
+}
+
+/// List the active sessions of the user (protected)
+#[utoipa::path(
+    get,
+    path = "/api/sessions",
+    responses(
+        (status = 200, description = "Active sessions, the requesting one marked as current"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn list_sessions(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SessionInfo>>, StatusCode> {
+    let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+    let sessions = session_service
+        .list(claims.user_id, claims.sid)
+        .await
+        .map_err(session_error_status)?;
+
+    Ok(Json(sessions))
+}
+
+/// End a session of the user, e.g. on a lost device (protected)
+#[utoipa::path(
+    delete,
+    path = "/api/sessions/{id}",
+    params(
+        ("id" = String, Path, description = "Session ID")
+    ),
+    responses(
+        (status = 200, description = "Session ended"),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Session not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn revoke_session(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+    session_service
+        .revoke(claims.user_id, session_id)
+        .await
+        .map_err(session_error_status)?;
+
+    Ok(Json(json!({ "message": "Session ended" })))
+}
+
+/// End all sessions of the user except the requesting one (protected)
+#[utoipa::path(
+    delete,
+    path = "/api/sessions",
+    responses(
+        (status = 200, description = "Other sessions ended"),
+        (status = 401, description = "Unauthorized"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn revoke_other_sessions(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let session_service = SessionService::new(state.db_conn.clone(), state.jwt_keys.clone());
+    let revoked = session_service
+        .revoke_all(claims.user_id, claims.sid)
+        .await
+        .map_err(session_error_status)?;
+
+    Ok(Json(json!({ "revoked": revoked })))
+}
+
+/// Tokens of a started or refreshed session, also set as cookies
+fn session_response(tokens: SessionTokens) -> impl IntoResponse {
+    let access_cookie = Cookie::build((ACCESS_COOKIE, tokens.access_token.clone()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .build();
+    let refresh_cookie = Cookie::build((REFRESH_COOKIE, tokens.refresh_token))
+        .path("/api")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(cookie::time::Duration::seconds(
+            (tokens.refresh_expires_at - Utc::now()).num_seconds(),
+        ))
+        .build();
+
+    (
+        StatusCode::OK,
+        AppendHeaders([
+            (header::SET_COOKIE, access_cookie.to_string()),
+            (header::SET_COOKIE, refresh_cookie.to_string()),
+        ]),
+        Json(AuthResponse {
+            token: tokens.access_token,
+            user_id: tokens.user.id.to_string(),
+            username: tokens.user.name,
+            two_factor_enabled: tokens.user.two_factor_enabled,
+            force_password_change: tokens.user.force_password_change,
+            expires_in: (tokens.access_expires_at - Utc::now()).num_seconds(),
+        }),
+    )
+}
+
+/// Removes the session cookies from the browser
+fn cleared_cookies() -> AppendHeaders<[(header::HeaderName, String); 2]> {
+    let clear = |name: &'static str, path: &'static str| {
+        Cookie::build((name, ""))
+            .path(path)
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .max_age(cookie::time::Duration::ZERO)
+            .build()
+            .to_string()
+    };
+
+    AppendHeaders([
+        (header::SET_COOKIE, clear(ACCESS_COOKIE, "/")),
+        (header::SET_COOKIE, clear(REFRESH_COOKIE, "/api")),
+    ])
+}
+
+fn request_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| Cookie::split_parse(value.to_string()))
+        .filter_map(Result::ok)
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_string())
+}
+
+fn device_info(peer: SocketAddr, headers: &HeaderMap) -> DeviceInfo {
+    DeviceInfo {
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        ip_address: Some(source_ip(peer, headers)),
+    }
+}
+
+pub(crate) fn lockout_error_status(e: LockoutError) -> StatusCode {
+    match e {
+        LockoutError::TooManyAttempts(retry_after) => {
+            tracing::warn!("Attempt refused, retry in {} seconds", retry_after);
+            StatusCode::TOO_MANY_REQUESTS
+        }
+        LockoutError::NotFound(_) | LockoutError::UserNotFound(_) => StatusCode::NOT_FOUND,
+        LockoutError::DatabaseError(_) => {
+            tracing::error!("Login throttling failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+fn session_error_status(e: SessionError) -> StatusCode {
+    match e {
+        SessionError::InvalidToken | SessionError::TokenReused(_) => StatusCode::UNAUTHORIZED,
+        SessionError::ConcurrentRefresh => StatusCode::CONFLICT,
+        SessionError::SessionNotFound(_) => StatusCode::NOT_FOUND,
+        SessionError::DatabaseError(_) | SessionError::JwtError(_) => {
+            tracing::error!("Session handling failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
 /// Get RSA public key for encryption
 #[utoipa::path(
     get,
@@ -270,6 +564,28 @@ pub async fn get_public_key(
     }
 }
 
+/// Public keys access tokens are signed with (JWKS)
+///
+/// Other services validate CSF tokens with the key named in the token's `kid`
+/// header. Keys are rotated, refetch the set when a `kid` is unknown.
+#[utoipa::path(
+    get,
+    path = "/api/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "JSON Web Key Set with the RS256 signing keys")
+    ),
+    tag = "Encryption"
+)]
+pub async fn get_jwks(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", JWKS_MAX_AGE_SECONDS),
+        )],
+        Json(state.jwt_keys.jwks()),
+    )
+}
+
 /// Get user profile (protected)
 #[utoipa::path(
     get,
@@ -354,6 +670,12 @@ pub struct TwoFactorCodeRequest {
     pub code: String,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    /// One-time codes that replace a 2FA code, only shown this once
+    pub recovery_codes: Vec<String>,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct ChangePasswordRequest {
     pub old_password: String,
@@ -399,8 +721,9 @@ pub async fn setup_2fa(
     path = "/api/2fa/enable",
     request_body = TwoFactorCodeRequest,
     responses(
-        (status = 200, description = "2FA enabled successfully"),
+        (status = 200, description = "2FA enabled successfully", body = RecoveryCodesResponse),
         (status = 400, description = "Invalid 2FA code"),
+        (status = 429, description = "Too many failed attempts"),
         (status = 500, description = "Internal server error")
     ),
     security(
@@ -411,15 +734,43 @@ pub async fn setup_2fa(
 pub async fn enable_2fa(
     AuthenticatedUser(claims): AuthenticatedUser,
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<TwoFactorCodeRequest>,
 ) -> Result<Json<Value>, StatusCode> {
     let auth_service = AuthService::new(state.db_conn.clone());
+    let lockout_service = LockoutService::new(state.db_conn.clone());
+    let ip = source_ip(peer, &headers);
+
+    lockout_service
+        .reserve(&claims.username, &ip)
+        .await
+        .map_err(lockout_error_status)?;
 
     match auth_service.enable_2fa(claims.user_id, payload.code).await {
-        Ok(_) => Ok(Json(json!({ "message": "2FA enabled successfully" }))),
-        Err(crate::auth_service::AuthError::InvalidTwoFactorCode) => Err(StatusCode::BAD_REQUEST),
+        Ok(recovery_codes) => {
+            lockout_service
+                .record_success(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Ok(Json(json!({
+                "message": "2FA enabled successfully",
+                "recovery_codes": recovery_codes,
+            })))
+        }
+        Err(crate::auth_service::AuthError::InvalidTwoFactorCode) => {
+            lockout_service
+                .record_failure(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::BAD_REQUEST)
+        }
         Err(err) => {
             tracing::error!("Failed to enable 2FA: {}", err);
+            lockout_service
+                .release(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
@@ -432,7 +783,8 @@ pub async fn enable_2fa(
     request_body = TwoFactorCodeRequest,
     responses(
         (status = 200, description = "2FA disabled successfully"),
-        (status = 400, description = "Invalid 2FA code"),
+        (status = 400, description = "Invalid 2FA or recovery code"),
+        (status = 429, description = "Too many failed attempts"),
         (status = 500, description = "Internal server error")
     ),
     security(
@@ -443,15 +795,129 @@ pub async fn enable_2fa(
 pub async fn disable_2fa(
     AuthenticatedUser(claims): AuthenticatedUser,
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<TwoFactorCodeRequest>,
 ) -> Result<Json<Value>, StatusCode> {
     let auth_service = AuthService::new(state.db_conn.clone());
+    let lockout_service = LockoutService::new(state.db_conn.clone());
+    let ip = source_ip(peer, &headers);
+
+    lockout_service
+        .reserve(&claims.username, &ip)
+        .await
+        .map_err(lockout_error_status)?;
 
     match auth_service.disable_2fa(claims.user_id, payload.code).await {
-        Ok(_) => Ok(Json(json!({ "message": "2FA disabled successfully" }))),
-        Err(crate::auth_service::AuthError::InvalidTwoFactorCode) => Err(StatusCode::BAD_REQUEST),
+        Ok(_) => {
+            lockout_service
+                .record_success(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Ok(Json(json!({ "message": "2FA disabled successfully" })))
+        }
+        Err(crate::auth_service::AuthError::InvalidTwoFactorCode) => {
+            lockout_service
+                .record_failure(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::BAD_REQUEST)
+        }
         Err(err) => {
             tracing::error!("Failed to disable 2FA: {}", err);
+            lockout_service
+                .release(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Replace the recovery codes with new ones (protected)
+#[utoipa::path(
+    post,
+    path = "/api/2fa/recovery-codes",
+    request_body = TwoFactorCodeRequest,
+    responses(
+        (status = 200, description = "New recovery codes, the old ones stop working", body = RecoveryCodesResponse),
+        (status = 400, description = "Invalid 2FA or recovery code, or 2FA not enabled"),
+        (status = 429, description = "Too many failed attempts"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn regenerate_recovery_codes(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>, StatusCode> {
+    let auth_service = AuthService::new(state.db_conn.clone());
+    let lockout_service = LockoutService::new(state.db_conn.clone());
+    let ip = source_ip(peer, &headers);
+
+    lockout_service
+        .reserve(&claims.username, &ip)
+        .await
+        .map_err(lockout_error_status)?;
+
+    match auth_service
+        .regenerate_recovery_codes(claims.user_id, payload.code)
+        .await
+    {
+        Ok(recovery_codes) => {
+            lockout_service
+                .record_success(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Ok(Json(RecoveryCodesResponse { recovery_codes }))
+        }
+        Err(crate::auth_service::AuthError::InvalidTwoFactorCode) => {
+            lockout_service
+                .record_failure(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::BAD_REQUEST)
+        }
+        Err(err) => {
+            tracing::error!("Failed to regenerate recovery codes: {}", err);
+            lockout_service
+                .release(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Number of unused recovery codes (protected)
+#[utoipa::path(
+    get,
+    path = "/api/2fa/recovery-codes",
+    responses(
+        (status = 200, description = "Unused recovery codes"),
+        (status = 500, description = "Internal server error")
+    ),
+    security(
+        ("bearer_auth" = [])
+    ),
+    tag = "Authentication"
+)]
+pub async fn get_recovery_codes_status(
+    AuthenticatedUser(claims): AuthenticatedUser,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let auth_service = AuthService::new(state.db_conn.clone());
+
+    match auth_service.remaining_recovery_codes(claims.user_id).await {
+        Ok(remaining) => Ok(Json(json!({ "remaining": remaining }))),
+        Err(err) => {
+            tracing::error!("Failed to count recovery codes: {}", err);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
@@ -465,6 +931,7 @@ pub async fn disable_2fa(
     responses(
         (status = 200, description = "Password changed successfully"),
         (status = 400, description = "Invalid old password"),
+        (status = 429, description = "Too many failed attempts"),
         (status = 500, description = "Internal server error")
     ),
     security(
@@ -475,18 +942,43 @@ pub async fn disable_2fa(
 pub async fn change_password(
     AuthenticatedUser(claims): AuthenticatedUser,
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<ChangePasswordRequest>,
 ) -> Result<Json<Value>, StatusCode> {
     let auth_service = AuthService::new(state.db_conn.clone());
+    let lockout_service = LockoutService::new(state.db_conn.clone());
+    let ip = source_ip(peer, &headers);
+
+    lockout_service
+        .reserve(&claims.username, &ip)
+        .await
+        .map_err(lockout_error_status)?;
 
     match auth_service
         .change_password(claims.user_id, payload.old_password, payload.new_password)
         .await
     {
-        Ok(_) => Ok(Json(json!({ "message": "Password changed successfully" }))),
-        Err(crate::auth_service::AuthError::InvalidCredentials) => Err(StatusCode::BAD_REQUEST),
+        Ok(_) => {
+            lockout_service
+                .record_success(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Ok(Json(json!({ "message": "Password changed successfully" })))
+        }
+        Err(crate::auth_service::AuthError::InvalidCredentials) => {
+            lockout_service
+                .record_failure(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
+            Err(StatusCode::BAD_REQUEST)
+        }
         Err(err) => {
             tracing::error!("Failed to change password: {}", err);
+            lockout_service
+                .release(&claims.username, &ip)
+                .await
+                .map_err(lockout_error_status)?;
             Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
@@ -10,6 +10,8 @@ use sysinfo::{Disks, Networks, System};
 use tokio::time::{interval, Duration};
 use uuid::Uuid;
 
+use crate::event_bus::{EventBus, LiveEvent};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalSystemMetrics {
     pub agent_id: Uuid,
@@ -46,13 +48,14 @@ pub struct LocalSystemMetrics {
 pub struct SelfMonitor {
     agent_id: Uuid,
     db_conn: Arc<DbConn>,
+    events: EventBus,
     system: System,
     networks: Networks,
     disks: Disks,
 }
 
 impl SelfMonitor {
-    pub async fn new(db_conn: Arc<DbConn>) -> Result<Self> {
+    pub async fn new(db_conn: Arc<DbConn>, events: EventBus) -> Result<Self> {
         // Get or create local agent
         let hostname = System::host_name().unwrap_or_else(|| "localhost".to_string());
         let agent_name = format!("CSF-Core-{}", hostname);
@@ -89,6 +92,12 @@ impl SelfMonitor {
                 capabilities: ActiveValue::Set(Some(Json::Array(vec![Json::String(
                     "self-monitor".to_string(),
                 )]))),
+                cert_expires_at: ActiveValue::Set(None),
+                relayed_by: ActiveValue::Set(None),
+                burst_until: ActiveValue::Set(None),
+                resource_group_id: ActiveValue::Set(None),
+                services: ActiveValue::Set(None),
+                reissue_approved_until: ActiveValue::Set(None),
             };
 
             let agent = new_agent.insert(db_conn.as_ref()).await?;
@@ -99,6 +108,7 @@ impl SelfMonitor {
         Ok(Self {
             agent_id,
             db_conn,
+            events,
             system: System::new_all(),
             networks: Networks::new_with_refreshed_list(),
             disks: Disks::new_with_refreshed_list(),
@@ -219,9 +229,12 @@ impl SelfMonitor {
             hostname: ActiveValue::Set(Some(metrics.hostname.clone())),
             uptime_seconds: ActiveValue::Set(Some(metrics.uptime_seconds as i64)),
             custom_metrics: ActiveValue::Set(None),
+            relayed_by: ActiveValue::Set(None),
         };
 
-        new_metrics.insert(self.db_conn.as_ref()).await?;
+        let stored = new_metrics.insert(self.db_conn.as_ref()).await?;
+        self.events
+            .publish(LiveEvent::AgentMetrics(Arc::new(stored)));
         Ok(())
     }
 
@@ -274,8 +287,8 @@ impl SelfMonitor {
     }
 }
 
-pub async fn start_self_monitoring(db_conn: Arc<DbConn>) {
-    match SelfMonitor::new(db_conn).await {
+pub async fn start_self_monitoring(db_conn: Arc<DbConn>, events: EventBus) {
+    match SelfMonitor::new(db_conn, events).await {
         Ok(monitor) => {
             tokio::spawn(async move {
                 let _ = monitor.run().await;
@@ -0,0 +1,305 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use entity::{refresh_tokens, user, RefreshTokens, User};
+use rand::RngCore;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::jwt::{Claims, JwtKeys, ACCESS_TOKEN_MINUTES};
+
+/// Lifetime of a refresh token, every refresh extends the session by this much
+pub const REFRESH_TOKEN_DAYS: i64 = 14;
+
+/// A rotated token presented again this soon comes from a concurrent refresh
+/// of the same client, not from a replay (seconds)
+const ROTATION_GRACE_SECONDS: i64 = 10;
+
+/// Longest user agent that is stored
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sea_orm::DbErr),
+    #[error("JWT error: {0}")]
+    JwtError(#[from] jsonwebtoken::errors::Error),
+    #[error("Refresh token is invalid, expired or revoked")]
+    InvalidToken,
+    #[error("Refresh token of session {0} was used again, session revoked")]
+    TokenReused(Uuid),
+    #[error("Refresh token was just rotated by another request")]
+    ConcurrentRefresh,
+    #[error("Session {0} not found")]
+    SessionNotFound(Uuid),
+}
+
+pub type SessionResult<T> = Result<T, SessionError>;
+
+/// Device a session is used from
+#[derive(Debug, Default, Clone)]
+pub struct DeviceInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+/// Tokens handed to the client when a session starts or is refreshed
+#[derive(Debug)]
+pub struct SessionTokens {
+    pub user: user::Model,
+    pub access_token: String,
+    pub access_expires_at: DateTime<Utc>,
+    pub refresh_token: String,
+    pub refresh_expires_at: DateTime<Utc>,
+}
+
+/// An active session of a user
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub started_at: NaiveDateTime,
+    /// When the session was last refreshed
+    pub last_used_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    /// The session of the requesting token
+    pub current: bool,
+}
+
+/// Login sessions made of short-lived access tokens and rotating refresh tokens
+#[derive(Clone)]
+pub struct SessionService {
+    db: DatabaseConnection,
+    keys: JwtKeys,
+}
+
+impl SessionService {
+    pub fn new(db: DatabaseConnection, keys: JwtKeys) -> Self {
+        Self { db, keys }
+    }
+
+    /// Starts a new session for a user that just logged in
+    pub async fn start(
+        &self,
+        user: user::Model,
+        device: DeviceInfo,
+    ) -> SessionResult<SessionTokens> {
+        let now = Utc::now();
+        let session_id = Uuid::new_v4();
+        let refresh_token = self
+            .issue_refresh_token(&self.db, user.id, session_id, now.naive_utc(), &device, now)
+            .await?;
+
+        self.issue_tokens(session_id, user, refresh_token, now)
+    }
+
+    /// Exchanges a refresh token for new tokens of the same session
+    ///
+    /// Every refresh token can only be used once. Presenting one that was
+    /// already exchanged means it leaked, so the whole session is revoked.
+    pub async fn refresh(
+        &self,
+        refresh_token: &str,
+        device: DeviceInfo,
+    ) -> SessionResult<SessionTokens> {
+        let now = Utc::now();
+        let token = RefreshTokens::find()
+            .filter(refresh_tokens::Column::TokenHash.eq(hash_token(refresh_token)))
+            .one(&self.db)
+            .await?
+            .ok_or(SessionError::InvalidToken)?;
+
+        if token.revoked_at.is_some() || token.expires_at < now.naive_utc() {
+            return Err(SessionError::InvalidToken);
+        }
+        if let Some(rotated_at) = token.rotated_at {
+            if now.naive_utc() - rotated_at < Duration::seconds(ROTATION_GRACE_SECONDS) {
+                return Err(SessionError::ConcurrentRefresh);
+            }
+            let revoked = self.revoke_family(token.family_id, now).await?;
+            tracing::warn!(
+                "Refresh token of session {} (user {}) was reused, revoked {} tokens",
+                token.family_id,
+                token.user_id,
+                revoked
+            );
+            return Err(SessionError::TokenReused(token.family_id));
+        }
+
+        let user = User::find_by_id(token.user_id)
+            .one(&self.db)
+            .await?
+            .ok_or(SessionError::InvalidToken)?;
+
+        let txn = self.db.begin().await?;
+        // Only one request can rotate the token, a concurrent one finds it rotated
+        let rotated = RefreshTokens::update_many()
+            .col_expr(
+                refresh_tokens::Column::RotatedAt,
+                sea_orm::sea_query::Expr::value(now.naive_utc()),
+            )
+            .filter(refresh_tokens::Column::Id.eq(token.id))
+            .filter(refresh_tokens::Column::RotatedAt.is_null())
+            .exec(&txn)
+            .await?
+            .rows_affected;
+        if rotated == 0 {
+            return Err(SessionError::ConcurrentRefresh);
+        }
+        let refresh_token = self
+            .issue_refresh_token(
+                &txn,
+                user.id,
+                token.family_id,
+                token.session_started_at,
+                &device,
+                now,
+            )
+            .await?;
+        txn.commit().await?;
+
+        self.issue_tokens(token.family_id, user, refresh_token, now)
+    }
+
+    /// Active sessions of a user, most recently used first
+    pub async fn list(
+        &self,
+        user_id: Uuid,
+        current: Option<Uuid>,
+    ) -> SessionResult<Vec<SessionInfo>> {
+        let tokens = self.active_tokens(user_id).await?;
+        Ok(tokens
+            .into_iter()
+            .map(|token| SessionInfo {
+                id: token.family_id,
+                user_agent: token.user_agent,
+                ip_address: token.ip_address,
+                started_at: token.session_started_at,
+                last_used_at: token.created_at,
+                expires_at: token.expires_at,
+                current: Some(token.family_id) == current,
+            })
+            .collect())
+    }
+
+    /// Ends a session of a user, its refresh token can no longer be used
+    pub async fn revoke(&self, user_id: Uuid, session_id: Uuid) -> SessionResult<()> {
+        let owned = RefreshTokens::find()
+            .filter(refresh_tokens::Column::FamilyId.eq(session_id))
+            .filter(refresh_tokens::Column::UserId.eq(user_id))
+            .filter(refresh_tokens::Column::RevokedAt.is_null())
+            .one(&self.db)
+            .await?
+            .is_some();
+        if !owned {
+            return Err(SessionError::SessionNotFound(session_id));
+        }
+
+        self.revoke_family(session_id, Utc::now()).await?;
+        Ok(())
+    }
+
+    /// Ends all sessions of a user except `keep`, returning how many were ended
+    pub async fn revoke_all(&self, user_id: Uuid, keep: Option<Uuid>) -> SessionResult<u64> {
+        let mut revoked = 0;
+        for token in self.active_tokens(user_id).await? {
+            if Some(token.family_id) != keep {
+                self.revoke_family(token.family_id, Utc::now()).await?;
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    /// The current refresh token of each active session of a user
+    async fn active_tokens(&self, user_id: Uuid) -> SessionResult<Vec<refresh_tokens::Model>> {
+        Ok(RefreshTokens::find()
+            .filter(refresh_tokens::Column::UserId.eq(user_id))
+            .filter(refresh_tokens::Column::RotatedAt.is_null())
+            .filter(refresh_tokens::Column::RevokedAt.is_null())
+            .filter(refresh_tokens::Column::ExpiresAt.gt(Utc::now().naive_utc()))
+            .order_by_desc(refresh_tokens::Column::CreatedAt)
+            .all(&self.db)
+            .await?)
+    }
+
+    async fn revoke_family(&self, family_id: Uuid, now: DateTime<Utc>) -> SessionResult<u64> {
+        Ok(RefreshTokens::update_many()
+            .col_expr(
+                refresh_tokens::Column::RevokedAt,
+                sea_orm::sea_query::Expr::value(now.naive_utc()),
+            )
+            .filter(refresh_tokens::Column::FamilyId.eq(family_id))
+            .filter(refresh_tokens::Column::RevokedAt.is_null())
+            .exec(&self.db)
+            .await?
+            .rows_affected)
+    }
+
+    /// Stores the hash of a new refresh token and returns the token
+    async fn issue_refresh_token<C: sea_orm::ConnectionTrait>(
+        &self,
+        db: &C,
+        user_id: Uuid,
+        family_id: Uuid,
+        session_started_at: NaiveDateTime,
+        device: &DeviceInfo,
+        now: DateTime<Utc>,
+    ) -> SessionResult<String> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = URL_SAFE_NO_PAD.encode(bytes);
+
+        let user_agent = device
+            .user_agent
+            .as_ref()
+            .map(|agent| agent.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        refresh_tokens::ActiveModel {
+            id: ActiveValue::Set(Uuid::new_v4()),
+            user_id: ActiveValue::Set(user_id),
+            family_id: ActiveValue::Set(family_id),
+            token_hash: ActiveValue::Set(hash_token(&token)),
+            user_agent: ActiveValue::Set(user_agent),
+            ip_address: ActiveValue::Set(device.ip_address.clone()),
+            session_started_at: ActiveValue::Set(session_started_at),
+            created_at: ActiveValue::Set(now.naive_utc()),
+            expires_at: ActiveValue::Set((now + Duration::days(REFRESH_TOKEN_DAYS)).naive_utc()),
+            rotated_at: ActiveValue::Set(None),
+            revoked_at: ActiveValue::Set(None),
+        }
+        .insert(db)
+        .await?;
+
+        Ok(token)
+    }
+
+    fn issue_tokens(
+        &self,
+        session_id: Uuid,
+        user: user::Model,
+        refresh_token: String,
+        now: DateTime<Utc>,
+    ) -> SessionResult<SessionTokens> {
+        let access_token = self
+            .keys
+            .sign(&Claims::new(user.id, user.name.clone(), session_id))?;
+        Ok(SessionTokens {
+            user,
+            access_token,
+            access_expires_at: now + Duration::minutes(ACCESS_TOKEN_MINUTES),
+            refresh_token,
+            refresh_expires_at: now + Duration::days(REFRESH_TOKEN_DAYS),
+        })
+    }
+}
+
+/// Tokens are random enough that a fast hash is as good as a slow one
+pub(crate) fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
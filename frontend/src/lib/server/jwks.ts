@@ -0,0 +1,38 @@
+import { createPublicKey, type KeyObject } from 'node:crypto';
+import { env } from '$env/dynamic/private';
+
+/** Backend as reached from the SvelteKit server, PUBLIC_API_BASE_URL may be relative */
+const BACKEND_URL = env.BACKEND_URL || 'http://localhost:8000';
+
+/** Unknown key IDs trigger a refetch at most this often */
+const MIN_REFETCH_MS = 30_000;
+
+let keys = new Map<string, KeyObject>();
+let fetchedAt = 0;
+
+async function fetchKeys(): Promise<void> {
+  fetchedAt = Date.now();
+  try {
+    const response = await fetch(`${BACKEND_URL}/api/.well-known/jwks.json`);
+    if (!response.ok) {
+      console.error('[jwks] Failed to fetch signing keys:', response.status);
+      return;
+    }
+
+    const jwks: { keys: (JsonWebKey & { kid: string })[] } = await response.json();
+    keys = new Map(jwks.keys.map((jwk) => [jwk.kid, createPublicKey({ key: jwk, format: 'jwk' })]));
+  } catch (error) {
+    console.error('[jwks] Failed to fetch signing keys:', error);
+  }
+}
+
+/**
+ * Public key the backend signed a token with. Keys are rotated, so the
+ * key set is fetched again when a token names a key that isn't known yet.
+ */
+export async function signingKey(kid: string): Promise<KeyObject | null> {
+  if (!keys.has(kid) && Date.now() - fetchedAt > MIN_REFETCH_MS) {
+    await fetchKeys();
+  }
+  return keys.get(kid) ?? null;
+}
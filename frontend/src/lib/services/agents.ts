@@ -1,8 +1,27 @@
-import type { Agent, AgentMetrics } from '$lib/types/agent';
+import type {
+  Agent,
+  AgentFilter,
+  AgentHealth,
+  AgentAnomaly,
+  AgentList,
+  AgentMetrics,
+  FleetSummary,
+  HealthState,
+} from '$lib/types/agent';
+import { PUBLIC_API_BASE_URL } from '$env/static/public';
 import { ApiClient } from './api-client';
 
-export async function listAgents(): Promise<Agent[]> {
-  const response = await ApiClient.get('/agents');
+export async function listAgents(
+  filter: AgentFilter = {},
+  page: number = 1,
+  perPage: number = 500
+): Promise<AgentList> {
+  const params = new URLSearchParams({ page: String(page), per_page: String(perPage) });
+  for (const [key, value] of Object.entries(filter)) {
+    if (value) params.set(key, value);
+  }
+
+  const response = await ApiClient.get(`/agents?${params}`);
 
   if (!response.ok) {
     throw new Error(`Failed to fetch agents: ${response.statusText}`);
@@ -21,6 +40,68 @@ export async function getAgentDetails(agentId: string): Promise<Agent> {
   return response.json();
 }
 
+export async function getAgentHealth(agentId: string): Promise<AgentHealth> {
+  const response = await ApiClient.get(`/agents/${agentId}/health`);
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch agent health: ${response.statusText}`);
+  }
+
+  return response.json();
+}
+
+export async function listAgentHealth(state?: HealthState): Promise<AgentHealth[]> {
+  const response = await ApiClient.get(state ? `/agents/health?state=${state}` : '/agents/health');
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch agent health: ${response.statusText}`);
+  }
+
+  return response.json();
+}
+
+export async function getFleetSummary(): Promise<FleetSummary> {
+  const response = await ApiClient.get('/agents/summary');
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch fleet summary: ${response.statusText}`);
+  }
+
+  return response.json();
+}
+
+export async function listAnomalies(
+  filter: { agent_id?: string; metric?: string; kind?: string; include_resolved?: boolean } = {}
+): Promise<AgentAnomaly[]> {
+  const params = new URLSearchParams();
+  for (const [key, value] of Object.entries(filter)) {
+    if (value !== undefined && value !== '') params.set(key, String(value));
+  }
+
+  const response = await ApiClient.get(`/agents/anomalies?${params}`);
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch anomalies: ${response.statusText}`);
+  }
+
+  return response.json();
+}
+
+export async function getAgentAnomalies(
+  agentId: string,
+  includeResolved: boolean = false
+): Promise<AgentAnomaly[]> {
+  const response = await ApiClient.get(
+    `/agents/${agentId}/anomalies?include_resolved=${includeResolved}`
+  );
+
+  if (!response.ok) {
+    throw new Error(`Failed to fetch agent anomalies: ${response.statusText}`);
+  }
+
+  return response.json();
+}
+
 export async function getAgentMetrics(
   agentId: string,
   limit: number = 100
@@ -34,6 +115,24 @@ export async function getAgentMetrics(
   return response.json();
 }
 
+/**
+ * Download link for the metrics of agents as CSV or NDJSON. The browser sends the
+ * login cookie, so the file streams straight to disk.
+ */
+export function metricsExportUrl(
+  agents: 'all' | string[],
+  options: { from?: Date; to?: Date; format?: 'csv' | 'ndjson' } = {}
+): string {
+  const params = new URLSearchParams({
+    agents: agents === 'all' ? 'all' : agents.join(','),
+    format: options.format ?? 'csv',
+  });
+  if (options.from) params.set('from', options.from.toISOString());
+  if (options.to) params.set('to', options.to.toISOString());
+
+  return `${PUBLIC_API_BASE_URL}/agents/metrics/export?${params}`;
+}
+
 export function formatBytes(bytes: number): string {
   if (bytes === 0) return '0 B';
   const k = 1024;
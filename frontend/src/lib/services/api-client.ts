@@ -12,7 +12,18 @@ export interface ApiError extends Error {
   statusText: string;
 }
 
+interface RefreshResponse {
+  token: string;
+  user_id: string;
+  username: string;
+  two_factor_enabled: boolean;
+  force_password_change: boolean;
+}
+
 export class ApiClient {
+  /** Refresh in flight, shared by all requests that got a 401 meanwhile */
+  private static refreshing: Promise<string | null> | null = null;
+
   /**
    * Makes an authenticated API request with automatic error handling
    * and logout on 401 errors
@@ -67,6 +78,22 @@ export class ApiClient {
         statusText: response.statusText,
       });
 
+      // Access token expired - refresh the session once and retry
+      if (response.status === 401 && browser) {
+        const newToken = await this.refreshSession();
+        if (newToken) {
+          headers['Authorization'] = `Bearer ${newToken}`;
+          const retried = await fetch(`${API_BASE_URL}${url}`, {
+            ...options,
+            headers,
+            credentials: 'include',
+          });
+          if (retried.status !== 401) {
+            return retried;
+          }
+        }
+      }
+
       // Handle 401 Unauthorized - session expired or invalid
       if (response.status === 401) {
         logger.warn('Received 401 Unauthorized, logging out user');
@@ -143,6 +170,60 @@ export class ApiClient {
     return this.fetch(url, { method: 'DELETE' }, token);
   }
 
+  /**
+   * Exchanges the refresh token cookie for a new access token.
+   * Concurrent callers share one request, since every refresh token
+   * can only be used once. Returns null if the session is gone.
+   */
+  static refreshSession(): Promise<string | null> {
+    if (!this.refreshing) {
+      this.refreshing = this.requestRefresh().finally(() => {
+        this.refreshing = null;
+      });
+    }
+    return this.refreshing;
+  }
+
+  private static async requestRefresh(retry = true): Promise<string | null> {
+    try {
+      const response = await fetch(`${API_BASE_URL}/refresh`, {
+        method: 'POST',
+        credentials: 'include',
+      });
+
+      // Another tab just rotated the token, its cookie is ours as well now
+      if (response.status === 409 && retry) {
+        await new Promise((resolve) => setTimeout(resolve, 500));
+        return this.requestRefresh(false);
+      }
+      if (!response.ok) {
+        logger.warn('Session refresh failed', { status: response.status });
+        return null;
+      }
+
+      const data: RefreshResponse = await response.json();
+      authStore.login(
+        {
+          id: data.user_id,
+          username: data.username,
+          two_factor_enabled: data.two_factor_enabled,
+          force_password_change: data.force_password_change,
+        },
+        data.token
+      );
+      await fetch('/api/set-auth-cookie', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ token: data.token }),
+      });
+      logger.debug('Session refreshed');
+      return data.token;
+    } catch (error) {
+      logger.error('Session refresh failed', error);
+      return null;
+    }
+  }
+
   /**
    * Handles unauthorized access - logs out user and redirects to signin
    */
@@ -22,6 +22,8 @@ interface AuthResponse {
   username: string;
   two_factor_enabled: boolean;
   force_password_change: boolean;
+  /** Seconds until the access token expires */
+  expires_in: number;
 }
 
 interface PublicKeyResponse {
@@ -63,6 +65,7 @@ export class AuthService {
 
     const response = await fetch(`${API_BASE_URL}/register`, {
       method: 'POST',
+      credentials: 'include', // Receive the refresh token cookie
       headers: {
         'Content-Type': 'application/json',
       },
@@ -95,6 +98,7 @@ export class AuthService {
 
     const response = await fetch(`${API_BASE_URL}/login`, {
       method: 'POST',
+      credentials: 'include', // Receive the refresh token cookie
       headers: {
         'Content-Type': 'application/json',
       },
@@ -108,6 +112,9 @@ export class AuthService {
       if (response.status === 403) {
         throw new Error('2FA_REQUIRED');
       }
+      if (response.status === 429) {
+        throw new Error('Too many failed attempts, please try again later');
+      }
       throw new Error('Login failed');
     }
 
@@ -117,6 +124,22 @@ export class AuthService {
   static async logout(token: string): Promise<void> {
     const response = await fetch(`${API_BASE_URL}/logout`, {
       method: 'POST',
+      credentials: 'include', // Clear the refresh token cookie
+      headers: {
+        Authorization: `Bearer ${token}`,
+      },
+    });
+
+    if (!response.ok && response.status !== 401) {
+      throw new Error('Logout failed');
+    }
+  }
+
+  /** Logs out on all devices, every token issued so far becomes invalid */
+  static async logoutEverywhere(token: string): Promise<void> {
+    const response = await fetch(`${API_BASE_URL}/logout-all`, {
+      method: 'POST',
+      credentials: 'include', // Clear the refresh token cookie
       headers: {
         Authorization: `Bearer ${token}`,
       },
@@ -6,6 +6,10 @@ export interface Setup2FAResponse {
   qr_code: string;
 }
 
+export interface RecoveryCodesResponse {
+  recovery_codes: string[];
+}
+
 export class SettingsService {
   /**
    * Setup 2FA - Generate secret and QR code
@@ -23,9 +27,9 @@ export class SettingsService {
   }
 
   /**
-   * Enable 2FA with verification code
+   * Enable 2FA with verification code, returns the recovery codes
    */
-  static async enable2FA(code: string): Promise<void> {
+  static async enable2FA(code: string): Promise<string[]> {
     logger.info('Enabling 2FA');
 
     const response = await ApiClient.post('/2fa/enable', { code });
@@ -34,6 +38,26 @@ export class SettingsService {
       const error = await response.text();
       throw new Error(error || 'Failed to enable 2FA');
     }
+
+    const data: RecoveryCodesResponse = await response.json();
+    return data.recovery_codes;
+  }
+
+  /**
+   * Replace the recovery codes, the old ones stop working
+   */
+  static async regenerateRecoveryCodes(code: string): Promise<string[]> {
+    logger.info('Regenerating recovery codes');
+
+    const response = await ApiClient.post('/2fa/recovery-codes', { code });
+
+    if (!response.ok) {
+      const error = await response.text();
+      throw new Error(error || 'Failed to regenerate recovery codes');
+    }
+
+    const data: RecoveryCodesResponse = await response.json();
+    return data.recovery_codes;
   }
 
   /**
@@ -0,0 +1,61 @@
+import { PUBLIC_API_BASE_URL } from '$env/static/public';
+import type { AgentMetrics } from '$lib/types/agent';
+import type { LocalSystemMetrics } from './system';
+
+export interface ResourceStatusEvent {
+  resource_id: string;
+  name: string;
+  resource_group_id: string;
+  status: 'pending' | 'running' | 'stopped' | 'error' | 'deleted' | string;
+  container_id: string | null;
+}
+
+export interface StreamSubscription {
+  /** 'all' or a list of agent IDs */
+  agents?: 'all' | string[];
+  /** 'all' or a list of resource IDs */
+  resources?: 'all' | string[];
+  /** Also receive the backend host's system metrics */
+  system?: boolean;
+}
+
+export interface StreamHandlers {
+  onMetrics?: (metrics: AgentMetrics) => void;
+  onResource?: (status: ResourceStatusEvent) => void;
+  onSystem?: (metrics: LocalSystemMetrics) => void;
+  /** Events were dropped because the client fell behind, reload to catch up */
+  onLagged?: (skipped: number) => void;
+}
+
+function selection(value: 'all' | string[]): string {
+  return value === 'all' ? 'all' : value.join(',');
+}
+
+/**
+ * Opens the live event stream. Authenticates with the login cookie and
+ * reconnects on its own; call the returned function to close it.
+ */
+export function openLiveStream(
+  subscription: StreamSubscription,
+  handlers: StreamHandlers
+): () => void {
+  const params = new URLSearchParams();
+  if (subscription.agents) params.set('agents', selection(subscription.agents));
+  if (subscription.resources) params.set('resources', selection(subscription.resources));
+  if (subscription.system) params.set('system', 'true');
+
+  const source = new EventSource(`${PUBLIC_API_BASE_URL}/stream?${params}`, {
+    withCredentials: true,
+  });
+
+  const listen = <T>(event: string, handler?: (data: T) => void) => {
+    if (!handler) return;
+    source.addEventListener(event, (e) => handler(JSON.parse((e as MessageEvent).data)));
+  };
+  listen('metrics', handlers.onMetrics);
+  listen('resource', handlers.onResource);
+  listen('system', handlers.onSystem);
+  listen<{ skipped: number }>('lagged', (data) => handlers.onLagged?.(data.skipped));
+
+  return () => source.close();
+}
@@ -5,26 +5,87 @@ export interface Agent {
   agent_version: string;
   os_type: string;
   os_version: string;
-  status: 'online' | 'offline' | 'error';
+  ip_address: string | null;
+  status: 'online' | 'offline' | 'error' | 'stopped' | 'deregistered' | 'decommissioned';
   last_heartbeat: string;
-  tags?: Record<string, string>;
+  tags: string[];
   capabilities?: string[];
-  organization_id?: string;
+  organization_id: string | null;
+  resource_group_id: string | null;
   created_at: string;
   updated_at: string;
 }
 
+export interface AgentFilter {
+  status?: string;
+  tag?: string;
+  os_type?: string;
+  organization_id?: string;
+  resource_group_id?: string;
+}
+
+export interface AgentList {
+  agents: Agent[];
+  total: number;
+  page: number;
+  per_page: number;
+}
+
+export type HealthState = 'healthy' | 'warning' | 'critical' | 'offline' | 'inactive';
+
+export interface HealthIssue {
+  check: string;
+  severity: 'warning' | 'critical';
+  message: string;
+}
+
+export interface AgentHealth {
+  agent_id: string;
+  name: string;
+  state: HealthState;
+  score: number | null;
+  issues: HealthIssue[];
+}
+
+export interface FleetSummary {
+  total: number;
+  by_state: Partial<Record<HealthState, number>>;
+  by_os: Record<string, number>;
+  by_version: Record<string, number>;
+  latest_version: string | null;
+  generated_at: string;
+}
+
+export type AnomalyMetric = 'cpu' | 'memory' | 'disk' | 'network_rx' | 'network_tx';
+
+export interface AgentAnomaly {
+  id: string;
+  agent_id: string;
+  metric: AnomalyMetric;
+  kind: 'deviation' | 'disk_full';
+  /** Usage in percent, network rates in bytes per second */
+  value: number;
+  baseline: number | null;
+  std_dev: number | null;
+  z_score: number | null;
+  days_until_full: number | null;
+  first_detected_at: string;
+  last_detected_at: string;
+  resolved_at: string | null;
+}
+
 export interface AgentMetrics {
   id: string;
   agent_id: string;
   timestamp: string;
-  cpu_usage_percent: number;
-  memory_usage_percent: number;
-  memory_total_bytes: number;
-  memory_used_bytes: number;
-  disk_usage_percent: number;
-  disk_total_bytes: number;
-  disk_used_bytes: number;
+  // Metric groups are collected on their own intervals, a sample only has the due ones
+  cpu_usage_percent: number | null;
+  memory_usage_percent: number | null;
+  memory_total_bytes: number | null;
+  memory_used_bytes: number | null;
+  disk_usage_percent: number | null;
+  disk_total_bytes: number | null;
+  disk_used_bytes: number | null;
   network_rx_bytes?: number;
   network_tx_bytes?: number;
   custom_metrics?: Record<string, any>;
@@ -1,6 +1,6 @@
 import type { LayoutServerLoad } from './$types';
 import jwt from 'jsonwebtoken';
-import { env } from '$env/dynamic/private';
+import { signingKey } from '$lib/server/jwks';
 
 interface User {
   id: string;
@@ -14,14 +14,20 @@ export const load: LayoutServerLoad = async ({ cookies }) => {
   if (token) {
     console.log('[+layout.server.ts] Auth token found:', token.substring(0, 15) + '...');
     try {
-      const JWT_SECRET = env.JWT_SECRET;
-      if (!JWT_SECRET) {
-        console.error('[+layout.server.ts] JWT_SECRET is not defined!');
+      const kid = jwt.decode(token, { complete: true })?.header.kid;
+      const key = kid ? await signingKey(kid) : null;
+      if (!key) {
+        console.error('[+layout.server.ts] Token was not signed with a known key');
         cookies.delete('auth_token', { path: '/' });
         return { user: null, token: null };
       }
       console.log('[+layout.server.ts] Verifying token...');
-      const decoded = jwt.verify(token, JWT_SECRET) as jwt.JwtPayload;
+      // Access tokens are short-lived, the client refreshes an expired one with the
+      // refresh token cookie. Whether the session is still valid is up to the backend.
+      const decoded = jwt.verify(token, key, {
+        algorithms: ['RS256'],
+        ignoreExpiration: true,
+      }) as jwt.JwtPayload;
       console.log('[+layout.server.ts] Token verified successfully. Decoded payload:', decoded);
 
       if (decoded && typeof decoded === 'object') {